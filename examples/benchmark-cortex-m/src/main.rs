@@ -7,8 +7,9 @@ use defmt::info;
 use defmt_rtt as _;
 use panic_probe as _;
 use static_cell::ConstStaticCell;
-use taskette::{arch::yield_now, scheduler::spawn, task::TaskConfig, timer::current_time};
+use taskette::{scheduler::spawn, task::TaskConfig};
 use taskette_cortex_m::Stack;
+use taskette_utils::{bench, time::Stopwatch};
 
 use crate::wrapper::init_scheduler;
 
@@ -17,7 +18,8 @@ static TASK2_STACK: ConstStaticCell<Stack<8192>> = ConstStaticCell::new(Stack::n
 
 const TICK_FREQ: u32 = 1000;
 
-const SWITCH_COUNT: usize = 1000;
+const SWITCH_COUNT: u32 = 1000;
+const LOCK_COUNT: u32 = 1000;
 
 #[wrapper::entry]
 fn main() -> ! {
@@ -32,38 +34,28 @@ fn main() -> ! {
     )
     .unwrap();
 
-    let _task2 = spawn(
-        task2_func,
-        TASK2_STACK.take(),
-        TaskConfig::default().with_priority(1),
-    )
-    .unwrap();
-
     scheduler.start();
 }
 
 fn task1_func() {
-    loop {
-        let start_time = current_time().unwrap();
-
-        for _ in 0..(SWITCH_COUNT / 2) {
-            // Switch to `task2` and back => 2 context switches
-            yield_now();
-        }
+    let partner_stack = TASK2_STACK.take();
+    let mut stopwatch = Stopwatch::start().unwrap();
 
-        let end_time = current_time().unwrap();
-        let time_ms = 1000 * (end_time - start_time) / TICK_FREQ as u64;
+    loop {
+        // In CPU cycles
+        let context_switch =
+            bench::measure_context_switch(SWITCH_COUNT, 1, &mut *partner_stack).unwrap();
+        info!(
+            "Context switch: min = {}, avg = {}, max = {}",
+            context_switch.min, context_switch.average, context_switch.max
+        );
 
-        info!("Time diff = {} ms", time_ms);
+        let lock = bench::measure_lock_uncontended(LOCK_COUNT);
         info!(
-            "Context switch time = {} us",
-            1000 * time_ms / SWITCH_COUNT as u64
+            "Uncontended lock: min = {}, avg = {}, max = {}",
+            lock.min, lock.average, lock.max
         );
-    }
-}
 
-fn task2_func() {
-    loop {
-        yield_now();
+        info!("Round time: {} us", stopwatch.lap().as_micros() as u64);
     }
 }
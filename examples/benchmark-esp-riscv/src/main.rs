@@ -3,23 +3,23 @@
 
 use defmt::info;
 use esp_backtrace as _;
-use esp_hal::interrupt::software::SoftwareInterruptControl;
+use esp_hal::{interrupt::software::SoftwareInterruptControl, timer::systimer::SystemTimer};
 use esp_println as _;
 use static_cell::ConstStaticCell;
 use taskette::{
-    arch::yield_now,
     scheduler::{SchedulerConfig, spawn},
     task::TaskConfig,
-    timer::current_time,
 };
 use taskette_esp_riscv::{Stack, init_scheduler};
+use taskette_utils::{bench, time::Stopwatch};
 
 static TASK1_STACK: ConstStaticCell<Stack<8192>> = ConstStaticCell::new(Stack::new());
 static TASK2_STACK: ConstStaticCell<Stack<8192>> = ConstStaticCell::new(Stack::new());
 
 const TICK_FREQ: u32 = 1000;
 
-const SWITCH_COUNT: usize = 1000;
+const SWITCH_COUNT: u32 = 1000;
+const LOCK_COUNT: u32 = 1000;
 
 esp_bootloader_esp_idf::esp_app_desc!();
 
@@ -30,11 +30,11 @@ fn main() -> ! {
     let clock = esp_hal::clock::CpuClock::max();
     let peripherals = esp_hal::init(esp_hal::Config::default().with_cpu_clock(clock));
     let sw_interrupt = SoftwareInterruptControl::new(peripherals.SW_INTERRUPT);
+    let systimer = SystemTimer::new(peripherals.SYSTIMER);
 
     let scheduler = init_scheduler(
-        peripherals.SYSTIMER,
+        systimer.alarm1,
         sw_interrupt.software_interrupt0,
-        1_000_000 * clock as u32,
         SchedulerConfig::default().with_tick_freq(TICK_FREQ),
     )
     .unwrap();
@@ -46,38 +46,28 @@ fn main() -> ! {
     )
     .unwrap();
 
-    let _task2 = spawn(
-        task2_func,
-        TASK2_STACK.take(),
-        TaskConfig::default().with_priority(1),
-    )
-    .unwrap();
-
     scheduler.start();
 }
 
 fn task1_func() {
-    loop {
-        let start_time = current_time().unwrap();
-
-        for _ in 0..(SWITCH_COUNT / 2) {
-            // Switch to `task2` and back => 2 context switches
-            yield_now();
-        }
+    let partner_stack = TASK2_STACK.take();
+    let mut stopwatch = Stopwatch::start().unwrap();
 
-        let end_time = current_time().unwrap();
-        let time_ms = 1000 * (end_time - start_time) / TICK_FREQ as u64;
+    loop {
+        // In microseconds (the resolution of the counter of the ESP port)
+        let context_switch =
+            bench::measure_context_switch(SWITCH_COUNT, 1, &mut *partner_stack).unwrap();
+        info!(
+            "Context switch: min = {}, avg = {}, max = {}",
+            context_switch.min, context_switch.average, context_switch.max
+        );
 
-        info!("Time diff = {} ms", time_ms);
+        let lock = bench::measure_lock_uncontended(LOCK_COUNT);
         info!(
-            "Context switch time = {} us",
-            1000 * time_ms / SWITCH_COUNT as u64
+            "Uncontended lock: min = {}, avg = {}, max = {}",
+            lock.min, lock.average, lock.max
         );
-    }
-}
 
-fn task2_func() {
-    loop {
-        yield_now();
+        info!("Round time: {} us", stopwatch.lap().as_micros() as u64);
     }
 }
@@ -9,10 +9,8 @@ use defmt::info;
 use embedded_hal::delay::DelayNs;
 use esp_backtrace as _;
 use esp_hal::{
-    interrupt::software::SoftwareInterruptControl,
-    peripherals::RMT,
-    rmt::Rmt,
-    time::Rate,
+    interrupt::software::SoftwareInterruptControl, peripherals::RMT, rmt::Rmt, time::Rate,
+    timer::systimer::SystemTimer,
 };
 use esp_hal_smartled::{SmartLedsAdapter, smart_led_buffer};
 use esp_println as _;
@@ -49,10 +47,10 @@ fn main() -> ! {
 
     // Init scheduler
     let swint = SoftwareInterruptControl::new(peripherals.SW_INTERRUPT);
+    let systimer = SystemTimer::new(peripherals.SYSTIMER);
     let scheduler = init_scheduler(
-        peripherals.SYSTIMER,
+        systimer.alarm1,
         swint.software_interrupt0,
-        1_000_000 * clock as u32,
         SchedulerConfig::default().with_tick_freq(TICK_FREQ),
     )
     .unwrap();
@@ -3,6 +3,7 @@
 #![no_std]
 #![no_main]
 
+use cortex_m::peripheral::NVIC;
 use defmt::{error, info};
 use defmt_rtt as _;
 use embedded_hal::{delay::DelayNs, digital::OutputPin};
@@ -10,17 +11,19 @@ use panic_halt as _;
 use rp2040_hal::{
     Clock,
     gpio::{FunctionSio, Pin, PullDown, SioOutput, bank0::Gpio25},
+    pac::{Interrupt, interrupt},
 };
 use static_cell::StaticCell;
 use taskette::{
     scheduler::{SchedulerConfig, spawn},
+    sync::IrqEvent,
     task::TaskConfig,
 };
 use taskette_cortex_m::{Stack, init_scheduler};
-use taskette_utils::delay::Delay;
+use taskette_utils::{delay::Delay, usb::serve};
 use usb_device::{
     UsbError,
-    bus::{UsbBus, UsbBusAllocator},
+    bus::UsbBusAllocator,
     device::{StringDescriptors, UsbDeviceBuilder, UsbVidPid},
 };
 use usbd_serial::{SerialPort, USB_CLASS_CDC};
@@ -28,6 +31,9 @@ use usbd_serial::{SerialPort, USB_CLASS_CDC};
 static BLINK_TASK_STACK: StaticCell<Stack<8192>> = StaticCell::new();
 static USB_TASK_STACK: StaticCell<Stack<8192>> = StaticCell::new();
 
+// Signaled by the USB interrupt
+static USB_EVENT: IrqEvent = IrqEvent::new();
+
 // This is necessary when directly using HAL without BSP
 // Reference: https://github.com/rp-rs/rp-hal/blob/50a77826533f759b331076712d151e93650cc2bc/rp2040-hal-examples/src/bin/blinky.rs#L27-L33
 #[unsafe(link_section = ".boot2")]
@@ -123,7 +129,7 @@ fn blink_task_func(mut led_pin: Pin<Gpio25, FunctionSio<SioOutput>, PullDown>) {
 fn usb_task_func(usb_bus: UsbBusAllocator<rp2040_hal::usb::UsbBus>) {
     info!("USB task started");
 
-    let mut serial_port = SerialPort::new(&usb_bus);
+    let serial_port = SerialPort::new(&usb_bus);
 
     let mut usb_dev = UsbDeviceBuilder::new(&usb_bus, UsbVidPid(0x16C0, 0x27DD))
         .strings(&[StringDescriptors::default()
@@ -136,44 +142,41 @@ fn usb_task_func(usb_bus: UsbBusAllocator<rp2040_hal::usb::UsbBus>) {
 
     let mut last_dev_state = usb_dev.state();
 
-    loop {
-        if !usb_dev.poll(&mut [&mut serial_port]) {
-            continue;
-        }
-
-        let dev_state = usb_dev.state();
-        if dev_state != last_dev_state {
-            info!("USB state changed: {}", dev_state);
-            last_dev_state = dev_state;
-        }
-
-        let mut buf = [0u8; 64];
-        match serial_port.read(&mut buf) {
-            Ok(count) => {
-                info!("CDC Received: {}", buf[..count]);
-
-                // Echo the received data back
-                match write_all(&mut serial_port, &buf[..count]) {
-                    Ok(_) => (),
-                    Err(err) => error!("{}", err),
-                }
+    // Polls only when the USB interrupt is raised, instead of spinning
+    serve(
+        &mut usb_dev,
+        &mut (serial_port,),
+        &USB_EVENT,
+        || unsafe { NVIC::unmask(Interrupt::USBCTRL_IRQ) },
+        |usb_dev, (serial_port,)| {
+            let dev_state = usb_dev.state();
+            if dev_state != last_dev_state {
+                info!("USB state changed: {}", dev_state);
+                last_dev_state = dev_state;
             }
-            Err(UsbError::WouldBlock) => (),
-            Err(err) => error!("{}", err),
-        }
-    }
-}
 
-fn write_all(serial_port: &mut SerialPort<'_, impl UsbBus>, data: &[u8]) -> Result<(), UsbError> {
-    let mut idx = 0;
+            let mut buf = [0u8; 64];
+            match serial_port.read(&mut buf) {
+                Ok(count) => {
+                    info!("CDC Received: {}", buf[..count]);
 
-    while idx < data.len() {
-        match serial_port.write(data) {
-            Ok(n) => idx += n,
-            Err(UsbError::WouldBlock) => (),
-            Err(err) => return Err(err),
-        }
-    }
+                    // Echo the received data back (dropped if the buffer is full, as the device is not polled here)
+                    match serial_port.write(&buf[..count]) {
+                        Ok(_) => (),
+                        Err(err) => error!("{}", err),
+                    }
+                }
+                Err(UsbError::WouldBlock) => (),
+                Err(err) => error!("{}", err),
+            }
+        },
+    )
+}
 
-    Ok(())
+#[allow(non_snake_case)]
+#[interrupt]
+fn USBCTRL_IRQ() {
+    // The interrupt stays pending until the device is polled, so it is masked until then
+    NVIC::mask(Interrupt::USBCTRL_IRQ);
+    USB_EVENT.signal();
 }
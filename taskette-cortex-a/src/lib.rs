@@ -0,0 +1,542 @@
+//! Architecture-specific part of Taskette for Arm Cortex-A (Armv7-A) processors running bare-metal.
+//!
+//! Tasks run in System (SYS) mode and context switching is done in the IRQ handler.
+//! A GIC (v1/v2) is used for interrupt dispatching and the generic timer generates the scheduler tick.
+//! Context switch is requested by sending a Software Generated Interrupt (SGI) to the current core.
+//!
+//! `taskette_irq_entry` has to be installed as the IRQ vector of the exception vector table by startup code.
+
+#![no_std]
+
+use core::cell::RefCell;
+#[cfg(feature = "rtos-awareness")]
+use core::mem::offset_of;
+
+use critical_section::Mutex;
+use static_cell::ConstStaticCell;
+#[cfg(feature = "rtos-awareness")]
+use taskette::rtos_awareness::ContextLayout;
+use taskette::{
+    arch::{StackAllocation, TickSource},
+    portable_atomic::{AtomicBool, Ordering},
+    scheduler::{Scheduler, SchedulerConfig, SchedulerStorage},
+    sync::interrupt_free,
+};
+
+const IDLE_TASK_STACK_SIZE: usize = 2048;
+/// SGI used for requesting a context switch
+const YIELD_SGI: u32 = 0;
+const SPURIOUS_IRQ: u32 = 1023;
+
+const MODE_SYS: u32 = 0x1F;
+
+// GIC distributor registers
+const GICD_CTLR: usize = 0x000;
+const GICD_ISENABLER0: usize = 0x100;
+const GICD_SGIR: usize = 0xF00;
+// GIC CPU interface registers
+const GICC_CTLR: usize = 0x000;
+const GICC_PMR: usize = 0x004;
+const GICC_IAR: usize = 0x00C;
+const GICC_EOIR: usize = 0x010;
+
+#[cfg(all(target_abi = "eabihf", target_feature = "d32"))]
+const NUM_DREGS: usize = 32;
+#[cfg(all(target_abi = "eabihf", not(target_feature = "d32")))]
+const NUM_DREGS: usize = 16;
+
+static IDLE_TASK_STACK: ConstStaticCell<Stack<IDLE_TASK_STACK_SIZE>> =
+    ConstStaticCell::new(Stack::new());
+static PORT_CONFIG: Mutex<RefCell<Option<PortConfig>>> = Mutex::new(RefCell::new(None));
+
+#[derive(Clone, Copy, Debug)]
+struct PortConfig {
+    gic: GicConfig,
+    irq_handler: fn(u32),
+    timer_period: u32,
+}
+
+/// Location of the GIC and the interrupt ID of the generic timer.
+#[derive(Clone, Copy, Debug)]
+#[non_exhaustive]
+pub struct GicConfig {
+    pub distributor_base: usize,
+    pub cpu_interface_base: usize,
+    pub timer_irq: u32,
+}
+
+impl GicConfig {
+    /// Creates a GIC configuration from base addresses of the distributor and the CPU interface.
+    ///
+    /// The timer interrupt defaults to PPI 29 (secure physical timer), which is used when running bare-metal in secure state.
+    pub fn new(distributor_base: usize, cpu_interface_base: usize) -> Self {
+        Self {
+            distributor_base,
+            cpu_interface_base,
+            timer_irq: 29,
+        }
+    }
+
+    /// Sets the interrupt ID of the generic timer (e.g. 30 for the non-secure physical timer).
+    pub fn with_timer_irq(self, timer_irq: u32) -> Self {
+        Self { timer_irq, ..self }
+    }
+}
+
+/// Context frame saved on the task stack.
+#[repr(C, align(8))]
+#[derive(Clone, Debug)]
+struct SavedRegisters {
+    #[cfg(target_abi = "eabihf")]
+    fpscr: u32,
+    #[cfg(target_abi = "eabihf")]
+    fpexc: u32,
+    // D16-D31 (if present) are placed before D0-D15
+    #[cfg(target_abi = "eabihf")]
+    dregs: [u64; NUM_DREGS],
+    r: [u32; 13],
+    lr: u32,
+    pc: u32,
+    cpsr: u32,
+}
+
+impl SavedRegisters {
+    fn from_pc_and_r0(pc: u32, r0: u32) -> Self {
+        let mut r = [0; 13];
+        r[0] = r0;
+        Self {
+            #[cfg(target_abi = "eabihf")]
+            fpscr: 0,
+            #[cfg(target_abi = "eabihf")]
+            fpexc: 1 << 30, // EN bit
+            #[cfg(target_abi = "eabihf")]
+            dregs: [0; NUM_DREGS],
+            r,
+            lr: 0,
+            pc: pc & !1,
+            // SYS mode with IRQ enabled, Thumb state if the entry point is Thumb code
+            cpsr: MODE_SYS | if pc & 1 != 0 { 1 << 5 } else { 0 },
+        }
+    }
+}
+
+/// Layout of the saved context for debuggers
+#[cfg(feature = "rtos-awareness")]
+#[unsafe(export_name = "taskette_context_layout")]
+static CONTEXT_LAYOUT: ContextLayout = {
+    let mut layout =
+        ContextLayout::new(size_of::<SavedRegisters>(), offset_of!(SavedRegisters, pc))
+            .with_register(14, offset_of!(SavedRegisters, lr))
+            .with_register(15, offset_of!(SavedRegisters, pc));
+    let mut n = 0;
+    while n < 13 {
+        layout = layout.with_register(n, offset_of!(SavedRegisters, r) + n * size_of::<u32>());
+        n += 1;
+    }
+    layout
+};
+
+/// Initializes the scheduler.
+///
+/// `irq_handler` is called with the interrupt ID for every interrupt not used by the scheduler.
+/// The tick frequency is derived from the generic timer frequency (CNTFRQ), which must be set by the boot firmware.
+///
+/// # Safety
+/// `gic` must point to the GIC of this core, and the GIC and the generic timer must not be used by other code.
+pub unsafe fn init_scheduler(
+    gic: GicConfig,
+    config: SchedulerConfig,
+    irq_handler: fn(u32),
+) -> Option<Scheduler> {
+    set_port_config(gic, irq_handler);
+
+    unsafe { Scheduler::init(read_cntfrq(), config) }
+}
+
+/// Initializes the scheduler with the memory supplied by the application (see [`SchedulerStorage`]).
+///
+/// # Safety
+/// Same as [`init_scheduler`].
+pub unsafe fn init_scheduler_with_storage<const TASKS: usize, const TIMERS: usize>(
+    gic: GicConfig,
+    config: SchedulerConfig,
+    irq_handler: fn(u32),
+    storage: &'static mut SchedulerStorage<TASKS, TIMERS>,
+) -> Option<Scheduler> {
+    set_port_config(gic, irq_handler);
+
+    unsafe { Scheduler::init_with_storage(read_cntfrq(), config, storage) }
+}
+
+fn set_port_config(gic: GicConfig, irq_handler: fn(u32)) {
+    interrupt_free(|cs| {
+        PORT_CONFIG.replace(
+            cs,
+            Some(PortConfig {
+                gic,
+                irq_handler,
+                timer_period: 0,
+            }),
+        )
+    });
+}
+
+// Saves/restores D16-D31 on VFP implementations with 32 double-precision registers
+#[cfg(target_feature = "d32")]
+macro_rules! save_upper_dregs {
+    () => {
+        "vpush {{d16-d31}}"
+    };
+}
+#[cfg(not(target_feature = "d32"))]
+macro_rules! save_upper_dregs {
+    () => {
+        ""
+    };
+}
+#[cfg(target_feature = "d32")]
+macro_rules! restore_upper_dregs {
+    () => {
+        "vpop {{d16-d31}}"
+    };
+}
+#[cfg(not(target_feature = "d32"))]
+macro_rules! restore_upper_dregs {
+    () => {
+        ""
+    };
+}
+
+/// IRQ exception handler.
+///
+/// Saves the context of the interrupted task on its stack, dispatches the interrupt on the IRQ stack,
+/// and restores the context of the (possibly different) task selected by the scheduler.
+#[cfg(not(target_abi = "eabihf"))] // No VFP
+#[unsafe(no_mangle)]
+#[unsafe(naked)]
+pub unsafe extern "C" fn taskette_irq_entry() {
+    core::arch::naked_asm!(
+        "sub lr, lr, #4",   // Calculate the return address
+        "srsdb sp!, #0x1F", // Push the return address and SPSR onto the task (SYS mode) stack
+        "cps #0x1F",    // Switch to SYS mode to access SP and LR of the task
+        "push {{r0-r12, lr}}",  // Save general-purpose registers
+        "mov r0, sp",   // SP of the task is used as the first argument
+        "cps #0x12",    // Return to IRQ mode to run the kernel on the IRQ stack
+        "bl {irq_dispatch}",    // R0 is used as the return value (SP of the next task)
+        "cps #0x1F",    // Switch to SYS mode
+        "mov sp, r0",   // Change SP to the next task
+        "pop {{r0-r12, lr}}",   // Restore general-purpose registers
+        "rfeia sp!",    // Restore PC and CPSR
+        irq_dispatch = sym irq_dispatch,
+    );
+}
+
+/// IRQ exception handler.
+///
+/// Saves the context of the interrupted task on its stack, dispatches the interrupt on the IRQ stack,
+/// and restores the context of the (possibly different) task selected by the scheduler.
+/// For chips with a VFP.
+#[cfg(target_abi = "eabihf")] // VFP
+#[unsafe(no_mangle)]
+#[unsafe(naked)]
+pub unsafe extern "C" fn taskette_irq_entry() {
+    core::arch::naked_asm!(
+        "sub lr, lr, #4",   // Calculate the return address
+        "srsdb sp!, #0x1F", // Push the return address and SPSR onto the task (SYS mode) stack
+        "cps #0x1F",    // Switch to SYS mode to access SP and LR of the task
+        "push {{r0-r12, lr}}",  // Save general-purpose registers
+        "vpush {{d0-d15}}", // Save VFP registers
+        save_upper_dregs!(),
+        "vmrs r0, fpscr",
+        "vmrs r1, fpexc",
+        "push {{r0, r1}}",  // Save VFP control registers
+        "mov r0, sp",   // SP of the task is used as the first argument
+        "cps #0x12",    // Return to IRQ mode to run the kernel on the IRQ stack
+        "bl {irq_dispatch}",    // R0 is used as the return value (SP of the next task)
+        "cps #0x1F",    // Switch to SYS mode
+        "mov sp, r0",   // Change SP to the next task
+        "pop {{r0, r1}}",
+        "vmsr fpexc, r1",   // Restore FPEXC first because VFP must be enabled for the following instructions
+        "vmsr fpscr, r0",
+        restore_upper_dregs!(),
+        "vpop {{d0-d15}}",  // Restore VFP registers
+        "pop {{r0-r12, lr}}",   // Restore general-purpose registers
+        "rfeia sp!",    // Restore PC and CPSR
+        irq_dispatch = sym irq_dispatch,
+    );
+}
+
+extern "C" fn irq_dispatch(sp: usize) -> usize {
+    let config = interrupt_free(|cs| *PORT_CONFIG.borrow_ref(cs))
+        .unwrap_or_else(|| unreachable!());
+    let gicc = config.gic.cpu_interface_base;
+
+    let iar = unsafe { read_reg(gicc + GICC_IAR) };
+    let irq = iar & 0x3FF;
+    if irq == SPURIOUS_IRQ {
+        return sp;
+    }
+
+    let next_sp = if irq == YIELD_SGI {
+        unsafe { taskette::scheduler::select_task(sp) }
+    } else if irq == config.gic.timer_irq {
+        write_cntp_tval(config.timer_period);
+        taskette::scheduler::handle_tick();
+        sp
+    } else {
+        (config.irq_handler)(irq);
+        sp
+    };
+
+    unsafe {
+        write_reg(gicc + GICC_EOIR, iar);
+    }
+
+    next_sp
+}
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_setup() {
+    let gic = interrupt_free(|cs| PORT_CONFIG.borrow_ref(cs).map(|config| config.gic))
+        .expect("Scheduler not initialized");
+
+    unsafe {
+        // Enable the SGI for context switching and the timer interrupt
+        write_reg(
+            gic.distributor_base + GICD_ISENABLER0,
+            (1 << YIELD_SGI) | (1 << gic.timer_irq),
+        );
+        write_reg(gic.distributor_base + GICD_CTLR, 1);
+        write_reg(gic.cpu_interface_base + GICC_PMR, 0xFF);
+        write_reg(gic.cpu_interface_base + GICC_CTLR, 1);
+    }
+}
+
+/// Tick generated by the non-secure physical timer (CNTP) of the generic timer
+struct GenericTimerTick;
+
+static TICK_SOURCE: GenericTimerTick = GenericTimerTick;
+
+impl TickSource for GenericTimerTick {
+    fn set_frequency(&self, clock_freq: u32, tick_freq: u32) {
+        let timer_period = clock_freq / tick_freq;
+        interrupt_free(|cs| {
+            let mut config = PORT_CONFIG.borrow_ref_mut(cs);
+            let config = config.as_mut().expect("Scheduler not initialized");
+            config.timer_period = timer_period;
+        });
+
+        // Configure the generic timer (but not started yet)
+        write_cntp_ctl(0);
+        write_cntp_tval(timer_period);
+    }
+
+    fn start(&self) {
+        write_cntp_ctl(1); // ENABLE=1, IMASK=0
+
+        unsafe {
+            core::arch::asm!("cpsie i");
+        }
+    }
+
+    fn stop(&self) {
+        write_cntp_ctl(0);
+    }
+}
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_tick_source() -> &'static dyn TickSource {
+    &TICK_SOURCE
+}
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_yield_now() {
+    let gic = interrupt_free(|cs| PORT_CONFIG.borrow_ref(cs).map(|config| config.gic))
+        .expect("Scheduler not initialized");
+
+    // Send the SGI to this core only (TargetListFilter = 0b10)
+    unsafe {
+        write_reg(gic.distributor_base + GICD_SGIR, (0b10 << 24) | YIELD_SGI);
+    }
+}
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_init_stack(sp: *mut u8, pc: usize, arg: *const u8, arg_size: usize) -> *mut u8 {
+    unsafe {
+        // Push the closure into the initial stack
+        let sp = push_to_stack(sp, arg, arg_size);
+        // Call `call_closure` with a pointer to the closure as the first argument
+        let sp = push_to_stack(
+            sp,
+            &SavedRegisters::from_pc_and_r0(pc as u32, sp as u32) as *const _ as *const u8,
+            core::mem::size_of::<SavedRegisters>(),
+        );
+        sp
+    }
+}
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub unsafe fn _taskette_run_with_stack(pc: usize, sp: *mut u8, _stack_limit: *mut u8) -> ! {
+    unsafe {
+        core::arch::asm!(
+            // Switch to SYS mode, which is used by tasks
+            "cps #0x1F",
+            // Set the SP with the new value
+            "mov sp, {new_sp}",
+            // Jump to the new PC
+            "blx {new_pc}",
+            new_sp = in(reg) sp,
+            new_pc = in(reg) pc,
+        );
+    }
+
+    unreachable!()
+}
+
+#[unsafe(no_mangle)]
+pub fn _taskette_get_idle_task_stack(core_id: usize) -> Option<&'static mut [u8]> {
+    if core_id != 0 {
+        return None;
+    }
+
+    if let Some(stack) = IDLE_TASK_STACK.try_take() {
+        Some(&mut stack.0)
+    } else {
+        None
+    }
+}
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_wait_for_interrupt() {
+    unsafe {
+        core::arch::asm!("wfi");
+    }
+}
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_core_id() -> usize {
+    0
+}
+
+// The IPI below cannot reach other cores
+const _: () = assert!(
+    taskette::scheduler::NUM_CORES == 1,
+    "This port is single-core; disable `smp` feature of taskette"
+);
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_ipi(_core_id: usize) {
+    // Single-core: only the current core exists
+    _taskette_yield_now();
+}
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_try_lock(lock: &AtomicBool) -> bool {
+    lock.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+        .is_ok()
+}
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_mask_interrupts() -> usize {
+    let cpsr: u32;
+    unsafe {
+        core::arch::asm!("mrs {}, cpsr", "cpsid i", out(reg) cpsr);
+    }
+    cpsr as usize
+}
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_restore_interrupts(state: usize) {
+    // Re-enable only if IRQ was not masked (I bit of CPSR) before
+    if state & (1 << 7) == 0 {
+        unsafe {
+            core::arch::asm!("cpsie i");
+        }
+    }
+}
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_cycle_count() -> u32 {
+    // Lower half of the physical count of the generic timer (CNTPCT)
+    let (low, _high): (u32, u32);
+    unsafe {
+        core::arch::asm!("isb", "mrrc p15, 0, {}, {}, c14", out(reg) low, out(reg) _high);
+    }
+    low
+}
+
+fn read_cntfrq() -> u32 {
+    let value: u32;
+    unsafe {
+        core::arch::asm!("mrc p15, 0, {}, c14, c0, 0", out(reg) value);
+    }
+    value
+}
+
+fn write_cntp_ctl(value: u32) {
+    unsafe {
+        core::arch::asm!("mcr p15, 0, {}, c14, c2, 1", "isb", in(reg) value);
+    }
+}
+
+fn write_cntp_tval(value: u32) {
+    unsafe {
+        core::arch::asm!("mcr p15, 0, {}, c14, c2, 0", "isb", in(reg) value);
+    }
+}
+
+unsafe fn read_reg(addr: usize) -> u32 {
+    unsafe { core::ptr::read_volatile(addr as *const u32) }
+}
+
+unsafe fn write_reg(addr: usize, value: u32) {
+    unsafe { core::ptr::write_volatile(addr as *mut u32, value) }
+}
+
+unsafe fn push_to_stack(sp: *mut u8, obj: *const u8, obj_size: usize) -> *mut u8 {
+    unsafe {
+        let size = obj_size;
+        // Ensure 8-byte alignment
+        let size = if size % 8 == 0 {
+            size
+        } else {
+            size + 8 - (size % 8)
+        };
+
+        let sp = sp.byte_sub(size);
+        core::ptr::copy(obj, sp, obj_size);
+
+        sp
+    }
+}
+
+/// Correctly aligned stack allocation helper.
+///
+/// It ensures allocation of a task-specific stack region correctly aligned at 8 bytes.
+/// Modeled after [rp2040-hal implementation](https://docs.rs/rp2040-hal/0.11.0/rp2040_hal/multicore/struct.Stack.html).
+#[repr(align(8))]
+pub struct Stack<const N: usize>([u8; N]);
+
+impl<const N: usize> Stack<N> {
+    pub const fn new() -> Self {
+        Self([0u8; N])
+    }
+}
+
+impl<const N: usize> StackAllocation for &mut Stack<N> {
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
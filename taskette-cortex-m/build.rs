@@ -0,0 +1,20 @@
+use std::env;
+
+fn main() {
+    let target = env::var("TARGET").unwrap();
+
+    // Armv8-M (Cortex-M23/M33/M55) has stack limit registers
+    println!("cargo::rustc-check-cfg=cfg(armv8m)");
+    if target.starts_with("thumbv8m.") {
+        println!("cargo::rustc-cfg=armv8m");
+    }
+
+    // Armv7-M and the Main Extension of Armv8-M have BASEPRI
+    println!("cargo::rustc-check-cfg=cfg(has_basepri)");
+    if target.starts_with("thumbv7m")
+        || target.starts_with("thumbv7em")
+        || target.starts_with("thumbv8m.main")
+    {
+        println!("cargo::rustc-cfg=has_basepri");
+    }
+}
@@ -0,0 +1,61 @@
+//! `critical-section` implementation raising BASEPRI instead of setting PRIMASK (`basepri` feature).
+//!
+//! Critical sections (including those of the kernel) only mask the interrupts whose priority value is
+//! equal to or greater than the threshold. Interrupts above the threshold (numerically lower values)
+//! are never delayed by the scheduler, so they can meet hard latency requirements.
+//! The kernel masks interrupts with the same threshold, without going through `critical-section`.
+//!
+//! In exchange, interrupts above the threshold are not excluded by critical sections at all. Their handlers must not:
+//! - call any API of taskette (e.g. `Futex::wake` or `handle_tick`),
+//! - access data shared through `critical_section::Mutex` or any other crate relying on `critical-section`.
+//!
+//! PendSV, SysTick, and every interrupt calling the kernel have to stay at or below the threshold
+//! (the port puts PendSV and SysTick at the lowest priority).
+//! No other `critical-section` implementation (e.g. `critical-section-single-core` of `cortex-m`) can be linked.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use cortex_m::register::{basepri, basepri_max};
+
+/// Threshold written to BASEPRI in critical sections
+static THRESHOLD: AtomicU8 = AtomicU8::new(0x80);
+
+/// Sets the priority threshold of critical sections. Default is `0x80`.
+///
+/// The value has the same form as the priority passed to `SCB::set_priority`
+/// (only the implemented upper bits are meaningful, e.g. `0x40` is priority 4 on a chip with 4 priority bits).
+/// Interrupts with a priority value below it are never masked. Has to be called before starting the scheduler.
+pub fn set_basepri_threshold(threshold: u8) {
+    // BASEPRI of 0 disables masking
+    assert!(threshold != 0, "BASEPRI threshold must not be 0");
+    THRESHOLD.store(threshold, Ordering::Relaxed);
+}
+
+struct BasepriCriticalSection;
+
+critical_section::set_impl!(BasepriCriticalSection);
+
+unsafe impl critical_section::Impl for BasepriCriticalSection {
+    unsafe fn acquire() -> critical_section::RawRestoreState {
+        mask()
+    }
+
+    unsafe fn release(prev: critical_section::RawRestoreState) {
+        unsafe { restore(prev) };
+    }
+}
+
+/// Raises BASEPRI to the threshold and returns the previous value. Also used for the kernel critical sections.
+#[inline]
+pub(crate) fn mask() -> u8 {
+    let prev = basepri::read();
+    // Only raises the masking level, so a nested section never lowers it
+    basepri_max::write(THRESHOLD.load(Ordering::Relaxed));
+    prev
+}
+
+/// Restores BASEPRI returned by [`mask`].
+#[inline]
+pub(crate) unsafe fn restore(prev: u8) {
+    unsafe { basepri::write(prev) };
+}
@@ -0,0 +1,16 @@
+//! Tick driven by a timer of the application instead of SysTick (`external-tick` feature).
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+use taskette::{arch::TickSource, sync::interrupt_free};
+
+static TICK_SOURCE: Mutex<Cell<Option<&'static dyn TickSource>>> = Mutex::new(Cell::new(None));
+
+pub(crate) fn set_tick_source(tick_source: &'static dyn TickSource) {
+    interrupt_free(|cs| TICK_SOURCE.borrow(cs).set(Some(tick_source)));
+}
+
+pub(crate) fn tick_source() -> &'static dyn TickSource {
+    interrupt_free(|cs| TICK_SOURCE.borrow(cs).get()).expect("Scheduler not initialized")
+}
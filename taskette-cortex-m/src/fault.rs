@@ -0,0 +1,85 @@
+//! Recovery from faults raised by tasks.
+//!
+//! MemManage, BusFault, and UsageFault are enabled (instead of escalating to HardFault),
+//! and a fault raised in Thread mode on the process stack terminates just the faulting task
+//! if a hook is registered by [`taskette::scheduler::set_task_fault_hook`].
+//! A stack overflow caught by PSPLIM on Armv8-M is reported to the stack overflow hook instead.
+//! Other faults (and faults without a hook) halt the system with a panic.
+
+use cortex_m::peripheral::SCB;
+
+/// Bit of EXC_RETURN indicating that the exception was taken from the process stack (PSP)
+const EXC_RETURN_SPSEL: u32 = 1 << 2;
+/// Enable bits of MemManage, BusFault, and UsageFault in SHCSR
+const SHCSR_FAULT_ENABLE: u32 = (1 << 16) | (1 << 17) | (1 << 18);
+/// Stack overflow bit (STKOF) of UFSR in CFSR
+#[cfg(armv8m)]
+const CFSR_STKOF: u32 = 1 << 20;
+
+/// Enables the configurable fault exceptions on the current core.
+pub(crate) fn enable_faults(scb: &mut SCB) {
+    unsafe {
+        scb.shcsr.modify(|value| value | SHCSR_FAULT_ENABLE);
+    }
+}
+
+#[unsafe(no_mangle)]
+#[unsafe(naked)]
+extern "C" fn MemoryManagement() {
+    core::arch::naked_asm!(
+        "mov r0, lr", // Pass EXC_RETURN
+        "b {handle_fault}",
+        handle_fault = sym handle_fault,
+    );
+}
+
+#[unsafe(no_mangle)]
+#[unsafe(naked)]
+extern "C" fn BusFault() {
+    core::arch::naked_asm!(
+        "mov r0, lr", // Pass EXC_RETURN
+        "b {handle_fault}",
+        handle_fault = sym handle_fault,
+    );
+}
+
+#[unsafe(no_mangle)]
+#[unsafe(naked)]
+extern "C" fn UsageFault() {
+    core::arch::naked_asm!(
+        "mov r0, lr", // Pass EXC_RETURN
+        "b {handle_fault}",
+        handle_fault = sym handle_fault,
+    );
+}
+
+/// Common part of the fault handlers. Returns to the exception return sequence (LR is still EXC_RETURN).
+extern "C" fn handle_fault(exc_return: u32) {
+    let scb = unsafe { &*SCB::PTR };
+    let cfsr = scb.cfsr.read();
+    let in_task = exc_return & EXC_RETURN_SPSEL != 0;
+
+    #[cfg(armv8m)]
+    if in_task
+        && cfsr & CFSR_STKOF != 0
+        && let Ok(task) = taskette::task::current()
+    {
+        // Panics unless a hook is registered by `taskette::scheduler::set_stack_overflow_hook`
+        taskette::scheduler::handle_stack_overflow(task.id());
+        taskette::arch::yield_now();
+        unsafe { scb.cfsr.write(cfsr) }; // Write 1 to clear
+        return;
+    }
+
+    if !in_task || !taskette::scheduler::handle_task_fault() {
+        panic!(
+            "Fault (CFSR = {:08X}, MMFAR = {:08X}, BFAR = {:08X})",
+            cfsr,
+            scb.mmfar.read(),
+            scb.bfar.read()
+        );
+    }
+
+    // The task is switched out by PendSV, which is tail-chained before returning to Thread mode
+    unsafe { scb.cfsr.write(cfsr) }; // Write 1 to clear
+}
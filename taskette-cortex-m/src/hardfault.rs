@@ -0,0 +1,48 @@
+//! HardFault handler reporting the faulting task.
+//!
+//! The stacked exception frame and the fault status registers are printed through `log` or `defmt`
+//! (whichever feature is enabled), and then the system is reset.
+
+use cortex_m::peripheral::SCB;
+use cortex_m_rt::ExceptionFrame;
+
+// Prints an error through the enabled logging crate
+macro_rules! report {
+    ( $( $arg:expr ),+ ) => {
+        {
+            #[cfg(feature = "log")]
+            log::error!( $( $arg ),+ );
+            #[cfg(feature = "defmt")]
+            defmt::error!( $( $arg ),+ );
+        }
+    };
+}
+
+#[cortex_m_rt::exception]
+unsafe fn HardFault(frame: &ExceptionFrame) -> ! {
+    match taskette::task::current() {
+        Ok(task) => report!("HardFault in Task #{}", task.id()),
+        Err(_) => report!("HardFault before the scheduler is initialized"),
+    }
+    report!(
+        "PC = {:#x}, LR = {:#x}, xPSR = {:#x}",
+        frame.pc(),
+        frame.lr(),
+        frame.xpsr()
+    );
+
+    // Fault status registers do not exist on Armv6-M
+    #[cfg(target_has_atomic = "ptr")]
+    {
+        let scb = unsafe { &*SCB::PTR };
+        report!(
+            "HFSR = {:#x}, CFSR = {:#x}, MMFAR = {:#x}, BFAR = {:#x}",
+            scb.hfsr.read(),
+            scb.cfsr.read(),
+            scb.mmfar.read(),
+            scb.bfar.read()
+        );
+    }
+
+    SCB::sys_reset()
+}
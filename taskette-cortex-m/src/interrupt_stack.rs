@@ -0,0 +1,78 @@
+//! Dedicated stack of interrupt handlers (`interrupt-stack` feature).
+//!
+//! Once the scheduler starts, tasks run on their own stacks (PSP) and only exception handlers use the main stack (MSP).
+//! By default the main stack stays where `main` left it, so its size is whatever remains of the RAM region of `cortex-m-rt`.
+//! With [`set_interrupt_stack`], MSP is moved to a stack allocated by the application when the scheduler starts:
+//!
+//! ```ignore
+//! static INTERRUPT_STACK: ConstStaticCell<Stack<4096>> = ConstStaticCell::new(Stack::new());
+//!
+//! set_interrupt_stack(INTERRUPT_STACK.take());
+//! scheduler.start();
+//! ```
+//!
+//! The bottom of the stack is filled with the canary pattern of the scheduler configuration,
+//! which is checked on every SysTick (or by [`check_interrupt_stack`]). On Armv8-M, MSPLIM is also set to the bottom,
+//! so that an overflow raises a fault at the offending instruction.
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+use taskette::sync::interrupt_free;
+
+use crate::Stack;
+
+/// Bottom and top of the interrupt stack of core 0
+static INTERRUPT_STACK: Mutex<Cell<Option<(usize, usize)>>> = Mutex::new(Cell::new(None));
+/// Length (in words) and pattern of the canary, once written
+static CANARY: Mutex<Cell<Option<(usize, u32)>>> = Mutex::new(Cell::new(None));
+
+/// Makes exception handlers of core 0 run on `stack` once the scheduler starts. Has to be called before starting it.
+pub fn set_interrupt_stack<const N: usize>(stack: &'static mut Stack<N>) {
+    let range = stack.0.as_mut_ptr_range();
+    interrupt_free(|cs| {
+        INTERRUPT_STACK
+            .borrow(cs)
+            .set(Some((range.start as usize, range.end as usize)))
+    });
+}
+
+/// Returns `false` if the canary at the bottom of the interrupt stack is overwritten.
+///
+/// Always `true` if no interrupt stack is set or the scheduler has not started yet.
+pub fn check_interrupt_stack() -> bool {
+    interrupt_free(|cs| {
+        let (Some((bottom, _)), Some((len, pattern))) =
+            (INTERRUPT_STACK.borrow(cs).get(), CANARY.borrow(cs).get())
+        else {
+            return true;
+        };
+        // SAFETY: the canary lies in the stack, which is never deallocated
+        let canary = unsafe { core::slice::from_raw_parts(bottom as *const u32, len) };
+        canary.iter().all(|word| *word == pattern)
+    })
+}
+
+/// Writes the canary and returns the bottom and top of the interrupt stack of `core`, if set.
+/// Called just before MSP is switched to it.
+pub(crate) fn prepare(core: usize) -> Option<(usize, usize)> {
+    if core != 0 {
+        return None;
+    }
+    let (bottom, top) = interrupt_free(|cs| INTERRUPT_STACK.borrow(cs).get())?;
+
+    let config = taskette::scheduler::get_config().ok()?;
+    let len = config
+        .stack_canary_len
+        .min((top - bottom) / size_of::<u32>());
+    // SAFETY: the stack is not in use yet, and is aligned by `Stack`
+    let canary = unsafe { core::slice::from_raw_parts_mut(bottom as *mut u32, len) };
+    canary.fill(config.stack_canary_pattern);
+    interrupt_free(|cs| {
+        CANARY
+            .borrow(cs)
+            .set(Some((len, config.stack_canary_pattern)))
+    });
+
+    Some((bottom, top))
+}
@@ -1,16 +1,98 @@
 #![no_std]
 
-use cortex_m::peripheral::{SCB, SYST, scb::SystemHandler, syst::SystClkSource};
+#[cfg(all(feature = "rp2040-smp", feature = "rp2350-smp"))]
+compile_error!("`rp2040-smp` and `rp2350-smp` features are mutually exclusive");
+#[cfg(all(feature = "smp", not(any(feature = "rp2040-smp", feature = "rp2350-smp"))))]
+compile_error!("`smp` feature requires a chip-specific feature (`rp2040-smp` or `rp2350-smp`)");
+
+#[cfg(all(feature = "fault-recovery", not(target_has_atomic = "ptr")))]
+compile_error!("`fault-recovery` feature requires Armv7-M or later (Armv6-M only has HardFault)");
+#[cfg(all(feature = "basepri", not(has_basepri)))]
+compile_error!("`basepri` feature requires Armv7-M or Armv8-M Mainline (BASEPRI register)");
+#[cfg(all(feature = "basepri", feature = "smp"))]
+compile_error!("`basepri` feature only supports single-core chips");
+
+#[cfg(feature = "basepri")]
+mod basepri;
+#[cfg(feature = "external-tick")]
+mod external_tick;
+#[cfg(feature = "fault-recovery")]
+mod fault;
+#[cfg(feature = "hardfault-report")]
+mod hardfault;
+#[cfg(feature = "interrupt-stack")]
+mod interrupt_stack;
+#[cfg(feature = "rp2040-smp")]
+mod rp2040;
+#[cfg(feature = "rp2040-smp")]
+use rp2040 as chip;
+#[cfg(feature = "rp2350-smp")]
+mod rp2350;
+#[cfg(feature = "rp2350-smp")]
+use rp2350 as chip;
+#[cfg(feature = "rtic")]
+pub mod rtic;
+#[cfg(feature = "smp")]
+mod sio;
+#[cfg(feature = "unprivileged")]
+pub mod syscall;
+
+#[cfg(feature = "basepri")]
+pub use basepri::set_basepri_threshold;
+#[cfg(feature = "interrupt-stack")]
+pub use interrupt_stack::{check_interrupt_stack, set_interrupt_stack};
+#[cfg(feature = "rtic")]
+pub use rtic::unpark_from_isr;
+#[cfg(feature = "unprivileged")]
+pub use syscall::spawn_unprivileged;
+
+#[cfg(feature = "rtos-awareness")]
+use core::mem::offset_of;
+#[cfg(not(feature = "external-tick"))]
+use core::sync::atomic::AtomicU32;
+
+#[cfg(target_has_atomic = "ptr")]
+use cortex_m::peripheral::DWT;
+use cortex_m::peripheral::{SCB, scb::SystemHandler};
+#[cfg(not(feature = "external-tick"))]
+use cortex_m::peripheral::{SYST, syst::SystClkSource};
 use static_cell::ConstStaticCell;
+#[cfg(feature = "rtos-awareness")]
+use taskette::rtos_awareness::ContextLayout;
 use taskette::{
-    arch::StackAllocation,
-    scheduler::{Scheduler, SchedulerConfig},
+    arch::{StackAllocation, TickSource},
+    portable_atomic::{AtomicBool, Ordering},
+    scheduler::{Scheduler, SchedulerConfig, SchedulerStorage},
+    sync::interrupt_free,
 };
 
 const IDLE_TASK_STACK_SIZE: usize = 2048;
 
 static IDLE_TASK_STACK: ConstStaticCell<Stack<IDLE_TASK_STACK_SIZE>> =
     ConstStaticCell::new(Stack::new());
+#[cfg(feature = "smp")]
+static IDLE_TASK_STACK_CORE1: ConstStaticCell<Stack<IDLE_TASK_STACK_SIZE>> =
+    ConstStaticCell::new(Stack::new());
+/// SysTick reload value shared by all cores
+#[cfg(not(feature = "external-tick"))]
+static SYSTICK_RELOAD: AtomicU32 = AtomicU32::new(0);
+/// Frequency of the core clock counted by the DWT cycle counter (0 if unknown)
+#[cfg(all(target_has_atomic = "ptr", not(feature = "external-tick")))]
+static CYCLE_FREQ: AtomicU32 = AtomicU32::new(0);
+/// Whether SysTick counts the reference clock instead of the core clock (set by [`init_scheduler_calibrated`])
+#[cfg(not(feature = "external-tick"))]
+static SYSTICK_REFERENCE_CLOCK: AtomicBool = AtomicBool::new(false);
+/// Number of SysTick interrupts on each core, used instead of the cycle counter on Armv6-M
+#[cfg(all(not(target_has_atomic = "ptr"), not(feature = "external-tick")))]
+static SYSTICK_COUNTS: [AtomicU32; taskette::scheduler::NUM_CORES] =
+    [const { AtomicU32::new(0) }; taskette::scheduler::NUM_CORES];
+/// Set while `spawn_unprivileged` is creating a task
+#[cfg(feature = "unprivileged")]
+static SPAWNING_UNPRIVILEGED: AtomicBool = AtomicBool::new(false);
+
+/// Bits of the CONTROL register
+const CONTROL_NPRIV: u32 = 1 << 0;
+const CONTROL_SPSEL: u32 = 1 << 1;
 
 #[repr(C, align(8))]
 #[derive(Clone, Debug)]
@@ -53,10 +135,11 @@ struct SoftwareSavedRegisters {
     r10: u32,
     r11: u32,
     exc_return: u32, // LR on exception
+    control: u32, // Restored only with `unprivileged` feature (otherwise it is just a padding for alignment)
 }
 
 impl SoftwareSavedRegisters {
-    fn new(fpu_regs_saved: bool) -> Self {
+    fn new(fpu_regs_saved: bool, unprivileged: bool) -> Self {
         Self {
             r4: 0,
             r5: 0,
@@ -71,11 +154,46 @@ impl SoftwareSavedRegisters {
             } else {
                 0xFFFFFFFD // thread-mode, PSP, no FPU regs
             },
+            control: if unprivileged {
+                CONTROL_NPRIV | CONTROL_SPSEL
+            } else {
+                CONTROL_SPSEL
+            },
         }
     }
 }
 
+/// Layout of the saved context for debuggers (software-saved registers followed by the hardware-saved frame).
+///
+/// Only the basic frame is described. If bit 4 of EXC_RETURN is 0, S16-S31 are placed between the two parts
+/// and the hardware-saved frame is extended with S0-S15 and FPSCR. Bit 9 of the stacked xPSR indicates an alignment padding.
+#[cfg(feature = "rtos-awareness")]
+#[unsafe(export_name = "taskette_context_layout")]
+static CONTEXT_LAYOUT: ContextLayout = {
+    const SW: usize = size_of::<SoftwareSavedRegisters>();
+    ContextLayout::new(
+        SW + size_of::<HardwareSavedRegisters>(),
+        SW + offset_of!(HardwareSavedRegisters, pc),
+    )
+    .with_register(0, SW + offset_of!(HardwareSavedRegisters, r0))
+    .with_register(1, SW + offset_of!(HardwareSavedRegisters, r1))
+    .with_register(2, SW + offset_of!(HardwareSavedRegisters, r2))
+    .with_register(3, SW + offset_of!(HardwareSavedRegisters, r3))
+    .with_register(4, offset_of!(SoftwareSavedRegisters, r4))
+    .with_register(5, offset_of!(SoftwareSavedRegisters, r5))
+    .with_register(6, offset_of!(SoftwareSavedRegisters, r6))
+    .with_register(7, offset_of!(SoftwareSavedRegisters, r7))
+    .with_register(8, offset_of!(SoftwareSavedRegisters, r8))
+    .with_register(9, offset_of!(SoftwareSavedRegisters, r9))
+    .with_register(10, offset_of!(SoftwareSavedRegisters, r10))
+    .with_register(11, offset_of!(SoftwareSavedRegisters, r11))
+    .with_register(12, SW + offset_of!(HardwareSavedRegisters, r12))
+    .with_register(14, SW + offset_of!(HardwareSavedRegisters, lr))
+    .with_register(15, SW + offset_of!(HardwareSavedRegisters, pc))
+};
+
 /// Safely initializes the scheduler.
+#[cfg(not(feature = "external-tick"))]
 pub fn init_scheduler(
     _syst: SYST,
     _scb: SCB,
@@ -85,53 +203,198 @@ pub fn init_scheduler(
     unsafe { Scheduler::init(clock_freq, config) }
 }
 
+/// Safely initializes the scheduler with the memory supplied by the application (see [`SchedulerStorage`]).
+#[cfg(not(feature = "external-tick"))]
+pub fn init_scheduler_with_storage<const TASKS: usize, const TIMERS: usize>(
+    _syst: SYST,
+    _scb: SCB,
+    clock_freq: u32,
+    config: SchedulerConfig,
+    storage: &'static mut SchedulerStorage<TASKS, TIMERS>,
+) -> Option<Scheduler> {
+    unsafe { Scheduler::init_with_storage(clock_freq, config, storage) }
+}
+
+/// Safely initializes the scheduler with the SysTick clock frequency derived from the calibration value (SYST_CALIB),
+/// instead of the frequency given by the application.
+///
+/// The calibration value is the reload value for 10 ms, which refers to the reference clock of SysTick
+/// if the chip has one (SysTick then counts that clock), and to the core clock otherwise.
+/// Returns `None` if the chip provides no calibration value. The tick rate is as exact as the value (see SKEW bit of SYST_CALIB).
+#[cfg(not(feature = "external-tick"))]
+pub fn init_scheduler_calibrated(
+    _syst: SYST,
+    _scb: SCB,
+    config: SchedulerConfig,
+) -> Option<Scheduler> {
+    let clock_freq = calibrated_clock_freq()?;
+    let scheduler = unsafe { Scheduler::init(clock_freq, config) }?;
+    SYSTICK_REFERENCE_CLOCK.store(SYST::has_reference_clock(), Ordering::Relaxed);
+
+    Some(scheduler)
+}
+
+/// Same as [`init_scheduler_calibrated`] with the memory supplied by the application (see [`SchedulerStorage`]).
+#[cfg(not(feature = "external-tick"))]
+pub fn init_scheduler_calibrated_with_storage<const TASKS: usize, const TIMERS: usize>(
+    _syst: SYST,
+    _scb: SCB,
+    config: SchedulerConfig,
+    storage: &'static mut SchedulerStorage<TASKS, TIMERS>,
+) -> Option<Scheduler> {
+    let clock_freq = calibrated_clock_freq()?;
+    let scheduler = unsafe { Scheduler::init_with_storage(clock_freq, config, storage) }?;
+    SYSTICK_REFERENCE_CLOCK.store(SYST::has_reference_clock(), Ordering::Relaxed);
+
+    Some(scheduler)
+}
+
+/// Frequency of the clock the SysTick calibration value refers to
+#[cfg(not(feature = "external-tick"))]
+fn calibrated_clock_freq() -> Option<u32> {
+    // TENMS is 0 if the calibration value is unknown
+    match SYST::get_ticks_per_10ms() {
+        0 => None,
+        reload => Some((reload + 1) * 100),
+    }
+}
+
+/// Safely initializes the scheduler driven by a timer of the application instead of SysTick (`external-tick` feature).
+///
+/// `tick_source` drives a timer (e.g. TIM or LPTIM) whose interrupt handler calls `taskette::scheduler::handle_tick`.
+/// It is started on each core when the scheduler starts. SysTick is neither configured nor handled by the port,
+/// so it remains usable by other libraries.
+/// Its `cycle_freq` may return `clock_freq` to let `taskette::timer::current_time_micros` interpolate with the DWT cycle counter.
+#[cfg(feature = "external-tick")]
+pub fn init_scheduler(
+    _scb: SCB,
+    tick_source: &'static dyn TickSource,
+    clock_freq: u32,
+    config: SchedulerConfig,
+) -> Option<Scheduler> {
+    let scheduler = unsafe { Scheduler::init(clock_freq, config) }?;
+    external_tick::set_tick_source(tick_source);
+
+    Some(scheduler)
+}
+
+/// Same as [`init_scheduler`] with the memory supplied by the application (see [`SchedulerStorage`]).
+#[cfg(feature = "external-tick")]
+pub fn init_scheduler_with_storage<const TASKS: usize, const TIMERS: usize>(
+    _scb: SCB,
+    tick_source: &'static dyn TickSource,
+    clock_freq: u32,
+    config: SchedulerConfig,
+    storage: &'static mut SchedulerStorage<TASKS, TIMERS>,
+) -> Option<Scheduler> {
+    let scheduler = unsafe { Scheduler::init_with_storage(clock_freq, config, storage) }?;
+    external_tick::set_tick_source(tick_source);
+
+    Some(scheduler)
+}
+
+// CONTROL of each task (privileged or not) is kept in the word above the software-saved registers
+#[cfg(feature = "unprivileged")]
+macro_rules! save_control {
+    () => {
+        "mrs r1, control\n str r1, [r0, #-4]!"
+    };
+}
+#[cfg(not(feature = "unprivileged"))]
+macro_rules! save_control {
+    () => {
+        "sub r0, #4"
+    };
+}
+#[cfg(feature = "unprivileged")]
+macro_rules! restore_control {
+    () => {
+        "ldr r1, [r0], #4\n msr control, r1"
+    };
+}
+#[cfg(not(feature = "unprivileged"))]
+macro_rules! restore_control {
+    () => {
+        "add r0, #4"
+    };
+}
+// Armv6-M versions (R0 is the process stack pointer above the hardware-saved registers)
+#[cfg(feature = "unprivileged")]
+macro_rules! save_control_v6m {
+    () => {
+        "mrs r2, control\n subs r3, r0, #4\n str r2, [r3]"
+    };
+}
+#[cfg(not(feature = "unprivileged"))]
+macro_rules! save_control_v6m {
+    () => {
+        ""
+    };
+}
+#[cfg(feature = "unprivileged")]
+macro_rules! restore_control_v6m {
+    () => {
+        "subs r3, r0, #4\n ldr r2, [r3]\n msr control, r2"
+    };
+}
+#[cfg(not(feature = "unprivileged"))]
+macro_rules! restore_control_v6m {
+    () => {
+        ""
+    };
+}
+
+// The context switching procedure is the PendSV handler, unless `exception-handlers` feature is disabled.
+// Then the application defines PendSV and jumps to `taskette_pendsv` at its end, with LR still holding EXC_RETURN
+// and R4-R11 untouched (i.e. a tail branch like `b {taskette_pendsv}` from a naked handler, not a call).
+// Without cortex-m-rt (e.g. with the startup code of a vendor SDK), `taskette_pendsv` and `taskette_systick`
+// can also be placed in the vector table directly, as both are exported with the C ABI.
+// The handlers of optional features keep the names of cortex-m-rt (`SVCall`, `MemoryManagement`, `BusFault`, `UsageFault`).
+
 /// Context switching procedure
+///
+/// # Safety
+/// Must only be entered as the PendSV handler (see above).
 #[cfg(not(target_has_atomic = "ptr"))] // No atomic => thumbv6m
-#[unsafe(no_mangle)]
+#[cfg_attr(feature = "exception-handlers", unsafe(export_name = "PendSV"))]
+#[cfg_attr(not(feature = "exception-handlers"), unsafe(no_mangle))]
 #[unsafe(naked)]
-extern "C" fn PendSV() {
+pub unsafe extern "C" fn taskette_pendsv() {
     // Registers {R0-R3, R12, LR, PC, xPSR} are saved in the process stack by the hardware
     core::arch::naked_asm!(
         "mrs r0, psp",  // Read the process stack pointer (PSP, because the SP is MSP now)
+        save_control_v6m!(),
 
-        "mov r1, sp",   // Temporarily save SP (MSP) in R1
-        "mov sp, r0",   // Set SP (MSP) to the loaded PSP value
-
-        "sub sp, #4*(5+1)",   // Move SP to just above R8-R11,LR (plus alignment)
-        "push {{r4-r7}}", // Save the lower half of the remaining registers in the process stack
-        "add sp, #4*9",   // Move the stack pointer to below R8-R11,LR
+        // The registers are stored through R0 with `stmia`, which only takes low registers and increments the address,
+        // so the frame is filled upwards from its bottom
+        "subs r0, #4*(9+1)",    // Move R0 to the bottom of R4-R11,LR (plus CONTROL)
+        "stmia r0!, {{r4-r7}}", // Save the lower half of the remaining registers in the process stack
         // Copy the higher half of the remaining registers into the lower half
-        "mov r2, r8",
-        "mov r3, r9",
-        "mov r4, r10",
-        "mov r5, r11",
-        "push {{r2-r5,lr}}", // Save the copied registers values and LR in the process stack
-        "sub sp, #4*4",   // Move the stack pointer to above R4-R7
-
-        "mov r0, sp",   // Update R0 using the new SP value
-
-        "mov sp, r1",   // Restore the value of original SP (MSP)
+        "mov r4, r8",
+        "mov r5, r9",
+        "mov r6, r10",
+        "mov r7, r11",
+        "stmia r0!, {{r4-r7}}", // Save the copied register values in the process stack
+        "mov r4, lr",
+        "str r4, [r0]", // Save EXC_RETURN in the process stack
+        "subs r0, #4*8",    // Move R0 back to the bottom of the frame
 
         "bl {select_task}",  // Call `select_task` function. R0 (process stack pointer) is used as the first argument and the return value.
 
-        "mov r1, sp",   // Temporarily save SP (MSP) in R1
-        "mov sp, r0",   // Set SP (MSP) to the returned PSP value
-
-        "add sp, #4*4", // Move the stack pointer to just above R8-R11,LR
-        "pop {{r2-r6}}",  // Load the values of R8-R11 and LR from the process stack to R2-R6
+        "adds r0, #4*4",    // Move R0 to the saved values of R8-R11
+        "ldmia r0!, {{r4-r7}}", // Load the values of R8-R11 from the process stack to R4-R7
         // Restore R8-R11 and LR from the loaded values
-        "mov r8, r2",
-        "mov r9, r3",
-        "mov r10, r4",
-        "mov r11, r5",
-        "mov lr, r6",
-        "sub sp, #4*9", // Move the stack pointer to above R4-R7
-        "pop {{r4-r7}}",    // Restore R4-R7 from the process stack
-        "add sp, #4*(5+1)", // Move the stack pointer to below R8-R11 (plus alignment)
-
-        "mov r0, sp",   // Update R0 with the new SP value
-        "mov sp, r1",   // Restore the value of original SP (MSP)
-
+        "mov r8, r4",
+        "mov r9, r5",
+        "mov r10, r6",
+        "mov r11, r7",
+        "ldr r4, [r0]",
+        "mov lr, r4",
+        "subs r0, #4*8",    // Move R0 back to the bottom of the frame
+        "ldmia r0!, {{r4-r7}}", // Restore R4-R7 from the process stack
+        "adds r0, #4*(5+1)",    // Move R0 to above the frame (i.e. the hardware-saved registers)
+
+        restore_control_v6m!(),
         "msr psp, r0",  // Set the PSP to the value of R0
 
         "bx lr",    // Exit the exception handler by jumping to EXC_RETURN
@@ -141,26 +404,30 @@ extern "C" fn PendSV() {
 }
 
 /// Context switching procedure
+///
+/// # Safety
+/// Must only be entered as the PendSV handler (see above).
 #[cfg(all(target_has_atomic = "ptr", target_abi = "eabi"))] // Has atomic => thumbv7m or above, No FPU
-#[unsafe(no_mangle)]
+#[cfg_attr(feature = "exception-handlers", unsafe(export_name = "PendSV"))]
+#[cfg_attr(not(feature = "exception-handlers"), unsafe(no_mangle))]
 #[unsafe(naked)]
-extern "C" fn PendSV() {
+pub unsafe extern "C" fn taskette_pendsv() {
     // Registers {R0-R3, R12, LR, PC, xPSR} are saved in the process stack by the hardware
     core::arch::naked_asm!(
         "mrs r0, psp",  // Read the process stack pointer (PSP, because the SP is MSP now)
 
-        "sub r0, #4",   // For stack alignment
+        save_control!(), // Also keeps the stack aligned
         "stmdb r0!, {{r4-r11,lr}}", // Save the remaining registers and EXC_RETURN in the process stack
 
         "bl {select_task}",  // Call `select_task` function. R0 (process stack pointer) is used as the first argument and the return value.
 
         "ldmia r0!, {{r4-r11,lr}}",  // Restore the registers not saved by the hardware and EXC_RETURN from the process stack
-        "add r0, #4",   // For stack alignment
+        restore_control!(),
 
         "msr psp, r0",   // Change PSP into the value returned by `select_task`
 
         "bx lr",
-        select_task = sym taskette::scheduler::select_task,
+        select_task = sym select_task,
     );
     // Hardware restores registers R0-R3 and R12 from the new stack
 }
@@ -168,10 +435,18 @@ extern "C" fn PendSV() {
 /// Context switching procedure
 /// For chips with an FPU.
 /// The approach based on the Armv8-M User Guide example: https://github.com/ARM-software/m-profile-user-guide-examples/tree/main/Exception_model/context-switch-fp
+///
+/// FP registers are only saved and restored for tasks with an FP context (FType of EXC_RETURN is 0),
+/// i.e. tasks that executed an FP instruction. Tasks start without one, so integer-only tasks never pay for the FPU.
+/// S0-S15 are stacked lazily by the hardware (`setup_core` enables FPCCR.LSPEN) and written only when S16-S31 are saved here.
+///
+/// # Safety
+/// Must only be entered as the PendSV handler (see above).
 #[cfg(target_abi = "eabihf")] // FPU
-#[unsafe(no_mangle)]
+#[cfg_attr(feature = "exception-handlers", unsafe(export_name = "PendSV"))]
+#[cfg_attr(not(feature = "exception-handlers"), unsafe(no_mangle))]
 #[unsafe(naked)]
-extern "C" fn PendSV() {
+pub unsafe extern "C" fn taskette_pendsv() {
     // Registers {R0-R3, R12, LR, PC, xPSR, S0-S15} are saved in the process stack by the hardware
     core::arch::naked_asm!(
         ".fpu fpv4-sp-d16", // Omitting this leads to a compile error in release mode (opt-level>0)
@@ -181,13 +456,13 @@ extern "C" fn PendSV() {
         "it eq",   // The next instruction is conditional
         "vstmdbeq r0!, {{s16-s31}}",    // Save the FP registers not saved by the hardware (if FType==0)
 
-        "sub r0, #4",   // For stack alignment
+        save_control!(), // Also keeps the stack aligned
         "stmdb r0!, {{r4-r11,lr}}", // Save the remaining registers and EXC_RETURN in the process stack
 
         "bl {select_task}",  // Call `select_task` function. R0 (process stack pointer) is used as the first argument and the return value.
 
         "ldmia r0!, {{r4-r11,lr}}",  // Restore the registers not saved by the hardware and EXC_RETURN from the process stack
-        "add r0, #4",   // For stack alignment
+        restore_control!(),
 
         "tst lr, #0x00000010",  // Check Bit 4 (FType) of EXC_RETURN (0 indicates the hardware-saved stack frame includes FP registers)
         "it eq",   // The next instruction is conditional
@@ -196,51 +471,241 @@ extern "C" fn PendSV() {
         "msr psp, r0",   // Change PSP into the value returned by `select_task`
 
         "bx lr",
-        select_task = sym taskette::scheduler::select_task,
+        select_task = sym select_task,
     );
     // Hardware restores registers R0-R3 and R12 from the new stack
 }
 
+/// Selects the next task and sets the stack limit register (PSPLIM) to the bottom of its stack,
+/// so that a stack overflow is caught by the hardware at the offending instruction.
+#[cfg(armv8m)]
+extern "C" fn select_task(orig_sp: usize) -> usize {
+    let next_sp = unsafe { taskette::scheduler::select_task(orig_sp) };
+    // PSP is not in use in the handler mode, so changing the limit here does not affect the original task
+    unsafe {
+        set_psplim(taskette::scheduler::current_stack_limit());
+    }
+    next_sp
+}
+
+#[cfg(all(not(armv8m), target_has_atomic = "ptr"))]
+use taskette::scheduler::select_task;
+
+#[cfg(armv8m)]
+unsafe fn set_psplim(limit: usize) {
+    unsafe {
+        core::arch::asm!("msr psplim, {}", in(reg) limit);
+    }
+}
+
+#[cfg(all(feature = "interrupt-stack", armv8m))]
+fn get_msplim() -> usize {
+    let limit;
+    unsafe {
+        core::arch::asm!("mrs {}, msplim", out(reg) limit);
+    }
+    limit
+}
+
+#[cfg(all(feature = "interrupt-stack", not(armv8m)))]
+fn get_msplim() -> usize {
+    0
+}
+
+// Sets MSP to R2 (and MSPLIM to R3 on Armv8-M)
+#[cfg(all(feature = "interrupt-stack", armv8m))]
+macro_rules! switch_interrupt_stack {
+    () => {
+        "msr msp, r2\n msr msplim, r3"
+    };
+}
+#[cfg(all(feature = "interrupt-stack", not(armv8m)))]
+macro_rules! switch_interrupt_stack {
+    () => {
+        "msr msp, r2"
+    };
+}
+#[cfg(not(feature = "interrupt-stack"))]
+macro_rules! switch_interrupt_stack {
+    () => {
+        ""
+    };
+}
+
+// Clears CONTROL.FPCA in {tmp}, so that the first task starts without an FP context
+// even if the code before the scheduler used the FPU
+#[cfg(target_abi = "eabihf")]
+macro_rules! clear_fpca {
+    () => {
+        "bic {tmp}, {tmp}, #4"
+    };
+}
+#[cfg(not(target_abi = "eabihf"))]
+macro_rules! clear_fpca {
+    () => {
+        ""
+    };
+}
+
+#[cfg(all(feature = "exception-handlers", not(feature = "external-tick")))]
 #[cortex_m_rt::exception]
 fn SysTick() {
+    taskette_systick();
+}
+
+/// Handles the SysTick exception. Has to be called from the SysTick handler of the application
+/// (or placed in the vector table) when `exception-handlers` feature is disabled.
+#[cfg(not(feature = "external-tick"))]
+#[cfg_attr(not(feature = "exception-handlers"), unsafe(no_mangle))]
+pub extern "C" fn taskette_systick() {
+    #[cfg(not(target_has_atomic = "ptr"))]
+    {
+        // Only this handler writes the count of this core, so load and store do not race
+        let count = &SYSTICK_COUNTS[_taskette_core_id()];
+        count.store(
+            count.load(Ordering::Relaxed).wrapping_add(1),
+            Ordering::Relaxed,
+        );
+    }
+
+    #[cfg(feature = "interrupt-stack")]
+    if !check_interrupt_stack() {
+        panic!("Interrupt stack overflow");
+    }
+
     taskette::scheduler::handle_tick();
 }
 
 /// INTERNAL USE ONLY
 #[unsafe(no_mangle)]
-pub fn _taskette_setup(clock_freq: u32, tick_freq: u32) {
+pub fn _taskette_setup() {
+    setup_core();
+
+    #[cfg(feature = "smp")]
+    sio::launch_core1(core1_entry);
+}
+
+/// Entry point of core 1
+#[cfg(feature = "smp")]
+extern "C" fn core1_entry() -> ! {
+    #[cfg(feature = "rp2350-smp")]
+    rp2350::enable_fpu();
+
+    setup_core();
+    taskette::scheduler::start_secondary_core()
+}
+
+/// Configures core exceptions of the current core
+fn setup_core() {
     let peripherals = unsafe { cortex_m::Peripherals::steal() };
     let mut scb = peripherals.SCB;
-    let mut syst = peripherals.SYST;
 
     // On armv6m `set_priority` is not atomic
-    critical_section::with(|_| unsafe {
+    interrupt_free(|_| unsafe {
         // Set priorities of core exceptions
         scb.set_priority(
             SystemHandler::PendSV,
             255, /* Lowest possible priority */
         );
+        #[cfg(not(feature = "external-tick"))]
         scb.set_priority(
             SystemHandler::SysTick,
             255, /* Lowest possible priority */
         );
     });
 
-    // Configure the SysTick timer
-    assert!(clock_freq / tick_freq <= 0xFFFFFF); // SysTick has 24-bit limit
-    syst.set_clock_source(SystClkSource::Core);
-    syst.set_reload(clock_freq / tick_freq);
-    syst.enable_interrupt();
+    #[cfg(feature = "fault-recovery")]
+    fault::enable_faults(&mut scb);
+
+    // Enable lazy FP state preservation (ASPEN, LSPEN), which is the reset value but may have been changed by the boot code.
+    // A task gets an FP context (CONTROL.FPCA) only when it executes an FP instruction,
+    // and S0-S15 are only stacked when the handler preempting it uses the FPU as well.
+    #[cfg(target_abi = "eabihf")]
+    unsafe {
+        const FPCCR_ASPEN: u32 = 1 << 31;
+        const FPCCR_LSPEN: u32 = 1 << 30;
+        peripherals
+            .FPU
+            .fpccr
+            .modify(|fpccr| fpccr | FPCCR_ASPEN | FPCCR_LSPEN);
+    }
+
+    // Start the cycle counter used for measuring run time
+    #[cfg(target_has_atomic = "ptr")]
+    {
+        let mut dcb = peripherals.DCB;
+        let mut dwt = peripherals.DWT;
+        dcb.enable_trace();
+        DWT::unlock();
+        dwt.enable_cycle_counter();
+    }
+
+    #[cfg(feature = "smp")]
+    chip::enable_reschedule_interrupt();
+}
+
+/// Tick generated by the SysTick timer of each core
+#[cfg(not(feature = "external-tick"))]
+struct SysTickSource;
+
+#[cfg(not(feature = "external-tick"))]
+static TICK_SOURCE: SysTickSource = SysTickSource;
+
+#[cfg(not(feature = "external-tick"))]
+impl TickSource for SysTickSource {
+    fn set_frequency(&self, clock_freq: u32, tick_freq: u32) {
+        assert!(clock_freq / tick_freq <= 0xFFFFFF); // SysTick has 24-bit limit
+        SYSTICK_RELOAD.store(clock_freq / tick_freq, Ordering::Relaxed);
+        // The calibrated frequency is that of the reference clock, not of the core clock
+        #[cfg(target_has_atomic = "ptr")]
+        if !SYSTICK_REFERENCE_CLOCK.load(Ordering::Relaxed) {
+            CYCLE_FREQ.store(clock_freq, Ordering::Relaxed);
+        }
+    }
+
+    fn start(&self) {
+        let peripherals = unsafe { cortex_m::Peripherals::steal() };
+        let mut syst = peripherals.SYST;
+
+        // Configure and start the SysTick timer
+        syst.set_clock_source(if SYSTICK_REFERENCE_CLOCK.load(Ordering::Relaxed) {
+            SystClkSource::External
+        } else {
+            SystClkSource::Core
+        });
+        syst.set_reload(SYSTICK_RELOAD.load(Ordering::Relaxed));
+        syst.enable_interrupt();
+        syst.enable_counter();
+    }
+
+    fn stop(&self) {
+        let peripherals = unsafe { cortex_m::Peripherals::steal() };
+        let mut syst = peripherals.SYST;
+
+        syst.disable_counter();
+    }
+
+    /// The DWT cycle counter (CYCCNT) counts the core clock on Armv7-M or later.
+    #[cfg(target_has_atomic = "ptr")]
+    fn cycle_freq(&self) -> Option<u32> {
+        match CYCLE_FREQ.load(Ordering::Relaxed) {
+            0 => None,
+            freq => Some(freq),
+        }
+    }
 }
 
 /// INTERNAL USE ONLY
 #[unsafe(no_mangle)]
-pub fn _taskette_start_timer() {
-    let peripherals = unsafe { cortex_m::Peripherals::steal() };
-    let mut syst = peripherals.SYST;
-
-    // Start the SysTick timer
-    syst.enable_counter();
+pub fn _taskette_tick_source() -> &'static dyn TickSource {
+    #[cfg(not(feature = "external-tick"))]
+    {
+        &TICK_SOURCE
+    }
+    #[cfg(feature = "external-tick")]
+    {
+        external_tick::tick_source()
+    }
 }
 
 /// INTERNAL USE ONLY
@@ -263,16 +728,41 @@ pub fn _taskette_init_stack(sp: *mut u8, pc: usize, arg: *const u8, arg_size: us
         );
         let sp = push_to_stack(
             sp,
-            &SoftwareSavedRegisters::new(false) as *const _ as *const u8,
+            &SoftwareSavedRegisters::new(false, spawning_unprivileged()) as *const _ as *const u8,
             core::mem::size_of::<SoftwareSavedRegisters>(),
         );
         sp
     }
 }
 
+fn spawning_unprivileged() -> bool {
+    #[cfg(feature = "unprivileged")]
+    {
+        SPAWNING_UNPRIVILEGED.load(Ordering::Relaxed)
+    }
+    #[cfg(not(feature = "unprivileged"))]
+    {
+        false
+    }
+}
+
 /// INTERNAL USE ONLY
 #[unsafe(no_mangle)]
-pub unsafe fn _taskette_run_with_stack(pc: usize, sp: *mut u8, _stack_limit: *mut u8) -> ! {
+pub unsafe fn _taskette_run_with_stack(pc: usize, sp: *mut u8, stack_limit: *mut u8) -> ! {
+    #[cfg(armv8m)]
+    unsafe {
+        set_psplim(stack_limit as usize);
+    }
+    #[cfg(not(armv8m))]
+    let _ = stack_limit;
+
+    // Exception handlers keep the current main stack unless an interrupt stack is set
+    #[cfg(feature = "interrupt-stack")]
+    let (msp_limit, msp) = interrupt_stack::prepare(_taskette_core_id())
+        .unwrap_or((get_msplim(), cortex_m::register::msp::read() as usize));
+    #[cfg(not(feature = "interrupt-stack"))]
+    let (msp_limit, msp) = (0, 0);
+
     unsafe {
         core::arch::asm!(
             // Write the new SP value to the PSP
@@ -280,14 +770,19 @@ pub unsafe fn _taskette_run_with_stack(pc: usize, sp: *mut u8, _stack_limit: *mu
             // Change SP from MSP to PSP by setting the SPSEL bit of CONTROL register
             "mrs {tmp}, control",
             "orrs {tmp}, {spsel_mask}",
+            clear_fpca!(),
             "msr control, {tmp}",
             "isb",
+            // MSP is no longer used by this thread, so it can be moved
+            switch_interrupt_stack!(),
             // Jump to the new PC
             "blx {new_pc}",
             new_sp = in(reg) sp,
             new_pc = in(reg) pc,
-            spsel_mask = in(reg) 0b10,
+            spsel_mask = in(reg) CONTROL_SPSEL,
             tmp = out(reg) _,
+            in("r2") msp,
+            in("r3") msp_limit,
         );
     }
 
@@ -295,8 +790,15 @@ pub unsafe fn _taskette_run_with_stack(pc: usize, sp: *mut u8, _stack_limit: *mu
 }
 
 #[unsafe(no_mangle)]
-pub fn _taskette_get_idle_task_stack() -> Option<&'static mut [u8]> {
-    if let Some(stack) = IDLE_TASK_STACK.try_take() {
+pub fn _taskette_get_idle_task_stack(core_id: usize) -> Option<&'static mut [u8]> {
+    let stack = match core_id {
+        0 => &IDLE_TASK_STACK,
+        #[cfg(feature = "smp")]
+        1 => &IDLE_TASK_STACK_CORE1,
+        _ => return None,
+    };
+
+    if let Some(stack) = stack.try_take() {
         Some(&mut stack.0)
     } else {
         None
@@ -309,6 +811,111 @@ pub fn _taskette_wait_for_interrupt() {
     cortex_m::asm::wfi();
 }
 
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_core_id() -> usize {
+    #[cfg(feature = "smp")]
+    {
+        sio::core_id()
+    }
+    #[cfg(not(feature = "smp"))]
+    {
+        0
+    }
+}
+
+// Without `smp` feature, the IPI below cannot reach other cores
+#[cfg(not(feature = "smp"))]
+const _: () = assert!(
+    taskette::scheduler::NUM_CORES == 1,
+    "Enable `smp` feature of taskette-cortex-m to use multiple cores"
+);
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_ipi(core_id: usize) {
+    if core_id == _taskette_core_id() {
+        SCB::set_pendsv();
+    } else {
+        #[cfg(feature = "smp")]
+        chip::notify_other_core();
+    }
+}
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_try_lock(lock: &AtomicBool) -> bool {
+    #[cfg(feature = "rp2040-smp")]
+    {
+        rp2040::try_lock(lock)
+    }
+    #[cfg(not(feature = "rp2040-smp"))]
+    {
+        lock.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+}
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_mask_interrupts() -> usize {
+    #[cfg(feature = "basepri")]
+    {
+        basepri::mask() as usize
+    }
+    #[cfg(not(feature = "basepri"))]
+    {
+        let was_active = cortex_m::register::primask::read().is_active();
+        cortex_m::interrupt::disable();
+        was_active as usize
+    }
+}
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_restore_interrupts(state: usize) {
+    #[cfg(feature = "basepri")]
+    unsafe {
+        basepri::restore(state as u8);
+    }
+    #[cfg(not(feature = "basepri"))]
+    if state != 0 {
+        unsafe {
+            cortex_m::interrupt::enable();
+        }
+    }
+}
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_cycle_count() -> u32 {
+    #[cfg(target_has_atomic = "ptr")]
+    {
+        DWT::cycle_count()
+    }
+    // Armv6-M has no cycle counter, so it is approximated with SysTick
+    #[cfg(all(not(target_has_atomic = "ptr"), not(feature = "external-tick")))]
+    {
+        let reload = SYSTICK_RELOAD.load(Ordering::Relaxed);
+        let count = &SYSTICK_COUNTS[_taskette_core_id()];
+        loop {
+            let ticks = count.load(Ordering::Relaxed);
+            let current = SYST::get_current();
+            // Retry if SysTick fired in between
+            if count.load(Ordering::Relaxed) == ticks {
+                return ticks
+                    .wrapping_mul(reload + 1)
+                    .wrapping_add(reload - current);
+            }
+        }
+    }
+    // Nothing to approximate it with when SysTick belongs to the application
+    #[cfg(all(not(target_has_atomic = "ptr"), feature = "external-tick"))]
+    {
+        0
+    }
+}
+
 unsafe fn push_to_stack(sp: *mut u8, obj: *const u8, obj_size: usize) -> *mut u8 {
     unsafe {
         let size = obj_size;
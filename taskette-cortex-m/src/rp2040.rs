@@ -0,0 +1,140 @@
+//! RP2040-specific part of the dual-core (SMP) support.
+//!
+//! The inter-core FIFO is also used for requesting a reschedule on the other core.
+//! The kernel lock is a `critical-section` implementation on SIO spinlock #31, which masks interrupts on this core
+//! and excludes the other one (so the `critical-section-impl` feature of `rp2040-hal` must not be enabled).
+
+use cortex_m::peripheral::{NVIC, SCB};
+use taskette::portable_atomic::{AtomicBool, AtomicU8, Ordering};
+
+use crate::sio::{SIO_BASE, SIO_FIFO_WR, core_id, drain_fifo, fifo_ready, read_reg, write_reg};
+
+pub(crate) const PSM_FRCE_OFF: usize = 0x4001_0000 + 0x4;
+pub(crate) const PSM_FRCE_OFF_PROC1: u32 = 1 << 16;
+
+/// Interrupt numbers of the inter-core FIFO for each core
+const SIO_IRQ_PROC: [usize; 2] = [15, 16];
+
+/// SIO hardware spinlock used by `SpinLock`
+const SIO_SPINLOCK: usize = SIO_BASE + 0x100 + 4 * 30;
+/// SIO hardware spinlock of the `critical-section` implementation (the same one as `rp2040-hal` uses)
+const CS_SPINLOCK: usize = SIO_BASE + 0x100 + 4 * 31;
+
+/// Core holding `CS_SPINLOCK` plus one, or 0 if none
+static CS_OWNER: AtomicU8 = AtomicU8::new(0);
+/// Restore state of a nested critical section, which leaves the spinlock and interrupts as they are
+const CS_NESTED: u8 = 2;
+
+struct SioCriticalSection;
+
+critical_section::set_impl!(SioCriticalSection);
+
+unsafe impl critical_section::Impl for SioCriticalSection {
+    unsafe fn acquire() -> u8 {
+        // Only this core sets the owner to itself, so the check is not racy
+        let owner = core_id() as u8 + 1;
+        if CS_OWNER.load(Ordering::Acquire) == owner {
+            return CS_NESTED;
+        }
+
+        loop {
+            let enabled = cortex_m::register::primask::read().is_active();
+            cortex_m::interrupt::disable();
+            // Reading the spinlock register claims it (zero means it is claimed by the other core)
+            if unsafe { read_reg(CS_SPINLOCK) } != 0 {
+                cortex_m::asm::dmb();
+                CS_OWNER.store(owner, Ordering::Relaxed);
+                return enabled as u8;
+            }
+            // Interrupts are taken while waiting for the other core
+            if enabled {
+                unsafe { cortex_m::interrupt::enable() };
+            }
+        }
+    }
+
+    unsafe fn release(state: u8) {
+        if state == CS_NESTED {
+            return;
+        }
+
+        CS_OWNER.store(0, Ordering::Relaxed);
+        cortex_m::asm::dmb();
+        unsafe {
+            write_reg(CS_SPINLOCK, 1); // Release
+        }
+        if state != 0 {
+            unsafe { cortex_m::interrupt::enable() };
+        }
+    }
+}
+
+/// Releases the spinlocks of the port, which keep their state across a reset of the cores (e.g. by a debugger).
+///
+/// Called while core 1 is held in reset and core 0 is outside critical sections.
+pub(crate) fn release_spinlocks() {
+    CS_OWNER.store(0, Ordering::Relaxed);
+    unsafe {
+        write_reg(SIO_SPINLOCK, 1);
+        write_reg(CS_SPINLOCK, 1);
+    }
+}
+
+/// Enables the interrupt for cross-core reschedule on the current core.
+pub(crate) fn enable_reschedule_interrupt() {
+    drain_fifo();
+    unsafe {
+        (*NVIC::PTR).iser[0].write(1 << SIO_IRQ_PROC[core_id()]);
+    }
+}
+
+/// Requests the other core to run the scheduler.
+pub(crate) fn notify_other_core() {
+    // If the FIFO is full, the other core has notifications pending anyway
+    if fifo_ready() {
+        unsafe {
+            write_reg(SIO_FIFO_WR, 0);
+        }
+    }
+}
+
+/// Sets `lock` if it is not set, atomically with respect to the other core.
+///
+/// Cortex-M0+ has no atomic read-modify-write instructions, so the check-and-set is guarded by a hardware spinlock.
+pub(crate) fn try_lock(lock: &AtomicBool) -> bool {
+    // Interrupts are masked because an interrupt handler spinning on the same hardware spinlock would never finish
+    cortex_m::interrupt::free(|_| {
+        // Reading the spinlock register claims it (zero means it is claimed by the other core)
+        if unsafe { read_reg(SIO_SPINLOCK) } == 0 {
+            return false;
+        }
+        cortex_m::asm::dmb();
+
+        let acquired = !lock.load(Ordering::Relaxed);
+        if acquired {
+            lock.store(true, Ordering::Relaxed);
+        }
+
+        cortex_m::asm::dmb();
+        unsafe {
+            write_reg(SIO_SPINLOCK, 1); // Release
+        }
+
+        acquired
+    })
+}
+
+fn handle_fifo_interrupt() {
+    drain_fifo();
+    SCB::set_pendsv();
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn SIO_IRQ_PROC0() {
+    handle_fifo_interrupt();
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn SIO_IRQ_PROC1() {
+    handle_fifo_interrupt();
+}
@@ -0,0 +1,55 @@
+//! RP2350-specific part of the dual-core (SMP) support.
+//!
+//! A reschedule on the other core is requested through the SIO doorbells,
+//! leaving the inter-core FIFO to the application after core 1 is launched.
+
+use cortex_m::peripheral::{NVIC, SCB};
+
+use crate::sio::{SIO_BASE, write_reg};
+
+pub(crate) const PSM_FRCE_OFF: usize = 0x4001_8000 + 0x4;
+pub(crate) const PSM_FRCE_OFF_PROC1: u32 = 1 << 24;
+
+const SIO_DOORBELL_OUT_SET: usize = SIO_BASE + 0x180;
+const SIO_DOORBELL_IN_CLR: usize = SIO_BASE + 0x18C;
+/// Doorbell used for cross-core reschedule
+const RESCHEDULE_DOORBELL: u32 = 1 << 0;
+
+/// Interrupt number of the doorbells (each core has its own one)
+const SIO_IRQ_BELL: usize = 26;
+
+/// Enables the interrupt for cross-core reschedule on the current core.
+pub(crate) fn enable_reschedule_interrupt() {
+    unsafe {
+        write_reg(SIO_DOORBELL_IN_CLR, RESCHEDULE_DOORBELL);
+        (*NVIC::PTR).iser[0].write(1 << SIO_IRQ_BELL);
+    }
+}
+
+/// Requests the other core to run the scheduler.
+pub(crate) fn notify_other_core() {
+    // Ringing an already ringing doorbell has no effect
+    unsafe {
+        write_reg(SIO_DOORBELL_OUT_SET, RESCHEDULE_DOORBELL);
+    }
+}
+
+/// Enables the FPU (CP10 and CP11) on core 1, which does not run the reset handler of `cortex-m-rt`.
+pub(crate) fn enable_fpu() {
+    #[cfg(target_abi = "eabihf")]
+    unsafe {
+        const CPACR: usize = 0xE000_ED88;
+        write_reg(CPACR, crate::sio::read_reg(CPACR) | (0b1111 << 20));
+        cortex_m::asm::dsb();
+        cortex_m::asm::isb();
+    }
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn SIO_IRQ_BELL() {
+    // Every core sees the doorbells rung by the other core at its own SIO
+    unsafe {
+        write_reg(SIO_DOORBELL_IN_CLR, RESCHEDULE_DOORBELL);
+    }
+    SCB::set_pendsv();
+}
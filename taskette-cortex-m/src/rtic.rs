@@ -0,0 +1,64 @@
+//! Running taskette beneath RTIC (`rtic` feature).
+//!
+//! RTIC schedules its hardware and software tasks with interrupt priorities, while taskette runs its tasks in Thread mode.
+//! They share a core when taskette is started from the `idle` task of RTIC:
+//! every taskette task has a lower priority than any RTIC task, and RTIC tasks preempt them like any interrupt.
+//!
+//! ```ignore
+//! #[rtic::app(device = pac, dispatchers = [SWI0])]
+//! mod app {
+//!     #[local]
+//!     struct Local {
+//!         core: Option<(SYST, SCB)>,
+//!     }
+//!
+//!     #[init]
+//!     fn init(cx: init::Context) -> (Shared, Local) {
+//!         (Shared {}, Local { core: Some((cx.core.SYST, cx.core.SCB)) })
+//!     }
+//!
+//!     #[idle(local = [core])]
+//!     fn idle(cx: idle::Context) -> ! {
+//!         let (syst, scb) = cx.local.core.take().unwrap();
+//!         let scheduler = init_scheduler(syst, scb, CLOCK_FREQ, SchedulerConfig::default()).unwrap();
+//!         let worker = spawn(worker, WORKER_STACK.take(), TaskConfig::default()).unwrap();
+//!         WORKER_ID.store(worker.id(), Ordering::Relaxed);
+//!         scheduler.start()
+//!     }
+//!
+//!     #[task(binds = UART0, priority = 2)]
+//!     fn uart(_: uart::Context) {
+//!         unpark_from_isr(WORKER_ID.load(Ordering::Relaxed)).unwrap();
+//!     }
+//! }
+//! ```
+//!
+//! Points to keep them from conflicting:
+//! - **Exceptions**: RTIC dispatches software tasks on the device interrupts listed in `dispatchers`,
+//!   so PendSV stays with taskette. SysTick does too, unless the `external-tick` feature is enabled:
+//!   RTIC tasks must not bind SysTick, and the monotonic of RTIC has to use another timer.
+//!   Both exceptions are set to the lowest priority, so a context switch never delays an RTIC task.
+//! - **Critical sections**: exactly one `critical-section` implementation is linked and shared by both.
+//!   `critical-section-single-core` of `cortex-m` masks every interrupt (PRIMASK) in kernel sections.
+//!   With the `basepri` feature, RTIC tasks above the threshold are never delayed by taskette,
+//!   but only tasks at or below it may call taskette (including [`unpark_from_isr`]).
+//!   The BASEPRI locks of RTIC resources nest with those sections, as both restore the previous value.
+//! - **Waking tasks**: an RTIC task wakes a taskette task with [`unpark_from_isr`] (or `Futex::wake` and channels).
+//!   The woken task runs after all RTIC tasks return.
+//!
+//! Resources of RTIC cannot be locked from taskette tasks; data is passed through taskette primitives or atomics instead.
+
+use cortex_m::peripheral::{SCB, scb::VectActive};
+use taskette::Error;
+
+/// Wakes a taskette task blocked by `taskette::task::park` from an interrupt handler (e.g. a hardware task of RTIC).
+///
+/// If the task is not parked, its next `park` returns immediately. The context switch is made by PendSV,
+/// which runs only after every interrupt handler returns.
+pub fn unpark_from_isr(task_id: usize) -> Result<(), Error> {
+    debug_assert!(
+        SCB::vect_active() != VectActive::ThreadMode,
+        "unpark_from_isr called outside an interrupt handler"
+    );
+    taskette::task::unpark(task_id)
+}
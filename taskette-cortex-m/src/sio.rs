@@ -0,0 +1,105 @@
+//! Dual-core (SMP) support common to the RP2040 and the RP2350.
+//!
+//! Core 1 is launched through the boot ROM protocol over the inter-core FIFO of the SIO.
+//! Chip-specific parts (power-on state machine and the interrupt used for cross-core reschedule)
+//! are in the `rp2040` and `rp2350` modules.
+//!
+//! On the RP2040, the port provides the multi-core `critical-section` implementation on a SIO hardware spinlock.
+//! On the RP2350, the application has to link a multi-core safe one (e.g. `critical-section-impl` feature of `rp235x-hal`).
+
+use cortex_m::peripheral::SCB;
+use static_cell::ConstStaticCell;
+
+use crate::{Stack, chip};
+
+const CORE1_MAIN_STACK_SIZE: usize = 2048;
+
+pub(crate) const SIO_BASE: usize = 0xD000_0000;
+const SIO_CPUID: usize = SIO_BASE + 0x000;
+const SIO_FIFO_ST: usize = SIO_BASE + 0x050;
+pub(crate) const SIO_FIFO_WR: usize = SIO_BASE + 0x054;
+const SIO_FIFO_RD: usize = SIO_BASE + 0x058;
+const FIFO_ST_VLD: u32 = 1 << 0;
+pub(crate) const FIFO_ST_RDY: u32 = 1 << 1;
+
+// Atomic register access aliases
+const ALIAS_SET: usize = 0x2000;
+const ALIAS_CLR: usize = 0x3000;
+
+/// Main stack (MSP) of core 1, used by exception handlers
+static CORE1_MAIN_STACK: ConstStaticCell<Stack<CORE1_MAIN_STACK_SIZE>> =
+    ConstStaticCell::new(Stack::new());
+
+pub(crate) fn core_id() -> usize {
+    unsafe { read_reg(SIO_CPUID) as usize }
+}
+
+/// Returns true if the inter-core FIFO has room for a new word.
+pub(crate) fn fifo_ready() -> bool {
+    unsafe { read_reg(SIO_FIFO_ST) & FIFO_ST_RDY != 0 }
+}
+
+/// Launches core 1 and makes it execute `entry` on its own main stack.
+pub(crate) fn launch_core1(entry: extern "C" fn() -> !) {
+    let stack = CORE1_MAIN_STACK
+        .try_take()
+        .expect("Core 1 is already launched");
+    let stack_ptr = stack.0.as_mut_ptr_range().end as u32;
+    let vector_table = unsafe { (*SCB::PTR).vtor.read() };
+
+    unsafe {
+        // Reset core 1
+        write_reg(chip::PSM_FRCE_OFF + ALIAS_SET, chip::PSM_FRCE_OFF_PROC1);
+        while read_reg(chip::PSM_FRCE_OFF) & chip::PSM_FRCE_OFF_PROC1 == 0 {}
+        write_reg(chip::PSM_FRCE_OFF + ALIAS_CLR, chip::PSM_FRCE_OFF_PROC1);
+    }
+    // Core 1 no longer holds any spinlock
+    #[cfg(feature = "rp2040-smp")]
+    chip::release_spinlocks();
+
+    // Launch sequence of the boot ROM (RP2040 Datasheet 2.8.2, RP2350 Datasheet 5.3)
+    let commands = [0, 0, 1, vector_table, stack_ptr, entry as usize as u32];
+    let mut seq = 0;
+    while seq < commands.len() {
+        let command = commands[seq];
+        if command == 0 {
+            drain_fifo();
+            cortex_m::asm::sev();
+        }
+
+        unsafe {
+            while !fifo_ready() {}
+            write_reg(SIO_FIFO_WR, command);
+        }
+        cortex_m::asm::sev();
+
+        let response = unsafe {
+            while read_reg(SIO_FIFO_ST) & FIFO_ST_VLD == 0 {
+                cortex_m::asm::wfe();
+            }
+            read_reg(SIO_FIFO_RD)
+        };
+
+        // Restart from the beginning if core 1 does not echo the command
+        seq = if response == command { seq + 1 } else { 0 };
+    }
+}
+
+/// Discards all words in the inter-core FIFO of the current core.
+pub(crate) fn drain_fifo() {
+    unsafe {
+        while read_reg(SIO_FIFO_ST) & FIFO_ST_VLD != 0 {
+            read_reg(SIO_FIFO_RD);
+        }
+        // Clear the sticky error flags
+        write_reg(SIO_FIFO_ST, 0);
+    }
+}
+
+pub(crate) unsafe fn read_reg(addr: usize) -> u32 {
+    unsafe { core::ptr::read_volatile(addr as *const u32) }
+}
+
+pub(crate) unsafe fn write_reg(addr: usize, value: u32) {
+    unsafe { core::ptr::write_volatile(addr as *mut u32, value) }
+}
@@ -0,0 +1,239 @@
+//! System calls for unprivileged tasks.
+//!
+//! A task spawned by [`spawn_unprivileged`] runs in unprivileged Thread mode,
+//! where it cannot mask interrupts (`CPSID` is ignored) or access the System Control Block.
+//! Therefore it cannot use the functions of `taskette` which enter a critical section or request a context switch,
+//! and has to enter the kernel through the functions of this module, which are implemented with the `SVC` instruction.
+//!
+//! Note that the memory of the kernel and other tasks is not protected (the MPU is not configured),
+//! and pointers passed to system calls are not validated.
+
+use taskette::{
+    Error,
+    arch::{RawStack, StackAllocation},
+    futex::Futex,
+    portable_atomic::Ordering,
+    scheduler,
+    sync::interrupt_free,
+    task::{TaskConfig, TaskHandle},
+    timer,
+};
+
+use crate::SPAWNING_UNPRIVILEGED;
+
+const SYS_YIELD: u8 = 0;
+const SYS_EXIT: u8 = 1;
+const SYS_SPAWN: u8 = 2;
+const SYS_FUTEX_WAIT: u8 = 3;
+const SYS_FUTEX_WAKE: u8 = 4;
+const SYS_WAIT_UNTIL: u8 = 5;
+const SYS_CURRENT_TIME: u8 = 6;
+
+/// Issues a system call with arguments in R0-R3. Returns R0-R2 written by the kernel.
+macro_rules! syscall {
+    ($number:expr, $r0:expr, $r1:expr, $r2:expr, $r3:expr) => {{
+        let (r0, r1, r2): (u32, u32, u32);
+        unsafe {
+            core::arch::asm!(
+                "svc {number}",
+                number = const $number,
+                inlateout("r0") $r0 => r0,
+                inlateout("r1") $r1 => r1,
+                inlateout("r2") $r2 => r2,
+                in("r3") $r3,
+            );
+        }
+        (r0, r1, r2)
+    }};
+}
+
+/// Creates a new task running in unprivileged Thread mode.
+///
+/// The task can only use the functions of this module to interact with the kernel.
+/// Can be called before the scheduler starts or from a privileged task.
+pub fn spawn_unprivileged<F: FnOnce() + Send + 'static, S: StackAllocation>(
+    func: F,
+    stack: S,
+    config: TaskConfig,
+) -> Result<TaskHandle, Error> {
+    // The flag is read by `_taskette_init_stack` during `spawn`
+    interrupt_free(|_| {
+        SPAWNING_UNPRIVILEGED.store(true, Ordering::Relaxed);
+        let result = scheduler::spawn(
+            move || {
+                func();
+                // Returning from the task function would enter the scheduler directly
+                exit()
+            },
+            stack,
+            config,
+        );
+        SPAWNING_UNPRIVILEGED.store(false, Ordering::Relaxed);
+
+        result
+    })
+}
+
+/// Gives the CPU to other tasks.
+pub fn yield_now() {
+    syscall!(SYS_YIELD, 0u32, 0u32, 0u32, 0u32);
+}
+
+/// Finishes the current task.
+pub fn exit() -> ! {
+    syscall!(SYS_EXIT, 0u32, 0u32, 0u32, 0u32);
+
+    // The task is switched out as soon as the system call returns
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+/// Creates a new unprivileged task with the specified priority. Returns the task ID.
+///
+/// The start and the end of `stack` are aligned at 8 bytes by discarding a few bytes if necessary.
+pub fn spawn(entry: fn(), stack: &'static mut [u8], priority: usize) -> Result<usize, Error> {
+    let range = stack.as_mut_ptr_range();
+    let (status, task_id, _) = syscall!(
+        SYS_SPAWN,
+        entry as usize as u32,
+        range.start as u32,
+        range.end as u32,
+        priority as u32
+    );
+    decode(status).map(|()| task_id as usize)
+}
+
+/// Same as [`Futex::wait`].
+pub fn futex_wait(futex: &Futex, compare_val: usize) -> Result<(), Error> {
+    let (status, _, _) = syscall!(
+        SYS_FUTEX_WAIT,
+        futex as *const Futex as u32,
+        compare_val as u32,
+        0u32,
+        0u32
+    );
+    decode(status)
+}
+
+/// Same as [`Futex::wake`].
+pub fn futex_wake(futex: &Futex, num: usize) -> Result<(), Error> {
+    let (status, _, _) = syscall!(
+        SYS_FUTEX_WAKE,
+        futex as *const Futex as u32,
+        num as u32,
+        0u32,
+        0u32
+    );
+    decode(status)
+}
+
+/// Same as [`timer::wait_until`].
+pub fn wait_until(time: u64) -> Result<(), Error> {
+    let (status, _, _) = syscall!(SYS_WAIT_UNTIL, time as u32, (time >> 32) as u32, 0u32, 0u32);
+    decode(status)
+}
+
+/// Same as [`timer::current_time`].
+pub fn current_time() -> Result<u64, Error> {
+    let (status, low, high) = syscall!(SYS_CURRENT_TIME, 0u32, 0u32, 0u32, 0u32);
+    decode(status).map(|()| ((high as u64) << 32) | low as u64)
+}
+
+/// Entry of the SVCall exception. Passes the hardware-saved registers of the calling task to `handle_syscall`.
+#[unsafe(no_mangle)]
+#[unsafe(naked)]
+extern "C" fn SVCall() {
+    core::arch::naked_asm!(
+        "push {{lr}}",  // Keep EXC_RETURN
+        "mrs r0, psp",  // System calls are issued by tasks, which use the PSP
+        "bl {handle_syscall}",
+        "pop {{pc}}",   // Return from the exception
+        handle_syscall = sym handle_syscall,
+    );
+}
+
+/// Performs a system call. `frame` points to R0-R3, R12, LR, PC, and xPSR saved by the hardware.
+extern "C" fn handle_syscall(frame: &mut [u32; 8]) {
+    // The immediate of the `SVC` instruction just before the return address
+    let number = unsafe { ((frame[6] - 2) as *const u8).read() };
+
+    match number {
+        SYS_YIELD => taskette::arch::yield_now(),
+        SYS_EXIT => {
+            let _ = scheduler::exit_current_task();
+        }
+        SYS_SPAWN => {
+            let entry: fn() = unsafe { core::mem::transmute(frame[0] as usize) };
+            // The memory is given up by the calling task
+            let stack = unsafe { RawStack::new(frame[1] as usize..frame[2] as usize) };
+            let config = TaskConfig::default().with_priority(frame[3] as usize);
+            match spawn_unprivileged(entry, stack, config) {
+                Ok(handle) => {
+                    frame[0] = 0;
+                    frame[1] = handle.id() as u32;
+                }
+                Err(error) => frame[0] = encode(Err(error)),
+            }
+        }
+        SYS_FUTEX_WAIT => {
+            let futex = unsafe { &*(frame[0] as *const Futex) };
+            frame[0] = encode(futex.wait(frame[1] as usize));
+        }
+        SYS_FUTEX_WAKE => {
+            let futex = unsafe { &*(frame[0] as *const Futex) };
+            frame[0] = encode(futex.wake(frame[1] as usize));
+        }
+        SYS_WAIT_UNTIL => {
+            let time = ((frame[1] as u64) << 32) | frame[0] as u64;
+            frame[0] = encode(timer::wait_until(time));
+        }
+        SYS_CURRENT_TIME => match timer::current_time() {
+            Ok(time) => {
+                frame[0] = 0;
+                frame[1] = time as u32;
+                frame[2] = (time >> 32) as u32;
+            }
+            Err(error) => frame[0] = encode(Err(error)),
+        },
+        _ => (), // Unknown system calls are ignored
+    }
+}
+
+fn encode(result: Result<(), Error>) -> u32 {
+    match result {
+        Ok(()) => 0,
+        Err(Error::TaskFull) => 1,
+        Err(Error::InvalidPriority) => 2,
+        Err(Error::NotFound) => 3,
+        Err(Error::NotInitialized) => 4,
+        Err(Error::TimerFull) => 5,
+        Err(Error::InvalidAffinity) => 6,
+        Err(Error::OutOfMemory) => 7,
+        Err(Error::NotPermitted) => 8,
+        Err(Error::QueueFull) => 9,
+        Err(Error::StackTooSmall) => 10,
+        Err(Error::TimedOut) => 11,
+        Err(Error::WouldBlock) => 12,
+        Err(Error::Cancelled) => 13,
+    }
+}
+
+fn decode(status: u32) -> Result<(), Error> {
+    match status {
+        0 => Ok(()),
+        1 => Err(Error::TaskFull),
+        2 => Err(Error::InvalidPriority),
+        3 => Err(Error::NotFound),
+        4 => Err(Error::NotInitialized),
+        5 => Err(Error::TimerFull),
+        6 => Err(Error::InvalidAffinity),
+        8 => Err(Error::NotPermitted),
+        9 => Err(Error::QueueFull),
+        10 => Err(Error::StackTooSmall),
+        11 => Err(Error::TimedOut),
+        12 => Err(Error::WouldBlock),
+        13 => Err(Error::Cancelled),
+        _ => Err(Error::OutOfMemory),
+    }
+}
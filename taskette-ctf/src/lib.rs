@@ -0,0 +1,339 @@
+//! Common Trace Format (CTF) output of Taskette.
+//!
+//! [`init`] installs trace hooks of `taskette` which serialize scheduler events as CTF 1.8 packets
+//! into a ring buffer in RAM. The application periodically moves the recorded bytes
+//! out of the buffer with [`drain`] (e.g. to RTT or UART) and stores them as the `stream` file of a CTF trace.
+//! The `metadata` file describing the packets is generated by [`write_metadata`].
+//!
+//! Packets are [`PACKET_SIZE`] bytes long. A packet is put into the ring buffer when it becomes full
+//! or when [`flush`] is called. If the ring buffer has no room, the whole packet is discarded
+//! and counted in the `events_discarded` field of later packets.
+
+#![no_std]
+
+use core::{cell::RefCell, fmt};
+
+use critical_section::Mutex;
+use taskette::{
+    arch::core_id,
+    sync::interrupt_free,
+    trace::{self, TraceHooks},
+};
+
+/// Size of a packet (in bytes)
+pub const PACKET_SIZE: usize = 256;
+
+const MAGIC: u32 = 0xC1FC1FC1;
+/// Size of the packet header and the packet context
+const PACKET_HEADER_SIZE: usize = 36;
+/// Size of the event header (ID, CPU ID, and timestamp)
+const EVENT_HEADER_SIZE: usize = 10;
+
+// Offsets of the fields of the packet context
+const TIMESTAMP_BEGIN_OFFSET: usize = 8;
+const TIMESTAMP_END_OFFSET: usize = 16;
+const CONTENT_SIZE_OFFSET: usize = 24;
+const PACKET_SIZE_OFFSET: usize = 28;
+const EVENTS_DISCARDED_OFFSET: usize = 32;
+
+// Event IDs (have to match the metadata)
+const EVENT_SCHED_SWITCH: u8 = 0;
+const EVENT_TASK_READY: u8 = 1;
+const EVENT_TASK_BLOCK: u8 = 2;
+const EVENT_TICK: u8 = 3;
+const EVENT_TASK_CREATE: u8 = 4;
+const EVENT_TASK_REMOVE: u8 = 5;
+
+const METADATA_BEFORE_FREQ: &str = r#"/* CTF 1.8 */
+
+typealias integer { size = 8; align = 8; signed = false; byte_order = le; } := uint8_t;
+typealias integer { size = 32; align = 8; signed = false; byte_order = le; } := uint32_t;
+typealias integer { size = 64; align = 8; signed = false; byte_order = le; } := uint64_t;
+
+trace {
+    major = 1;
+    minor = 8;
+    byte_order = le;
+    packet.header := struct {
+        uint32_t magic;
+        uint32_t stream_id;
+    };
+};
+
+env {
+    domain = "taskette";
+};
+
+clock {
+    name = timestamp_clock;
+    freq = "#;
+
+const METADATA_AFTER_FREQ: &str = r#";
+};
+
+typealias integer {
+    size = 64; align = 8; signed = false; byte_order = le;
+    map = clock.timestamp_clock.value;
+} := timestamp_t;
+
+stream {
+    id = 0;
+    packet.context := struct {
+        timestamp_t timestamp_begin;
+        timestamp_t timestamp_end;
+        uint32_t content_size;
+        uint32_t packet_size;
+        uint32_t events_discarded;
+    };
+    event.header := struct {
+        uint8_t id;
+        uint8_t cpu_id;
+        timestamp_t timestamp;
+    };
+};
+
+event {
+    name = "sched_switch";
+    id = 0;
+    stream_id = 0;
+    fields := struct {
+        uint32_t prev_tid;
+        uint32_t next_tid;
+    };
+};
+
+event {
+    name = "task_ready";
+    id = 1;
+    stream_id = 0;
+    fields := struct {
+        uint32_t tid;
+    };
+};
+
+event {
+    name = "task_block";
+    id = 2;
+    stream_id = 0;
+    fields := struct {
+        uint32_t tid;
+    };
+};
+
+event {
+    name = "tick";
+    id = 3;
+    stream_id = 0;
+};
+
+event {
+    name = "task_create";
+    id = 4;
+    stream_id = 0;
+    fields := struct {
+        uint32_t tid;
+        uint32_t priority;
+    };
+};
+
+event {
+    name = "task_remove";
+    id = 5;
+    stream_id = 0;
+    fields := struct {
+        uint32_t tid;
+    };
+};
+"#;
+
+static RECORDER: Mutex<RefCell<Option<Recorder>>> = Mutex::new(RefCell::new(None));
+
+/// Starts recording scheduler events into `buffer`.
+///
+/// `clock` returns the timestamp of events (e.g. a hardware cycle counter), and its frequency has to be passed to [`write_metadata`].
+/// Like trace hooks, it is called inside the critical section of the scheduler and must not call functions of `taskette`.
+/// `buffer` should be at least a few times larger than [`PACKET_SIZE`].
+pub fn init(buffer: &'static mut [u8], clock: fn() -> u64) {
+    interrupt_free(|cs| {
+        RECORDER.borrow(cs).replace(Some(Recorder {
+            clock,
+            ring: buffer,
+            head: 0,
+            len: 0,
+            packet: [0; PACKET_SIZE],
+            packet_len: 0,
+            packet_events: 0,
+            last_timestamp: 0,
+            events_discarded: 0,
+        }));
+    });
+
+    trace::set_hooks(
+        TraceHooks::new()
+            .with_on_create(on_create)
+            .with_on_remove(on_remove)
+            .with_on_switch(on_switch)
+            .with_on_block(on_block)
+            .with_on_ready(on_ready)
+            .with_on_tick(on_tick),
+    );
+}
+
+/// Moves recorded bytes into `out`. Returns the number of bytes written.
+///
+/// The bytes are a continuous stream, so a packet may be split across calls.
+pub fn drain(out: &mut [u8]) -> usize {
+    interrupt_free(|cs| {
+        let mut recorder = RECORDER.borrow_ref_mut(cs);
+        match recorder.as_mut() {
+            Some(recorder) => recorder.drain(out),
+            None => 0,
+        }
+    })
+}
+
+/// Puts the partially filled packet into the ring buffer.
+pub fn flush() {
+    interrupt_free(|cs| {
+        if let Some(recorder) = RECORDER.borrow_ref_mut(cs).as_mut() {
+            recorder.close_packet();
+        }
+    });
+}
+
+/// Writes the CTF metadata (in TSDL) matching the recorded stream.
+///
+/// `clock_freq` is the frequency of the clock passed to [`init`] (in Hz).
+pub fn write_metadata<W: fmt::Write>(w: &mut W, clock_freq: u64) -> fmt::Result {
+    w.write_str(METADATA_BEFORE_FREQ)?;
+    write!(w, "{}", clock_freq)?;
+    w.write_str(METADATA_AFTER_FREQ)
+}
+
+fn on_create(task_id: usize, priority: usize) {
+    record(EVENT_TASK_CREATE, &[task_id as u32, priority as u32]);
+}
+
+fn on_remove(task_id: usize) {
+    record(EVENT_TASK_REMOVE, &[task_id as u32]);
+}
+
+fn on_switch(from: usize, to: usize) {
+    if from != to {
+        record(EVENT_SCHED_SWITCH, &[from as u32, to as u32]);
+    }
+}
+
+fn on_block(task_id: usize) {
+    record(EVENT_TASK_BLOCK, &[task_id as u32]);
+}
+
+fn on_ready(task_id: usize) {
+    record(EVENT_TASK_READY, &[task_id as u32]);
+}
+
+fn on_tick() {
+    record(EVENT_TICK, &[]);
+}
+
+fn record(event_id: u8, fields: &[u32]) {
+    interrupt_free(|cs| {
+        if let Some(recorder) = RECORDER.borrow_ref_mut(cs).as_mut() {
+            recorder.record(event_id, fields);
+        }
+    });
+}
+
+struct Recorder {
+    clock: fn() -> u64,
+    ring: &'static mut [u8],
+    /// Index of the oldest byte in the ring buffer
+    head: usize,
+    /// Number of bytes in the ring buffer
+    len: usize,
+    /// Packet being filled
+    packet: [u8; PACKET_SIZE],
+    /// Number of bytes written into the packet (0 if no packet is open)
+    packet_len: usize,
+    packet_events: u32,
+    last_timestamp: u64,
+    /// Total number of discarded events
+    events_discarded: u32,
+}
+
+impl Recorder {
+    fn record(&mut self, event_id: u8, fields: &[u32]) {
+        let timestamp = (self.clock)();
+        let event_size = EVENT_HEADER_SIZE + fields.len() * 4;
+
+        if self.packet_len + event_size > PACKET_SIZE {
+            self.close_packet();
+        }
+        if self.packet_len == 0 {
+            self.open_packet(timestamp);
+        }
+
+        self.write(&[event_id, core_id() as u8]);
+        self.write(&timestamp.to_le_bytes());
+        for field in fields {
+            self.write(&field.to_le_bytes());
+        }
+        self.packet_events += 1;
+        self.last_timestamp = timestamp;
+    }
+
+    fn open_packet(&mut self, timestamp: u64) {
+        self.packet[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        self.packet[4..8].copy_from_slice(&0u32.to_le_bytes()); // Stream ID
+        self.packet[TIMESTAMP_BEGIN_OFFSET..TIMESTAMP_BEGIN_OFFSET + 8]
+            .copy_from_slice(&timestamp.to_le_bytes());
+        self.packet_len = PACKET_HEADER_SIZE;
+        self.packet_events = 0;
+    }
+
+    fn close_packet(&mut self) {
+        if self.packet_len == 0 {
+            return;
+        }
+
+        let content_size = (self.packet_len * 8) as u32;
+        let packet_size = (PACKET_SIZE * 8) as u32;
+        self.packet[TIMESTAMP_END_OFFSET..TIMESTAMP_END_OFFSET + 8]
+            .copy_from_slice(&self.last_timestamp.to_le_bytes());
+        self.packet[CONTENT_SIZE_OFFSET..CONTENT_SIZE_OFFSET + 4]
+            .copy_from_slice(&content_size.to_le_bytes());
+        self.packet[PACKET_SIZE_OFFSET..PACKET_SIZE_OFFSET + 4]
+            .copy_from_slice(&packet_size.to_le_bytes());
+        self.packet[EVENTS_DISCARDED_OFFSET..EVENTS_DISCARDED_OFFSET + 4]
+            .copy_from_slice(&self.events_discarded.to_le_bytes());
+        self.packet[self.packet_len..].fill(0); // Padding
+
+        if self.ring.len() - self.len >= PACKET_SIZE {
+            let capacity = self.ring.len();
+            for (i, byte) in self.packet.iter().enumerate() {
+                self.ring[(self.head + self.len + i) % capacity] = *byte;
+            }
+            self.len += PACKET_SIZE;
+        } else {
+            self.events_discarded = self.events_discarded.wrapping_add(self.packet_events);
+        }
+
+        self.packet_len = 0;
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.packet[self.packet_len..self.packet_len + bytes.len()].copy_from_slice(bytes);
+        self.packet_len += bytes.len();
+    }
+
+    fn drain(&mut self, out: &mut [u8]) -> usize {
+        let count = out.len().min(self.len);
+        for (i, byte) in out[..count].iter_mut().enumerate() {
+            *byte = self.ring[(self.head + i) % self.ring.len()];
+        }
+        self.head = (self.head + count) % self.ring.len().max(1);
+        self.len -= count;
+
+        count
+    }
+}
@@ -0,0 +1,75 @@
+//! Clock-gated wait of the idle task (`clock-gate` feature).
+//!
+//! Once enabled, the CPU clock is gated while the idle task waits in `wfi` (WAITI mode).
+//! Peripherals keep their clocks and state, so no retention or re-initialization is needed on wake,
+//! and any interrupt ends the wait as usual.
+//!
+//! A driver with a transfer in flight (e.g. DMA) holds a [`DeepIdleVeto`] to keep the CPU clock running,
+//! which also keeps the idle task out of light sleep (`light-sleep` feature):
+//!
+//! ```ignore
+//! let _veto = veto_deep_idle();
+//! let transfer = spi.write(buffer)?;
+//! transfer.wait();
+//! ```
+
+#[cfg(any(feature = "esp32c6", feature = "esp32h2"))]
+use esp_hal::peripherals::PCR;
+#[cfg(not(any(feature = "esp32c6", feature = "esp32h2")))]
+use esp_hal::peripherals::SYSTEM;
+use taskette::portable_atomic::{AtomicBool, AtomicUsize, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// Number of living `DeepIdleVeto`s
+static VETOES: AtomicUsize = AtomicUsize::new(0);
+
+/// Lets the idle task gate the CPU clock while waiting for an interrupt.
+pub fn enable_clock_gate() {
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+/// Prevents clock gating and light sleep of the idle task while it is alive.
+#[must_use = "the veto is lifted when dropped"]
+pub struct DeepIdleVeto {
+    _private: (),
+}
+
+impl Drop for DeepIdleVeto {
+    fn drop(&mut self) {
+        VETOES.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Vetoes deep idle until the returned guard is dropped. Can be called from tasks and interrupt handlers.
+pub fn veto_deep_idle() -> DeepIdleVeto {
+    VETOES.fetch_add(1, Ordering::SeqCst);
+    DeepIdleVeto { _private: () }
+}
+
+/// Whether any driver vetoes deep idle
+pub fn deep_idle_vetoed() -> bool {
+    VETOES.load(Ordering::SeqCst) > 0
+}
+
+/// Configures the next `wfi` to gate the CPU clock unless vetoed.
+pub(crate) fn prepare_wait() {
+    if ENABLED.load(Ordering::Relaxed) {
+        set_clock_gate(!deep_idle_vetoed());
+    }
+}
+
+/// If `CPU_WAIT_MODE_FORCE_ON` is cleared, the CPU clock is gated in WAITI mode.
+#[cfg(not(any(feature = "esp32c6", feature = "esp32h2")))]
+fn set_clock_gate(gate: bool) {
+    SYSTEM::regs()
+        .cpu_per_conf()
+        .modify(|_, w| w.cpu_wait_mode_force_on().bit(!gate));
+}
+
+/// If `CPU_WAIT_MODE_FORCE_ON` is cleared, the CPU clock is gated in WAITI mode.
+#[cfg(any(feature = "esp32c6", feature = "esp32h2"))]
+fn set_clock_gate(gate: bool) {
+    PCR::regs()
+        .cpu_waiti_conf()
+        .modify(|_, w| w.cpu_wait_mode_force_on().bit(!gate));
+}
@@ -0,0 +1,75 @@
+//! Coexistence with the time driver of `esp-hal-embassy` (`esp-embassy-compat` feature).
+//!
+//! The tick of taskette and the time driver of `esp-hal-embassy` use different alarms of the same `SystemTimer`,
+//! so each of them keeps its own alarm without reprogramming the other:
+//!
+//! ```ignore
+//! let systimer = SystemTimer::new(peripherals.SYSTIMER);
+//! esp_hal_embassy::init(systimer.alarm0);
+//! let scheduler = init_scheduler(systimer.alarm1, sw_interrupt.software_interrupt1, config)?;
+//! ```
+//!
+//! Async drivers then run on an [`Executor`] inside one task, while other tasks are preempted as usual:
+//!
+//! ```ignore
+//! static EXECUTOR: StaticCell<Executor> = StaticCell::new();
+//!
+//! spawn(|| EXECUTOR.init(Executor::new()).run(|spawner| spawner.must_spawn(main_async())), stack, config)?;
+//! ```
+//!
+//! Unlike the executors of `esp-hal-embassy`, which wait for interrupts with `wfi`,
+//! [`Executor`] blocks its task while idle so that lower-priority tasks can run.
+//! The `executors` feature of `esp-hal-embassy` has to be disabled, because both define the pender of `embassy-executor`.
+
+use embassy_executor::{Spawner, raw};
+use taskette::{futex::Futex, portable_atomic::Ordering};
+
+/// `embassy-executor` executor running inside a task of taskette.
+pub struct Executor {
+    /// Set to 1 by the pender
+    signal: Futex,
+    inner: Option<raw::Executor>,
+}
+
+impl Executor {
+    pub const fn new() -> Self {
+        Self {
+            signal: Futex::new(0),
+            inner: None,
+        }
+    }
+
+    /// Runs the executor in the current task, after calling `init` to spawn the first async tasks.
+    pub fn run(&'static mut self, init: impl FnOnce(Spawner)) -> ! {
+        let Self { signal, inner } = self;
+        let signal: &'static Futex = signal;
+        let inner: &'static raw::Executor =
+            inner.insert(raw::Executor::new(signal as *const Futex as *mut ()));
+
+        init(inner.spawner());
+
+        loop {
+            // SAFETY: `poll` is only called from this loop, and never from the pender
+            unsafe { inner.poll() };
+
+            // Blocks until the pender is called, unless it was already called during the poll
+            signal.wait(0).expect("Failed to wait a futex");
+            signal.as_ref().store(0, Ordering::SeqCst);
+        }
+    }
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Called by `embassy-executor` (possibly from an interrupt handler) when the executor has work to do
+#[unsafe(export_name = "__pender")]
+fn pender(context: *mut ()) {
+    // SAFETY: `context` is the signal of an `Executor`, which lives forever
+    let signal = unsafe { &*(context as *const Futex) };
+    signal.as_ref().store(1, Ordering::SeqCst);
+    signal.wake_all().expect("Failed to wake the executor task");
+}
@@ -0,0 +1,598 @@
+//! Scheduler of `esp-radio` implemented on taskette (`esp-radio` feature).
+//!
+//! `esp-radio` (Wi-Fi and BLE) does not bring its own scheduler, but calls the one registered through
+//! `esp-radio-rtos-driver`. This module registers taskette as that scheduler, together with the semaphores, queues,
+//! and timers the radio stack uses, so the radio runs alongside the other tasks without `esp-rtos`.
+//! Nothing has to be called for it, except that `esp_radio::init` has to be called from a task,
+//! and a global allocator (e.g. `esp-alloc`) is needed because the radio creates these objects at runtime:
+//!
+//! ```ignore
+//! esp_alloc::heap_allocator!(size: 72 * 1024);
+//!
+//! let scheduler = init_scheduler(systimer.alarm0, sw_interrupt.software_interrupt0, config)?;
+//! spawn(|| {
+//!     let radio = esp_radio::init().unwrap();
+//!     let (controller, interfaces) = esp_radio::wifi::new(&radio, peripherals.WIFI, Default::default()).unwrap();
+//!     // ...
+//! }, stack, TaskConfig::default())?;
+//! scheduler.start();
+//! ```
+//!
+//! - Tasks of the radio are spawned with heap-allocated stacks, with their priorities limited to `MAX_PRIORITY`.
+//! - Timeouts are rounded up to ticks, so waits of the radio are as fine as the tick of the scheduler.
+//! - Timer callbacks run in a timer task of the highest priority, which is spawned with the first timer.
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    vec::Vec,
+};
+use core::{
+    cell::{Cell, RefCell},
+    ffi::c_void,
+    ptr::NonNull,
+};
+
+use critical_section::Mutex;
+use esp_radio_rtos_driver::{
+    Scheduler,
+    queue::{QueueImplementation, QueuePtr},
+    register_queue_implementation, register_semaphore_implementation,
+    register_timer_implementation, scheduler_impl,
+    semaphore::{SemaphoreImplementation, SemaphoreKind, SemaphorePtr},
+    timer::{TimerImplementation, TimerPtr},
+};
+use taskette::{
+    arch::yield_now,
+    futex::Futex,
+    portable_atomic::{AtomicBool, AtomicUsize, Ordering},
+    scheduler::{MAX_PRIORITY, get_config, spawn_heap},
+    task::{self, TaskConfig},
+    timer::{current_time, current_time_micros, wait_until},
+};
+
+/// Stack size of the timer task
+const TIMER_TASK_STACK_SIZE: usize = 4096;
+
+/// Semaphores returned by `current_task_thread_semaphore`, by task ID
+static THREAD_SEMAPHORES: Mutex<RefCell<BTreeMap<usize, usize>>> =
+    Mutex::new(RefCell::new(BTreeMap::new()));
+/// Addresses of all the timers, scanned by the timer task
+static TIMERS: Mutex<RefCell<Vec<usize>>> = Mutex::new(RefCell::new(Vec::new()));
+/// Incremented when a timer is armed or disarmed, waited on by the timer task
+static TIMERS_CHANGED: Futex = Futex::new(0);
+static TIMER_TASK_SPAWNED: AtomicBool = AtomicBool::new(false);
+
+/// Converts a task ID into a handle of `esp-radio`, which must not be null (even for the idle task #0)
+fn task_handle(task_id: usize) -> *mut c_void {
+    (task_id + 1) as *mut c_void
+}
+
+/// Converts a time in microseconds into the first tick at or after it
+fn micros_to_ticks(micros: u64) -> u64 {
+    let tick_freq = get_config().expect("Scheduler not initialized").tick_freq as u64;
+    (micros * tick_freq).div_ceil(1_000_000)
+}
+
+/// Tick at which a wait of `timeout_us` gives up (`None` means forever)
+fn deadline(timeout_us: Option<u32>) -> Option<u64> {
+    let now = current_time().expect("Failed to acquire current time");
+    timeout_us.map(|timeout_us| now + micros_to_ticks(timeout_us as u64))
+}
+
+/// Blocks until the value of `futex` changes from `compare_val` or the deadline passes. Returns `false` on timeout.
+fn wait(futex: &Futex, compare_val: usize, deadline: Option<u64>) -> bool {
+    match deadline {
+        Some(time) => futex
+            .wait_until(compare_val, time)
+            .expect("Failed to wait a futex"),
+        None => {
+            futex.wait(compare_val).expect("Failed to wait a futex");
+            true
+        }
+    }
+}
+
+struct TasketteScheduler;
+
+scheduler_impl!(static SCHEDULER: TasketteScheduler = TasketteScheduler);
+
+impl Scheduler for TasketteScheduler {
+    fn initialized(&self) -> bool {
+        get_config().is_ok()
+    }
+
+    fn yield_task(&self) {
+        yield_now();
+    }
+
+    fn yield_task_from_isr(&self) {
+        // The switch is taken when the interrupt handler returns
+        yield_now();
+    }
+
+    fn max_task_priority(&self) -> u32 {
+        MAX_PRIORITY as u32
+    }
+
+    fn task_create(
+        &self,
+        _name: &str,
+        task: extern "C" fn(*mut c_void),
+        param: *mut c_void,
+        priority: u32,
+        _pin_to_core: Option<u32>,
+        task_stack_size: usize,
+    ) -> *mut c_void {
+        let param = param as usize;
+        let config = TaskConfig::default()
+            .with_name("esp-radio")
+            .with_priority((priority as usize).clamp(1, MAX_PRIORITY));
+
+        spawn_heap(move || task(param as *mut c_void), task_stack_size, config)
+            .map_or(core::ptr::null_mut(), |handle| task_handle(handle.id()))
+    }
+
+    fn current_task(&self) -> *mut c_void {
+        task::current().map_or(core::ptr::null_mut(), |handle| task_handle(handle.id()))
+    }
+
+    fn schedule_task_deletion(&self, task_handle: *mut c_void) {
+        let task_id = if task_handle.is_null() {
+            task::current().expect("No current task").id()
+        } else {
+            task_handle as usize - 1
+        };
+
+        let semaphore =
+            critical_section::with(|cs| THREAD_SEMAPHORES.borrow_ref_mut(cs).remove(&task_id));
+        if let Some(semaphore) = semaphore {
+            // SAFETY: created by `current_task_thread_semaphore` and only used by the deleted task
+            unsafe { Semaphore::delete(NonNull::new_unchecked(semaphore as *mut ())) };
+        }
+
+        // Does not return if the task deletes itself
+        let _ = task::kill(task_id);
+    }
+
+    fn current_task_thread_semaphore(&self) -> SemaphorePtr {
+        let task_id = task::current().expect("No current task").id();
+        let semaphore =
+            critical_section::with(|cs| THREAD_SEMAPHORES.borrow_ref(cs).get(&task_id).copied());
+
+        let semaphore = semaphore.unwrap_or_else(|| {
+            let semaphore = Semaphore::create(SemaphoreKind::Counting { max: 1, initial: 0 });
+            critical_section::with(|cs| {
+                THREAD_SEMAPHORES
+                    .borrow_ref_mut(cs)
+                    .insert(task_id, semaphore.as_ptr() as usize)
+            });
+            semaphore.as_ptr() as usize
+        });
+
+        // SAFETY: semaphores are never null
+        unsafe { NonNull::new_unchecked(semaphore as *mut ()) }
+    }
+
+    fn usleep(&self, us: u32) {
+        if us == 0 {
+            yield_now();
+        } else if let Some(time) = deadline(Some(us)) {
+            wait_until(time).expect("Failed to sleep");
+        }
+    }
+
+    fn usleep_until(&self, target: u64) {
+        wait_until(micros_to_ticks(target)).expect("Failed to sleep");
+    }
+
+    fn now(&self) -> u64 {
+        current_time_micros().unwrap_or(0)
+    }
+}
+
+/// Counting semaphore, also used as a (recursive) mutex with the owner recorded.
+struct Semaphore {
+    /// Number of available units, waited on while 0
+    count: Futex,
+    max: usize,
+    recursive: bool,
+    /// Task ID + 1 of the holder of a mutex (0 if none)
+    owner: AtomicUsize,
+    /// Number of nested takes of a recursive mutex by the owner
+    depth: AtomicUsize,
+}
+
+impl Semaphore {
+    fn from_ptr<'a>(semaphore: SemaphorePtr) -> &'a Self {
+        // SAFETY: created by `Semaphore::create` and not deleted yet (required by the callers)
+        unsafe { semaphore.cast::<Self>().as_ref() }
+    }
+
+    fn try_decrement(&self) -> bool {
+        self.count
+            .as_ref()
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                count.checked_sub(1)
+            })
+            .is_ok()
+    }
+
+    fn increment(&self) -> bool {
+        let incremented = self
+            .count
+            .as_ref()
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                (count < self.max).then_some(count + 1)
+            })
+            .is_ok();
+        if incremented {
+            self.count.wake_one().expect("Failed to wake a task");
+        }
+
+        incremented
+    }
+
+    fn current_owner() -> usize {
+        task::current().map_or(0, |handle| handle.id() + 1)
+    }
+}
+
+unsafe impl SemaphoreImplementation for Semaphore {
+    fn create(kind: SemaphoreKind) -> SemaphorePtr {
+        let (initial, max, recursive) = match kind {
+            SemaphoreKind::Counting { max, initial } => (initial as usize, max as usize, false),
+            SemaphoreKind::Mutex => (1, 1, false),
+            SemaphoreKind::RecursiveMutex => (1, 1, true),
+        };
+        let semaphore = Box::new(Self {
+            count: Futex::new(initial),
+            max,
+            recursive,
+            owner: AtomicUsize::new(0),
+            depth: AtomicUsize::new(0),
+        });
+
+        NonNull::from(Box::leak(semaphore)).cast()
+    }
+
+    unsafe fn delete(semaphore: SemaphorePtr) {
+        drop(unsafe { Box::from_raw(semaphore.cast::<Self>().as_ptr()) });
+    }
+
+    unsafe fn take(semaphore: SemaphorePtr, timeout_us: Option<u32>) -> bool {
+        let this = Self::from_ptr(semaphore);
+        let owner = Self::current_owner();
+        if this.recursive && this.owner.load(Ordering::SeqCst) == owner {
+            this.depth.fetch_add(1, Ordering::SeqCst);
+            return true;
+        }
+
+        let deadline = deadline(timeout_us);
+        while !this.try_decrement() {
+            if !wait(&this.count, 0, deadline) {
+                return false;
+            }
+        }
+
+        this.owner.store(owner, Ordering::SeqCst);
+        this.depth.store(1, Ordering::SeqCst);
+        true
+    }
+
+    unsafe fn give(semaphore: SemaphorePtr) -> bool {
+        let this = Self::from_ptr(semaphore);
+        // Only the owner gives a mutex back, so the depth is not changed by others in the meantime
+        if this.recursive && this.depth.load(Ordering::SeqCst) > 1 {
+            this.depth.fetch_sub(1, Ordering::SeqCst);
+            return true;
+        }
+
+        this.owner.store(0, Ordering::SeqCst);
+        this.increment()
+    }
+
+    unsafe fn current_count(semaphore: SemaphorePtr) -> u32 {
+        Self::from_ptr(semaphore)
+            .count
+            .as_ref()
+            .load(Ordering::SeqCst) as u32
+    }
+
+    unsafe fn try_take(semaphore: SemaphorePtr) -> bool {
+        unsafe { Self::take(semaphore, Some(0)) }
+    }
+
+    unsafe fn try_give_from_isr(
+        semaphore: SemaphorePtr,
+        _higher_prio_task_waken: Option<&mut bool>,
+    ) -> bool {
+        // Waking a task requests the switch by itself
+        Self::from_ptr(semaphore).increment()
+    }
+
+    unsafe fn try_take_from_isr(
+        semaphore: SemaphorePtr,
+        _higher_prio_task_waken: Option<&mut bool>,
+    ) -> bool {
+        Self::from_ptr(semaphore).try_decrement()
+    }
+}
+
+register_semaphore_implementation!(Semaphore);
+
+/// Bounded queue of fixed-size items copied in and out as bytes.
+struct Queue {
+    item_size: usize,
+    capacity: usize,
+    items: Mutex<RefCell<VecDeque<u8>>>,
+    /// Number of queued items, waited on by receivers while 0 and by senders while `capacity`
+    len: Futex,
+}
+
+impl Queue {
+    fn from_ptr<'a>(queue: QueuePtr) -> &'a Self {
+        // SAFETY: created by `Queue::create` and not deleted yet (required by the callers)
+        unsafe { queue.cast::<Self>().as_ref() }
+    }
+
+    fn try_send(&self, item: *const u8, to_front: bool) -> bool {
+        // SAFETY: `esp-radio` passes an item of `item_size` bytes
+        let item = unsafe { core::slice::from_raw_parts(item, self.item_size) };
+        let sent = critical_section::with(|cs| {
+            let mut items = self.items.borrow_ref_mut(cs);
+            let len = items.len() / self.item_size;
+            if len >= self.capacity {
+                return false;
+            }
+
+            if to_front {
+                item.iter().rev().for_each(|byte| items.push_front(*byte));
+            } else {
+                items.extend(item);
+            }
+            self.len.as_ref().store(len + 1, Ordering::SeqCst);
+            true
+        });
+        if sent {
+            self.len.wake_all().expect("Failed to wake tasks");
+        }
+
+        sent
+    }
+
+    fn try_receive(&self, item: *mut u8) -> bool {
+        // SAFETY: `esp-radio` passes a buffer of `item_size` bytes
+        let item = unsafe { core::slice::from_raw_parts_mut(item, self.item_size) };
+        let received = critical_section::with(|cs| {
+            let mut items = self.items.borrow_ref_mut(cs);
+            if items.is_empty() {
+                return false;
+            }
+
+            item.iter_mut()
+                .zip(items.drain(..self.item_size))
+                .for_each(|(dst, src)| *dst = src);
+            self.len
+                .as_ref()
+                .store(items.len() / self.item_size, Ordering::SeqCst);
+            true
+        });
+        if received {
+            self.len.wake_all().expect("Failed to wake tasks");
+        }
+
+        received
+    }
+
+    fn send(&self, item: *const u8, to_front: bool, timeout_us: Option<u32>) -> bool {
+        let deadline = deadline(timeout_us);
+        while !self.try_send(item, to_front) {
+            if !wait(&self.len, self.capacity, deadline) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+unsafe impl QueueImplementation for Queue {
+    fn create(capacity: usize, item_size: usize) -> QueuePtr {
+        let queue = Box::new(Self {
+            item_size,
+            capacity,
+            items: Mutex::new(RefCell::new(VecDeque::with_capacity(capacity * item_size))),
+            len: Futex::new(0),
+        });
+
+        NonNull::from(Box::leak(queue)).cast()
+    }
+
+    unsafe fn delete(queue: QueuePtr) {
+        drop(unsafe { Box::from_raw(queue.cast::<Self>().as_ptr()) });
+    }
+
+    unsafe fn send_to_front(queue: QueuePtr, item: *const u8, timeout_us: Option<u32>) -> bool {
+        Self::from_ptr(queue).send(item, true, timeout_us)
+    }
+
+    unsafe fn send_to_back(queue: QueuePtr, item: *const u8, timeout_us: Option<u32>) -> bool {
+        Self::from_ptr(queue).send(item, false, timeout_us)
+    }
+
+    unsafe fn try_send_to_back_from_isr(
+        queue: QueuePtr,
+        item: *const u8,
+        _higher_prio_task_waken: Option<&mut bool>,
+    ) -> bool {
+        Self::from_ptr(queue).try_send(item, false)
+    }
+
+    unsafe fn receive(queue: QueuePtr, item: *mut u8, timeout_us: Option<u32>) -> bool {
+        let this = Self::from_ptr(queue);
+        let deadline = deadline(timeout_us);
+        while !this.try_receive(item) {
+            if !wait(&this.len, 0, deadline) {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    unsafe fn try_receive_from_isr(
+        queue: QueuePtr,
+        item: *mut u8,
+        _higher_prio_task_waken: Option<&mut bool>,
+    ) -> bool {
+        Self::from_ptr(queue).try_receive(item)
+    }
+
+    unsafe fn remove(queue: QueuePtr, item: *const u8) {
+        let this = Self::from_ptr(queue);
+        // SAFETY: `esp-radio` passes an item of `item_size` bytes
+        let item = unsafe { core::slice::from_raw_parts(item, this.item_size) };
+        let removed = critical_section::with(|cs| {
+            let mut items = this.items.borrow_ref_mut(cs);
+            let Some(position) = items
+                .make_contiguous()
+                .chunks(this.item_size)
+                .position(|queued| queued == item)
+            else {
+                return false;
+            };
+
+            items.drain(position * this.item_size..(position + 1) * this.item_size);
+            this.len
+                .as_ref()
+                .store(items.len() / this.item_size, Ordering::SeqCst);
+            true
+        });
+        if removed {
+            this.len.wake_all().expect("Failed to wake tasks");
+        }
+    }
+
+    fn messages_waiting(queue: QueuePtr) -> usize {
+        Self::from_ptr(queue).len.as_ref().load(Ordering::SeqCst)
+    }
+}
+
+register_queue_implementation!(Queue);
+
+/// One-shot or periodic timer whose callback is run by the timer task.
+struct Timer {
+    callback: unsafe extern "C" fn(*mut c_void),
+    data: usize,
+    /// Expiry time (in microseconds) and period (`None` for one-shot), if armed
+    armed: Mutex<Cell<Option<(u64, Option<u64>)>>>,
+}
+
+impl Timer {
+    fn from_ptr<'a>(timer: TimerPtr) -> &'a Self {
+        // SAFETY: created by `Timer::create` and not deleted yet (required by the callers)
+        unsafe { timer.cast::<Self>().as_ref() }
+    }
+
+    fn set(&self, armed: Option<(u64, Option<u64>)>) {
+        critical_section::with(|cs| self.armed.borrow(cs).set(armed));
+        TIMERS_CHANGED.as_ref().fetch_add(1, Ordering::SeqCst);
+        TIMERS_CHANGED
+            .wake_all()
+            .expect("Failed to wake the timer task");
+    }
+}
+
+unsafe impl TimerImplementation for Timer {
+    fn create(function: unsafe extern "C" fn(*mut c_void), data: *mut c_void) -> TimerPtr {
+        if !TIMER_TASK_SPAWNED.swap(true, Ordering::SeqCst) {
+            spawn_heap(
+                run_timers,
+                TIMER_TASK_STACK_SIZE,
+                TaskConfig::default()
+                    .with_name("esp-radio timer")
+                    .with_priority(MAX_PRIORITY),
+            )
+            .expect("Failed to spawn the timer task");
+        }
+
+        let timer = NonNull::from(Box::leak(Box::new(Self {
+            callback: function,
+            data: data as usize,
+            armed: Mutex::new(Cell::new(None)),
+        })));
+        critical_section::with(|cs| TIMERS.borrow_ref_mut(cs).push(timer.as_ptr() as usize));
+
+        timer.cast()
+    }
+
+    unsafe fn delete(timer: TimerPtr) {
+        let address = timer.as_ptr() as usize;
+        critical_section::with(|cs| TIMERS.borrow_ref_mut(cs).retain(|&other| other != address));
+        drop(unsafe { Box::from_raw(timer.cast::<Self>().as_ptr()) });
+    }
+
+    unsafe fn arm(timer: TimerPtr, timeout: u64, periodic: bool) {
+        let now = current_time_micros().expect("Failed to acquire current time");
+        Self::from_ptr(timer).set(Some((now + timeout, periodic.then_some(timeout))));
+    }
+
+    unsafe fn is_active(timer: TimerPtr) -> bool {
+        critical_section::with(|cs| Self::from_ptr(timer).armed.borrow(cs).get().is_some())
+    }
+
+    unsafe fn disarm(timer: TimerPtr) {
+        Self::from_ptr(timer).set(None);
+    }
+}
+
+register_timer_implementation!(Timer);
+
+/// Body of the timer task, which runs the callbacks of expired timers and sleeps until the next expiry.
+fn run_timers() {
+    loop {
+        let changes = TIMERS_CHANGED.as_ref().load(Ordering::SeqCst);
+        let now = current_time_micros().expect("Failed to acquire current time");
+
+        // Expired timers are rearmed or disarmed before their callbacks, which may arm them again
+        let (expired, next) = critical_section::with(|cs| {
+            let mut next: Option<u64> = None;
+            let mut expired = Vec::new();
+            for &address in TIMERS.borrow_ref(cs).iter() {
+                // SAFETY: timers are removed from `TIMERS` before they are deleted
+                let timer = unsafe { &*(address as *const Timer) };
+                let armed = timer.armed.borrow(cs);
+                let Some((time, period)) = armed.get() else {
+                    continue;
+                };
+
+                let time = if time <= now {
+                    expired.push((timer.callback, timer.data));
+                    let rearmed = period.map(|period| (time + period.max(1), Some(period)));
+                    armed.set(rearmed);
+                    match rearmed {
+                        Some((time, _)) => time,
+                        None => continue,
+                    }
+                } else {
+                    time
+                };
+                next = Some(next.map_or(time, |next| next.min(time)));
+            }
+
+            (expired, next)
+        });
+
+        if !expired.is_empty() {
+            for (callback, data) in expired {
+                // SAFETY: the callback and its data are registered together by `esp-radio`
+                unsafe { callback(data as *mut c_void) };
+            }
+            continue;
+        }
+
+        // Arming or disarming a timer in the meantime changes the value and ends the wait
+        wait(&TIMERS_CHANGED, changes, next.map(micros_to_ticks));
+    }
+}
@@ -4,74 +4,344 @@
 
 #![no_std]
 
+#[cfg(feature = "esp-radio")]
+extern crate alloc;
+
+#[cfg(feature = "clock-gate")]
+mod clock_gate;
+#[cfg(feature = "esp-embassy-compat")]
+pub mod embassy;
+#[cfg(feature = "esp-radio")]
+mod esp_radio;
+#[cfg(feature = "light-sleep")]
+mod light_sleep;
+#[cfg(feature = "user-mode")]
+pub mod pmp;
+#[cfg(feature = "stack-guard")]
+mod stack_guard;
+#[cfg(feature = "user-mode")]
+pub mod syscall;
+
 use core::cell::RefCell;
+#[cfg(feature = "rtos-awareness")]
+use core::mem::offset_of;
 
 use critical_section::Mutex;
 use esp_hal::{
-    Blocking, handler,
+    Blocking,
+    clock::Clocks,
+    handler,
     interrupt::{InterruptHandler, Priority, software::SoftwareInterrupt},
-    peripherals::SYSTIMER,
     riscv,
     time::Duration,
-    timer::{PeriodicTimer, systimer::SystemTimer},
+    timer::{AnyTimer, PeriodicTimer},
 };
+#[cfg(not(any(feature = "esp32c6", feature = "esp32h2")))]
+use esp_hal::peripherals::INTERRUPT_CORE0;
+#[cfg(any(feature = "esp32c6", feature = "esp32h2"))]
+use esp_hal::peripherals::PLIC_MX;
 use static_cell::ConstStaticCell;
+#[cfg(feature = "rtos-awareness")]
+use taskette::rtos_awareness::ContextLayout;
 use taskette::{
-    arch::StackAllocation,
-    scheduler::{Scheduler, SchedulerConfig},
+    arch::{StackAllocation, TickSource},
+    portable_atomic::{AtomicBool, AtomicU8, Ordering},
+    scheduler::{Scheduler, SchedulerConfig, SchedulerStorage},
+    sync::interrupt_free,
 };
 
+#[cfg(feature = "clock-gate")]
+pub use clock_gate::{DeepIdleVeto, deep_idle_vetoed, enable_clock_gate, veto_deep_idle};
+#[cfg(feature = "light-sleep")]
+pub use light_sleep::{LightSleepConfig, enable_light_sleep};
+#[cfg(feature = "stack-guard")]
+pub use stack_guard::enable_stack_guard;
+#[cfg(feature = "user-mode")]
+pub use syscall::spawn_user;
+
 const IDLE_TASK_STACK_SIZE: usize = 2048;
-const SWINT_IDX: u8 = 0;
+/// Size of a general-purpose register (XLEN / 8)
+const REGBYTES: usize = core::mem::size_of::<usize>();
 
 static IDLE_TASK_STACK: ConstStaticCell<Stack<IDLE_TASK_STACK_SIZE>> =
     ConstStaticCell::new(Stack::new());
 static TICK_FREQ: Mutex<RefCell<Option<u32>>> = Mutex::new(RefCell::new(None));
 static TIMER: Mutex<RefCell<Option<PeriodicTimer<'static, Blocking>>>> =
     Mutex::new(RefCell::new(None));
+/// Peripherals passed to `init_scheduler`, kept until they are set up in `_taskette_setup`
+static PERIPHERALS: Mutex<RefCell<Option<SchedulerPeripherals>>> = Mutex::new(RefCell::new(None));
+/// Index of the software interrupt used for context switching
+static SWINT_INDEX: AtomicU8 = AtomicU8::new(0);
+/// Set while `spawn_user` is creating a task
+#[cfg(feature = "user-mode")]
+static SPAWNING_USER: AtomicBool = AtomicBool::new(false);
 
-static mut MSTATUS_SAVE: u32 = 0;
-static mut MAIN_STACK_PTR: u32 = 0;
+// Load/store instructions matching the register width (XLEN)
+#[cfg(target_pointer_width = "32")]
+macro_rules! load {
+    () => {
+        "lw"
+    };
+}
+#[cfg(target_pointer_width = "32")]
+macro_rules! store {
+    () => {
+        "sw"
+    };
+}
+#[cfg(target_pointer_width = "64")]
+macro_rules! load {
+    () => {
+        "ld"
+    };
+}
+#[cfg(target_pointer_width = "64")]
+macro_rules! store {
+    () => {
+        "sd"
+    };
+}
+
+// Literal register width for use in assembler expressions
+#[cfg(target_pointer_width = "32")]
+macro_rules! regbytes {
+    () => {
+        "4"
+    };
+}
+#[cfg(target_pointer_width = "64")]
+macro_rules! regbytes {
+    () => {
+        "8"
+    };
+}
+
+// Floating-point load/store instructions and register width (FLEN)
+#[cfg(all(target_feature = "f", not(target_feature = "d")))]
+macro_rules! fload {
+    () => {
+        "flw"
+    };
+}
+#[cfg(all(target_feature = "f", not(target_feature = "d")))]
+macro_rules! fstore {
+    () => {
+        "fsw"
+    };
+}
+#[cfg(all(target_feature = "f", not(target_feature = "d")))]
+macro_rules! flen {
+    () => {
+        "4"
+    };
+}
+#[cfg(target_feature = "d")]
+macro_rules! fload {
+    () => {
+        "fld"
+    };
+}
+#[cfg(target_feature = "d")]
+macro_rules! fstore {
+    () => {
+        "fsd"
+    };
+}
+#[cfg(target_feature = "d")]
+macro_rules! flen {
+    () => {
+        "8"
+    };
+}
+
+// Operate on all FP registers stored after the general-purpose registers in `SavedRegisters`
+#[cfg(target_feature = "f")]
+macro_rules! for_fp_regs {
+    ($op:ident) => {
+        concat!(
+            $op!(), " f0, 32*", regbytes!(), "+", flen!(), "*0(sp)\n",
+            $op!(), " f1, 32*", regbytes!(), "+", flen!(), "*1(sp)\n",
+            $op!(), " f2, 32*", regbytes!(), "+", flen!(), "*2(sp)\n",
+            $op!(), " f3, 32*", regbytes!(), "+", flen!(), "*3(sp)\n",
+            $op!(), " f4, 32*", regbytes!(), "+", flen!(), "*4(sp)\n",
+            $op!(), " f5, 32*", regbytes!(), "+", flen!(), "*5(sp)\n",
+            $op!(), " f6, 32*", regbytes!(), "+", flen!(), "*6(sp)\n",
+            $op!(), " f7, 32*", regbytes!(), "+", flen!(), "*7(sp)\n",
+            $op!(), " f8, 32*", regbytes!(), "+", flen!(), "*8(sp)\n",
+            $op!(), " f9, 32*", regbytes!(), "+", flen!(), "*9(sp)\n",
+            $op!(), " f10, 32*", regbytes!(), "+", flen!(), "*10(sp)\n",
+            $op!(), " f11, 32*", regbytes!(), "+", flen!(), "*11(sp)\n",
+            $op!(), " f12, 32*", regbytes!(), "+", flen!(), "*12(sp)\n",
+            $op!(), " f13, 32*", regbytes!(), "+", flen!(), "*13(sp)\n",
+            $op!(), " f14, 32*", regbytes!(), "+", flen!(), "*14(sp)\n",
+            $op!(), " f15, 32*", regbytes!(), "+", flen!(), "*15(sp)\n",
+            $op!(), " f16, 32*", regbytes!(), "+", flen!(), "*16(sp)\n",
+            $op!(), " f17, 32*", regbytes!(), "+", flen!(), "*17(sp)\n",
+            $op!(), " f18, 32*", regbytes!(), "+", flen!(), "*18(sp)\n",
+            $op!(), " f19, 32*", regbytes!(), "+", flen!(), "*19(sp)\n",
+            $op!(), " f20, 32*", regbytes!(), "+", flen!(), "*20(sp)\n",
+            $op!(), " f21, 32*", regbytes!(), "+", flen!(), "*21(sp)\n",
+            $op!(), " f22, 32*", regbytes!(), "+", flen!(), "*22(sp)\n",
+            $op!(), " f23, 32*", regbytes!(), "+", flen!(), "*23(sp)\n",
+            $op!(), " f24, 32*", regbytes!(), "+", flen!(), "*24(sp)\n",
+            $op!(), " f25, 32*", regbytes!(), "+", flen!(), "*25(sp)\n",
+            $op!(), " f26, 32*", regbytes!(), "+", flen!(), "*26(sp)\n",
+            $op!(), " f27, 32*", regbytes!(), "+", flen!(), "*27(sp)\n",
+            $op!(), " f28, 32*", regbytes!(), "+", flen!(), "*28(sp)\n",
+            $op!(), " f29, 32*", regbytes!(), "+", flen!(), "*29(sp)\n",
+            $op!(), " f30, 32*", regbytes!(), "+", flen!(), "*30(sp)\n",
+            $op!(), " f31, 32*", regbytes!(), "+", flen!(), "*31(sp)\n",
+        )
+    };
+}
+
+// Saves FP registers and FCSR if the task has used the FPU (i.e. `mstatus.FS` in T0 is Clean or Dirty)
+#[cfg(target_feature = "f")]
+macro_rules! save_fp_regs {
+    () => {
+        concat!(
+            "srli t1, t0, 13\n",
+            "andi t1, t1, 3\n",
+            "li t2, 2\n",
+            "bltu t1, t2, 1f\n",
+            for_fp_regs!(fstore),
+            "frcsr t1\n",
+            store!(), " t1, 32*", regbytes!(), "+", flen!(), "*32(sp)\n",
+            "1:",
+        )
+    };
+}
+#[cfg(not(target_feature = "f"))]
+macro_rules! save_fp_regs {
+    () => {
+        ""
+    };
+}
+
+// Restores FP registers and FCSR if they were saved (`mstatus.FS` in T0 is Clean or Dirty)
+// FS is set to Clean afterwards, because the restored state is identical to the saved one.
+#[cfg(target_feature = "f")]
+macro_rules! restore_fp_regs {
+    () => {
+        concat!(
+            "srli t1, t0, 13\n",
+            "andi t1, t1, 3\n",
+            "li t2, 2\n",
+            "bltu t1, t2, 2f\n",
+            load!(), " t1, 32*", regbytes!(), "+", flen!(), "*32(sp)\n",
+            "fscsr t1\n",
+            for_fp_regs!(fload),
+            "li t1, 1 << 13\n",
+            "csrc mstatus, t1\n",
+            "2:",
+        )
+    };
+}
+#[cfg(not(target_feature = "f"))]
+macro_rules! restore_fp_regs {
+    () => {
+        ""
+    };
+}
+
+// Stops/restarts the hardware stack guard around the scheduler running on the main stack
+#[cfg(feature = "stack-guard")]
+macro_rules! stop_stack_guard {
+    () => {
+        "call taskette_stack_guard_stop"
+    };
+}
+#[cfg(not(feature = "stack-guard"))]
+macro_rules! stop_stack_guard {
+    () => {
+        ""
+    };
+}
+#[cfg(feature = "stack-guard")]
+macro_rules! start_stack_guard {
+    () => {
+        "call taskette_stack_guard_start"
+    };
+}
+#[cfg(not(feature = "stack-guard"))]
+macro_rules! start_stack_guard {
+    () => {
+        ""
+    };
+}
+
+// Programs PMP for the next task after changing to its stack
+#[cfg(feature = "user-mode")]
+macro_rules! switch_pmp {
+    () => {
+        "call taskette_pmp_switch"
+    };
+}
+#[cfg(not(feature = "user-mode"))]
+macro_rules! switch_pmp {
+    () => {
+        ""
+    };
+}
 
+static mut MSTATUS_SAVE: usize = 0;
+static mut MAIN_STACK_PTR: usize = 0;
+
+/// Floating-point register (FLEN wide)
+#[cfg(all(target_feature = "f", not(target_feature = "d")))]
+type FReg = u32;
+#[cfg(target_feature = "d")]
+type FReg = u64;
+
+/// Context frame saved on the task stack.
+/// Each field is XLEN wide, so the frame is 128 bytes on RV32 and 256 bytes on RV64.
+/// On targets with the F/D extension, the FP registers follow but are only saved for tasks which have used the FPU.
 #[repr(C, align(16))]
 #[derive(Clone, Debug)]
 struct SavedRegisters {
-    ra: u32,
-    gp: u32,
-    tp: u32,
-    t0: u32,
-    t1: u32,
-    t2: u32,
-    s0: u32,
-    s1: u32,
-    a0: u32,
-    a1: u32,
-    a2: u32,
-    a3: u32,
-    a4: u32,
-    a5: u32,
-    a6: u32,
-    a7: u32,
-    s2: u32,
-    s3: u32,
-    s4: u32,
-    s5: u32,
-    s6: u32,
-    s7: u32,
-    s8: u32,
-    s9: u32,
-    s10: u32,
-    s11: u32,
-    t3: u32,
-    t4: u32,
-    t5: u32,
-    t6: u32,
-    pc: u32,
-    mstatus: u32,
+    ra: usize,
+    gp: usize,
+    tp: usize,
+    t0: usize,
+    t1: usize,
+    t2: usize,
+    s0: usize,
+    s1: usize,
+    a0: usize,
+    a1: usize,
+    a2: usize,
+    a3: usize,
+    a4: usize,
+    a5: usize,
+    a6: usize,
+    a7: usize,
+    s2: usize,
+    s3: usize,
+    s4: usize,
+    s5: usize,
+    s6: usize,
+    s7: usize,
+    s8: usize,
+    s9: usize,
+    s10: usize,
+    s11: usize,
+    t3: usize,
+    t4: usize,
+    t5: usize,
+    t6: usize,
+    pc: usize,
+    mstatus: usize,
+    #[cfg(target_feature = "f")]
+    fregs: [FReg; 32],
+    #[cfg(target_feature = "f")]
+    fcsr: usize,
 }
 
 impl SavedRegisters {
-    pub fn from_pc_and_a0(pc: u32, a0: u32) -> Self {
+    /// `user` selects the privilege mode the task starts in (U-mode or M-mode).
+    pub fn from_pc_and_a0(pc: usize, a0: usize, user: bool) -> Self {
+        let mpp = if user { 0 } else { 3 };
+
         Self {
             ra: 0,
             gp: 0,
@@ -104,62 +374,196 @@ impl SavedRegisters {
             t5: 0,
             t6: 0,
             pc,
-            mstatus: (/* MPP */ 3 << 11) | (/* MPIE */ 1 << 7),
+            #[cfg(not(target_feature = "f"))]
+            mstatus: (/* MPP */ mpp << 11) | (/* MPIE */ 1 << 7),
+            // FS=Initial enables the FPU without FP registers being saved until they are first used
+            #[cfg(target_feature = "f")]
+            mstatus: (/* FS */ 1 << 13) | (/* MPP */ mpp << 11) | (/* MPIE */ 1 << 7),
+            #[cfg(target_feature = "f")]
+            fregs: [0; 32],
+            #[cfg(target_feature = "f")]
+            fcsr: 0,
         }
     }
 }
 
+/// Layout of the saved context for debuggers (registers in the DWARF numbering: x1 = ra, x2 = sp, ...).
+#[cfg(feature = "rtos-awareness")]
+#[unsafe(export_name = "taskette_context_layout")]
+static CONTEXT_LAYOUT: ContextLayout =
+    ContextLayout::new(size_of::<SavedRegisters>(), offset_of!(SavedRegisters, pc))
+        .with_register(1, offset_of!(SavedRegisters, ra))
+        .with_register(3, offset_of!(SavedRegisters, gp))
+        .with_register(4, offset_of!(SavedRegisters, tp))
+        .with_register(5, offset_of!(SavedRegisters, t0))
+        .with_register(6, offset_of!(SavedRegisters, t1))
+        .with_register(7, offset_of!(SavedRegisters, t2))
+        .with_register(8, offset_of!(SavedRegisters, s0))
+        .with_register(9, offset_of!(SavedRegisters, s1))
+        .with_register(10, offset_of!(SavedRegisters, a0))
+        .with_register(11, offset_of!(SavedRegisters, a1))
+        .with_register(12, offset_of!(SavedRegisters, a2))
+        .with_register(13, offset_of!(SavedRegisters, a3))
+        .with_register(14, offset_of!(SavedRegisters, a4))
+        .with_register(15, offset_of!(SavedRegisters, a5))
+        .with_register(16, offset_of!(SavedRegisters, a6))
+        .with_register(17, offset_of!(SavedRegisters, a7))
+        .with_register(18, offset_of!(SavedRegisters, s2))
+        .with_register(19, offset_of!(SavedRegisters, s3))
+        .with_register(20, offset_of!(SavedRegisters, s4))
+        .with_register(21, offset_of!(SavedRegisters, s5))
+        .with_register(22, offset_of!(SavedRegisters, s6))
+        .with_register(23, offset_of!(SavedRegisters, s7))
+        .with_register(24, offset_of!(SavedRegisters, s8))
+        .with_register(25, offset_of!(SavedRegisters, s9))
+        .with_register(26, offset_of!(SavedRegisters, s10))
+        .with_register(27, offset_of!(SavedRegisters, s11))
+        .with_register(28, offset_of!(SavedRegisters, t3))
+        .with_register(29, offset_of!(SavedRegisters, t4))
+        .with_register(30, offset_of!(SavedRegisters, t5))
+        .with_register(31, offset_of!(SavedRegisters, t6));
+
+/// Peripherals owned by the scheduler
+struct SchedulerPeripherals {
+    tick_timer: AnyTimer<'static>,
+    /// Registers the handler of the software interrupt passed to `init_scheduler`
+    register_swint: fn(),
+}
+
 /// Safely initializes the scheduler.
-pub fn init_scheduler(
-    _systimer: SYSTIMER,
-    _sw_interrupt: SoftwareInterrupt<SWINT_IDX>,
-    clock_freq: u32,
+///
+/// `tick_timer` generates the tick interrupt. It can be any alarm of the `SystemTimer`
+/// (e.g. `SystemTimer::new(peripherals.SYSTIMER).alarm1`) or a timer of a timer group (e.g. `TimerGroup::new(peripherals.TIMG0).timer0`).
+/// `sw_interrupt` (any of the four, e.g. one not used by `esp-rtos`) is used for context switching.
+/// Both are owned by the scheduler, while the other timers remain usable by the application.
+///
+/// The CPU clock frequency is taken from the configuration applied by `esp_hal::init`, which must be called before.
+pub fn init_scheduler<const N: u8>(
+    tick_timer: impl Into<AnyTimer<'static>>,
+    sw_interrupt: SoftwareInterrupt<'static, N>,
     config: SchedulerConfig,
 ) -> Option<Scheduler> {
-    unsafe { Scheduler::init(clock_freq, config) }
+    let scheduler = unsafe { Scheduler::init(cpu_clock_freq(), config) }?;
+    store_peripherals(tick_timer.into(), sw_interrupt);
+
+    Some(scheduler)
 }
 
-/// INTERNAL USE ONLY
-#[unsafe(no_mangle)]
-pub fn _taskette_setup(_clock_freq: u32, tick_freq: u32) {
-    let systimer = SystemTimer::new(unsafe { esp_hal::peripherals::Peripherals::steal() }.SYSTIMER);
-    let mut swint = unsafe { SoftwareInterrupt::<SWINT_IDX>::steal() };
+/// Safely initializes the scheduler with the memory supplied by the application (see [`SchedulerStorage`]).
+pub fn init_scheduler_with_storage<const TASKS: usize, const TIMERS: usize, const N: u8>(
+    tick_timer: impl Into<AnyTimer<'static>>,
+    sw_interrupt: SoftwareInterrupt<'static, N>,
+    config: SchedulerConfig,
+    storage: &'static mut SchedulerStorage<TASKS, TIMERS>,
+) -> Option<Scheduler> {
+    let scheduler = unsafe { Scheduler::init_with_storage(cpu_clock_freq(), config, storage) }?;
+    store_peripherals(tick_timer.into(), sw_interrupt);
+
+    Some(scheduler)
+}
+
+/// CPU clock frequency configured by `esp_hal::init` (in Hz)
+fn cpu_clock_freq() -> u32 {
+    Clocks::get().cpu_clock.as_hz()
+}
+
+/// Keeps the peripherals until the scheduler starts.
+///
+/// The interrupt handlers are not registered yet, because tasks spawned before the start raise the software interrupt.
+/// The software interrupt itself is accessed through `steal` afterwards,
+/// which is sound because the scheduler took its ownership and never gives it back.
+fn store_peripherals<const N: u8>(
+    tick_timer: AnyTimer<'static>,
+    _sw_interrupt: SoftwareInterrupt<'static, N>,
+) {
+    SWINT_INDEX.store(N, Ordering::Relaxed);
+    interrupt_free(|cs| {
+        PERIPHERALS.replace(
+            cs,
+            Some(SchedulerPeripherals {
+                tick_timer,
+                register_swint: register_swint::<N>,
+            }),
+        );
+    });
+}
+
+fn register_swint<const N: u8>() {
     // Use non-nesting interrupt handler to avoid getting messed up by another interrupt
     // Reference:
     //  https://github.com/esp-rs/esp-hal/blob/93d5d9af1cabc9d8f3bb2b29ae3e15613109c870/esp-rtos/src/task/riscv.rs#L296-L301
-    swint.set_interrupt_handler(InterruptHandler::new_not_nested(
-        swint_handler,
-        Priority::min(),
-    ));
+    unsafe { SoftwareInterrupt::<N>::steal() }.set_interrupt_handler(
+        InterruptHandler::new_not_nested(swint_handler::<N>, Priority::min()),
+    );
+}
 
-    let mut timer = PeriodicTimer::new(systimer.alarm1); // Alarm 0 is used by `esp-hal::time::Instant::now`
-    timer.set_interrupt_handler(systimer_handler);
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_setup() {
+    let SchedulerPeripherals {
+        tick_timer,
+        register_swint,
+    } = interrupt_free(|cs| PERIPHERALS.take(cs)).expect("Scheduler not initialized");
+
+    register_swint();
+
+    let mut timer = PeriodicTimer::new(tick_timer);
+    timer.set_interrupt_handler(tick_handler);
     timer.listen(); // This is necessary for timer interrupts to fire
 
-    critical_section::with(|cs| {
-        TICK_FREQ.replace(cs, Some(tick_freq));
-        TIMER.replace(cs, Some(timer));
-    });
+    interrupt_free(|cs| TIMER.replace(cs, Some(timer)));
+}
+
+/// Tick generated by the timer passed to `init_scheduler`
+struct TimerTick;
+
+static TICK_SOURCE: TimerTick = TimerTick;
+
+impl TickSource for TimerTick {
+    fn set_frequency(&self, _clock_freq: u32, tick_freq: u32) {
+        interrupt_free(|cs| TICK_FREQ.replace(cs, Some(tick_freq)));
+    }
+
+    fn start(&self) {
+        interrupt_free(|cs| {
+            let tick_freq = TICK_FREQ.borrow_ref(cs);
+            let tick_freq = tick_freq.as_ref().expect("Scheduler not initialized");
+            let mut timer = TIMER.borrow_ref_mut(cs);
+            let timer = timer.as_mut().expect("Scheduler not initialized");
+
+            timer
+                .start(tick_period(*tick_freq))
+                .expect("Failed to start the tick timer");
+        });
+
+        // Called on the stack of the idle task
+        #[cfg(feature = "stack-guard")]
+        stack_guard::start();
+    }
+
+    fn stop(&self) {
+        interrupt_free(|cs| {
+            if let Some(timer) = TIMER.borrow_ref_mut(cs).as_mut() {
+                let _ = timer.cancel();
+            }
+        });
+    }
 }
 
 /// INTERNAL USE ONLY
 #[unsafe(no_mangle)]
-pub fn _taskette_start_timer() {
-    critical_section::with(|cs| {
-        let tick_freq = TICK_FREQ.borrow_ref(cs);
-        let tick_freq = tick_freq.as_ref().expect("Scheduler not initialized");
-        let mut timer = TIMER.borrow_ref_mut(cs);
-        let timer = timer.as_mut().expect("Scheduler not initialized");
+pub fn _taskette_tick_source() -> &'static dyn TickSource {
+    &TICK_SOURCE
+}
 
-        timer
-            .start(Duration::from_micros(1_000_000 / *tick_freq as u64))
-            .expect("Failed to start the system timer");
-    });
+/// Interval of the tick interrupt
+fn tick_period(tick_freq: u32) -> Duration {
+    Duration::from_micros(1_000_000 / tick_freq as u64)
 }
 
 #[handler(priority = Priority::min())]
-fn systimer_handler() {
-    critical_section::with(|cs| {
+fn tick_handler() {
+    interrupt_free(|cs| {
         let mut timer = TIMER.borrow_ref_mut(cs);
         let timer = timer.as_mut().unwrap_or_else(|| unreachable!());
         timer.clear_interrupt();
@@ -168,15 +572,18 @@ fn systimer_handler() {
     taskette::scheduler::handle_tick();
 }
 
-extern "C" fn swint_handler() {
+extern "C" fn swint_handler<const N: u8>() {
     unsafe {
-        SoftwareInterrupt::<SWINT_IDX>::steal().reset();
+        SoftwareInterrupt::<N>::steal().reset();
 
         // Save MSTATUS (as it will be modified by `mret`)
         let mut mstatus = riscv::register::mstatus::read();
-        MSTATUS_SAVE = mstatus.bits() as u32;
+        MSTATUS_SAVE = mstatus.bits();
         // Prohibit interruption during context switching
         mstatus.set_mpie(false);
+        // The interrupted task may be in U-mode, but the context switching code has to run in M-mode
+        #[cfg(feature = "user-mode")]
+        mstatus.set_mpp(riscv::register::mstatus::MPP::Machine);
         riscv::register::mstatus::write(mstatus);
         // Save the original MEPC in MSCRATCH
         riscv::register::mscratch::write(riscv::register::mepc::read());
@@ -191,103 +598,173 @@ extern "C" fn swint_handler() {
 unsafe extern "C" fn switch_context() {
     core::arch::naked_asm!(
         // Move stack pointer
-        "addi sp, sp, -0x80",
+        "addi sp, sp, -{frame_size}",
         // Save registers on the stack
-        "sw ra, 0(sp)",
-        "sw gp, 4*1(sp)",
-        "sw tp, 4*2(sp)",
-        "sw t0, 4*3(sp)",
-        "sw t1, 4*4(sp)",
-        "sw t2, 4*5(sp)",
-        "sw s0, 4*6(sp)",
-        "sw s1, 4*7(sp)",
-        "sw a0, 4*8(sp)",
-        "sw a1, 4*9(sp)",
-        "sw a2, 4*10(sp)",
-        "sw a3, 4*11(sp)",
-        "sw a4, 4*12(sp)",
-        "sw a5, 4*13(sp)",
-        "sw a6, 4*14(sp)",
-        "sw a7, 4*15(sp)",
-        "sw s2, 4*16(sp)",
-        "sw s3, 4*17(sp)",
-        "sw s4, 4*18(sp)",
-        "sw s5, 4*19(sp)",
-        "sw s6, 4*20(sp)",
-        "sw s7, 4*21(sp)",
-        "sw s8, 4*22(sp)",
-        "sw s9, 4*23(sp)",
-        "sw s10, 4*24(sp)",
-        "sw s11, 4*25(sp)",
-        "sw t3, 4*26(sp)",
-        "sw t4, 4*27(sp)",
-        "sw t5, 4*28(sp)",
-        "sw t6, 4*29(sp)",
+        concat!(store!(), " ra, 0(sp)"),
+        concat!(store!(), " gp, {regbytes}*1(sp)"),
+        concat!(store!(), " tp, {regbytes}*2(sp)"),
+        concat!(store!(), " t0, {regbytes}*3(sp)"),
+        concat!(store!(), " t1, {regbytes}*4(sp)"),
+        concat!(store!(), " t2, {regbytes}*5(sp)"),
+        concat!(store!(), " s0, {regbytes}*6(sp)"),
+        concat!(store!(), " s1, {regbytes}*7(sp)"),
+        concat!(store!(), " a0, {regbytes}*8(sp)"),
+        concat!(store!(), " a1, {regbytes}*9(sp)"),
+        concat!(store!(), " a2, {regbytes}*10(sp)"),
+        concat!(store!(), " a3, {regbytes}*11(sp)"),
+        concat!(store!(), " a4, {regbytes}*12(sp)"),
+        concat!(store!(), " a5, {regbytes}*13(sp)"),
+        concat!(store!(), " a6, {regbytes}*14(sp)"),
+        concat!(store!(), " a7, {regbytes}*15(sp)"),
+        concat!(store!(), " s2, {regbytes}*16(sp)"),
+        concat!(store!(), " s3, {regbytes}*17(sp)"),
+        concat!(store!(), " s4, {regbytes}*18(sp)"),
+        concat!(store!(), " s5, {regbytes}*19(sp)"),
+        concat!(store!(), " s6, {regbytes}*20(sp)"),
+        concat!(store!(), " s7, {regbytes}*21(sp)"),
+        concat!(store!(), " s8, {regbytes}*22(sp)"),
+        concat!(store!(), " s9, {regbytes}*23(sp)"),
+        concat!(store!(), " s10, {regbytes}*24(sp)"),
+        concat!(store!(), " s11, {regbytes}*25(sp)"),
+        concat!(store!(), " t3, {regbytes}*26(sp)"),
+        concat!(store!(), " t4, {regbytes}*27(sp)"),
+        concat!(store!(), " t5, {regbytes}*28(sp)"),
+        concat!(store!(), " t6, {regbytes}*29(sp)"),
         // Save the original PC (MEPC) value stored in MSCRATCH
         "csrr t0, mscratch",
-        "sw t0, 4*30(sp)",
+        concat!(store!(), " t0, {regbytes}*30(sp)"),
         // Save MSTATUS
-        "lw t0, {mstatus_save}",
-        "sw t0, 4*31(sp)",
+        concat!(load!(), " t0, {mstatus_save}"),
+        concat!(store!(), " t0, {regbytes}*31(sp)"),
+        // Save FP registers (if present)
+        save_fp_regs!(),
+        stop_stack_guard!(),
         // Set the first argument to SP
         "mv a0, sp",
         // Change the stack to the main stack
-        "lw sp, {main_stack_ptr}",
-        // Call the scheduling function
+        concat!(load!(), " sp, {main_stack_ptr}"),
+        // Call the scheduling function (with higher-priority interrupts enabled)
         "call {select_task}",
         // Set SP with the return value
         "mv sp, a0",
+        start_stack_guard!(),
+        switch_pmp!(),
         // Restore PC value to MEPC
-        "lw t0, 4*30(sp)",
+        concat!(load!(), " t0, {regbytes}*30(sp)"),
         "csrw mepc, t0",
         // Restore MSTATUS
-        "lw t0, 4*31(sp)",
+        concat!(load!(), " t0, {regbytes}*31(sp)"),
         "csrw mstatus, t0",
+        // Restore FP registers (if saved)
+        restore_fp_regs!(),
         // Restore registers
-        "lw ra, 0(sp)",
-        "lw gp, 4*1(sp)",
-        "lw tp, 4*2(sp)",
-        "lw t0, 4*3(sp)",
-        "lw t1, 4*4(sp)",
-        "lw t2, 4*5(sp)",
-        "lw s0, 4*6(sp)",
-        "lw s1, 4*7(sp)",
-        "lw a0, 4*8(sp)",
-        "lw a1, 4*9(sp)",
-        "lw a2, 4*10(sp)",
-        "lw a3, 4*11(sp)",
-        "lw a4, 4*12(sp)",
-        "lw a5, 4*13(sp)",
-        "lw a6, 4*14(sp)",
-        "lw a7, 4*15(sp)",
-        "lw s2, 4*16(sp)",
-        "lw s3, 4*17(sp)",
-        "lw s4, 4*18(sp)",
-        "lw s5, 4*19(sp)",
-        "lw s6, 4*20(sp)",
-        "lw s7, 4*21(sp)",
-        "lw s8, 4*22(sp)",
-        "lw s9, 4*23(sp)",
-        "lw s10, 4*24(sp)",
-        "lw s11, 4*25(sp)",
-        "lw t3, 4*26(sp)",
-        "lw t4, 4*27(sp)",
-        "lw t5, 4*28(sp)",
-        "lw t6, 4*29(sp)",
+        concat!(load!(), " ra, 0(sp)"),
+        concat!(load!(), " gp, {regbytes}*1(sp)"),
+        concat!(load!(), " tp, {regbytes}*2(sp)"),
+        concat!(load!(), " t0, {regbytes}*3(sp)"),
+        concat!(load!(), " t1, {regbytes}*4(sp)"),
+        concat!(load!(), " t2, {regbytes}*5(sp)"),
+        concat!(load!(), " s0, {regbytes}*6(sp)"),
+        concat!(load!(), " s1, {regbytes}*7(sp)"),
+        concat!(load!(), " a0, {regbytes}*8(sp)"),
+        concat!(load!(), " a1, {regbytes}*9(sp)"),
+        concat!(load!(), " a2, {regbytes}*10(sp)"),
+        concat!(load!(), " a3, {regbytes}*11(sp)"),
+        concat!(load!(), " a4, {regbytes}*12(sp)"),
+        concat!(load!(), " a5, {regbytes}*13(sp)"),
+        concat!(load!(), " a6, {regbytes}*14(sp)"),
+        concat!(load!(), " a7, {regbytes}*15(sp)"),
+        concat!(load!(), " s2, {regbytes}*16(sp)"),
+        concat!(load!(), " s3, {regbytes}*17(sp)"),
+        concat!(load!(), " s4, {regbytes}*18(sp)"),
+        concat!(load!(), " s5, {regbytes}*19(sp)"),
+        concat!(load!(), " s6, {regbytes}*20(sp)"),
+        concat!(load!(), " s7, {regbytes}*21(sp)"),
+        concat!(load!(), " s8, {regbytes}*22(sp)"),
+        concat!(load!(), " s9, {regbytes}*23(sp)"),
+        concat!(load!(), " s10, {regbytes}*24(sp)"),
+        concat!(load!(), " s11, {regbytes}*25(sp)"),
+        concat!(load!(), " t3, {regbytes}*26(sp)"),
+        concat!(load!(), " t4, {regbytes}*27(sp)"),
+        concat!(load!(), " t5, {regbytes}*28(sp)"),
+        concat!(load!(), " t6, {regbytes}*29(sp)"),
         // Move stack pointer
-        "addi sp, sp, 0x80",
+        "addi sp, sp, {frame_size}",
         // Exit the ISR
         "mret",
-        select_task = sym taskette::scheduler::select_task,
+        select_task = sym select_task,
         mstatus_save = sym MSTATUS_SAVE,
         main_stack_ptr = sym MAIN_STACK_PTR,
+        regbytes = const REGBYTES,
+        frame_size = const core::mem::size_of::<SavedRegisters>(),
     )
 }
 
+/// Runs the scheduler with the interrupts above the kernel priority enabled, so that they are not delayed by a context switch.
+///
+/// `switch_context` otherwise runs with all interrupts disabled. The tick and the software interrupt (both at `Priority::min()`)
+/// stay masked, and the interrupts taken here use the main stack.
+extern "C" fn select_task(orig_sp: usize) -> usize {
+    let threshold = mask_kernel_interrupts();
+    unsafe { riscv::interrupt::enable() };
+
+    let next_sp = unsafe { taskette::scheduler::select_task(orig_sp) };
+
+    riscv::interrupt::disable();
+    restore_threshold(threshold);
+
+    next_sp
+}
+
+/// Raises the interrupt threshold above the kernel priority and returns the previous threshold.
+#[cfg(not(any(feature = "esp32c6", feature = "esp32h2")))]
+fn mask_kernel_interrupts() -> u32 {
+    let intr = INTERRUPT_CORE0::regs();
+    let threshold = intr.cpu_int_thresh().read().bits();
+    // Interrupts with priority >= threshold are taken
+    intr.cpu_int_thresh()
+        .write(|w| unsafe { w.bits(threshold.max(Priority::min() as u32 + 1)) });
+    threshold
+}
+
+#[cfg(not(any(feature = "esp32c6", feature = "esp32h2")))]
+fn restore_threshold(threshold: u32) {
+    INTERRUPT_CORE0::regs()
+        .cpu_int_thresh()
+        .write(|w| unsafe { w.bits(threshold) });
+}
+
+/// Raises the interrupt threshold above the kernel priority and returns the previous threshold.
+#[cfg(any(feature = "esp32c6", feature = "esp32h2"))]
+fn mask_kernel_interrupts() -> u32 {
+    let plic = PLIC_MX::regs();
+    let threshold = plic.mxint_thresh().read().cpu_mxint_thresh().bits();
+    // Interrupts with priority >= threshold are taken
+    plic.mxint_thresh().write(|w| unsafe {
+        w.cpu_mxint_thresh()
+            .bits(threshold.max(Priority::min() as u8 + 1))
+    });
+    threshold as u32
+}
+
+#[cfg(any(feature = "esp32c6", feature = "esp32h2"))]
+fn restore_threshold(threshold: u32) {
+    PLIC_MX::regs()
+        .mxint_thresh()
+        .write(|w| unsafe { w.cpu_mxint_thresh().bits(threshold as u8) });
+}
+
 /// INTERNAL USE ONLY
 #[unsafe(no_mangle)]
 pub fn _taskette_yield_now() {
-    unsafe { SoftwareInterrupt::<0>::steal() }.raise();
+    // The index is only known at runtime, while `SoftwareInterrupt` takes it as a const generic
+    match SWINT_INDEX.load(Ordering::Relaxed) {
+        0 => unsafe { SoftwareInterrupt::<0>::steal() }.raise(),
+        1 => unsafe { SoftwareInterrupt::<1>::steal() }.raise(),
+        2 => unsafe { SoftwareInterrupt::<2>::steal() }.raise(),
+        3 => unsafe { SoftwareInterrupt::<3>::steal() }.raise(),
+        _ => unreachable!(),
+    }
 }
 
 /// INTERNAL USE ONLY
@@ -299,20 +776,32 @@ pub fn _taskette_init_stack(sp: *mut u8, pc: usize, arg: *const u8, arg_size: us
         // Call `call_closure` with a pointer to the closure as the first argument
         let sp = push_to_stack(
             sp,
-            &SavedRegisters::from_pc_and_a0(pc as u32, sp as u32) as *const _ as *const u8,
+            &SavedRegisters::from_pc_and_a0(pc, sp as usize, spawning_user()) as *const _
+                as *const u8,
             core::mem::size_of::<SavedRegisters>(),
         );
         sp
     }
 }
 
+fn spawning_user() -> bool {
+    #[cfg(feature = "user-mode")]
+    {
+        SPAWNING_USER.load(Ordering::Relaxed)
+    }
+    #[cfg(not(feature = "user-mode"))]
+    {
+        false
+    }
+}
+
 #[unsafe(no_mangle)]
 pub unsafe fn _taskette_run_with_stack(pc: usize, sp: *mut u8, _stack_limit: *mut u8) -> ! {
     unsafe {
         core::arch::asm!(
             // Remember the main stack
             "la {main_stack_ptr_reg}, {main_stack_ptr}",
-            "sw sp, 0({main_stack_ptr_reg})",
+            concat!(store!(), " sp, 0({main_stack_ptr_reg})"),
             // Set the SP with the new value
             "mv sp, {new_sp}",
             // Jump to the new PC
@@ -328,7 +817,11 @@ pub unsafe fn _taskette_run_with_stack(pc: usize, sp: *mut u8, _stack_limit: *mu
 }
 
 #[unsafe(no_mangle)]
-pub fn _taskette_get_idle_task_stack() -> Option<&'static mut [u8]> {
+pub fn _taskette_get_idle_task_stack(core_id: usize) -> Option<&'static mut [u8]> {
+    if core_id != 0 {
+        return None;
+    }
+
     if let Some(stack) = IDLE_TASK_STACK.try_take() {
         Some(&mut stack.0)
     } else {
@@ -339,9 +832,72 @@ pub fn _taskette_get_idle_task_stack() -> Option<&'static mut [u8]> {
 /// INTERNAL USE ONLY
 #[unsafe(no_mangle)]
 pub fn _taskette_wait_for_interrupt() {
+    #[cfg(feature = "light-sleep")]
+    if light_sleep::idle() {
+        return;
+    }
+
+    #[cfg(feature = "clock-gate")]
+    clock_gate::prepare_wait();
+
     riscv::asm::wfi();
 }
 
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_core_id() -> usize {
+    0
+}
+
+// The IPI below cannot reach other cores
+const _: () = assert!(
+    taskette::scheduler::NUM_CORES == 1,
+    "This port is single-core; disable `smp` feature of taskette"
+);
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_ipi(_core_id: usize) {
+    // Single-core: only the current core exists
+    _taskette_yield_now();
+}
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_try_lock(lock: &AtomicBool) -> bool {
+    lock.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+        .is_ok()
+}
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_mask_interrupts() -> usize {
+    let mstatus: usize;
+    unsafe {
+        core::arch::asm!("csrrci {}, mstatus, 8", out(reg) mstatus); // Clear MIE
+    }
+    mstatus
+}
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_restore_interrupts(state: usize) {
+    if state & 8 != 0 {
+        unsafe {
+            core::arch::asm!("csrsi mstatus, 8"); // Set MIE
+        }
+    }
+}
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_cycle_count() -> u32 {
+    // Microseconds counted by the SYSTIMER (the ESP32-C series has no standard cycle counter)
+    esp_hal::time::Instant::now()
+        .duration_since_epoch()
+        .as_micros() as u32
+}
+
 unsafe fn push_to_stack(sp: *mut u8, obj: *const u8, obj_size: usize) -> *mut u8 {
     unsafe {
         let size = obj_size;
@@ -0,0 +1,153 @@
+//! Light sleep of the idle task (`light-sleep` feature, ESP32-C2/C3/C6).
+//!
+//! While a task is sleeping on a timer and no task is ready, the idle task stops the tick timer
+//! and enters light sleep with the RTC timer programmed for the next wakeup, instead of just `wfi`.
+//! On wake, the time slept (measured by the RTC, which keeps running) is added to the tick count at once.
+//!
+//! Peripheral interrupts do not end light sleep unless they are configured as wakeup sources,
+//! so this suits applications whose tasks are mostly driven by timers.
+//!
+//! Long waits (e.g. `wait_until(now + days)`) can be ended early by the RTC timer with
+//! [`LightSleepConfig::with_long_wait`]. The tick timer is then re-armed to count the last ticks,
+//! so that the wakeup is timed by the high-speed clock while it stays off for most of the wait.
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use esp_hal::{
+    interrupt,
+    peripherals::LPWR,
+    rtc_cntl::{Rtc, sleep::TimerWakeupSource},
+    system::Cpu,
+};
+use taskette::{
+    sync::interrupt_free,
+    timer::{current_time, next_wakeup, skip_ticks},
+};
+
+use crate::{TICK_FREQ, TIMER, tick_period};
+
+static SLEEP: Mutex<RefCell<Option<Sleep>>> = Mutex::new(RefCell::new(None));
+
+struct Sleep {
+    rtc: Rtc<'static>,
+    config: LightSleepConfig,
+    /// Wakeup time of the last long wait, whose remaining ticks are left to the tick timer
+    rearmed_for: Option<u64>,
+}
+
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct LightSleepConfig {
+    /// Shortest idle time worth sleeping (in ticks)
+    pub min_ticks: u64,
+    /// Shortest idle time treated as a long wait (in ticks)
+    pub long_wait_ticks: u64,
+    /// Ticks at the end of a long wait counted by the tick timer instead of the RTC timer
+    pub guard_ticks: u64,
+}
+
+impl LightSleepConfig {
+    /// Sets the shortest idle time worth sleeping. It should cover the time to enter and leave light sleep (around a millisecond).
+    pub fn with_min_ticks(self, min_ticks: u64) -> Self {
+        Self { min_ticks, ..self }
+    }
+
+    /// Makes the RTC timer end waits of at least `long_wait_ticks` ticks `guard_ticks` ticks early. Disabled by default.
+    ///
+    /// `guard_ticks` should cover the drift of the RTC slow clock over the longest wait.
+    pub fn with_long_wait(self, long_wait_ticks: u64, guard_ticks: u64) -> Self {
+        Self {
+            long_wait_ticks,
+            guard_ticks,
+            ..self
+        }
+    }
+}
+
+impl Default for LightSleepConfig {
+    fn default() -> Self {
+        Self {
+            min_ticks: 2,
+            long_wait_ticks: u64::MAX,
+            guard_ticks: 0,
+        }
+    }
+}
+
+/// Lets the idle task enter light sleep when no task wakes up within `config.min_ticks` ticks.
+pub fn enable_light_sleep(lpwr: LPWR<'static>, config: LightSleepConfig) {
+    interrupt_free(|cs| {
+        SLEEP.replace(
+            cs,
+            Some(Sleep {
+                rtc: Rtc::new(lpwr),
+                config,
+                rearmed_for: None,
+            }),
+        );
+    });
+}
+
+/// Sleeps until the next wakeup if possible. Returns `false` if the idle task should wait with `wfi` instead.
+pub(crate) fn idle() -> bool {
+    interrupt_free(|cs| {
+        let mut sleep = SLEEP.borrow_ref_mut(cs);
+        let Some(sleep) = sleep.as_mut() else {
+            return false;
+        };
+        // A driver may have a transfer in flight
+        #[cfg(feature = "clock-gate")]
+        if crate::clock_gate::deep_idle_vetoed() {
+            return false;
+        }
+        // A pending interrupt (e.g. a context switch requested just before) has to be handled first
+        if interrupt::status(Cpu::current())
+            .iterator()
+            .next()
+            .is_some()
+        {
+            return false;
+        }
+        let (Ok(now), Ok(Some(next_wakeup))) = (current_time(), next_wakeup()) else {
+            return false;
+        };
+        let Some(tick_freq) = *TICK_FREQ.borrow_ref(cs) else {
+            return false;
+        };
+        if sleep.rearmed_for == Some(next_wakeup) {
+            return false;
+        }
+        // The last tick before the wakeup is left to the tick timer
+        let mut ticks = next_wakeup.saturating_sub(now + 1);
+        if ticks >= sleep.config.long_wait_ticks {
+            ticks = ticks.saturating_sub(sleep.config.guard_ticks);
+            sleep.rearmed_for = Some(next_wakeup);
+        }
+        if ticks < sleep.config.min_ticks {
+            return false;
+        }
+
+        let mut timer = TIMER.borrow_ref_mut(cs);
+        let Some(timer) = timer.as_mut() else {
+            return false;
+        };
+        let _ = timer.cancel();
+
+        let start = sleep.rtc.time_since_boot().as_micros();
+        let duration = core::time::Duration::from_micros(ticks * 1_000_000 / tick_freq as u64);
+        sleep.rtc.sleep_light(&[&TimerWakeupSource::new(duration)]);
+        let slept = sleep
+            .rtc
+            .time_since_boot()
+            .as_micros()
+            .saturating_sub(start);
+
+        skip_ticks(slept * tick_freq as u64 / 1_000_000);
+        timer
+            .start(tick_period(tick_freq))
+            .expect("Failed to start the tick timer");
+
+        true
+    })
+}
@@ -0,0 +1,145 @@
+//! Physical Memory Protection (PMP) setup for user-mode tasks.
+//!
+//! PMP entries only restrict U-mode (unless locked), so the kernel and privileged tasks are not affected.
+//! A U-mode access which matches no entry is denied, therefore the application has to grant access to
+//! the code, read-only data, and any shared data used by user-mode tasks with [`set_user_region`].
+//!
+//! Entries are used in top-of-range (TOR) pairs:
+//! entries 0-13 hold up to [`NUM_USER_REGIONS`] regions shared by all user-mode tasks,
+//! and entries 14 and 15 are reprogrammed at every context switch to cover the stack of the next task.
+//! Only the standard PMP CSRs of RV32 are used.
+
+use core::{cell::RefCell, ops::Range};
+
+use critical_section::Mutex;
+use taskette::{Error, sync::interrupt_free};
+
+/// Number of regions which can be configured by [`set_user_region`]
+pub const NUM_USER_REGIONS: usize = 7;
+/// Maximum number of user-mode task stacks known at the same time
+const MAX_USER_STACKS: usize = 16;
+
+/// Permission to read a region
+pub const READ: u8 = 1 << 0;
+/// Permission to write a region
+pub const WRITE: u8 = 1 << 1;
+/// Permission to execute a region
+pub const EXECUTE: u8 = 1 << 2;
+
+/// Address matching mode of `pmpcfg` (top of range)
+const TOR: u8 = 1 << 3;
+/// Entry pair used for the stack of the running task
+const STACK_ENTRY: usize = 2 * NUM_USER_REGIONS;
+
+/// Stacks of the tasks spawned in U-mode. The entry of a stack is replaced when its memory is reused.
+static USER_STACKS: Mutex<RefCell<[Option<Range<usize>>; MAX_USER_STACKS]>> =
+    Mutex::new(RefCell::new([const { None }; MAX_USER_STACKS]));
+
+/// Grants user-mode tasks access to `range` with `permissions` (a combination of [`READ`], [`WRITE`], and [`EXECUTE`]).
+///
+/// `region` is an index smaller than [`NUM_USER_REGIONS`]. Both ends of `range` have to be aligned at 4 bytes.
+/// Passing `0` as `permissions` disables the region.
+pub fn set_user_region(region: usize, range: Range<usize>, permissions: u8) {
+    assert!(region < NUM_USER_REGIONS, "Invalid PMP region");
+    assert!(permissions & !(READ | WRITE | EXECUTE) == 0, "Invalid PMP permissions");
+
+    interrupt_free(|_| unsafe {
+        set_tor_region(2 * region, range, permissions);
+    });
+}
+
+/// Remembers the stack of a task being spawned in U-mode.
+pub(crate) fn register_user_stack(stack: Range<usize>) -> Result<(), Error> {
+    interrupt_free(|cs| {
+        let mut stacks = USER_STACKS.borrow_ref_mut(cs);
+        // A stack overlapping the new one belongs to a finished task
+        let slot = stacks
+            .iter()
+            .position(|entry| {
+                entry
+                    .as_ref()
+                    .is_some_and(|range| range.start < stack.end && stack.start < range.end)
+            })
+            .or_else(|| stacks.iter().position(Option::is_none))
+            .ok_or(Error::TaskFull)?;
+        stacks[slot] = Some(stack);
+
+        Ok(())
+    })
+}
+
+/// Called by the context switching code after changing to the stack of the next task.
+#[unsafe(no_mangle)]
+extern "C" fn taskette_pmp_switch() {
+    let stack_limit = taskette::scheduler::current_stack_limit();
+    let stack = interrupt_free(|cs| {
+        USER_STACKS
+            .borrow_ref(cs)
+            .iter()
+            .flatten()
+            .find(|range| range.contains(&stack_limit))
+            .cloned()
+    });
+
+    unsafe {
+        match stack {
+            Some(range) => set_tor_region(STACK_ENTRY, range, READ | WRITE),
+            // Privileged tasks are not restricted by PMP
+            None => set_tor_region(STACK_ENTRY, 0..0, 0),
+        }
+    }
+}
+
+/// Programs the entry pair starting from `entry` to cover `range`.
+unsafe fn set_tor_region(entry: usize, range: Range<usize>, permissions: u8) {
+    unsafe {
+        // Disable the region while changing the addresses
+        write_pmpcfg(entry + 1, 0);
+        // The lower entry only supplies the start address
+        write_pmpaddr(entry, range.start >> 2);
+        write_pmpaddr(entry + 1, range.end >> 2);
+        if permissions != 0 {
+            write_pmpcfg(entry + 1, TOR | permissions);
+        }
+    }
+}
+
+unsafe fn write_pmpaddr(entry: usize, value: usize) {
+    macro_rules! write_csr {
+        ($($index:literal),*) => {
+            match entry {
+                $($index => core::arch::asm!(concat!("csrw pmpaddr", $index, ", {}"), in(reg) value),)*
+                _ => unreachable!(),
+            }
+        };
+    }
+
+    unsafe {
+        write_csr!(0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15);
+    }
+}
+
+/// Replaces the configuration byte of `entry` (each `pmpcfg` register holds 4 entries on RV32).
+unsafe fn write_pmpcfg(entry: usize, cfg: u8) {
+    let shift = (entry % 4) * 8;
+    let mask = 0xFFusize << shift;
+    let bits = (cfg as usize) << shift;
+
+    macro_rules! write_csr {
+        ($($index:literal),*) => {
+            match entry / 4 {
+                $($index => core::arch::asm!(
+                    concat!("csrc pmpcfg", $index, ", {mask}"),
+                    concat!("csrs pmpcfg", $index, ", {bits}"),
+                    mask = in(reg) mask,
+                    bits = in(reg) bits,
+                ),)*
+                _ => unreachable!(),
+            }
+        };
+    }
+
+    unsafe {
+        write_csr!(0, 1, 2, 3);
+    }
+}
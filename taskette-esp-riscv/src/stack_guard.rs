@@ -0,0 +1,77 @@
+//! Hardware stack guard using the stack pointer monitor of the Debug Assist peripheral.
+//!
+//! The monitor raises an interrupt as soon as SP goes below the bottom of the running task's stack,
+//! so an overflow is caught even if the overflowing code skips over the canary (e.g. a large, sparsely written local array).
+//! PMP is not used because it does not restrict machine mode (in which tasks run) unless the entry is locked until reset.
+//!
+//! The monitor is stopped while the scheduler runs on the main stack, and restarted with the bounds of the next task.
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use esp_hal::{
+    assist_debug::DebugAssist,
+    handler,
+    interrupt::Priority,
+    peripherals::ASSIST_DEBUG,
+};
+use taskette::sync::interrupt_free;
+
+static DEBUG_ASSIST: Mutex<RefCell<Option<DebugAssist<'static>>>> = Mutex::new(RefCell::new(None));
+
+/// Enables the hardware stack guard.
+///
+/// Has to be called before starting the scheduler.
+pub fn enable_stack_guard(assist_debug: ASSIST_DEBUG<'static>) {
+    let mut debug_assist = DebugAssist::new(assist_debug);
+    debug_assist.set_interrupt_handler(stack_guard_handler);
+
+    interrupt_free(|cs| DEBUG_ASSIST.replace(cs, Some(debug_assist)));
+}
+
+/// Called by the context switching code before leaving the stack of the original task.
+#[unsafe(no_mangle)]
+extern "C" fn taskette_stack_guard_stop() {
+    interrupt_free(|cs| {
+        if let Some(debug_assist) = DEBUG_ASSIST.borrow_ref_mut(cs).as_mut() {
+            debug_assist.disable_sp_monitor();
+        }
+    });
+}
+
+/// Called by the context switching code after changing to the stack of the next task.
+#[unsafe(no_mangle)]
+extern "C" fn taskette_stack_guard_start() {
+    start();
+}
+
+/// Starts monitoring SP with the bounds of the running task.
+pub(crate) fn start() {
+    let stack_limit = taskette::scheduler::current_stack_limit();
+    interrupt_free(|cs| {
+        if let Some(debug_assist) = DEBUG_ASSIST.borrow_ref_mut(cs).as_mut() {
+            // Only the lower bound matters (stack top is not known here)
+            debug_assist.enable_sp_monitor(stack_limit as u32, u32::MAX);
+        }
+    });
+}
+
+#[handler(priority = Priority::max())]
+fn stack_guard_handler() {
+    let pc = interrupt_free(|cs| {
+        let mut debug_assist = DEBUG_ASSIST.borrow_ref_mut(cs);
+        let debug_assist = debug_assist.as_mut().unwrap_or_else(|| unreachable!());
+        debug_assist.disable_sp_monitor();
+        debug_assist.clear_sp_monitor_interrupt();
+        debug_assist.sp_monitor_pc()
+    });
+
+    let Ok(task) = taskette::task::current() else {
+        panic!("Stack overflow detected before the scheduler started (PC = {:08X})", pc);
+    };
+
+    // Panics unless a hook is registered by `taskette::scheduler::set_stack_overflow_hook`
+    taskette::scheduler::handle_stack_overflow(task.id());
+    // Switch away from the removed task
+    taskette::arch::yield_now();
+}
@@ -0,0 +1,270 @@
+//! System calls for user-mode tasks.
+//!
+//! A task spawned by [`spawn_user`] runs in U-mode, where it cannot touch the CSRs (e.g. to mask interrupts)
+//! and can only access the memory granted by PMP (its own stack and the regions set by [`crate::pmp::set_user_region`]).
+//! Therefore it cannot use the functions of `taskette` which enter a critical section or request a context switch,
+//! and has to enter the kernel through the functions of this module, which are implemented with the `ecall` instruction.
+//!
+//! Other exceptions raised in U-mode (e.g. PMP access faults) terminate just the faulting task
+//! if a hook is registered by [`taskette::scheduler::set_task_fault_hook`]. Otherwise they stop the system.
+//! Pointers passed to system calls are not validated.
+
+use esp_hal::trapframe::TrapFrame;
+use taskette::{
+    Error,
+    arch::{RawStack, StackAllocation},
+    futex::Futex,
+    portable_atomic::Ordering,
+    scheduler,
+    sync::interrupt_free,
+    task::{TaskConfig, TaskHandle},
+    timer,
+};
+
+use crate::{SPAWNING_USER, pmp};
+
+const SYS_YIELD: usize = 0;
+const SYS_EXIT: usize = 1;
+const SYS_SPAWN: usize = 2;
+const SYS_FUTEX_WAIT: usize = 3;
+const SYS_FUTEX_WAKE: usize = 4;
+const SYS_WAIT_UNTIL: usize = 5;
+const SYS_CURRENT_TIME: usize = 6;
+
+/// `mcause` of an environment call from U-mode
+const ECALL_FROM_U: usize = 8;
+
+/// Issues a system call with the number in A7 and arguments in A0-A3. Returns A0-A2 written by the kernel.
+macro_rules! syscall {
+    ($number:expr, $a0:expr, $a1:expr, $a2:expr, $a3:expr) => {{
+        let (a0, a1, a2): (usize, usize, usize);
+        unsafe {
+            core::arch::asm!(
+                "ecall",
+                in("a7") $number,
+                inlateout("a0") $a0 => a0,
+                inlateout("a1") $a1 => a1,
+                inlateout("a2") $a2 => a2,
+                in("a3") $a3,
+            );
+        }
+        (a0, a1, a2)
+    }};
+}
+
+/// Creates a new task running in U-mode.
+///
+/// The task can only use the functions of this module to interact with the kernel.
+/// Can be called before the scheduler starts or from a privileged task.
+pub fn spawn_user<F: FnOnce() + Send + 'static, S: StackAllocation>(
+    func: F,
+    stack: S,
+    config: TaskConfig,
+) -> Result<TaskHandle, Error> {
+    let mut stack = stack;
+    let range = stack.as_mut_slice().as_mut_ptr_range();
+    pmp::register_user_stack(range.start as usize..range.end as usize)?;
+
+    // The flag is read by `_taskette_init_stack` during `spawn`
+    interrupt_free(|_| {
+        SPAWNING_USER.store(true, Ordering::Relaxed);
+        let result = scheduler::spawn(
+            move || {
+                func();
+                // Returning from the task function would enter the scheduler directly
+                exit()
+            },
+            stack,
+            config,
+        );
+        SPAWNING_USER.store(false, Ordering::Relaxed);
+
+        result
+    })
+}
+
+/// Gives the CPU to other tasks.
+pub fn yield_now() {
+    syscall!(SYS_YIELD, 0usize, 0usize, 0usize, 0usize);
+}
+
+/// Finishes the current task.
+pub fn exit() -> ! {
+    syscall!(SYS_EXIT, 0usize, 0usize, 0usize, 0usize);
+
+    // The task is switched out as soon as the system call returns
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+/// Creates a new user-mode task with the specified priority. Returns the task ID.
+///
+/// The start and the end of `stack` are aligned at 16 bytes by discarding a few bytes if necessary.
+pub fn spawn(entry: fn(), stack: &'static mut [u8], priority: usize) -> Result<usize, Error> {
+    let range = stack.as_mut_ptr_range();
+    let (status, task_id, _) = syscall!(
+        SYS_SPAWN,
+        entry as usize,
+        range.start as usize,
+        range.end as usize,
+        priority
+    );
+    decode(status).map(|()| task_id)
+}
+
+/// Same as [`Futex::wait`].
+pub fn futex_wait(futex: &Futex, compare_val: usize) -> Result<(), Error> {
+    let (status, _, _) = syscall!(
+        SYS_FUTEX_WAIT,
+        futex as *const Futex as usize,
+        compare_val,
+        0usize,
+        0usize
+    );
+    decode(status)
+}
+
+/// Same as [`Futex::wake`].
+pub fn futex_wake(futex: &Futex, num: usize) -> Result<(), Error> {
+    let (status, _, _) = syscall!(
+        SYS_FUTEX_WAKE,
+        futex as *const Futex as usize,
+        num,
+        0usize,
+        0usize
+    );
+    decode(status)
+}
+
+/// Same as [`timer::wait_until`].
+pub fn wait_until(time: u64) -> Result<(), Error> {
+    let (status, _, _) = syscall!(
+        SYS_WAIT_UNTIL,
+        time as u32 as usize,
+        (time >> 32) as usize,
+        0usize,
+        0usize
+    );
+    decode(status)
+}
+
+/// Same as [`timer::current_time`].
+pub fn current_time() -> Result<u64, Error> {
+    let (status, low, high) = syscall!(SYS_CURRENT_TIME, 0usize, 0usize, 0usize, 0usize);
+    decode(status).map(|()| ((high as u64) << 32) | low as u32 as u64)
+}
+
+/// Replaces the default exception handler of `esp-hal`.
+///
+/// The registers in `frame` are written back when the handler returns, so they are used to pass the results.
+#[unsafe(export_name = "ExceptionHandler")]
+extern "C" fn exception_handler(frame: &mut TrapFrame) {
+    if frame.mcause == ECALL_FROM_U {
+        handle_syscall(frame);
+        // Return to the instruction after `ecall`
+        frame.pc += 4;
+        return;
+    }
+
+    let from_user = (frame.mstatus >> 11) & 3 == 0; // MPP
+    // Only the faulting task is terminated if a hook is set by `taskette::scheduler::set_task_fault_hook`
+    if !from_user || !scheduler::handle_task_fault() {
+        panic!(
+            "Exception {} at {:08X} (MTVAL = {:08X})",
+            frame.mcause, frame.pc, frame.mtval
+        );
+    }
+
+    // Spin in U-mode instead of re-executing the faulting instruction until the task is switched out
+    frame.pc = parked as usize;
+}
+
+/// Performs a system call requested by `ecall` in U-mode.
+fn handle_syscall(frame: &mut TrapFrame) {
+    match frame.a7 {
+        SYS_YIELD => taskette::arch::yield_now(),
+        SYS_EXIT => {
+            let _ = scheduler::exit_current_task();
+        }
+        SYS_SPAWN => {
+            let entry: fn() = unsafe { core::mem::transmute(frame.a0) };
+            // The memory is given up by the calling task
+            let stack = unsafe { RawStack::new(frame.a1..frame.a2) };
+            let config = TaskConfig::default().with_priority(frame.a3);
+            match spawn_user(entry, stack, config) {
+                Ok(handle) => {
+                    frame.a0 = 0;
+                    frame.a1 = handle.id();
+                }
+                Err(error) => frame.a0 = encode(Err(error)),
+            }
+        }
+        SYS_FUTEX_WAIT => {
+            let futex = unsafe { &*(frame.a0 as *const Futex) };
+            frame.a0 = encode(futex.wait(frame.a1));
+        }
+        SYS_FUTEX_WAKE => {
+            let futex = unsafe { &*(frame.a0 as *const Futex) };
+            frame.a0 = encode(futex.wake(frame.a1));
+        }
+        SYS_WAIT_UNTIL => {
+            let time = ((frame.a1 as u64) << 32) | frame.a0 as u32 as u64;
+            frame.a0 = encode(timer::wait_until(time));
+        }
+        SYS_CURRENT_TIME => match timer::current_time() {
+            Ok(time) => {
+                frame.a0 = 0;
+                frame.a1 = time as u32 as usize;
+                frame.a2 = (time >> 32) as usize;
+            }
+            Err(error) => frame.a0 = encode(Err(error)),
+        },
+        _ => (), // Unknown system calls are ignored
+    }
+}
+
+/// Where a task stopped by an exception waits for the context switch
+fn parked() -> ! {
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+fn encode(result: Result<(), Error>) -> usize {
+    match result {
+        Ok(()) => 0,
+        Err(Error::TaskFull) => 1,
+        Err(Error::InvalidPriority) => 2,
+        Err(Error::NotFound) => 3,
+        Err(Error::NotInitialized) => 4,
+        Err(Error::TimerFull) => 5,
+        Err(Error::InvalidAffinity) => 6,
+        Err(Error::OutOfMemory) => 7,
+        Err(Error::NotPermitted) => 8,
+        Err(Error::QueueFull) => 9,
+        Err(Error::StackTooSmall) => 10,
+        Err(Error::TimedOut) => 11,
+        Err(Error::WouldBlock) => 12,
+        Err(Error::Cancelled) => 13,
+    }
+}
+
+fn decode(status: usize) -> Result<(), Error> {
+    match status {
+        0 => Ok(()),
+        1 => Err(Error::TaskFull),
+        2 => Err(Error::InvalidPriority),
+        3 => Err(Error::NotFound),
+        4 => Err(Error::NotInitialized),
+        5 => Err(Error::TimerFull),
+        6 => Err(Error::InvalidAffinity),
+        8 => Err(Error::NotPermitted),
+        9 => Err(Error::QueueFull),
+        10 => Err(Error::StackTooSmall),
+        11 => Err(Error::TimedOut),
+        12 => Err(Error::WouldBlock),
+        13 => Err(Error::Cancelled),
+        _ => Err(Error::OutOfMemory),
+    }
+}
@@ -0,0 +1,26 @@
+/* sys_arch port of lwIP over taskette (see the `lwip` feature of the `taskette-ffi` crate) */
+#ifndef TASKETTE_LWIP_SYS_ARCH_H
+#define TASKETTE_LWIP_SYS_ARCH_H
+
+#include <stddef.h>
+
+/* Opaque objects taken from fixed pools */
+typedef struct taskette_lwip_sem *sys_sem_t;
+typedef struct taskette_mutex *sys_mutex_t;
+typedef struct taskette_lwip_mbox *sys_mbox_t;
+/* Task ID */
+typedef size_t sys_thread_t;
+/* Unused, as protection nests by counting */
+typedef int sys_prot_t;
+
+#define SYS_SEM_NULL NULL
+#define SYS_MBOX_NULL NULL
+
+#define sys_sem_valid(sem) (((sem) != NULL) && (*(sem) != NULL))
+#define sys_sem_set_invalid(sem) do { if ((sem) != NULL) { *(sem) = NULL; } } while (0)
+#define sys_mutex_valid(mutex) (((mutex) != NULL) && (*(mutex) != NULL))
+#define sys_mutex_set_invalid(mutex) do { if ((mutex) != NULL) { *(mutex) = NULL; } } while (0)
+#define sys_mbox_valid(mbox) (((mbox) != NULL) && (*(mbox) != NULL))
+#define sys_mbox_set_invalid(mbox) do { if ((mbox) != NULL) { *(mbox) = NULL; } } while (0)
+
+#endif /* TASKETTE_LWIP_SYS_ARCH_H */
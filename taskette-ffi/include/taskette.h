@@ -0,0 +1,73 @@
+/* C API of taskette (see the `taskette-ffi` crate) */
+#ifndef TASKETTE_H
+#define TASKETTE_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define TASKETTE_OK 0
+/* Errors of the kernel */
+#define TASKETTE_ERR_TASK_FULL (-1)
+#define TASKETTE_ERR_INVALID_PRIORITY (-2)
+#define TASKETTE_ERR_NOT_FOUND (-3)
+#define TASKETTE_ERR_NOT_INITIALIZED (-4)
+#define TASKETTE_ERR_TIMER_FULL (-5)
+#define TASKETTE_ERR_INVALID_AFFINITY (-6)
+#define TASKETTE_ERR_OUT_OF_MEMORY (-7)
+#define TASKETTE_ERR_NOT_PERMITTED (-8)
+#define TASKETTE_ERR_QUEUE_FULL (-9)
+#define TASKETTE_ERR_STACK_TOO_SMALL (-10)
+#define TASKETTE_ERR_TIMED_OUT (-11)
+#define TASKETTE_ERR_CANCELLED (-12)
+/* Errors of the C API */
+#define TASKETTE_ERR_INVALID_ARGUMENT (-100)
+#define TASKETTE_ERR_WOULD_BLOCK (-101)
+#define TASKETTE_ERR_DEADLOCK (-102)
+
+typedef struct taskette_task_config {
+    size_t priority;  /* Higher value means higher priority */
+    intptr_t affinity;  /* Core the task is pinned to, or -1 to allow any core */
+    const char *name;  /* Null-terminated name living forever, or NULL */
+} taskette_task_config_t;
+
+typedef void (*taskette_task_entry_t)(void *arg);
+
+/* Opaque objects taken from fixed pools */
+typedef struct taskette_mutex taskette_mutex_t;
+typedef struct taskette_queue taskette_queue_t;
+
+/* Tasks */
+int taskette_spawn(taskette_task_entry_t entry, void *arg, void *stack, size_t stack_size,
+                   const taskette_task_config_t *config, size_t *task_id);
+int taskette_current_task_id(size_t *task_id);
+void taskette_yield(void);
+
+/* Time (in ticks) */
+int taskette_sleep(uint64_t ticks);
+int taskette_sleep_until(uint64_t time);
+int taskette_current_time(uint64_t *time);
+
+/* Mutexes (non-recursive, owned by the locking task) */
+int taskette_mutex_create(taskette_mutex_t **mutex);
+int taskette_mutex_delete(taskette_mutex_t *mutex);
+int taskette_mutex_lock(taskette_mutex_t *mutex);
+int taskette_mutex_try_lock(taskette_mutex_t *mutex);
+int taskette_mutex_unlock(taskette_mutex_t *mutex);
+
+/* Queues of fixed-size items copied into `buffer` (item_size * capacity bytes) */
+int taskette_queue_create(void *buffer, size_t item_size, size_t capacity, taskette_queue_t **queue);
+int taskette_queue_delete(taskette_queue_t *queue);
+int taskette_queue_send(taskette_queue_t *queue, const void *item);
+int taskette_queue_try_send(taskette_queue_t *queue, const void *item);  /* Also from interrupt handlers */
+int taskette_queue_recv(taskette_queue_t *queue, void *item);
+int taskette_queue_try_recv(taskette_queue_t *queue, void *item);  /* Also from interrupt handlers */
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* TASKETTE_H */
@@ -0,0 +1,206 @@
+//! C API of Taskette.
+//!
+//! Tasks, sleeping, mutexes, and message queues are exposed as `extern "C"` functions declared in `include/taskette.h`,
+//! so that C code linked into the firmware can use them. The scheduler is initialized and started from Rust as usual.
+//!
+//! Every function returns [`TASKETTE_OK`] or a negative error code. Mutexes and queues are taken from fixed pools
+//! of [`mutex::MAX_MUTEXES`] and [`queue::MAX_QUEUES`] objects, and handed to C as opaque pointers.
+
+#![no_std]
+
+#[cfg(feature = "lwip")]
+pub mod lwip;
+pub mod mutex;
+pub mod queue;
+mod slots;
+
+use core::ffi::{CStr, c_char, c_int, c_void};
+
+use taskette::{
+    Error,
+    arch::RawStack,
+    scheduler::spawn,
+    task::{self, TaskConfig},
+    timer::{current_time, wait_until},
+};
+
+pub const TASKETTE_OK: c_int = 0;
+// Errors of the kernel (`taskette::Error`)
+pub const TASKETTE_ERR_TASK_FULL: c_int = -1;
+pub const TASKETTE_ERR_INVALID_PRIORITY: c_int = -2;
+pub const TASKETTE_ERR_NOT_FOUND: c_int = -3;
+pub const TASKETTE_ERR_NOT_INITIALIZED: c_int = -4;
+pub const TASKETTE_ERR_TIMER_FULL: c_int = -5;
+pub const TASKETTE_ERR_INVALID_AFFINITY: c_int = -6;
+pub const TASKETTE_ERR_OUT_OF_MEMORY: c_int = -7;
+pub const TASKETTE_ERR_NOT_PERMITTED: c_int = -8;
+pub const TASKETTE_ERR_QUEUE_FULL: c_int = -9;
+pub const TASKETTE_ERR_STACK_TOO_SMALL: c_int = -10;
+pub const TASKETTE_ERR_TIMED_OUT: c_int = -11;
+pub const TASKETTE_ERR_CANCELLED: c_int = -12;
+// Errors of the C API
+/// A pointer is null or a size is zero.
+pub const TASKETTE_ERR_INVALID_ARGUMENT: c_int = -100;
+/// A non-blocking operation would block (mutex locked, queue full or empty).
+pub const TASKETTE_ERR_WOULD_BLOCK: c_int = -101;
+/// The calling task already holds the mutex.
+pub const TASKETTE_ERR_DEADLOCK: c_int = -102;
+
+/// Parameters of a task created by [`taskette_spawn`] (`taskette_task_config_t`).
+#[repr(C)]
+#[derive(Clone, Debug)]
+pub struct TasketteTaskConfig {
+    /// Higher value means higher priority (see `TaskConfig::with_priority`)
+    pub priority: usize,
+    /// Core the task is pinned to, or -1 to allow any core
+    pub affinity: isize,
+    /// Null-terminated name living forever, or null
+    pub name: *const c_char,
+}
+
+/// Entry function of a task created by [`taskette_spawn`]
+pub type TasketteTaskEntry = extern "C" fn(arg: *mut c_void);
+
+fn error_code(error: Error) -> c_int {
+    match error {
+        Error::TaskFull => TASKETTE_ERR_TASK_FULL,
+        Error::InvalidPriority => TASKETTE_ERR_INVALID_PRIORITY,
+        Error::NotFound => TASKETTE_ERR_NOT_FOUND,
+        Error::NotInitialized => TASKETTE_ERR_NOT_INITIALIZED,
+        Error::TimerFull => TASKETTE_ERR_TIMER_FULL,
+        Error::InvalidAffinity => TASKETTE_ERR_INVALID_AFFINITY,
+        Error::OutOfMemory => TASKETTE_ERR_OUT_OF_MEMORY,
+        Error::NotPermitted => TASKETTE_ERR_NOT_PERMITTED,
+        Error::QueueFull => TASKETTE_ERR_QUEUE_FULL,
+        Error::StackTooSmall => TASKETTE_ERR_STACK_TOO_SMALL,
+        Error::TimedOut => TASKETTE_ERR_TIMED_OUT,
+        Error::WouldBlock => TASKETTE_ERR_WOULD_BLOCK,
+        Error::Cancelled => TASKETTE_ERR_CANCELLED,
+    }
+}
+
+fn result_code(result: Result<(), Error>) -> c_int {
+    match result {
+        Ok(()) => TASKETTE_OK,
+        Err(error) => error_code(error),
+    }
+}
+
+/// Argument of a task entry, which is up to the C code to share safely
+struct TaskArg(*mut c_void);
+
+unsafe impl Send for TaskArg {}
+
+/// Creates a task running `entry(arg)` on `stack` (`stack_size` bytes, kept alive while the task exists).
+///
+/// `config` may be null for the default configuration. The ID of the task is stored in `task_id` unless it is null.
+///
+/// # Safety
+/// `stack` must be valid for writes of `stack_size` bytes and not used by anything else,
+/// and `config` and `task_id` must be valid or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn taskette_spawn(
+    entry: Option<TasketteTaskEntry>,
+    arg: *mut c_void,
+    stack: *mut c_void,
+    stack_size: usize,
+    config: *const TasketteTaskConfig,
+    task_id: *mut usize,
+) -> c_int {
+    let Some(entry) = entry else {
+        return TASKETTE_ERR_INVALID_ARGUMENT;
+    };
+    if stack.is_null() || stack_size == 0 {
+        return TASKETTE_ERR_INVALID_ARGUMENT;
+    }
+
+    let mut task_config = TaskConfig::default();
+    if let Some(config) = unsafe { config.as_ref() } {
+        task_config = task_config.with_priority(config.priority);
+        if let Ok(core_id) = usize::try_from(config.affinity) {
+            task_config = task_config.with_affinity(core_id);
+        }
+        if !config.name.is_null() {
+            // Names which are not UTF-8 are ignored
+            if let Ok(name) = unsafe { CStr::from_ptr(config.name) }.to_str() {
+                task_config = task_config.with_name(name);
+            }
+        }
+    }
+
+    let arg = TaskArg(arg);
+    // Valid and unused while the task exists (see Safety)
+    let stack = unsafe { RawStack::new(stack as usize..stack as usize + stack_size) };
+    let result = spawn(
+        move || {
+            let arg = arg;
+            entry(arg.0)
+        },
+        stack,
+        task_config,
+    );
+
+    match result {
+        Ok(handle) => {
+            if let Some(task_id) = unsafe { task_id.as_mut() } {
+                *task_id = handle.id();
+            }
+            TASKETTE_OK
+        }
+        Err(error) => error_code(error),
+    }
+}
+
+/// Stores the ID of the calling task in `task_id`.
+///
+/// # Safety
+/// `task_id` must be valid or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn taskette_current_task_id(task_id: *mut usize) -> c_int {
+    let Some(task_id) = (unsafe { task_id.as_mut() }) else {
+        return TASKETTE_ERR_INVALID_ARGUMENT;
+    };
+    match task::current() {
+        Ok(handle) => {
+            *task_id = handle.id();
+            TASKETTE_OK
+        }
+        Err(error) => error_code(error),
+    }
+}
+
+/// Yields the CPU to another task of the same priority.
+#[unsafe(no_mangle)]
+pub extern "C" fn taskette_yield() {
+    taskette::arch::yield_now();
+}
+
+/// Blocks the calling task for `ticks` ticks.
+#[unsafe(no_mangle)]
+pub extern "C" fn taskette_sleep(ticks: u64) -> c_int {
+    result_code(current_time().and_then(|now| wait_until(now.saturating_add(ticks))))
+}
+
+/// Blocks the calling task until the time reaches `time` (in ticks).
+#[unsafe(no_mangle)]
+pub extern "C" fn taskette_sleep_until(time: u64) -> c_int {
+    result_code(wait_until(time))
+}
+
+/// Stores the current time (in ticks) in `time`.
+///
+/// # Safety
+/// `time` must be valid or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn taskette_current_time(time: *mut u64) -> c_int {
+    let Some(time) = (unsafe { time.as_mut() }) else {
+        return TASKETTE_ERR_INVALID_ARGUMENT;
+    };
+    match current_time() {
+        Ok(now) => {
+            *time = now;
+            TASKETTE_OK
+        }
+        Err(error) => error_code(error),
+    }
+}
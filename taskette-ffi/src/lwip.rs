@@ -0,0 +1,543 @@
+//! `sys_arch` port of lwIP (`lwip` feature), so that the `tcpip` thread of lwIP runs as a taskette task.
+//!
+//! The types of the port are defined in `include/arch/sys_arch.h`, which lwIP includes as `arch/sys_arch.h`
+//! (the application still provides `arch/cc.h` and `lwipopts.h`). With `NO_SYS = 0`, this module implements
+//! semaphores, mutexes, mailboxes, thread creation, `sys_now`, and the lightweight protection of `SYS_LIGHTWEIGHT_PROT`.
+//!
+//! Semaphores and mailboxes are taken from fixed pools of [`MAX_SEMS`] and [`MAX_MBOXES`] objects,
+//! and mutexes from the pool of `taskette_mutex_*`. Stacks of threads are allocated from the heap.
+
+use core::{
+    cell::UnsafeCell,
+    ffi::{CStr, c_char, c_int, c_void},
+    sync::atomic::Ordering,
+};
+
+use critical_section::RestoreState;
+use taskette::{
+    Error,
+    futex::Futex,
+    scheduler::{get_config, spawn_heap},
+    sync::SpinLock,
+    task::TaskConfig,
+    timer::{current_time, current_time_micros},
+};
+
+use crate::{
+    TASKETTE_OK,
+    mutex::{
+        TasketteMutex, taskette_mutex_create, taskette_mutex_delete, taskette_mutex_lock,
+        taskette_mutex_unlock,
+    },
+    slots::Slots,
+};
+
+/// `err_t` of lwIP
+pub type ErrT = i8;
+pub const ERR_OK: ErrT = 0;
+pub const ERR_MEM: ErrT = -1;
+pub const ERR_ARG: ErrT = -16;
+
+/// Returned by `sys_arch_sem_wait` and `sys_arch_mbox_fetch` on timeout
+pub const SYS_ARCH_TIMEOUT: u32 = u32::MAX;
+/// Returned by `sys_arch_mbox_tryfetch` if the mailbox is empty
+pub const SYS_MBOX_EMPTY: u32 = SYS_ARCH_TIMEOUT;
+
+/// Maximum number of semaphores existing at the same time
+pub const MAX_SEMS: usize = 32;
+/// Maximum number of mailboxes existing at the same time
+pub const MAX_MBOXES: usize = 16;
+/// Maximum number of messages in a mailbox (also used if lwIP asks for size 0)
+pub const MBOX_CAPACITY: usize = 32;
+
+/// Counting semaphore (`sys_sem_t` points to it), whose value is the count.
+pub struct LwipSem {
+    count: Futex,
+}
+
+static SEMS: Slots<LwipSem, MAX_SEMS> = Slots::new(
+    [const {
+        LwipSem {
+            count: Futex::new(0),
+        }
+    }; MAX_SEMS],
+);
+
+/// Bounded queue of pointers (`sys_mbox_t` points to it).
+pub struct LwipMbox {
+    ring: SpinLock<Ring>,
+    /// Incremented on every post (fetchers wait on this)
+    posted: Futex,
+    /// Incremented on every fetch (posters wait on this)
+    fetched: Futex,
+}
+
+struct Ring {
+    messages: [*mut c_void; MBOX_CAPACITY],
+    capacity: usize,
+    /// Index of the oldest message
+    head: usize,
+    len: usize,
+}
+
+// Messages are only passed through, and the C code is responsible for sharing them
+unsafe impl Send for Ring {}
+
+static MBOXES: Slots<LwipMbox, MAX_MBOXES> = Slots::new(
+    [const {
+        LwipMbox {
+            ring: SpinLock::new(Ring {
+                messages: [core::ptr::null_mut(); MBOX_CAPACITY],
+                capacity: 0,
+                head: 0,
+                len: 0,
+            }),
+            posted: Futex::new(0),
+            fetched: Futex::new(0),
+        }
+    }; MAX_MBOXES],
+);
+
+/// Entry function of a thread (`lwip_thread_fn`)
+pub type LwipThreadFn = extern "C" fn(arg: *mut c_void);
+
+/// Argument of a thread entry, which is up to the C code to share safely
+struct ThreadArg(*mut c_void);
+
+unsafe impl Send for ThreadArg {}
+
+/// Returns the time in ticks `timeout_ms` after now, or `None` for 0 (no timeout).
+fn deadline(timeout_ms: u32) -> Result<Option<u64>, Error> {
+    if timeout_ms == 0 {
+        return Ok(None);
+    }
+    let tick_freq = get_config()?.tick_freq as u64;
+    let ticks = (timeout_ms as u64 * tick_freq).div_ceil(1000);
+    Ok(Some(current_time()?.saturating_add(ticks)))
+}
+
+/// Blocks while `futex` equals `value`, until `deadline` if any. Returns `false` on timeout.
+fn wait(futex: &Futex, value: usize, deadline: Option<u64>) -> Result<bool, Error> {
+    match deadline {
+        Some(time) => futex.wait_until(value, time),
+        None => futex.wait(value).map(|_| true),
+    }
+}
+
+/// Milliseconds elapsed since `start_us` (in microseconds), as returned by blocking functions.
+fn elapsed_ms(start_us: u64) -> u32 {
+    let now_us = current_time_micros().unwrap_or(start_us);
+    ((now_us - start_us) / 1000).min(SYS_ARCH_TIMEOUT as u64 - 1) as u32
+}
+
+/// Called by lwIP before anything else. Nothing to do, as the scheduler is initialized from Rust.
+#[unsafe(no_mangle)]
+pub extern "C" fn sys_init() {}
+
+/// Returns the current time in milliseconds, wrapping around.
+#[unsafe(no_mangle)]
+pub extern "C" fn sys_now() -> u32 {
+    (current_time_micros().unwrap_or(0) / 1000) as u32
+}
+
+/// Creates a semaphore with an initial count of `count` and stores it in `sem`.
+///
+/// # Safety
+/// `sem` must be valid or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sys_sem_new(sem: *mut *mut LwipSem, count: u8) -> ErrT {
+    let Some(sem) = (unsafe { sem.as_mut() }) else {
+        return ERR_ARG;
+    };
+    let Some(object) = SEMS.claim() else {
+        return ERR_MEM;
+    };
+
+    object
+        .count
+        .as_ref()
+        .store(count as usize, Ordering::SeqCst);
+    *sem = object as *const LwipSem as *mut LwipSem;
+    ERR_OK
+}
+
+/// Deletes a semaphore. No thread may be waiting on it.
+///
+/// # Safety
+/// `sem` must be valid or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sys_sem_free(sem: *mut *mut LwipSem) {
+    if let Some(object) = unsafe { sem.as_ref() }.and_then(|sem| SEMS.get(*sem)) {
+        SEMS.release(object);
+    }
+}
+
+/// Increments the count and wakes up a waiting thread.
+///
+/// # Safety
+/// `sem` must be valid or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sys_sem_signal(sem: *mut *mut LwipSem) {
+    let Some(object) = unsafe { sem.as_ref() }.and_then(|sem| SEMS.get(*sem)) else {
+        return;
+    };
+
+    object.count.as_ref().fetch_add(1, Ordering::SeqCst);
+    // Waking up cannot fail after the scheduler is initialized
+    let _ = object.count.wake_one();
+}
+
+/// Decrements the count, blocking while it is zero for at most `timeout` ms (forever if 0).
+///
+/// Returns the milliseconds spent waiting, or [`SYS_ARCH_TIMEOUT`].
+///
+/// # Safety
+/// `sem` must be valid or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sys_arch_sem_wait(sem: *mut *mut LwipSem, timeout: u32) -> u32 {
+    let Some(object) = unsafe { sem.as_ref() }.and_then(|sem| SEMS.get(*sem)) else {
+        return SYS_ARCH_TIMEOUT;
+    };
+    let Ok(start_us) = current_time_micros() else {
+        return SYS_ARCH_TIMEOUT;
+    };
+    let Ok(deadline) = deadline(timeout) else {
+        return SYS_ARCH_TIMEOUT;
+    };
+
+    let count = object.count.as_ref();
+    loop {
+        let value = count.load(Ordering::SeqCst);
+        if value > 0 {
+            if count
+                .compare_exchange(value, value - 1, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+            {
+                return elapsed_ms(start_us);
+            }
+            continue;
+        }
+
+        match wait(&object.count, 0, deadline) {
+            Ok(true) => (),
+            Ok(false) | Err(_) => return SYS_ARCH_TIMEOUT,
+        }
+    }
+}
+
+/// Creates an unlocked mutex and stores it in `mutex`.
+///
+/// # Safety
+/// `mutex` must be valid or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sys_mutex_new(mutex: *mut *mut TasketteMutex) -> ErrT {
+    match unsafe { taskette_mutex_create(mutex) } {
+        TASKETTE_OK => ERR_OK,
+        _ => ERR_MEM,
+    }
+}
+
+/// Deletes an unlocked mutex.
+///
+/// # Safety
+/// `mutex` must be valid or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sys_mutex_free(mutex: *mut *mut TasketteMutex) {
+    if let Some(mutex) = unsafe { mutex.as_ref() } {
+        taskette_mutex_delete(*mutex);
+    }
+}
+
+/// Locks the mutex, blocking while another thread holds it.
+///
+/// # Safety
+/// `mutex` must be valid or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sys_mutex_lock(mutex: *mut *mut TasketteMutex) {
+    if let Some(mutex) = unsafe { mutex.as_ref() } {
+        taskette_mutex_lock(*mutex);
+    }
+}
+
+/// Unlocks the mutex held by the calling thread.
+///
+/// # Safety
+/// `mutex` must be valid or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sys_mutex_unlock(mutex: *mut *mut TasketteMutex) {
+    if let Some(mutex) = unsafe { mutex.as_ref() } {
+        taskette_mutex_unlock(*mutex);
+    }
+}
+
+/// Creates an empty mailbox of `size` messages ([`MBOX_CAPACITY`] if 0) and stores it in `mbox`.
+///
+/// # Safety
+/// `mbox` must be valid or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sys_mbox_new(mbox: *mut *mut LwipMbox, size: c_int) -> ErrT {
+    let Some(mbox) = (unsafe { mbox.as_mut() }) else {
+        return ERR_ARG;
+    };
+    let capacity = match usize::try_from(size) {
+        Ok(0) => MBOX_CAPACITY,
+        Ok(size) if size <= MBOX_CAPACITY => size,
+        _ => return ERR_MEM,
+    };
+    let Some(object) = MBOXES.claim() else {
+        return ERR_MEM;
+    };
+
+    let mut ring = object.ring.lock_irq();
+    ring.capacity = capacity;
+    ring.head = 0;
+    ring.len = 0;
+    drop(ring);
+    *mbox = object as *const LwipMbox as *mut LwipMbox;
+    ERR_OK
+}
+
+/// Deletes a mailbox. No thread may be waiting on it.
+///
+/// # Safety
+/// `mbox` must be valid or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sys_mbox_free(mbox: *mut *mut LwipMbox) {
+    if let Some(object) = unsafe { mbox.as_ref() }.and_then(|mbox| MBOXES.get(*mbox)) {
+        MBOXES.release(object);
+    }
+}
+
+/// Posts `msg`, blocking while the mailbox is full.
+///
+/// # Safety
+/// `mbox` must be valid or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sys_mbox_post(mbox: *mut *mut LwipMbox, msg: *mut c_void) {
+    let Some(object) = unsafe { mbox.as_ref() }.and_then(|mbox| MBOXES.get(*mbox)) else {
+        return;
+    };
+
+    loop {
+        // The counter is read before the attempt, so that a fetch in between makes `wait` return immediately
+        let fetched = object.fetched.as_ref().load(Ordering::SeqCst);
+        if try_post(object, msg) {
+            return;
+        }
+
+        if object.fetched.wait(fetched).is_err() {
+            return;
+        }
+    }
+}
+
+/// Posts `msg` if the mailbox is not full.
+///
+/// # Safety
+/// `mbox` must be valid or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sys_mbox_trypost(mbox: *mut *mut LwipMbox, msg: *mut c_void) -> ErrT {
+    let Some(object) = unsafe { mbox.as_ref() }.and_then(|mbox| MBOXES.get(*mbox)) else {
+        return ERR_ARG;
+    };
+
+    if try_post(object, msg) {
+        ERR_OK
+    } else {
+        ERR_MEM
+    }
+}
+
+/// Same as [`sys_mbox_trypost`], for interrupt handlers.
+///
+/// # Safety
+/// `mbox` must be valid or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sys_mbox_trypost_fromisr(
+    mbox: *mut *mut LwipMbox,
+    msg: *mut c_void,
+) -> ErrT {
+    unsafe { sys_mbox_trypost(mbox, msg) }
+}
+
+/// Takes the oldest message into `msg` (unless null), blocking while the mailbox is empty
+/// for at most `timeout` ms (forever if 0).
+///
+/// Returns the milliseconds spent waiting, or [`SYS_ARCH_TIMEOUT`].
+///
+/// # Safety
+/// `mbox` and `msg` must be valid or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sys_arch_mbox_fetch(
+    mbox: *mut *mut LwipMbox,
+    msg: *mut *mut c_void,
+    timeout: u32,
+) -> u32 {
+    let Some(object) = unsafe { mbox.as_ref() }.and_then(|mbox| MBOXES.get(*mbox)) else {
+        return SYS_ARCH_TIMEOUT;
+    };
+    let Ok(start_us) = current_time_micros() else {
+        return SYS_ARCH_TIMEOUT;
+    };
+    let Ok(deadline) = deadline(timeout) else {
+        return SYS_ARCH_TIMEOUT;
+    };
+
+    loop {
+        let posted = object.posted.as_ref().load(Ordering::SeqCst);
+        if let Some(message) = try_fetch(object) {
+            if let Some(msg) = unsafe { msg.as_mut() } {
+                *msg = message;
+            }
+            return elapsed_ms(start_us);
+        }
+
+        match wait(&object.posted, posted, deadline) {
+            Ok(true) => (),
+            Ok(false) | Err(_) => return SYS_ARCH_TIMEOUT,
+        }
+    }
+}
+
+/// Takes the oldest message into `msg` (unless null) if the mailbox is not empty.
+///
+/// Returns 0, or [`SYS_MBOX_EMPTY`].
+///
+/// # Safety
+/// `mbox` and `msg` must be valid or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sys_arch_mbox_tryfetch(
+    mbox: *mut *mut LwipMbox,
+    msg: *mut *mut c_void,
+) -> u32 {
+    let Some(object) = unsafe { mbox.as_ref() }.and_then(|mbox| MBOXES.get(*mbox)) else {
+        return SYS_MBOX_EMPTY;
+    };
+
+    match try_fetch(object) {
+        Some(message) => {
+            if let Some(msg) = unsafe { msg.as_mut() } {
+                *msg = message;
+            }
+            0
+        }
+        None => SYS_MBOX_EMPTY,
+    }
+}
+
+fn try_post(mbox: &LwipMbox, msg: *mut c_void) -> bool {
+    {
+        let mut ring = mbox.ring.lock_irq();
+        if ring.len == ring.capacity {
+            return false;
+        }
+        let index = (ring.head + ring.len) % ring.capacity;
+        ring.messages[index] = msg;
+        ring.len += 1;
+    }
+
+    mbox.posted.as_ref().fetch_add(1, Ordering::SeqCst);
+    let _ = mbox.posted.wake_one();
+    true
+}
+
+fn try_fetch(mbox: &LwipMbox) -> Option<*mut c_void> {
+    let message = {
+        let mut ring = mbox.ring.lock_irq();
+        if ring.len == 0 {
+            return None;
+        }
+        let message = ring.messages[ring.head];
+        ring.head = (ring.head + 1) % ring.capacity;
+        ring.len -= 1;
+        message
+    };
+
+    mbox.fetched.as_ref().fetch_add(1, Ordering::SeqCst);
+    let _ = mbox.fetched.wake_one();
+    Some(message)
+}
+
+/// Creates a thread running `thread(arg)` with a heap-allocated stack of `stacksize` bytes,
+/// and the taskette priority `prio`. Returns its task ID.
+///
+/// `name` has to live forever (lwIP passes string literals). Aborts if the task cannot be created,
+/// as lwIP has no way to handle it.
+///
+/// # Safety
+/// `name` must be a valid null-terminated string or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sys_thread_new(
+    name: *const c_char,
+    thread: Option<LwipThreadFn>,
+    arg: *mut c_void,
+    stacksize: c_int,
+    prio: c_int,
+) -> usize {
+    let thread = thread.expect("sys_thread_new: null thread function");
+    let mut config = TaskConfig::default().with_priority(prio.max(0) as usize);
+    if !name.is_null() {
+        // Names which are not UTF-8 are ignored
+        if let Ok(name) = unsafe { CStr::from_ptr(name) }.to_str() {
+            config = config.with_name(name);
+        }
+    }
+
+    let arg = ThreadArg(arg);
+    spawn_heap(
+        move || {
+            let arg = arg;
+            thread(arg.0)
+        },
+        stacksize.max(0) as usize,
+        config,
+    )
+    .expect("sys_thread_new: cannot create a task")
+    .id()
+}
+
+/// Nesting depth and saved state of `sys_arch_protect`
+struct Protection {
+    depth: UnsafeCell<usize>,
+    restore_state: UnsafeCell<RestoreState>,
+}
+
+// Only accessed inside the critical section
+unsafe impl Sync for Protection {}
+
+static PROTECTION: Protection = Protection {
+    depth: UnsafeCell::new(0),
+    restore_state: UnsafeCell::new(RestoreState::invalid()),
+};
+
+/// Enters a critical section (`SYS_ARCH_PROTECT`), which may be nested.
+#[unsafe(no_mangle)]
+pub extern "C" fn sys_arch_protect() -> c_int {
+    // SAFETY: released by the matching `sys_arch_unprotect`
+    let restore_state = unsafe { critical_section::acquire() };
+    // SAFETY: in the critical section
+    unsafe {
+        let depth = &mut *PROTECTION.depth.get();
+        if *depth == 0 {
+            *PROTECTION.restore_state.get() = restore_state;
+        } else {
+            // Nested: the outermost level restores the state
+            critical_section::release(restore_state);
+        }
+        *depth += 1;
+    }
+    0
+}
+
+/// Leaves a critical section entered by [`sys_arch_protect`] (`SYS_ARCH_UNPROTECT`).
+#[unsafe(no_mangle)]
+pub extern "C" fn sys_arch_unprotect(_pval: c_int) {
+    // SAFETY: in the critical section
+    unsafe {
+        let depth = &mut *PROTECTION.depth.get();
+        *depth -= 1;
+        if *depth == 0 {
+            critical_section::release(*PROTECTION.restore_state.get());
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! Mutexes (`taskette_mutex_*`).
+
+use core::{ffi::c_int, sync::atomic::Ordering};
+
+use taskette::{futex::Futex, task};
+
+use crate::{
+    TASKETTE_ERR_DEADLOCK, TASKETTE_ERR_INVALID_ARGUMENT, TASKETTE_ERR_NOT_PERMITTED,
+    TASKETTE_ERR_OUT_OF_MEMORY, TASKETTE_ERR_WOULD_BLOCK, TASKETTE_OK, error_code, slots::Slots,
+};
+
+/// Maximum number of mutexes existing at the same time
+pub const MAX_MUTEXES: usize = 16;
+
+/// Non-recursive mutex owned by a task (`taskette_mutex_t`), whose waiters block on a futex.
+pub struct TasketteMutex {
+    /// 0 while unlocked, otherwise the ID of the owner task plus one
+    state: Futex,
+}
+
+static MUTEXES: Slots<TasketteMutex, MAX_MUTEXES> = Slots::new(
+    [const {
+        TasketteMutex {
+            state: Futex::new(0),
+        }
+    }; MAX_MUTEXES],
+);
+
+/// State value of the calling task holding the mutex
+fn owner_state() -> Result<usize, c_int> {
+    task::current()
+        .map(|handle| handle.id() + 1)
+        .map_err(error_code)
+}
+
+/// Creates an unlocked mutex and stores it in `mutex`.
+///
+/// # Safety
+/// `mutex` must be valid or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn taskette_mutex_create(mutex: *mut *mut TasketteMutex) -> c_int {
+    let Some(mutex) = (unsafe { mutex.as_mut() }) else {
+        return TASKETTE_ERR_INVALID_ARGUMENT;
+    };
+    let Some(object) = MUTEXES.claim() else {
+        return TASKETTE_ERR_OUT_OF_MEMORY;
+    };
+
+    object.state.as_ref().store(0, Ordering::SeqCst);
+    *mutex = object as *const TasketteMutex as *mut TasketteMutex;
+    TASKETTE_OK
+}
+
+/// Deletes an unlocked mutex.
+#[unsafe(no_mangle)]
+pub extern "C" fn taskette_mutex_delete(mutex: *mut TasketteMutex) -> c_int {
+    let Some(object) = MUTEXES.get(mutex) else {
+        return TASKETTE_ERR_INVALID_ARGUMENT;
+    };
+    if object.state.as_ref().load(Ordering::SeqCst) != 0 {
+        return TASKETTE_ERR_NOT_PERMITTED;
+    }
+
+    MUTEXES.release(object);
+    TASKETTE_OK
+}
+
+/// Locks the mutex, blocking the calling task while another task holds it.
+#[unsafe(no_mangle)]
+pub extern "C" fn taskette_mutex_lock(mutex: *mut TasketteMutex) -> c_int {
+    let Some(object) = MUTEXES.get(mutex) else {
+        return TASKETTE_ERR_INVALID_ARGUMENT;
+    };
+    let me = match owner_state() {
+        Ok(me) => me,
+        Err(code) => return code,
+    };
+
+    loop {
+        match object
+            .state
+            .as_ref()
+            .compare_exchange(0, me, Ordering::SeqCst, Ordering::SeqCst)
+        {
+            Ok(_) => return TASKETTE_OK,
+            Err(owner) if owner == me => return TASKETTE_ERR_DEADLOCK,
+            // Returns immediately if the owner has changed in between
+            Err(owner) => {
+                if let Err(error) = object.state.wait(owner) {
+                    return error_code(error);
+                }
+            }
+        }
+    }
+}
+
+/// Locks the mutex if no task holds it.
+#[unsafe(no_mangle)]
+pub extern "C" fn taskette_mutex_try_lock(mutex: *mut TasketteMutex) -> c_int {
+    let Some(object) = MUTEXES.get(mutex) else {
+        return TASKETTE_ERR_INVALID_ARGUMENT;
+    };
+    let me = match owner_state() {
+        Ok(me) => me,
+        Err(code) => return code,
+    };
+
+    match object
+        .state
+        .as_ref()
+        .compare_exchange(0, me, Ordering::SeqCst, Ordering::SeqCst)
+    {
+        Ok(_) => TASKETTE_OK,
+        Err(owner) if owner == me => TASKETTE_ERR_DEADLOCK,
+        Err(_) => TASKETTE_ERR_WOULD_BLOCK,
+    }
+}
+
+/// Unlocks the mutex held by the calling task and wakes up a waiting task.
+#[unsafe(no_mangle)]
+pub extern "C" fn taskette_mutex_unlock(mutex: *mut TasketteMutex) -> c_int {
+    let Some(object) = MUTEXES.get(mutex) else {
+        return TASKETTE_ERR_INVALID_ARGUMENT;
+    };
+    let me = match owner_state() {
+        Ok(me) => me,
+        Err(code) => return code,
+    };
+
+    if object
+        .state
+        .as_ref()
+        .compare_exchange(me, 0, Ordering::SeqCst, Ordering::SeqCst)
+        .is_err()
+    {
+        return TASKETTE_ERR_NOT_PERMITTED;
+    }
+    match object.state.wake_one() {
+        Ok(()) => TASKETTE_OK,
+        Err(error) => error_code(error),
+    }
+}
@@ -0,0 +1,251 @@
+//! Message queues (`taskette_queue_*`), which copy fixed-size items into a buffer supplied by C.
+
+use core::{
+    ffi::{c_int, c_void},
+    sync::atomic::Ordering,
+};
+
+use taskette::{futex::Futex, sync::SpinLock};
+
+use crate::{
+    TASKETTE_ERR_INVALID_ARGUMENT, TASKETTE_ERR_OUT_OF_MEMORY, TASKETTE_ERR_WOULD_BLOCK,
+    TASKETTE_OK, error_code, slots::Slots,
+};
+
+/// Maximum number of queues existing at the same time
+pub const MAX_QUEUES: usize = 8;
+
+/// Bounded multi-producer multi-consumer queue of fixed-size items (`taskette_queue_t`).
+///
+/// Works like `taskette::sync::Channel`. `taskette_queue_try_send` and `taskette_queue_try_recv` can also be used in interrupt handlers.
+pub struct TasketteQueue {
+    ring: SpinLock<Ring>,
+    /// Incremented on every send (receivers wait on this)
+    sent: Futex,
+    /// Incremented on every receive (senders wait on this)
+    received: Futex,
+}
+
+/// Ring buffer in the memory supplied by C
+struct Ring {
+    buffer: *mut u8,
+    item_size: usize,
+    capacity: usize,
+    /// Index of the oldest item
+    head: usize,
+    len: usize,
+}
+
+// The buffer is only accessed under the lock
+unsafe impl Send for Ring {}
+
+impl Ring {
+    /// # Safety
+    /// `item` must be valid for reads of `item_size` bytes.
+    unsafe fn push(&mut self, item: *const u8) -> bool {
+        if self.len == self.capacity {
+            return false;
+        }
+        let index = (self.head + self.len) % self.capacity;
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                item,
+                self.buffer.add(index * self.item_size),
+                self.item_size,
+            );
+        }
+        self.len += 1;
+        true
+    }
+
+    /// # Safety
+    /// `item` must be valid for writes of `item_size` bytes.
+    unsafe fn pop(&mut self, item: *mut u8) -> bool {
+        if self.len == 0 {
+            return false;
+        }
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                self.buffer.add(self.head * self.item_size),
+                item,
+                self.item_size,
+            );
+        }
+        self.head = (self.head + 1) % self.capacity;
+        self.len -= 1;
+        true
+    }
+}
+
+static QUEUES: Slots<TasketteQueue, MAX_QUEUES> = Slots::new(
+    [const {
+        TasketteQueue {
+            ring: SpinLock::new(Ring {
+                buffer: core::ptr::null_mut(),
+                item_size: 0,
+                capacity: 0,
+                head: 0,
+                len: 0,
+            }),
+            sent: Futex::new(0),
+            received: Futex::new(0),
+        }
+    }; MAX_QUEUES],
+);
+
+/// Creates an empty queue of `capacity` items of `item_size` bytes in `buffer`, and stores it in `queue`.
+///
+/// # Safety
+/// `buffer` must be valid for reads and writes of `item_size * capacity` bytes until the queue is deleted,
+/// and `queue` must be valid or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn taskette_queue_create(
+    buffer: *mut c_void,
+    item_size: usize,
+    capacity: usize,
+    queue: *mut *mut TasketteQueue,
+) -> c_int {
+    let Some(queue) = (unsafe { queue.as_mut() }) else {
+        return TASKETTE_ERR_INVALID_ARGUMENT;
+    };
+    if buffer.is_null() || item_size == 0 || capacity == 0 {
+        return TASKETTE_ERR_INVALID_ARGUMENT;
+    }
+    let Some(object) = QUEUES.claim() else {
+        return TASKETTE_ERR_OUT_OF_MEMORY;
+    };
+
+    *object.ring.lock_irq() = Ring {
+        buffer: buffer as *mut u8,
+        item_size,
+        capacity,
+        head: 0,
+        len: 0,
+    };
+    *queue = object as *const TasketteQueue as *mut TasketteQueue;
+    TASKETTE_OK
+}
+
+/// Deletes a queue, after which its buffer may be reused. No task may be waiting on it.
+#[unsafe(no_mangle)]
+pub extern "C" fn taskette_queue_delete(queue: *mut TasketteQueue) -> c_int {
+    let Some(object) = QUEUES.get(queue) else {
+        return TASKETTE_ERR_INVALID_ARGUMENT;
+    };
+
+    QUEUES.release(object);
+    TASKETTE_OK
+}
+
+/// Copies an item into the queue, blocking the calling task while the queue is full.
+///
+/// # Safety
+/// `item` must be valid for reads of the item size of the queue.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn taskette_queue_send(
+    queue: *mut TasketteQueue,
+    item: *const c_void,
+) -> c_int {
+    let Some(object) = QUEUES.get(queue) else {
+        return TASKETTE_ERR_INVALID_ARGUMENT;
+    };
+
+    loop {
+        // The counter is read before the attempt, so that a receive in between makes `wait` return immediately
+        let received = object.received.as_ref().load(Ordering::SeqCst);
+        match unsafe { try_send(object, item) } {
+            TASKETTE_ERR_WOULD_BLOCK => (),
+            code => return code,
+        }
+
+        if let Err(error) = object.received.wait(received) {
+            return error_code(error);
+        }
+    }
+}
+
+/// Copies an item into the queue if it is not full.
+///
+/// # Safety
+/// `item` must be valid for reads of the item size of the queue.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn taskette_queue_try_send(
+    queue: *mut TasketteQueue,
+    item: *const c_void,
+) -> c_int {
+    let Some(object) = QUEUES.get(queue) else {
+        return TASKETTE_ERR_INVALID_ARGUMENT;
+    };
+
+    unsafe { try_send(object, item) }
+}
+
+/// Copies the oldest item out of the queue into `item`, blocking the calling task while the queue is empty.
+///
+/// # Safety
+/// `item` must be valid for writes of the item size of the queue.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn taskette_queue_recv(
+    queue: *mut TasketteQueue,
+    item: *mut c_void,
+) -> c_int {
+    let Some(object) = QUEUES.get(queue) else {
+        return TASKETTE_ERR_INVALID_ARGUMENT;
+    };
+
+    loop {
+        let sent = object.sent.as_ref().load(Ordering::SeqCst);
+        match unsafe { try_recv(object, item) } {
+            TASKETTE_ERR_WOULD_BLOCK => (),
+            code => return code,
+        }
+
+        if let Err(error) = object.sent.wait(sent) {
+            return error_code(error);
+        }
+    }
+}
+
+/// Copies the oldest item out of the queue into `item` if the queue is not empty.
+///
+/// # Safety
+/// `item` must be valid for writes of the item size of the queue.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn taskette_queue_try_recv(
+    queue: *mut TasketteQueue,
+    item: *mut c_void,
+) -> c_int {
+    let Some(object) = QUEUES.get(queue) else {
+        return TASKETTE_ERR_INVALID_ARGUMENT;
+    };
+
+    unsafe { try_recv(object, item) }
+}
+
+unsafe fn try_send(queue: &TasketteQueue, item: *const c_void) -> c_int {
+    if item.is_null() {
+        return TASKETTE_ERR_INVALID_ARGUMENT;
+    }
+    if !unsafe { queue.ring.lock_irq().push(item as *const u8) } {
+        return TASKETTE_ERR_WOULD_BLOCK;
+    }
+
+    // The item has to be visible to other cores before receivers are woken up
+    queue.sent.as_ref().fetch_add(1, Ordering::SeqCst);
+    // Waking up cannot fail after the scheduler is initialized
+    let _ = queue.sent.wake_one();
+    TASKETTE_OK
+}
+
+unsafe fn try_recv(queue: &TasketteQueue, item: *mut c_void) -> c_int {
+    if item.is_null() {
+        return TASKETTE_ERR_INVALID_ARGUMENT;
+    }
+    if !unsafe { queue.ring.lock_irq().pop(item as *mut u8) } {
+        return TASKETTE_ERR_WOULD_BLOCK;
+    }
+
+    queue.received.as_ref().fetch_add(1, Ordering::SeqCst);
+    let _ = queue.received.wake_one();
+    TASKETTE_OK
+}
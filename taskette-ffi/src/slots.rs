@@ -0,0 +1,52 @@
+//! Fixed pool of objects handed to C as pointers.
+
+use core::sync::atomic::Ordering;
+
+use taskette::portable_atomic::AtomicBool;
+
+pub(crate) struct Slots<T, const N: usize> {
+    objects: [T; N],
+    /// Whether each object is handed out
+    used: [AtomicBool; N],
+}
+
+impl<T, const N: usize> Slots<T, N> {
+    pub(crate) const fn new(objects: [T; N]) -> Self {
+        Self {
+            objects,
+            used: [const { AtomicBool::new(false) }; N],
+        }
+    }
+
+    /// Takes a free object, which is handed out until `release` is called.
+    pub(crate) fn claim(&self) -> Option<&T> {
+        self.used
+            .iter()
+            .position(|used| {
+                used.compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+            })
+            .map(|index| &self.objects[index])
+    }
+
+    /// Returns the object pointed by a pointer handed out before, or `None` if it is not from this pool.
+    pub(crate) fn get(&self, ptr: *const T) -> Option<&T> {
+        let index = self.index_of(ptr)?;
+        self.used[index]
+            .load(Ordering::SeqCst)
+            .then(|| &self.objects[index])
+    }
+
+    /// Makes an object handed out before free again.
+    pub(crate) fn release(&self, object: &T) {
+        if let Some(index) = self.index_of(object) {
+            self.used[index].store(false, Ordering::SeqCst);
+        }
+    }
+
+    fn index_of(&self, ptr: *const T) -> Option<usize> {
+        self.objects
+            .iter()
+            .position(|object| core::ptr::eq(object, ptr))
+    }
+}
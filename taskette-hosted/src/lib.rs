@@ -0,0 +1,413 @@
+//! Simulation of Taskette on a hosted (`std`) environment.
+//!
+//! Each task runs on its own OS thread, but only one of them is allowed to run at a time.
+//! The tick is generated by a background thread, which plays the role of an interrupt.
+//!
+//! Because an OS thread cannot be suspended at an arbitrary point, preemption only happens when the running task
+//! leaves a critical section or calls a kernel function (e.g. `yield_now`, `spawn`, `Futex::wait`).
+//! A task which busy-loops without calling the kernel is never preempted.
+//!
+//! With [`set_virtual_time`], time skips forward while all tasks are blocked, so that long timeouts can be tested quickly.
+//!
+//! This crate also provides the `critical-section` implementation, so no other implementation can be linked
+//! (unless the `preemption-critical-section` feature selects the one of taskette).
+
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    sync::{Condvar, Mutex, MutexGuard, OnceLock},
+    thread::{self, ThreadId},
+    time::{Duration, Instant},
+};
+
+use taskette::{
+    arch::{StackAllocation, TickSource},
+    portable_atomic::{AtomicBool, AtomicU32, Ordering},
+    scheduler::{Scheduler, SchedulerConfig, SchedulerStorage},
+    sync::interrupt_free,
+    timer,
+};
+
+const IDLE_TASK_STACK_SIZE: usize = 2048;
+/// Clock frequency reported to the scheduler (not meaningful in simulation)
+const CLOCK_FREQ: u32 = 1_000_000_000;
+
+static IDLE_TASK_TAKEN: AtomicBool = AtomicBool::new(false);
+static TICK_FREQ: AtomicU32 = AtomicU32::new(0);
+/// Set while the tick thread is running
+static TICKING: AtomicBool = AtomicBool::new(false);
+/// Set when the tick thread has been spawned
+static TICK_THREAD_SPAWNED: AtomicBool = AtomicBool::new(false);
+/// Set when a context switch is requested
+static SWITCH_PENDING: AtomicBool = AtomicBool::new(false);
+/// Set by `set_virtual_time`
+static VIRTUAL_TIME: AtomicBool = AtomicBool::new(false);
+
+/// Entry points of tasks which are not started yet, keyed by their initial stack pointer
+static CONTEXTS: Mutex<Option<HashMap<usize, Context>>> = Mutex::new(None);
+/// Number of tasks created with each initial stack pointer (a stack may be reused after its task finishes)
+static GENERATIONS: Mutex<Option<HashMap<usize, u64>>> = Mutex::new(None);
+/// Stack pointer (which identifies a task) of the task allowed to run
+static RUNNING: Mutex<usize> = Mutex::new(0);
+static RUNNING_CHANGED: Condvar = Condvar::new();
+/// Number of ticks generated so far, used for waking up the idle task
+static TICKS: Mutex<u64> = Mutex::new(0);
+static TICKED: Condvar = Condvar::new();
+
+static CS_OWNER: Mutex<Option<ThreadId>> = Mutex::new(None);
+static CS_RELEASED: Condvar = Condvar::new();
+
+thread_local! {
+    /// Stack pointer of the task running on this thread (`None` for non-task threads)
+    static CURRENT_CONTEXT: Cell<Option<usize>> = const { Cell::new(None) };
+    /// Generation of the stack pointer in `CURRENT_CONTEXT`
+    static CURRENT_GENERATION: Cell<u64> = const { Cell::new(0) };
+    /// Nesting level of critical sections
+    static CS_DEPTH: Cell<usize> = const { Cell::new(0) };
+    /// True while this thread is inside the scheduler
+    static IN_SWITCH: Cell<bool> = const { Cell::new(false) };
+}
+
+struct Context {
+    pc: usize,
+    arg: usize,
+    generation: u64,
+}
+
+/// Initializes the scheduler.
+pub fn init_scheduler(config: SchedulerConfig) -> Option<Scheduler> {
+    unsafe { Scheduler::init(CLOCK_FREQ, config) }
+}
+
+/// Initializes the scheduler with the memory supplied by the application (see [`SchedulerStorage`]).
+pub fn init_scheduler_with_storage<const TASKS: usize, const TIMERS: usize>(
+    config: SchedulerConfig,
+    storage: &'static mut SchedulerStorage<TASKS, TIMERS>,
+) -> Option<Scheduler> {
+    unsafe { Scheduler::init_with_storage(CLOCK_FREQ, config, storage) }
+}
+
+/// Enables or disables virtual time.
+///
+/// With virtual time, ticks are generated immediately instead of every tick period while all tasks are blocked
+/// and some of them are sleeping, so that a long sleep completes instantly.
+/// Ticks still happen one by one, so tasks wake up in the same order as in real time.
+/// While a task is running, ticks are generated periodically as usual.
+pub fn set_virtual_time(enabled: bool) {
+    VIRTUAL_TIME.store(enabled, Ordering::SeqCst);
+}
+
+struct HostedCriticalSection;
+#[cfg(not(feature = "preemption-critical-section"))]
+critical_section::set_impl!(HostedCriticalSection);
+
+unsafe impl critical_section::Impl for HostedCriticalSection {
+    unsafe fn acquire() {
+        let depth = CS_DEPTH.get();
+        if depth == 0 {
+            let me = thread::current().id();
+            let mut owner = lock(&CS_OWNER);
+            while owner.is_some() {
+                owner = CS_RELEASED
+                    .wait(owner)
+                    .unwrap_or_else(|err| err.into_inner());
+            }
+            *owner = Some(me);
+        }
+        CS_DEPTH.set(depth + 1);
+    }
+
+    unsafe fn release(_: ()) {
+        let depth = CS_DEPTH.get() - 1;
+        CS_DEPTH.set(depth);
+        if depth == 0 {
+            *lock(&CS_OWNER) = None;
+            CS_RELEASED.notify_one();
+
+            // Context switch requested inside the critical section is performed here,
+            // like PendSV being taken after interrupts are re-enabled.
+            switch_if_pending();
+        }
+    }
+}
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_setup() {}
+
+/// Tick generated by a background thread
+struct ThreadTick;
+
+static TICK_SOURCE: ThreadTick = ThreadTick;
+
+impl TickSource for ThreadTick {
+    fn set_frequency(&self, _clock_freq: u32, tick_freq: u32) {
+        TICK_FREQ.store(tick_freq, Ordering::SeqCst);
+    }
+
+    fn start(&self) {
+        TICKING.store(true, Ordering::SeqCst);
+        if TICK_THREAD_SPAWNED.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let period = tick_period();
+        thread::spawn(move || {
+            loop {
+                thread::sleep(period);
+                if !TICKING.load(Ordering::SeqCst) {
+                    continue;
+                }
+
+                // Ticks are handled atomically with respect to tasks, as an interrupt handler is
+                interrupt_free(|_| taskette::scheduler::handle_tick());
+
+                *lock(&TICKS) += 1;
+                TICKED.notify_all();
+            }
+        });
+    }
+
+    fn stop(&self) {
+        TICKING.store(false, Ordering::SeqCst);
+    }
+
+    /// `_taskette_cycle_count` counts microseconds, which wrap around after about 71 minutes.
+    fn cycle_freq(&self) -> Option<u32> {
+        Some(1_000_000)
+    }
+}
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_tick_source() -> &'static dyn TickSource {
+    &TICK_SOURCE
+}
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_yield_now() {
+    SWITCH_PENDING.store(true, Ordering::SeqCst);
+
+    if CS_DEPTH.get() == 0 {
+        switch_if_pending();
+    }
+}
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub unsafe fn _taskette_init_stack(sp: *mut u8, pc: usize, arg: *const u8, arg_size: usize) -> *mut u8 {
+    // The argument is placed on the task stack as on real hardware
+    // (the task itself runs on the stack of its OS thread).
+    let sp = unsafe {
+        let sp = sp.byte_sub(arg_size);
+        let sp = sp.byte_sub(sp as usize % 16);
+        core::ptr::copy(arg, sp, arg_size);
+        sp
+    };
+
+    let generation = {
+        let mut generations = lock(&GENERATIONS);
+        let generation = generations
+            .get_or_insert_with(HashMap::new)
+            .entry(sp as usize)
+            .or_insert(0);
+        *generation += 1;
+        *generation
+    };
+
+    lock(&CONTEXTS).get_or_insert_with(HashMap::new).insert(
+        sp as usize,
+        Context {
+            pc,
+            arg: sp as usize,
+            generation,
+        },
+    );
+
+    sp
+}
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub unsafe fn _taskette_run_with_stack(pc: usize, sp: *mut u8, _stack_limit: *mut u8) -> ! {
+    // The calling thread becomes the idle task
+    CURRENT_CONTEXT.set(Some(sp as usize));
+    *lock(&RUNNING) = sp as usize;
+
+    let entry: fn() -> ! = unsafe { core::mem::transmute(pc) };
+    entry()
+}
+
+#[unsafe(no_mangle)]
+pub fn _taskette_get_idle_task_stack(core_id: usize) -> Option<&'static mut [u8]> {
+    if core_id != 0 || IDLE_TASK_TAKEN.swap(true, Ordering::SeqCst) {
+        None
+    } else {
+        Some(Box::leak(Box::new([0u8; IDLE_TASK_STACK_SIZE])))
+    }
+}
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_wait_for_interrupt() {
+    if !SWITCH_PENDING.load(Ordering::SeqCst) {
+        let sleeping = timer::next_wakeup().is_ok_and(|wakeup| wakeup.is_some());
+        if VIRTUAL_TIME.load(Ordering::SeqCst) && sleeping {
+            // Skip to the next tick (the idle task is running, so every task is blocked)
+            interrupt_free(|_| taskette::scheduler::handle_tick());
+            *lock(&TICKS) += 1;
+        } else {
+            // Sleep until the next tick
+            let ticks = lock(&TICKS);
+            let _ = TICKED.wait_timeout(ticks, tick_period());
+        }
+    }
+
+    switch_if_pending();
+}
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_core_id() -> usize {
+    0
+}
+
+// The IPI below cannot reach other cores
+const _: () = assert!(
+    taskette::scheduler::NUM_CORES == 1,
+    "This port is single-core; disable `smp` feature of taskette"
+);
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_ipi(_core_id: usize) {
+    // Single-core: only the current core exists
+    _taskette_yield_now();
+}
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_try_lock(lock: &AtomicBool) -> bool {
+    lock.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+        .is_ok()
+}
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_mask_interrupts() -> usize {
+    // Ticks are held off by a critical section, as interrupts are
+    unsafe { <HostedCriticalSection as critical_section::Impl>::acquire() };
+    0
+}
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_restore_interrupts(_state: usize) {
+    unsafe { <HostedCriticalSection as critical_section::Impl>::release(()) };
+}
+
+/// INTERNAL USE ONLY
+#[unsafe(no_mangle)]
+pub fn _taskette_cycle_count() -> u32 {
+    // Microseconds since the first call
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+    EPOCH.get_or_init(Instant::now).elapsed().as_micros() as u32
+}
+
+/// Performs a requested context switch if the current thread is a task and not inside a critical section.
+fn switch_if_pending() {
+    let Some(my_sp) = CURRENT_CONTEXT.get() else {
+        // Non-task threads (e.g. the tick thread) cannot be switched out
+        return;
+    };
+    if IN_SWITCH.get() || CS_DEPTH.get() != 0 {
+        return;
+    }
+
+    while SWITCH_PENDING.swap(false, Ordering::SeqCst) {
+        IN_SWITCH.set(true);
+        let next_sp = unsafe { taskette::scheduler::select_task(my_sp) };
+        IN_SWITCH.set(false);
+
+        if next_sp == my_sp {
+            continue;
+        }
+
+        // Start the thread of the next task if it is not started yet
+        if let Some(context) = lock(&CONTEXTS)
+            .as_mut()
+            .and_then(|contexts| contexts.remove(&next_sp))
+        {
+            thread::spawn(move || {
+                CURRENT_CONTEXT.set(Some(next_sp));
+                CURRENT_GENERATION.set(context.generation);
+                wait_until_running(next_sp);
+
+                let entry: extern "C" fn(usize) -> ! = unsafe { core::mem::transmute(context.pc) };
+                entry(context.arg)
+            });
+        }
+
+        // Pass the CPU to the next task and wait until this task is selected again
+        *lock(&RUNNING) = next_sp;
+        RUNNING_CHANGED.notify_all();
+        wait_until_running(my_sp);
+    }
+}
+
+fn wait_until_running(sp: usize) {
+    let mut running = lock(&RUNNING);
+    while *running != sp {
+        running = RUNNING_CHANGED
+            .wait(running)
+            .unwrap_or_else(|err| err.into_inner());
+    }
+    drop(running);
+
+    let generation = lock(&GENERATIONS)
+        .as_ref()
+        .and_then(|generations| generations.get(&sp).copied())
+        .unwrap_or(0);
+    if generation != CURRENT_GENERATION.get() {
+        // The task of this thread has finished and its stack is now used by another task
+        loop {
+            thread::park();
+        }
+    }
+}
+
+fn tick_period() -> Duration {
+    let tick_freq = TICK_FREQ.load(Ordering::SeqCst);
+    assert!(tick_freq != 0, "Scheduler not started");
+    Duration::from_secs(1) / tick_freq
+}
+
+/// Locks a mutex ignoring poisoning (a panicking task should not take the whole simulation down)
+fn lock<T>(mutex: &'static Mutex<T>) -> MutexGuard<'static, T> {
+    mutex.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Stack allocation for a task.
+///
+/// Tasks are executed on stacks of OS threads, so this is only used for storing the task closure.
+#[repr(align(16))]
+pub struct Stack<const N: usize>([u8; N]);
+
+impl<const N: usize> Stack<N> {
+    pub const fn new() -> Self {
+        Self([0u8; N])
+    }
+}
+
+impl<const N: usize> Default for Stack<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> StackAllocation for &mut Stack<N> {
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
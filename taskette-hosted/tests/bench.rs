@@ -0,0 +1,47 @@
+//! Test of the benchmarking helpers
+
+use std::process::ExitCode;
+
+use taskette::{scheduler::spawn, task::TaskConfig};
+use taskette_hosted::{Stack, init_scheduler};
+use taskette_utils::bench::{
+    Measurement, measure_context_switch, measure_futex_wake_uncontended,
+    measure_lock_irq_uncontended, measure_lock_uncontended,
+};
+
+const ITERATIONS: u32 = 100;
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(Default::default()).unwrap();
+
+    spawn(
+        || {
+            let partner_stack = Box::leak(Box::new(Stack::<8192>::new()));
+            let results = [
+                measure_context_switch(ITERATIONS, 1, &mut *partner_stack).unwrap(),
+                // The stack can be reused because the partner has finished
+                measure_context_switch(ITERATIONS, 1, &mut *partner_stack).unwrap(),
+                measure_lock_uncontended(ITERATIONS),
+                measure_lock_irq_uncontended(ITERATIONS),
+                measure_futex_wake_uncontended(ITERATIONS),
+            ];
+
+            let valid = |result: &Measurement| {
+                result.iterations == ITERATIONS
+                    && result.min <= result.average
+                    && result.average <= result.max
+            };
+            if results.iter().all(valid) {
+                std::process::exit(0);
+            } else {
+                println!("{:?}", results);
+                std::process::exit(1);
+            }
+        },
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
@@ -0,0 +1,71 @@
+//! Test of waiting for UTC times and daily alarms
+
+use std::{process::ExitCode, time::Duration};
+
+use taskette::{
+    Error,
+    scheduler::{SchedulerConfig, spawn},
+    task::TaskConfig,
+    timer::{
+        current_time, wait_until,
+        wall_clock::{self, DailyAlarm, DateTime, wait_until_utc},
+    },
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+/// 2024-02-29T00:00:00Z
+const MIDNIGHT: u64 = 1_709_164_800;
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default().with_tick_freq(100)).unwrap();
+
+    spawn(
+        task_main,
+        Box::leak(Box::new(Stack::<65536>::new())),
+        TaskConfig::default().with_priority(2),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+fn task_main() {
+    assert!(matches!(
+        wait_until_utc(Duration::from_secs(MIDNIGHT)),
+        Err(Error::NotInitialized)
+    ));
+
+    // Occurrences of a daily alarm
+    let noon = DailyAlarm::new(12, 0, 0);
+    let today = Duration::from_secs(MIDNIGHT + 12 * 3600);
+    let tomorrow = today + Duration::from_secs(86400);
+    assert_eq!(noon.next_after(Duration::from_secs(MIDNIGHT)), today);
+    assert_eq!(noon.next_after(today - Duration::from_micros(1)), today);
+    assert_eq!(noon.next_after(today), tomorrow);
+
+    // Goes off half a second after being set
+    wall_clock::set_utc(today - Duration::from_millis(500)).unwrap();
+    let start = current_time().unwrap();
+    assert_eq!(noon.wait().unwrap(), today);
+    let elapsed = current_time().unwrap() - start;
+    assert!((50..=52).contains(&elapsed), "{}", elapsed);
+    let now = DateTime::now().unwrap();
+    assert_eq!((now.hour, now.minute, now.second), (12, 0, 0));
+
+    // Woken early when the wall clock is set forward during the wait (e.g. resynchronized after a deep sleep)
+    spawn(
+        || {
+            wait_until(current_time().unwrap() + 5).unwrap();
+            wall_clock::set_utc(Duration::from_secs(MIDNIGHT + 18 * 3600)).unwrap();
+        },
+        Box::leak(Box::new(Stack::<65536>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+    let start = current_time().unwrap();
+    wait_until_utc(Duration::from_secs(MIDNIGHT + 13 * 3600)).unwrap();
+    let elapsed = current_time().unwrap() - start;
+    assert!((5..=7).contains(&elapsed), "{}", elapsed);
+
+    std::process::exit(0);
+}
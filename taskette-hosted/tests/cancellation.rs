@@ -0,0 +1,100 @@
+//! Test of waking blocked tasks with cancellation tokens
+
+use std::{
+    process::ExitCode,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use taskette::{
+    Error,
+    scheduler::{SchedulerConfig, spawn},
+    sync::{CancellationToken, Channel},
+    task::TaskConfig,
+    timer::{current_time, wait_until},
+};
+use taskette_hosted::{Stack, init_scheduler};
+use taskette_utils::futures::block_on;
+
+static SHUTDOWN: CancellationToken = CancellationToken::new();
+/// Kept full
+static OUTBOX: Channel<u32, 1> = Channel::new();
+/// Kept empty
+static INBOX: Channel<u32, 1> = Channel::new();
+/// Number of tasks which saw the cancellation
+static CANCELLED: AtomicUsize = AtomicUsize::new(0);
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default().with_tick_freq(1000)).unwrap();
+
+    let config = TaskConfig::default().with_priority(2);
+    spawn(task_receiver, new_stack(), config.clone()).unwrap();
+    spawn(task_sender, new_stack(), config.clone()).unwrap();
+    spawn(task_sleeper, new_stack(), config.clone()).unwrap();
+    spawn(task_waiter, new_stack(), config.clone()).unwrap();
+    spawn(task_async, new_stack(), config).unwrap();
+    spawn(
+        task_coordinator,
+        new_stack(),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+fn new_stack() -> &'static mut Stack<65536> {
+    Box::leak(Box::new(Stack::<65536>::new()))
+}
+
+fn count(result: Result<(), Error>) {
+    if matches!(result, Err(Error::Cancelled)) {
+        CANCELLED.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+fn task_receiver() {
+    let session = SHUTDOWN.child();
+    count(INBOX.recv_cancellable(&session).map(|_| ()));
+}
+
+fn task_sender() {
+    OUTBOX.send(1).unwrap();
+    count(OUTBOX.send_cancellable(2, &SHUTDOWN));
+}
+
+fn task_sleeper() {
+    count(SHUTDOWN.sleep_until(current_time().unwrap() + 1_000_000));
+}
+
+fn task_waiter() {
+    let child = SHUTDOWN.child();
+    let grandchild = child.child();
+    grandchild.wait().unwrap();
+    count(grandchild.check());
+}
+
+fn task_async() {
+    block_on(SHUTDOWN.cancelled());
+    count(SHUTDOWN.check());
+}
+
+fn task_coordinator() {
+    // Cancelling a child does not affect the parent
+    let child = SHUTDOWN.child();
+    child.cancel();
+    assert!(child.is_cancelled() && !SHUTDOWN.is_cancelled());
+
+    wait_until(current_time().unwrap() + 10).unwrap();
+    assert_eq!(CANCELLED.load(Ordering::SeqCst), 0);
+
+    SHUTDOWN.cancel();
+    wait_until(current_time().unwrap() + 10).unwrap();
+
+    let cancelled = CANCELLED.load(Ordering::SeqCst);
+    if cancelled == 5 {
+        std::process::exit(0);
+    } else {
+        println!("{} tasks cancelled", cancelled);
+        std::process::exit(1);
+    }
+}
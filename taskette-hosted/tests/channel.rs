@@ -0,0 +1,52 @@
+//! Test of the channel
+
+use std::process::ExitCode;
+
+use taskette::{scheduler::spawn, sync::Channel, task::TaskConfig};
+use taskette_hosted::{Stack, init_scheduler};
+
+const NUM_VALUES: u32 = 1000;
+
+static CHANNEL: Channel<u32, 4> = Channel::new();
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(Default::default()).unwrap();
+
+    // The receiver has higher priority, so it is blocked on the empty channel most of the time
+    spawn(
+        task_receiver,
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(2),
+    )
+    .unwrap();
+    spawn(
+        task_sender,
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+fn task_sender() {
+    for i in 0..NUM_VALUES {
+        CHANNEL.send(i).unwrap();
+    }
+}
+
+fn task_receiver() {
+    for i in 0..NUM_VALUES {
+        let value = CHANNEL.recv().unwrap();
+        if value != i {
+            println!("Expected {} but received {}", i, value);
+            std::process::exit(1);
+        }
+    }
+
+    if CHANNEL.try_recv().is_none() {
+        std::process::exit(0);
+    } else {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,52 @@
+//! Test of the CPU load measurement
+
+use std::process::ExitCode;
+
+use taskette::{
+    scheduler::{SchedulerConfig, cpu_load_percent, cpu_load_stats, reset_cpu_load_stats, spawn},
+    sync::interrupt_free,
+    task::TaskConfig,
+    timer::{current_time, wait_until},
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(
+        SchedulerConfig::default()
+            .with_tick_freq(100)
+            .with_cpu_load_window(10),
+    )
+    .unwrap();
+
+    spawn(
+        || {
+            // Mostly idle
+            wait_until(current_time().unwrap() + 30).unwrap();
+            let idle_load = cpu_load_percent().unwrap();
+
+            // Busy (a hosted task is only preempted on kernel calls, so leaving a critical section keeps it running)
+            reset_cpu_load_stats();
+            let end = current_time().unwrap() + 30;
+            while current_time().unwrap() < end {
+                interrupt_free(|_| ());
+            }
+            let busy_load = cpu_load_percent().unwrap();
+            let stats = cpu_load_stats(0).unwrap();
+
+            if idle_load < 50 && busy_load > 50 && stats.windows >= 2 && stats.min <= stats.max {
+                std::process::exit(0);
+            } else {
+                println!(
+                    "idle_load = {}, busy_load = {}, stats = {:?}",
+                    idle_load, busy_load, stats
+                );
+                std::process::exit(1);
+            }
+        },
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default(),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
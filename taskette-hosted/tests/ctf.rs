@@ -0,0 +1,106 @@
+//! Test of the Common Trace Format output
+
+use std::{
+    process::ExitCode,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use taskette::{
+    scheduler::{SchedulerConfig, spawn},
+    timer::{current_time, wait_until},
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+const MAGIC: u32 = 0xC1FC1FC1;
+const EVENT_SCHED_SWITCH: u8 = 0;
+const EVENT_TASK_CREATE: u8 = 4;
+
+static CLOCK: AtomicU64 = AtomicU64::new(0);
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default().with_tick_freq(100)).unwrap();
+
+    taskette_ctf::init(
+        Box::leak(Box::new([0u8; 16 * taskette_ctf::PACKET_SIZE])),
+        || CLOCK.fetch_add(1, Ordering::SeqCst),
+    );
+
+    spawn(
+        || {
+            for _ in 0..5 {
+                wait_until(current_time().unwrap() + 2).unwrap();
+            }
+
+            taskette_ctf::flush();
+            let mut stream = vec![0u8; 32 * taskette_ctf::PACKET_SIZE];
+            let len = taskette_ctf::drain(&mut stream);
+            stream.truncate(len);
+
+            let mut metadata = String::new();
+            taskette_ctf::write_metadata(&mut metadata, 1_000_000).unwrap();
+
+            match check_stream(&stream) {
+                Ok(()) if metadata.contains("freq = 1000000;") => std::process::exit(0),
+                result => {
+                    println!("result = {:?}, metadata = {}", result, metadata);
+                    std::process::exit(1);
+                }
+            }
+        },
+        Box::leak(Box::new(Stack::<8192>::new())),
+        taskette::task::TaskConfig::default(),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+/// Walks the packets and checks that a task creation and context switches are recorded.
+fn check_stream(stream: &[u8]) -> Result<(), String> {
+    if stream.is_empty() || !stream.len().is_multiple_of(taskette_ctf::PACKET_SIZE) {
+        return Err(format!("stream length {}", stream.len()));
+    }
+
+    let read_u32 = |bytes: &[u8], offset: usize| {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    };
+
+    let mut created = false;
+    let mut switches = 0;
+    for packet in stream.chunks(taskette_ctf::PACKET_SIZE) {
+        if read_u32(packet, 0) != MAGIC {
+            return Err("bad magic".into());
+        }
+        let content_size = read_u32(packet, 24) as usize / 8;
+        if read_u32(packet, 28) as usize != taskette_ctf::PACKET_SIZE * 8
+            || content_size > packet.len()
+        {
+            return Err("bad packet size".into());
+        }
+
+        let mut offset = 36;
+        while offset < content_size {
+            let event_id = packet[offset];
+            offset += 10;
+            offset += match event_id {
+                EVENT_SCHED_SWITCH => {
+                    switches += 1;
+                    8
+                }
+                EVENT_TASK_CREATE => {
+                    created = true;
+                    8
+                }
+                1 | 2 | 5 => 4,
+                3 => 0,
+                _ => return Err(format!("unknown event {}", event_id)),
+            };
+        }
+    }
+
+    if created && switches >= 5 {
+        Ok(())
+    } else {
+        Err(format!("created = {}, switches = {}", created, switches))
+    }
+}
@@ -0,0 +1,59 @@
+//! Test of sub-tick accuracy of the hybrid delay
+
+use std::{
+    process::ExitCode,
+    time::{Duration, Instant},
+};
+
+use embedded_hal::delay::DelayNs;
+use taskette::{
+    scheduler::{SchedulerConfig, spawn},
+    task::TaskConfig,
+};
+use taskette_hosted::{Stack, init_scheduler};
+use taskette_utils::delay::Delay;
+
+fn main() -> ExitCode {
+    // 50 ms ticks
+    let scheduler = init_scheduler(SchedulerConfig::default().with_tick_freq(20)).unwrap();
+
+    spawn(
+        task_main,
+        Box::leak(Box::new(Stack::<65536>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+fn measure(f: impl FnOnce()) -> Duration {
+    let start = Instant::now();
+    f();
+    start.elapsed()
+}
+
+fn task_main() {
+    let mut delay = Delay::new().unwrap();
+    let mut hybrid = Delay::new().unwrap().with_hybrid(true);
+
+    // Shorter than a tick: busy-waits instead of rounding up to a tick
+    let elapsed = measure(|| hybrid.delay_us(2_000));
+    assert!(elapsed >= Duration::from_millis(2), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_millis(25), "{:?}", elapsed);
+
+    let elapsed = measure(|| hybrid.delay_ns(500_000));
+    assert!(elapsed >= Duration::from_micros(500), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_millis(25), "{:?}", elapsed);
+
+    // Longer than a tick: blocks for whole ticks, then busy-waits the rest
+    let elapsed = measure(|| hybrid.delay_ms(120));
+    assert!(elapsed >= Duration::from_millis(120), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_millis(145), "{:?}", elapsed);
+
+    // Without the hybrid mode, a short delay still takes up to a tick
+    let elapsed = measure(|| delay.delay_us(2_000));
+    assert!(elapsed < Duration::from_millis(75), "{:?}", elapsed);
+
+    std::process::exit(0);
+}
@@ -0,0 +1,111 @@
+//! Test of running several event-driven drivers in one task
+
+use std::{
+    future::poll_fn,
+    pin::pin,
+    process::ExitCode,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    task::{Poll, Waker},
+};
+
+use taskette::{
+    scheduler::{SchedulerConfig, spawn},
+    sync::IrqEvent,
+    task::TaskConfig,
+    timer::{current_time, wait_until},
+};
+use taskette_hosted::{Stack, init_scheduler};
+use taskette_utils::driver_host::{run_drivers, wait_event};
+
+static UART_EVENT: IrqEvent = IrqEvent::new();
+static GPIO_EVENT: IrqEvent = IrqEvent::new();
+static UART_COUNT: AtomicUsize = AtomicUsize::new(0);
+static GPIO_COUNT: AtomicUsize = AtomicUsize::new(0);
+static POLLS: AtomicUsize = AtomicUsize::new(0);
+
+/// Flag completed by another task through a waker, like a DMA driver with its own waker registration
+static DMA_DONE: AtomicBool = AtomicBool::new(false);
+static DMA_WAKER: Mutex<Option<Waker>> = Mutex::new(None);
+
+async fn uart_driver() {
+    loop {
+        POLLS.fetch_add(1, Ordering::SeqCst);
+        wait_event(&UART_EVENT).await.unwrap();
+        UART_COUNT.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Finishes after two edges
+async fn gpio_driver() {
+    for _ in 0..2 {
+        wait_event(&GPIO_EVENT).await.unwrap();
+        GPIO_COUNT.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+async fn dma_driver() {
+    poll_fn(|cx| {
+        if DMA_DONE.load(Ordering::SeqCst) {
+            Poll::Ready(())
+        } else {
+            *DMA_WAKER.lock().unwrap() = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    })
+    .await;
+}
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default().with_tick_freq(100)).unwrap();
+
+    spawn(
+        || run_drivers([pin!(uart_driver()), pin!(gpio_driver()), pin!(dma_driver())]),
+        Box::leak(Box::new(Stack::<65536>::new())),
+        TaskConfig::default().with_priority(2),
+    )
+    .unwrap();
+    spawn(
+        task_interrupts,
+        Box::leak(Box::new(Stack::<65536>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+fn sleep(ticks: u64) {
+    wait_until(current_time().unwrap() + ticks).unwrap();
+}
+
+/// Plays the interrupt handlers, signaling each event some time apart
+fn task_interrupts() {
+    sleep(2);
+    UART_EVENT.signal();
+    sleep(2);
+    GPIO_EVENT.signal();
+    UART_EVENT.signal();
+    sleep(2);
+    GPIO_EVENT.signal();
+    sleep(2);
+    // The GPIO driver has finished, so this signal has no effect
+    GPIO_EVENT.signal();
+    sleep(2);
+    DMA_DONE.store(true, Ordering::SeqCst);
+    DMA_WAKER.lock().unwrap().take().unwrap().wake();
+    sleep(2);
+
+    assert_eq!(UART_COUNT.load(Ordering::SeqCst), 2);
+    assert_eq!(GPIO_COUNT.load(Ordering::SeqCst), 2);
+    // The host parks while idle, so the UART driver is polled only a few times per wakeup
+    let polls = POLLS.load(Ordering::SeqCst);
+    if polls <= 10 {
+        std::process::exit(0);
+    } else {
+        println!("UART driver polled {} times", polls);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,53 @@
+//! Test of the task status dump
+
+use std::process::ExitCode;
+
+use taskette::{
+    futex::Futex,
+    scheduler::{SchedulerConfig, dump_tasks, spawn},
+    task::TaskConfig,
+    timer::{current_time, wait_until},
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+static FUTEX: Futex = Futex::new(0);
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default().with_tick_freq(100)).unwrap();
+
+    spawn(
+        || {
+            FUTEX.wait(0).unwrap();
+        },
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(1).with_name("waiter"),
+    )
+    .unwrap();
+
+    spawn(
+        || {
+            // Lets the waiter block
+            wait_until(current_time().unwrap() + 5).unwrap();
+
+            let mut table = String::new();
+            dump_tasks(&mut table).unwrap();
+            print!("{}", table);
+
+            let row = |name: &str| table.lines().find(|line| line.contains(name)).unwrap_or("");
+            if table.starts_with("  ID NAME")
+                && row("idle").contains("Ready")
+                && row("waiter").contains("Blocked")
+                && row("dumper").contains("Running")
+            {
+                std::process::exit(0);
+            } else {
+                std::process::exit(1);
+            }
+        },
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(2).with_name("dumper"),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
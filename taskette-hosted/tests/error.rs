@@ -0,0 +1,46 @@
+//! Test of the error type and the stack size check of `spawn`
+
+use std::process::ExitCode;
+
+use taskette::{
+    Error,
+    scheduler::{MIN_STACK_SIZE, SchedulerConfig, spawn},
+    task::TaskConfig,
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+fn spawn_small() -> Result<(), Box<dyn std::error::Error>> {
+    spawn(
+        || {},
+        Box::leak(Box::new(Stack::<64>::new())),
+        TaskConfig::default(),
+    )?;
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let _scheduler = init_scheduler(SchedulerConfig::default()).unwrap();
+
+    // Converted into a boxed error with `?`
+    let error = spawn_small().unwrap_err();
+    if error.to_string() != "stack too small" {
+        println!("Unexpected error: {}", error);
+        return ExitCode::FAILURE;
+    }
+
+    let result = spawn(
+        || {},
+        Box::leak(Box::new(Stack::<{ MIN_STACK_SIZE + 64 }>::new())),
+        TaskConfig::default(),
+    );
+    if result.is_err() {
+        println!("Minimum stack rejected: {:?}", result);
+        return ExitCode::FAILURE;
+    }
+
+    if Error::NotInitialized.to_string() != "scheduler not initialized" {
+        return ExitCode::FAILURE;
+    }
+
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,128 @@
+//! Test of the C API, called the same way as from C
+
+use std::{
+    ffi::{c_int, c_void},
+    process::ExitCode,
+    ptr::null_mut,
+};
+
+use taskette::{scheduler::spawn, task::TaskConfig};
+use taskette_ffi::{
+    TASKETTE_ERR_DEADLOCK, TASKETTE_ERR_NOT_PERMITTED, TASKETTE_ERR_WOULD_BLOCK, TASKETTE_OK,
+    TasketteTaskConfig,
+    mutex::{
+        TasketteMutex, taskette_mutex_create, taskette_mutex_delete, taskette_mutex_lock,
+        taskette_mutex_try_lock, taskette_mutex_unlock,
+    },
+    queue::{TasketteQueue, taskette_queue_create, taskette_queue_recv, taskette_queue_send},
+    taskette_spawn,
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+const NUM_VALUES: u32 = 100;
+
+/// Objects shared with the receiver
+struct Shared {
+    queue: *mut TasketteQueue,
+    mutex: *mut TasketteMutex,
+}
+
+fn check(code: c_int, expected: c_int) {
+    if code != expected {
+        println!("Expected {} but got {}", expected, code);
+        std::process::exit(1);
+    }
+}
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(Default::default()).unwrap();
+
+    spawn(
+        task_sender,
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+fn task_sender() {
+    let mut shared = Shared {
+        queue: null_mut(),
+        mutex: null_mut(),
+    };
+    let buffer = Box::leak(Box::new([0u32; 4]));
+    unsafe {
+        check(
+            taskette_queue_create(buffer.as_mut_ptr() as *mut c_void, 4, 4, &mut shared.queue),
+            TASKETTE_OK,
+        );
+        check(taskette_mutex_create(&mut shared.mutex), TASKETTE_OK);
+    }
+    check(taskette_mutex_lock(shared.mutex), TASKETTE_OK);
+    let (queue, mutex) = (shared.queue, shared.mutex);
+
+    // The receiver has higher priority, so it is blocked on the empty queue most of the time
+    let stack = Box::leak(vec![0u8; 8192].into_boxed_slice());
+    let config = TasketteTaskConfig {
+        priority: 2,
+        affinity: -1,
+        name: c"receiver".as_ptr(),
+    };
+    unsafe {
+        check(
+            taskette_spawn(
+                Some(task_receiver),
+                Box::leak(Box::new(shared)) as *mut Shared as *mut c_void,
+                stack.as_mut_ptr() as *mut c_void,
+                stack.len(),
+                &config,
+                null_mut(),
+            ),
+            TASKETTE_OK,
+        );
+    }
+
+    for i in 0..NUM_VALUES {
+        check(
+            unsafe { taskette_queue_send(queue, &i as *const u32 as *const c_void) },
+            TASKETTE_OK,
+        );
+    }
+
+    // The receiver waits for this
+    check(taskette_mutex_unlock(mutex), TASKETTE_OK);
+}
+
+extern "C" fn task_receiver(arg: *mut c_void) {
+    let shared = unsafe { &*(arg as *const Shared) };
+
+    for i in 0..NUM_VALUES {
+        let mut value = 0u32;
+        check(
+            unsafe { taskette_queue_recv(shared.queue, &mut value as *mut u32 as *mut c_void) },
+            TASKETTE_OK,
+        );
+        if value != i {
+            println!("Expected {} but received {}", i, value);
+            std::process::exit(1);
+        }
+    }
+
+    // Held by the sender until it sent everything
+    check(
+        taskette_mutex_try_lock(shared.mutex),
+        TASKETTE_ERR_WOULD_BLOCK,
+    );
+    check(taskette_mutex_lock(shared.mutex), TASKETTE_OK);
+    check(taskette_mutex_lock(shared.mutex), TASKETTE_ERR_DEADLOCK);
+    check(
+        taskette_mutex_delete(shared.mutex),
+        TASKETTE_ERR_NOT_PERMITTED,
+    );
+    check(taskette_mutex_unlock(shared.mutex), TASKETTE_OK);
+    check(taskette_mutex_delete(shared.mutex), TASKETTE_OK);
+
+    std::process::exit(0);
+}
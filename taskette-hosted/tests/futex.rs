@@ -0,0 +1,64 @@
+//! Test of the futex mechanism
+
+use std::{process::ExitCode, sync::Mutex};
+
+use taskette::{futex::Futex, portable_atomic::Ordering, scheduler::spawn, task::TaskConfig};
+use taskette_hosted::{Stack, init_scheduler};
+
+static NUMBERS: Mutex<Vec<i32>> = Mutex::new(Vec::new());
+
+static FUTEX: Futex = Futex::new(0);
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(Default::default()).unwrap();
+
+    let _task_low = spawn(
+        task_low,
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+fn task_low() {
+    // Launch a high-priority task (but it blocks first)
+    let _task_high = spawn(
+        task_high,
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(2),
+    )
+    .unwrap();
+
+    // This runs the first despite `task_high` has higher priority
+    for i in 0..1000 {
+        put_number(i);
+    }
+
+    // Allow `task_high` to run
+    FUTEX.as_ref().store(1, Ordering::Release);
+    FUTEX.wake_all().unwrap();
+
+    // Check result
+    let numbers = NUMBERS.lock().unwrap();
+    if numbers.iter().cloned().eq(0..2000) {
+        std::process::exit(0);
+    } else {
+        println!("{:?}", numbers);
+        std::process::exit(1);
+    }
+}
+
+fn task_high() {
+    // Wait until `task_low` allows
+    FUTEX.wait(0).unwrap();
+
+    for i in 1000..2000 {
+        put_number(i);
+    }
+}
+
+fn put_number(num: i32) {
+    NUMBERS.lock().unwrap().push(num);
+}
@@ -0,0 +1,57 @@
+//! Test of futex waits with a deadline
+
+use std::process::ExitCode;
+
+use taskette::{
+    futex::Futex, portable_atomic::Ordering, scheduler::spawn, task::TaskConfig,
+    timer::current_time,
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+static FUTEX: Futex = Futex::new(0);
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(Default::default()).unwrap();
+
+    spawn(
+        task_waiter,
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+fn task_waiter() {
+    // Nobody wakes it up, so it times out
+    let start = current_time().unwrap();
+    if FUTEX.wait_until(0, start + 10).unwrap() || current_time().unwrap() < start + 10 {
+        std::process::exit(1);
+    }
+
+    // Woken up by a higher-priority task before the deadline
+    spawn(
+        task_waker,
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(2),
+    )
+    .unwrap();
+    let start = current_time().unwrap();
+    if !FUTEX.wait_until(0, start + 1000).unwrap() || current_time().unwrap() >= start + 1000 {
+        std::process::exit(1);
+    }
+
+    // Returns at once when the value differs
+    if !FUTEX.wait_until(0, current_time().unwrap() + 1000).unwrap() {
+        std::process::exit(1);
+    }
+
+    std::process::exit(0);
+}
+
+fn task_waker() {
+    taskette::timer::wait_until(current_time().unwrap() + 5).unwrap();
+    FUTEX.as_ref().store(1, Ordering::SeqCst);
+    FUTEX.wake_all().unwrap();
+}
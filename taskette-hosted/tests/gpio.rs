@@ -0,0 +1,76 @@
+//! Test of waiting for GPIO edges signaled by an interrupt handler
+
+use std::{
+    process::ExitCode,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use taskette::{
+    scheduler::{SchedulerConfig, spawn},
+    sync::IrqEvent,
+    task::TaskConfig,
+    timer::{current_time, wait_until},
+};
+use taskette_hosted::{Stack, init_scheduler};
+use taskette_utils::gpio::{Edge, IrqPin, wait_for_edge, wait_for_edge_until};
+
+static EVENT: IrqEvent = IrqEvent::new();
+/// Edge the interrupt of the fake pin is enabled on (0 while disabled)
+static ENABLED: AtomicU8 = AtomicU8::new(0);
+
+struct FakePin;
+
+fn set_interrupt(_pin: &mut FakePin, edge: Option<Edge>) {
+    let value = match edge {
+        None => 0,
+        Some(Edge::Rising) => 1,
+        Some(Edge::Falling) => 2,
+        Some(Edge::Both) => 3,
+    };
+    ENABLED.store(value, Ordering::SeqCst);
+}
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default().with_tick_freq(100)).unwrap();
+
+    spawn(
+        task_waiter,
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(2),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+fn task_waiter() {
+    let mut pin = IrqPin::new(FakePin, &EVENT, set_interrupt);
+
+    // Nobody signals, so it times out with the interrupt disabled again
+    let start = current_time().unwrap();
+    assert!(!wait_for_edge_until(&mut pin, Edge::Rising, start + 5).unwrap());
+    assert_eq!(ENABLED.load(Ordering::SeqCst), 0);
+
+    // A signal from before the wait is not taken as an edge
+    EVENT.signal();
+    let start = current_time().unwrap();
+    assert!(!wait_for_edge_until(&mut pin, Edge::Rising, start + 5).unwrap());
+
+    // The interrupt handler (played by a lower-priority task) fires a few ticks after the interrupt is enabled
+    spawn(
+        || {
+            wait_until(current_time().unwrap() + 3).unwrap();
+            assert_eq!(ENABLED.load(Ordering::SeqCst), 2);
+            EVENT.signal();
+        },
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+    let start = current_time().unwrap();
+    wait_for_edge(&mut pin, Edge::Falling).unwrap();
+    assert!(current_time().unwrap() >= start + 3);
+    assert_eq!(ENABLED.load(Ordering::SeqCst), 0);
+
+    std::process::exit(0);
+}
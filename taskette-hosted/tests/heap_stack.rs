@@ -0,0 +1,51 @@
+//! Test of heap-allocated stacks
+
+use std::{
+    process::ExitCode,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use taskette::{
+    arch::yield_now,
+    scheduler::{spawn, spawn_heap},
+    task::TaskConfig,
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+/// More than the maximum number of tasks, so that finished tasks have to be removed
+const NUM_TASKS: usize = 50;
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(Default::default()).unwrap();
+
+    spawn(
+        task_spawner,
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+fn task_spawner() {
+    for i in 0..NUM_TASKS {
+        // Fails if finished tasks are not removed
+        spawn_heap(
+            || {
+                COUNTER.fetch_add(1, Ordering::SeqCst);
+            },
+            8192,
+            TaskConfig::default().with_priority(2),
+        )
+        .unwrap();
+
+        while COUNTER.load(Ordering::SeqCst) != i + 1 {
+            yield_now();
+        }
+    }
+
+    std::process::exit(0);
+}
@@ -0,0 +1,109 @@
+//! Test of the heap usage statistics of the allocator wrapper
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    process::ExitCode,
+};
+
+use taskette::{
+    heap::{self, TaskAlloc},
+    scheduler::{SchedulerConfig, for_each_task, reset_stats, spawn, stats},
+    task::{self, TaskConfig},
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+/// Allocator failing requests larger than a limit, like a small heap
+struct LimitedAlloc;
+
+const LIMIT: usize = 1024;
+
+unsafe impl GlobalAlloc for LimitedAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if layout.size() > LIMIT {
+            core::ptr::null_mut()
+        } else {
+            unsafe { System.alloc(layout) }
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+static HEAP: TaskAlloc<LimitedAlloc> = TaskAlloc::new(LimitedAlloc);
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default()).unwrap();
+
+    reset_stats();
+    // The holder runs first, then the checker
+    spawn(
+        task_hold,
+        Box::leak(Box::new(Stack::<65536>::new())),
+        TaskConfig::default().with_priority(2),
+    )
+    .unwrap();
+    spawn(
+        task_check,
+        Box::leak(Box::new(Stack::<65536>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+fn task_hold() {
+    unsafe {
+        let ptr = HEAP.alloc(Layout::from_size_align(100, 8).unwrap());
+        assert!(!ptr.is_null());
+        // Kept until the end
+    }
+
+    loop {
+        task::park().unwrap();
+    }
+}
+
+fn task_check() {
+    unsafe {
+        let layout = Layout::from_size_align(300, 8).unwrap();
+        let ptr = HEAP.alloc(layout);
+        assert!(!ptr.is_null());
+        // Grown and then freed
+        let ptr = HEAP.realloc(ptr, layout, 500);
+        assert!(!ptr.is_null());
+        let layout = Layout::from_size_align(500, 8).unwrap();
+        assert!(HEAP.realloc(ptr, layout, 2 * LIMIT).is_null());
+        HEAP.dealloc(ptr, layout);
+
+        assert!(
+            HEAP.alloc(Layout::from_size_align(2 * LIMIT, 8).unwrap())
+                .is_null()
+        );
+    }
+
+    let heap = stats().heap;
+    let mut balances = Vec::new();
+    for_each_task(|task| {
+        if task.priority > 0 {
+            balances.push(task.heap_balance);
+        }
+    });
+
+    let expected = (heap.used, heap.peak, heap.failed_allocations);
+    if expected != (100, 600, 2) || balances != [Some(100), Some(0)] {
+        println!("{:?}, balances {:?}", heap, balances);
+        std::process::exit(1);
+    }
+
+    heap::reset_peak();
+    let heap = heap::stats();
+    if (heap.used, heap.peak, heap.failed_allocations) == (100, 100, 0) {
+        std::process::exit(0);
+    } else {
+        println!("After reset: {:?}", heap);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,55 @@
+//! Test of waiting for an IRQ event with a timeout
+
+use std::process::ExitCode;
+
+use taskette::{
+    scheduler::{SchedulerConfig, spawn},
+    sync::IrqEvent,
+    task::TaskConfig,
+    timer::{current_time, wait_until},
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+static EVENT: IrqEvent = IrqEvent::new();
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default().with_tick_freq(100)).unwrap();
+
+    spawn(
+        task_waiter,
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(2),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+fn task_waiter() {
+    // Nobody signals, so it times out
+    let start = current_time().unwrap();
+    assert!(!EVENT.wait_until(start + 5).unwrap());
+    assert!(current_time().unwrap() >= start + 5);
+
+    // Signaled by a lower-priority task as soon as this one waits
+    spawn(
+        || EVENT.signal(),
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+    let start = current_time().unwrap();
+    assert!(EVENT.wait_until(start + 10).unwrap());
+    assert!(current_time().unwrap() < start + 10);
+
+    // The timeout left in the timer queue does not cut a later sleep short
+    let start = current_time().unwrap();
+    wait_until(start + 20).unwrap();
+    let elapsed = current_time().unwrap() - start;
+    if elapsed >= 20 {
+        std::process::exit(0);
+    } else {
+        println!("Woke up after {} ticks", elapsed);
+        std::process::exit(1);
+    }
+}
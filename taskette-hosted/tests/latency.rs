@@ -0,0 +1,46 @@
+//! Test of the latency instrumentation
+
+use std::process::ExitCode;
+
+use taskette::{
+    scheduler::{SchedulerConfig, reset_stats, spawn, stats},
+    task::TaskConfig,
+    timer::{current_time, wait_until},
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default().with_tick_freq(100)).unwrap();
+
+    spawn(
+        || {
+            reset_stats();
+
+            for _ in 0..5 {
+                wait_until(current_time().unwrap() + 2).unwrap();
+            }
+
+            let stats = stats();
+            let measured = [
+                stats.tick_handler,
+                stats.context_switch,
+                stats.critical_section,
+            ];
+            if measured.iter().all(|latency| {
+                latency.samples >= 5
+                    && latency.min <= latency.average
+                    && latency.average <= latency.max
+            }) {
+                std::process::exit(0);
+            } else {
+                println!("{:?}", stats);
+                std::process::exit(1);
+            }
+        },
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
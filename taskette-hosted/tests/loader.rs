@@ -0,0 +1,127 @@
+//! Test of the applet loader
+
+use std::process::ExitCode;
+
+use taskette::task::TaskConfig;
+use taskette_hosted::{Stack, init_scheduler};
+use taskette_utils::loader::{self, LoadError};
+
+const WORD: usize = size_of::<usize>();
+const TEXT_SIZE: usize = 16;
+const BSS_SIZE: usize = 2 * WORD;
+const PLAIN_VALUE: usize = 0x1234;
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(Default::default()).unwrap();
+
+    let blob = build_blob();
+
+    // Broken blobs are rejected
+    let mut bad_magic = blob.to_vec();
+    bad_magic[0] = 0;
+    let ram = vec![0usize; 8].leak();
+    if loader::load(as_bytes(Vec::leak(bad_magic)), as_bytes_mut(ram)) != Err(LoadError::BadMagic) {
+        return ExitCode::FAILURE;
+    }
+    let truncated = &blob[..blob.len() - 1];
+    let ram = vec![0usize; 8].leak();
+    if loader::load(as_bytes(truncated), as_bytes_mut(ram)) != Err(LoadError::Truncated) {
+        return ExitCode::FAILURE;
+    }
+    let ram = vec![0usize; 1].leak();
+    if loader::load(as_bytes(blob), as_bytes_mut(ram)) != Err(LoadError::RamTooSmall) {
+        return ExitCode::FAILURE;
+    }
+    // Text size and number of relocations whose sizes do not fit in `u32`
+    for (field, value) in [(1, u32::MAX), (6, u32::MAX / 2)] {
+        let mut overflowing = blob.to_vec();
+        overflowing[field] = value;
+        let ram = vec![0usize; 8].leak();
+        if loader::load(as_bytes(Vec::leak(overflowing)), as_bytes_mut(ram))
+            != Err(LoadError::Overflow)
+        {
+            return ExitCode::FAILURE;
+        }
+    }
+    // Relocation of the first word of BSS, which is still inside the RAM
+    let mut out_of_range = blob.to_vec();
+    *out_of_range.last_mut().unwrap() = (3 * WORD) as u32;
+    let ram = vec![0usize; 8].leak();
+    if loader::load(as_bytes(Vec::leak(out_of_range)), as_bytes_mut(ram))
+        != Err(LoadError::BadRelocation)
+    {
+        return ExitCode::FAILURE;
+    }
+
+    let ram = vec![usize::MAX; 8].leak();
+    let ram_addr = ram.as_ptr() as usize;
+    let applet = loader::load(as_bytes(blob), as_bytes_mut(ram)).unwrap();
+    let text_base = blob.as_ptr() as usize + 7 * 4;
+    if applet.entry != text_base + 4 || applet.got != ram_addr {
+        return ExitCode::FAILURE;
+    }
+
+    // The blob does not contain real code, so a native function receives the GOT instead
+    unsafe {
+        loader::spawn_loaded(
+            applet_main as *const () as usize,
+            applet.got,
+            Box::leak(Box::new(Stack::<8192>::new())),
+            TaskConfig::default().with_priority(1),
+        )
+        .unwrap();
+    }
+
+    scheduler.start();
+}
+
+extern "C" fn applet_main(got: usize) {
+    let words = unsafe { std::slice::from_raw_parts(got as *const usize, 5) };
+    let text_base = BLOB.get().unwrap().as_ptr() as usize + 7 * 4;
+
+    let expected = [text_base + 8, got + WORD, PLAIN_VALUE, 0, 0];
+    if words == expected {
+        std::process::exit(0);
+    } else {
+        println!("Expected {:X?} but got {:X?}", expected, words);
+        std::process::exit(1);
+    }
+}
+
+static BLOB: std::sync::OnceLock<&'static [u32]> = std::sync::OnceLock::new();
+
+/// Builds an applet with a 3-word GOT (text pointer, data pointer, and a plain value) followed by BSS
+fn build_blob() -> &'static [u32] {
+    let data_size = 3 * WORD;
+    let mut blob = vec![
+        u32::from_le_bytes(*b"TKAP"),
+        TEXT_SIZE as u32,
+        data_size as u32,
+        BSS_SIZE as u32,
+        4, // entry
+        0, // got
+        2, // num_relocs
+    ];
+    blob.extend([0u32; TEXT_SIZE / 4]);
+    for value in [8, WORD, PLAIN_VALUE] {
+        blob.extend(
+            value
+                .to_ne_bytes()
+                .chunks(4)
+                .map(|c| u32::from_ne_bytes(c.try_into().unwrap())),
+        );
+    }
+    blob.extend([0, WORD as u32 | (1 << 31)]);
+
+    let blob = blob.leak();
+    BLOB.set(blob).unwrap();
+    blob
+}
+
+fn as_bytes(words: &'static [u32]) -> &'static [u8] {
+    unsafe { std::slice::from_raw_parts(words.as_ptr() as *const u8, words.len() * 4) }
+}
+
+fn as_bytes_mut(words: &'static mut [usize]) -> &'static mut [u8] {
+    unsafe { std::slice::from_raw_parts_mut(words.as_mut_ptr() as *mut u8, words.len() * WORD) }
+}
@@ -0,0 +1,58 @@
+//! Test of the lock hold-time watchdog
+
+use std::{process::ExitCode, sync::Mutex, time::Duration};
+
+use taskette::{
+    lock_watchdog::{self, HoldTimeViolation, LockKind},
+    scheduler::spawn,
+    sync::SpinLock,
+    task::{self, TaskConfig},
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+static LOCK: SpinLock<usize> = SpinLock::new(0);
+static VIOLATIONS: Mutex<Vec<HoldTimeViolation>> = Mutex::new(Vec::new());
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(Default::default()).unwrap();
+
+    // 1 ms (the counter of the hosted port counts microseconds)
+    lock_watchdog::set_threshold(1_000);
+    lock_watchdog::set_hook(|violation| VIOLATIONS.lock().unwrap().push(violation));
+
+    spawn(
+        || {
+            let task_id = task::current().unwrap().id();
+
+            // Short hold
+            *LOCK.lock() += 1;
+            if !VIOLATIONS.lock().unwrap().is_empty() {
+                std::process::exit(1);
+            }
+
+            // Long hold
+            {
+                let mut value = LOCK.lock();
+                std::thread::sleep(Duration::from_millis(5));
+                *value += 1;
+            }
+
+            let violations = VIOLATIONS.lock().unwrap();
+            if violations.iter().any(|violation| {
+                violation.lock == LockKind::SpinLock
+                    && violation.task_id == Some(task_id)
+                    && violation.duration >= 5_000
+            }) {
+                std::process::exit(0);
+            } else {
+                println!("{:?}", violations);
+                std::process::exit(1);
+            }
+        },
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default(),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
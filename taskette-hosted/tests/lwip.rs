@@ -0,0 +1,130 @@
+//! Test of the lwIP sys_arch port, called the same way as lwIP does
+
+use std::{ffi::c_void, process::ExitCode, ptr::null_mut};
+
+use taskette::{
+    scheduler::{SchedulerConfig, spawn},
+    task::TaskConfig,
+};
+use taskette_ffi::lwip::{
+    ERR_MEM, ERR_OK, LwipMbox, LwipSem, SYS_ARCH_TIMEOUT, SYS_MBOX_EMPTY, sys_arch_mbox_fetch,
+    sys_arch_mbox_tryfetch, sys_arch_protect, sys_arch_sem_wait, sys_arch_unprotect, sys_mbox_new,
+    sys_mbox_post, sys_mbox_trypost, sys_now, sys_sem_new, sys_sem_signal, sys_thread_new,
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+const NUM_MESSAGES: usize = 100;
+
+/// Objects shared with the thread
+struct Shared {
+    mbox: *mut LwipMbox,
+    done: *mut LwipSem,
+}
+
+fn check<T: PartialEq + std::fmt::Debug>(value: T, expected: T) {
+    if value != expected {
+        println!("Expected {:?} but got {:?}", expected, value);
+        std::process::exit(1);
+    }
+}
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default().with_tick_freq(1000)).unwrap();
+
+    spawn(
+        task_main,
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+fn task_main() {
+    let shared = Box::leak(Box::new(Shared {
+        mbox: null_mut(),
+        done: null_mut(),
+    }));
+    unsafe {
+        check(sys_mbox_new(&mut shared.mbox, 4), ERR_OK);
+        check(sys_sem_new(&mut shared.done, 0), ERR_OK);
+    }
+
+    // Empty mailbox and semaphore
+    let mut message = null_mut();
+    unsafe {
+        check(
+            sys_arch_mbox_tryfetch(&mut shared.mbox, &mut message),
+            SYS_MBOX_EMPTY,
+        );
+    }
+    let start = sys_now();
+    check(
+        unsafe { sys_arch_sem_wait(&mut shared.done, 20) },
+        SYS_ARCH_TIMEOUT,
+    );
+    check(sys_now().wrapping_sub(start) >= 20, true);
+
+    // Nested protection
+    let outer = sys_arch_protect();
+    let inner = sys_arch_protect();
+    sys_arch_unprotect(inner);
+    sys_arch_unprotect(outer);
+
+    // The thread has higher priority, like the tcpip thread
+    unsafe {
+        sys_thread_new(
+            c"tcpip_thread".as_ptr(),
+            Some(thread_echo),
+            shared as *mut Shared as *mut c_void,
+            8192,
+            2,
+        );
+    }
+
+    for i in 1..=NUM_MESSAGES {
+        unsafe { sys_mbox_post(&mut shared.mbox, i as *mut c_void) };
+    }
+    // Returns the time waited
+    check(
+        unsafe { sys_arch_sem_wait(&mut shared.done, 0) } != SYS_ARCH_TIMEOUT,
+        true,
+    );
+    std::process::exit(0);
+}
+
+extern "C" fn thread_echo(arg: *mut c_void) {
+    let shared = unsafe { &mut *(arg as *mut Shared) };
+
+    for i in 1..=NUM_MESSAGES {
+        let mut message = null_mut();
+        let waited = unsafe { sys_arch_mbox_fetch(&mut shared.mbox, &mut message, 1000) };
+        if waited == SYS_ARCH_TIMEOUT {
+            println!("Message #{} timed out", i);
+            std::process::exit(1);
+        }
+        check(message as usize, i);
+    }
+
+    // Nothing more is posted
+    let mut message = null_mut();
+    check(
+        unsafe { sys_arch_mbox_fetch(&mut shared.mbox, &mut message, 10) },
+        SYS_ARCH_TIMEOUT,
+    );
+
+    // Full mailbox
+    for i in 0..4 {
+        check(
+            unsafe { sys_mbox_trypost(&mut shared.mbox, i as *mut c_void) },
+            ERR_OK,
+        );
+    }
+    check(
+        unsafe { sys_mbox_trypost(&mut shared.mbox, null_mut()) },
+        ERR_MEM,
+    );
+
+    unsafe { sys_sem_signal(&mut shared.done) };
+}
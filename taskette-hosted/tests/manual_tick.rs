@@ -0,0 +1,59 @@
+//! Test of the manual tick injection
+
+use std::{
+    process::ExitCode,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use taskette::{
+    scheduler::{SchedulerConfig, spawn, test_advance_ticks},
+    task::TaskConfig,
+    timer::{current_time, wait_until},
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+/// Time when the sleeper woke up (0 while sleeping)
+static WOKEN_AT: AtomicU64 = AtomicU64::new(0);
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default().with_manual_tick(true)).unwrap();
+
+    spawn(
+        || {
+            // Runs until blocked, as it has higher priority than the driver
+            spawn(
+                || {
+                    wait_until(5).unwrap();
+                    WOKEN_AT.store(current_time().unwrap(), Ordering::SeqCst);
+                },
+                Box::leak(Box::new(Stack::<8192>::new())),
+                TaskConfig::default().with_priority(2),
+            )
+            .unwrap();
+
+            // Time does not advance by itself
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            if current_time().unwrap() != 0 {
+                std::process::exit(1);
+            }
+
+            test_advance_ticks(4).unwrap();
+            if current_time().unwrap() != 4 || WOKEN_AT.load(Ordering::SeqCst) != 0 {
+                std::process::exit(1);
+            }
+
+            // The sleeper runs before this returns
+            test_advance_ticks(1).unwrap();
+            if WOKEN_AT.load(Ordering::SeqCst) == 5 {
+                std::process::exit(0);
+            } else {
+                std::process::exit(1);
+            }
+        },
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
@@ -0,0 +1,104 @@
+//! Test of the monitor shell
+
+use std::{convert::Infallible, process::ExitCode};
+
+use embedded_io::{ErrorType, Read, Write};
+use taskette::{
+    futex::Futex,
+    scheduler::{SchedulerConfig, spawn},
+    task::TaskConfig,
+    timer::{current_time, wait_until},
+};
+use taskette_hosted::{Stack, init_scheduler};
+use taskette_utils::monitor::Monitor;
+
+static FUTEX: Futex = Futex::new(0);
+
+/// Stream reading from a fixed input and recording the output
+struct Terminal {
+    input: &'static [u8],
+    output: Vec<u8>,
+}
+
+impl ErrorType for Terminal {
+    type Error = Infallible;
+}
+
+impl Read for Terminal {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+        let len = buf.len().min(self.input.len());
+        buf[..len].copy_from_slice(&self.input[..len]);
+        self.input = &self.input[len..];
+        Ok(len)
+    }
+}
+
+impl Write for Terminal {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+        self.output.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default().with_tick_freq(100)).unwrap();
+
+    let waiter = spawn(
+        || {
+            FUTEX.wait(0).unwrap();
+        },
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(1).with_name("waiter"),
+    )
+    .unwrap();
+
+    spawn(
+        move || {
+            // Lets the waiter block
+            wait_until(current_time().unwrap() + 5).unwrap();
+
+            let input = format!(
+                "ps\r\nstacks\nkill {}\nps\nkill 0\nstats\nfoo\n",
+                waiter.id()
+            );
+            let terminal = Terminal {
+                input: String::leak(input).as_bytes(),
+                output: Vec::new(),
+            };
+            let mut monitor = Monitor::new(terminal).with_echo(false);
+            monitor.run().unwrap();
+            let output = String::from_utf8(monitor.into_inner().output).unwrap();
+            print!("{}", output);
+
+            // The futex is still usable after its waiter is killed
+            FUTEX.wake_one().unwrap();
+
+            let sections: Vec<&str> = output.split("> ").collect();
+            if sections.len() == 9
+                && sections[1].contains("waiter")
+                && sections[2].starts_with("  ID NAME")
+                && sections[2].contains("USED")
+                && sections[3] == format!("Task #{} killed\r\n", waiter.id())
+                && !sections[4].contains("waiter")
+                && sections[4].contains("shell")
+                && sections[5] == "Cannot kill Task #0: NotPermitted\r\n"
+                && sections[6].starts_with("context switches")
+                && sections[7].starts_with("Unknown command: foo")
+                && !output.replace("\r\n", "").contains('\n')
+            {
+                std::process::exit(0);
+            } else {
+                std::process::exit(1);
+            }
+        },
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(2).with_name("shell"),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
@@ -0,0 +1,151 @@
+//! Test of the smoltcp network task and blocking sockets over a loopback device
+
+use std::process::ExitCode;
+
+use smoltcp::{
+    iface::{Config, Interface, SocketSet},
+    phy::{self, Device, DeviceCapabilities, Loopback, Medium},
+    socket::{tcp, udp},
+    time::Instant,
+    wire::{HardwareAddress, IpAddress, IpCidr, IpEndpoint, Ipv4Address},
+};
+use taskette::{
+    scheduler::{SchedulerConfig, spawn},
+    sync::IrqEvent,
+    task::TaskConfig,
+};
+use taskette_hosted::{Stack, init_scheduler};
+use taskette_utils::net::NetStack;
+
+const ADDRESS: Ipv4Address = Ipv4Address::new(127, 0, 0, 1);
+
+static EVENT: IrqEvent = IrqEvent::new();
+
+/// Loopback device signaling `EVENT` on every transmission, like a MAC raising an RX interrupt
+struct IrqLoopback(Loopback);
+
+struct IrqTxToken<T>(T);
+
+impl<T: phy::TxToken> phy::TxToken for IrqTxToken<T> {
+    fn consume<R, F: FnOnce(&mut [u8]) -> R>(self, len: usize, f: F) -> R {
+        let result = self.0.consume(len, f);
+        EVENT.signal();
+        result
+    }
+}
+
+impl Device for IrqLoopback {
+    type RxToken<'a> = <Loopback as Device>::RxToken<'a>;
+    type TxToken<'a> = IrqTxToken<<Loopback as Device>::TxToken<'a>>;
+
+    fn receive(&mut self, timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        self.0
+            .receive(timestamp)
+            .map(|(rx, tx)| (rx, IrqTxToken(tx)))
+    }
+
+    fn transmit(&mut self, timestamp: Instant) -> Option<Self::TxToken<'_>> {
+        self.0.transmit(timestamp).map(IrqTxToken)
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        self.0.capabilities()
+    }
+}
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default().with_tick_freq(100)).unwrap();
+
+    let mut device = IrqLoopback(Loopback::new(Medium::Ip));
+    let mut iface = Interface::new(Config::new(HardwareAddress::Ip), &mut device, Instant::ZERO);
+    iface.update_ip_addrs(|addrs| {
+        addrs
+            .push(IpCidr::new(IpAddress::Ipv4(ADDRESS), 8))
+            .unwrap();
+    });
+    let stack: &'static NetStack<'static> = Box::leak(Box::new(NetStack::new(
+        iface,
+        SocketSet::new(vec![]),
+        &EVENT,
+    )));
+
+    spawn(
+        move || stack.run(&mut device),
+        Box::leak(Box::new(Stack::<65536>::new())),
+        TaskConfig::default().with_priority(3),
+    )
+    .unwrap();
+    spawn(
+        move || task_server(stack),
+        Box::leak(Box::new(Stack::<65536>::new())),
+        TaskConfig::default().with_priority(2),
+    )
+    .unwrap();
+    spawn(
+        move || task_client(stack),
+        Box::leak(Box::new(Stack::<65536>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+fn tcp_socket() -> tcp::Socket<'static> {
+    tcp::Socket::new(
+        tcp::SocketBuffer::new(vec![0; 1024]),
+        tcp::SocketBuffer::new(vec![0; 1024]),
+    )
+}
+
+fn udp_socket() -> udp::Socket<'static> {
+    udp::Socket::new(
+        udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY; 4], vec![0; 1024]),
+        udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY; 4], vec![0; 1024]),
+    )
+}
+
+fn task_server(stack: &'static NetStack<'static>) {
+    // Echoes a TCP connection until the client closes it
+    let socket = stack.tcp(stack.add_socket(tcp_socket()));
+    socket.accept(1234).unwrap();
+    let mut buf = [0u8; 64];
+    loop {
+        let len = socket.read(&mut buf).unwrap();
+        if len == 0 {
+            break;
+        }
+        socket.write(&buf[..len]).unwrap();
+    }
+    socket.close();
+
+    // Echoes a UDP datagram
+    let socket = stack.udp(stack.add_socket(udp_socket()));
+    socket.bind(5678).unwrap();
+    let (len, remote) = socket.recv_from(&mut buf).unwrap();
+    socket.send_to(&buf[..len], remote).unwrap();
+}
+
+fn task_client(stack: &'static NetStack<'static>) {
+    let socket = stack.tcp(stack.add_socket(tcp_socket()));
+    socket.connect((ADDRESS, 1234), 49152).unwrap();
+    socket.write(b"hello").unwrap();
+    let mut buf = [0u8; 64];
+    let mut received = 0;
+    while received < 5 {
+        received += socket.read(&mut buf[received..]).unwrap();
+    }
+    assert_eq!(&buf[..5], b"hello");
+    socket.close();
+    // Returns 0 once the server closes its side too
+    assert_eq!(socket.read(&mut buf).unwrap(), 0);
+
+    let socket = stack.udp(stack.add_socket(udp_socket()));
+    socket.bind(5679).unwrap();
+    socket.send_to(b"world", (ADDRESS, 5678)).unwrap();
+    let (len, remote) = socket.recv_from(&mut buf).unwrap();
+    assert_eq!(&buf[..len], b"world");
+    assert_eq!(remote, IpEndpoint::new(IpAddress::Ipv4(ADDRESS), 5678));
+
+    std::process::exit(0);
+}
@@ -0,0 +1,66 @@
+//! Test of the scheduler invariant checks under blocking, waking, and finishing tasks
+
+use std::process::ExitCode;
+
+use taskette::{
+    scheduler::{set_assert_hook, spawn},
+    sync::Channel,
+    task::TaskConfig,
+    timer::{current_time, wait_until},
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+const NUM_VALUES: u32 = 100;
+
+static CHANNEL: Channel<u32, 2> = Channel::new();
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(Default::default()).unwrap();
+
+    set_assert_hook(|message| {
+        println!("{}", message);
+        std::process::exit(1);
+    });
+
+    // Short-lived sleepers
+    for priority in 1..=3 {
+        spawn(
+            move || {
+                for _ in 0..3 {
+                    wait_until(current_time().unwrap() + priority as u64).unwrap();
+                }
+            },
+            Box::leak(Box::new(Stack::<8192>::new())),
+            TaskConfig::default().with_priority(priority),
+        )
+        .unwrap();
+    }
+
+    spawn(
+        || {
+            for i in 0..NUM_VALUES {
+                CHANNEL.send(i).unwrap();
+            }
+        },
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    spawn(
+        || {
+            for _ in 0..NUM_VALUES {
+                CHANNEL.recv().unwrap();
+            }
+            // Let the sleepers finish
+            wait_until(current_time().unwrap() + 20).unwrap();
+
+            std::process::exit(0);
+        },
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(2),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
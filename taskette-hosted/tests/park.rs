@@ -0,0 +1,77 @@
+//! Test of parking and unparking tasks
+
+use std::{process::ExitCode, sync::Mutex};
+
+use taskette::{
+    Error,
+    scheduler::spawn,
+    task::{self, TaskConfig},
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+static NUMBERS: Mutex<Vec<i32>> = Mutex::new(Vec::new());
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(Default::default()).unwrap();
+
+    spawn(
+        task_low,
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+fn task_low() {
+    // Launch a high-priority task, which runs until it parks
+    let task_high = spawn(
+        task_high,
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(2),
+    )
+    .unwrap();
+
+    for i in 1000..2000 {
+        put_number(i);
+    }
+
+    // Allow `task_high` to finish
+    task::unpark(task_high.id()).unwrap();
+
+    assert!(matches!(task::unpark(task_high.id()), Err(Error::NotFound)));
+
+    // Check result
+    let numbers = NUMBERS.lock().unwrap();
+    if numbers.iter().cloned().eq(0..3000) {
+        std::process::exit(0);
+    } else {
+        println!("{:?}", numbers);
+        std::process::exit(1);
+    }
+}
+
+fn task_high() {
+    let me = task::current().unwrap().id();
+
+    // The token is left, so this returns immediately. Tokens do not accumulate.
+    task::unpark(me).unwrap();
+    task::unpark(me).unwrap();
+    task::park().unwrap();
+
+    for i in 0..1000 {
+        put_number(i);
+    }
+
+    // Wait until `task_low` unparks
+    task::park().unwrap();
+
+    for i in 2000..3000 {
+        put_number(i);
+    }
+}
+
+fn put_number(num: i32) {
+    NUMBERS.lock().unwrap().push(num);
+}
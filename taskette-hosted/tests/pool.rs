@@ -0,0 +1,61 @@
+//! Test of the memory pool
+
+use std::process::ExitCode;
+
+use taskette::{
+    scheduler::spawn,
+    sync::{Channel, Pool, PoolBox},
+    task::TaskConfig,
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+const NUM_VALUES: u32 = 1000;
+
+/// Smaller than the channel, so that the sender is blocked by the pool
+static POOL: Pool<u32, 2> = Pool::new();
+static CHANNEL: Channel<PoolBox<'static, u32>, 4> = Channel::new();
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(Default::default()).unwrap();
+
+    spawn(
+        task_receiver,
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+    spawn(
+        task_sender,
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(2),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+fn task_sender() {
+    for i in 0..NUM_VALUES {
+        let message = POOL.alloc(i).unwrap();
+        if CHANNEL.send(message).is_err() {
+            std::process::exit(1);
+        }
+    }
+}
+
+fn task_receiver() {
+    for i in 0..NUM_VALUES {
+        let message = CHANNEL.recv().unwrap();
+        if *message != i {
+            println!("Expected {} but received {}", i, *message);
+            std::process::exit(1);
+        }
+    }
+
+    // All blocks are returned
+    if POOL.available() == 2 {
+        std::process::exit(0);
+    } else {
+        std::process::exit(1);
+    }
+}
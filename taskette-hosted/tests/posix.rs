@@ -0,0 +1,148 @@
+//! Test of the POSIX threads subset, called the same way as from C
+
+use std::{
+    ffi::{c_int, c_void},
+    mem::MaybeUninit,
+    process::ExitCode,
+    ptr::{null, null_mut},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use taskette::{scheduler::spawn, task::TaskConfig};
+use taskette_hosted::{Stack, init_scheduler};
+use taskette_posix::{
+    EBUSY, EDEADLK, PthreadAttr, PthreadT,
+    cond::{PthreadCond, pthread_cond_init, pthread_cond_signal, pthread_cond_wait},
+    mutex::{
+        PthreadMutex, pthread_mutex_init, pthread_mutex_lock, pthread_mutex_trylock,
+        pthread_mutex_unlock,
+    },
+    pthread_attr_init, pthread_attr_setstack, pthread_create, pthread_join, pthread_self,
+    sched_yield,
+    semaphore::{Sem, sem_getvalue, sem_init, sem_post, sem_wait},
+};
+
+const NUM_VALUES: usize = 100;
+const RETVAL: usize = 42;
+
+/// Objects shared with the producer
+struct Shared {
+    mutex: *mut PthreadMutex,
+    cond: *mut PthreadCond,
+    sem: *mut Sem,
+    /// Protected by `mutex`
+    count: AtomicUsize,
+}
+
+fn check(code: c_int, expected: c_int) {
+    if code != expected {
+        println!("Expected {} but got {}", expected, code);
+        std::process::exit(1);
+    }
+}
+
+fn leak<T>() -> *mut T {
+    Box::leak(Box::new(MaybeUninit::<T>::uninit())).as_mut_ptr()
+}
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(Default::default()).unwrap();
+
+    spawn(
+        task_consumer,
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+fn task_consumer() {
+    let shared = Box::leak(Box::new(Shared {
+        mutex: leak(),
+        cond: leak(),
+        sem: leak(),
+        count: AtomicUsize::new(0),
+    }));
+    unsafe {
+        check(pthread_mutex_init(shared.mutex, null()), 0);
+        check(pthread_cond_init(shared.cond, null()), 0);
+        check(sem_init(shared.sem, 0, 0), 0);
+    }
+
+    // Not created by `pthread_create`
+    assert_eq!(pthread_self(), 0);
+
+    let mut attr = MaybeUninit::<PthreadAttr>::uninit();
+    let stack = Box::leak(vec![0u8; 8192].into_boxed_slice());
+    let mut thread: PthreadT = 0;
+    unsafe {
+        check(pthread_attr_init(attr.as_mut_ptr()), 0);
+        check(
+            pthread_attr_setstack(
+                attr.as_mut_ptr(),
+                stack.as_mut_ptr() as *mut c_void,
+                stack.len(),
+            ),
+            0,
+        );
+        check(
+            pthread_create(
+                &mut thread,
+                attr.as_ptr(),
+                Some(thread_producer),
+                shared as *mut Shared as *mut c_void,
+            ),
+            0,
+        );
+    }
+    assert_ne!(thread, 0);
+
+    for _ in 0..NUM_VALUES {
+        check(unsafe { sem_wait(shared.sem) }, 0);
+    }
+    let mut value = -1;
+    check(unsafe { sem_getvalue(shared.sem, &mut value) }, 0);
+    assert_eq!(value, 0);
+
+    unsafe {
+        check(pthread_mutex_lock(shared.mutex), 0);
+        check(pthread_mutex_lock(shared.mutex), EDEADLK);
+        while shared.count.load(Ordering::Relaxed) < NUM_VALUES {
+            check(pthread_cond_wait(shared.cond, shared.mutex), 0);
+        }
+        check(pthread_mutex_unlock(shared.mutex), 0);
+    }
+
+    let mut retval = null_mut();
+    check(unsafe { pthread_join(thread, &mut retval) }, 0);
+    assert_eq!(retval as usize, RETVAL);
+
+    std::process::exit(0);
+}
+
+extern "C" fn thread_producer(arg: *mut c_void) -> *mut c_void {
+    let shared = unsafe { &*(arg as *const Shared) };
+
+    assert_ne!(pthread_self(), 0);
+
+    for _ in 0..NUM_VALUES {
+        unsafe {
+            check(pthread_mutex_lock(shared.mutex), 0);
+            shared.count.fetch_add(1, Ordering::Relaxed);
+            check(pthread_cond_signal(shared.cond), 0);
+            check(pthread_mutex_unlock(shared.mutex), 0);
+            check(sem_post(shared.sem), 0);
+        }
+        sched_yield();
+    }
+
+    unsafe {
+        check(pthread_mutex_trylock(shared.mutex), 0);
+        check(pthread_mutex_trylock(shared.mutex), EBUSY);
+        check(pthread_mutex_unlock(shared.mutex), 0);
+    }
+
+    RETVAL as *mut c_void
+}
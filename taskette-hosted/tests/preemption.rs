@@ -0,0 +1,56 @@
+//! Test of preemption by higher-priority task
+
+use std::{process::ExitCode, sync::Mutex};
+
+use taskette::{scheduler::spawn, task::TaskConfig};
+use taskette_hosted::{Stack, init_scheduler};
+
+static NUMBERS: Mutex<Vec<i32>> = Mutex::new(Vec::new());
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(Default::default()).unwrap();
+
+    let _task_low = spawn(
+        task_low,
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+fn task_low() {
+    // Launch high-priority task
+    let _task_high = spawn(
+        task_high,
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(2),
+    )
+    .unwrap();
+
+    // This will be delayed until the high-priority task completes
+    for i in 1000..2000 {
+        put_number(i);
+    }
+
+    // Check result
+    let numbers = NUMBERS.lock().unwrap();
+    if numbers.iter().cloned().eq(0..2000) {
+        std::process::exit(0);
+    } else {
+        // If the low priority task is not preempted correctly, the numbers will be incorrectly ordered
+        println!("{:?}", numbers);
+        std::process::exit(1);
+    }
+}
+
+fn task_high() {
+    for i in 0..1000 {
+        put_number(i);
+    }
+}
+
+fn put_number(num: i32) {
+    NUMBERS.lock().unwrap().push(num);
+}
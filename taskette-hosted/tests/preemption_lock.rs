@@ -0,0 +1,77 @@
+//! Test of the preemption lock
+
+use std::{process::ExitCode, sync::Mutex};
+
+use taskette::{
+    scheduler::{lock_preemption, spawn},
+    task::TaskConfig,
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+static NUMBERS: Mutex<Vec<i32>> = Mutex::new(Vec::new());
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(Default::default()).unwrap();
+
+    spawn(
+        task_low,
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+fn task_low() {
+    {
+        let _guard = lock_preemption();
+
+        // Not switched to until the lock is released
+        spawn_high(1000);
+
+        for i in 0..1000 {
+            put_number(i);
+        }
+    }
+
+    // With the feature, a critical section also only locks preemption
+    #[cfg(feature = "preemption-critical-section")]
+    critical_section::with(|_| {
+        spawn_high(3000);
+
+        for i in 2000..3000 {
+            put_number(i);
+        }
+    });
+    #[cfg(not(feature = "preemption-critical-section"))]
+    for i in 2000..4000 {
+        put_number(i);
+    }
+
+    // Check result
+    let numbers = NUMBERS.lock().unwrap();
+    if numbers.iter().cloned().eq(0..4000) {
+        std::process::exit(0);
+    } else {
+        println!("{:?}", numbers);
+        std::process::exit(1);
+    }
+}
+
+fn spawn_high(start: i32) {
+    spawn(
+        move || {
+            for i in start..start + 1000 {
+                put_number(i);
+            }
+        },
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(2),
+    )
+    .unwrap();
+}
+
+fn put_number(num: i32) {
+    NUMBERS.lock().unwrap().push(num);
+}
@@ -0,0 +1,68 @@
+//! Test of running finished tasks again on their original stacks
+
+use std::{
+    process::ExitCode,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use taskette::{
+    Error,
+    scheduler::{SchedulerConfig, spawn},
+    task::{self, TaskConfig, spawn_respawnable},
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default()).unwrap();
+
+    spawn(
+        task_coordinator,
+        new_stack(),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+fn new_stack() -> &'static mut Stack<65536> {
+    Box::leak(Box::new(Stack::<65536>::new()))
+}
+
+fn worker() {
+    RUNS.fetch_add(1, Ordering::SeqCst);
+}
+
+fn parker() {
+    loop {
+        task::park().unwrap();
+    }
+}
+
+fn task_coordinator() {
+    let config = TaskConfig::default().with_priority(2).with_name("worker");
+
+    // The higher-priority worker runs to completion right away
+    let handle = spawn_respawnable(worker, new_stack(), config.clone()).unwrap();
+    assert!(handle.is_finished());
+    for runs in 2..=5 {
+        handle.respawn().unwrap();
+        assert!(handle.is_finished());
+        assert_eq!(RUNS.load(Ordering::SeqCst), runs);
+    }
+
+    // Not while the task is alive
+    let parked = spawn_respawnable(parker, new_stack(), config.clone()).unwrap();
+    assert!(matches!(parked.respawn(), Err(Error::NotPermitted)));
+    task::kill(parked.id()).unwrap();
+    parked.respawn().unwrap();
+    assert!(!parked.is_finished());
+
+    // Only respawnable tasks
+    let normal = spawn(worker, new_stack(), config).unwrap();
+    assert!(matches!(normal.respawn(), Err(Error::NotFound)));
+
+    std::process::exit(0);
+}
@@ -0,0 +1,76 @@
+//! Test of the task table for debuggers
+
+use std::process::ExitCode;
+
+use taskette::{
+    rtos_awareness::{DebugInfo, DebugTaskState, LAYOUT_VERSION},
+    scheduler::{SchedulerConfig, spawn},
+    task::{TaskConfig, current},
+    timer::{current_time, wait_until},
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+unsafe extern "Rust" {
+    // Read through the exported symbol, as a debugger does
+    #[link_name = "taskette_debug_info"]
+    static DEBUG_INFO: DebugInfo;
+}
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default()).unwrap();
+
+    let sleeper = spawn(
+        || loop {
+            wait_until(current_time().unwrap() + 1000).unwrap();
+        },
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_name("sleeper"),
+    )
+    .unwrap();
+
+    spawn(
+        move || {
+            // Let the sleeper block
+            wait_until(current_time().unwrap() + 2).unwrap();
+
+            let this_id = current().unwrap().id();
+            let info = critical_section::with(|_| unsafe {
+                std::ptr::read_volatile(&raw const DEBUG_INFO)
+            });
+
+            let find = |id: usize| {
+                info.tasks
+                    .iter()
+                    .find(|slot| slot.state != DebugTaskState::Unused as u32 && slot.id == id)
+            };
+            let name = |id: usize| {
+                find(id).map(|slot| unsafe {
+                    std::str::from_utf8(std::slice::from_raw_parts(slot.name, slot.name_len))
+                        .unwrap()
+                })
+            };
+
+            let ok = info.version == LAYOUT_VERSION
+                && info.current_task[0] == this_id
+                && find(this_id).is_some_and(|slot| slot.state == DebugTaskState::Running as u32)
+                && find(sleeper.id()).is_some_and(|slot| {
+                    slot.state == DebugTaskState::Blocked as u32
+                        && (slot.stack_limit..slot.stack_end).contains(&slot.stack_pointer)
+                })
+                && name(this_id) == Some("checker")
+                && name(sleeper.id()) == Some("sleeper");
+
+            if ok {
+                std::process::exit(0);
+            } else {
+                println!("{:?}", info);
+                std::process::exit(1);
+            }
+        },
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_name("checker"),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
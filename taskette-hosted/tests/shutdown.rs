@@ -0,0 +1,91 @@
+//! Test of winding down tasks with a shutdown request
+
+use std::{
+    process::ExitCode,
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use taskette::{
+    Error,
+    scheduler::{
+        SchedulerConfig, is_shutdown_requested, request_shutdown, set_shutdown_hook,
+        shutdown_token, spawn,
+    },
+    supervisor::{RestartPolicy, spawn_supervised},
+    sync::Channel,
+    task::{TaskConfig, TaskHandle},
+    timer::{current_time, wait_until},
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+static REQUESTS: Channel<u32, 4> = Channel::new();
+static RUNS: AtomicUsize = AtomicUsize::new(0);
+static WORKERS: Mutex<Vec<TaskHandle>> = Mutex::new(Vec::new());
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default().with_tick_freq(1000)).unwrap();
+
+    set_shutdown_hook(shutdown_hook);
+
+    let config = TaskConfig::default().with_priority(2);
+    let receiver = spawn(task_receiver, new_stack(), config.clone()).unwrap();
+    let supervised = spawn_supervised(
+        task_supervised,
+        new_stack(),
+        config,
+        RestartPolicy::Always,
+        5,
+    )
+    .unwrap();
+    *WORKERS.lock().unwrap() = vec![receiver, supervised];
+
+    spawn(
+        task_coordinator,
+        new_stack(),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+fn new_stack() -> &'static mut Stack<65536> {
+    Box::leak(Box::new(Stack::<65536>::new()))
+}
+
+fn task_receiver() {
+    loop {
+        match REQUESTS.recv_cancellable(shutdown_token()) {
+            Ok(_) => (),
+            Err(Error::Cancelled) => return,
+            Err(error) => panic!("{:?}", error),
+        }
+    }
+}
+
+/// Restarted after each short run, until the shutdown
+fn task_supervised() {
+    RUNS.fetch_add(1, Ordering::SeqCst);
+    let _ = shutdown_token().sleep_until(current_time().unwrap() + 3);
+}
+
+fn task_coordinator() {
+    wait_until(current_time().unwrap() + 30).unwrap();
+    assert!(RUNS.load(Ordering::SeqCst) > 1);
+
+    request_shutdown(current_time().unwrap() + 1000).unwrap();
+    unreachable!("The hook does not return");
+}
+
+fn shutdown_hook() -> ! {
+    let exited = WORKERS.lock().unwrap().iter().all(TaskHandle::is_finished);
+
+    std::process::exit(if exited && is_shutdown_requested() {
+        0
+    } else {
+        1
+    });
+}
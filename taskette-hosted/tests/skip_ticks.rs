@@ -0,0 +1,63 @@
+//! Test of skipping ticks at once (as done by ports which sleep while idle)
+
+use std::{
+    process::ExitCode,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use taskette::{
+    scheduler::{SchedulerConfig, spawn, test_advance_ticks},
+    task::TaskConfig,
+    timer::{current_time, skip_ticks, wait_until},
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+/// Time when the sleeper woke up (0 while sleeping)
+static WOKEN_AT: AtomicU64 = AtomicU64::new(0);
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default().with_manual_tick(true)).unwrap();
+
+    spawn(
+        || {
+            spawn(
+                || {
+                    wait_until(50).unwrap();
+                    WOKEN_AT.store(current_time().unwrap(), Ordering::SeqCst);
+                },
+                Box::leak(Box::new(Stack::<8192>::new())),
+                TaskConfig::default().with_priority(2),
+            )
+            .unwrap();
+
+            skip_ticks(20);
+            if current_time().unwrap() != 20 || WOKEN_AT.load(Ordering::SeqCst) != 0 {
+                std::process::exit(1);
+            }
+
+            // Stops just before the wakeup, which is left to the next tick
+            skip_ticks(100);
+            if current_time().unwrap() != 49 || WOKEN_AT.load(Ordering::SeqCst) != 0 {
+                std::process::exit(1);
+            }
+
+            test_advance_ticks(1).unwrap();
+            if WOKEN_AT.load(Ordering::SeqCst) != 50 {
+                std::process::exit(1);
+            }
+
+            // Without pending wakeups, the time advances freely
+            skip_ticks(100);
+            if current_time().unwrap() == 150 {
+                std::process::exit(0);
+            } else {
+                std::process::exit(1);
+            }
+        },
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
@@ -0,0 +1,47 @@
+//! Test of the spin lock
+
+use std::process::ExitCode;
+
+use taskette::{arch::yield_now, scheduler::spawn, sync::SpinLock, task::TaskConfig};
+use taskette_hosted::{Stack, init_scheduler};
+
+const NUM_INCREMENTS: usize = 1000;
+
+static COUNTER: SpinLock<(usize, usize)> = SpinLock::new((0, 0));
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(Default::default()).unwrap();
+
+    for _ in 0..2 {
+        spawn(
+            task_increment,
+            Box::leak(Box::new(Stack::<8192>::new())),
+            TaskConfig::default(),
+        )
+        .unwrap();
+    }
+
+    scheduler.start();
+}
+
+fn task_increment() {
+    for _ in 0..NUM_INCREMENTS {
+        let mut counter = COUNTER.lock_irq();
+        counter.0 += 1;
+        drop(counter);
+
+        yield_now();
+    }
+
+    // The second task to finish checks the result
+    let mut counter = COUNTER.lock();
+    counter.1 += 1;
+    if counter.1 == 2 {
+        if counter.0 == 2 * NUM_INCREMENTS && COUNTER.try_lock().is_none() {
+            std::process::exit(0);
+        } else {
+            println!("{:?}", *counter);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! Test of the per-task setting of the stack canary
+
+use std::{
+    process::ExitCode,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use taskette::{
+    arch::yield_now,
+    scheduler::{SchedulerConfig, set_stack_overflow_hook, spawn},
+    task::TaskConfig,
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+static OVERFLOWED: AtomicUsize = AtomicUsize::new(usize::MAX);
+static PROTECTED_ID: AtomicUsize = AtomicUsize::new(0);
+
+fn main() -> ExitCode {
+    // Only tasks opting in have the canary
+    let scheduler =
+        init_scheduler(SchedulerConfig::default().with_stack_canary_by_default(false)).unwrap();
+    set_stack_overflow_hook(|task_id| OVERFLOWED.store(task_id, Ordering::SeqCst));
+
+    let unprotected_stack = Box::leak(Box::new(Stack::<65536>::new()));
+    let unprotected_bottom = unprotected_stack as *mut Stack<65536> as *mut u32;
+    spawn(task_unprotected, unprotected_stack, TaskConfig::default()).unwrap();
+
+    let protected_stack = Box::leak(Box::new(Stack::<65536>::new()));
+    let protected_bottom = protected_stack as *mut Stack<65536> as *mut u32;
+    let protected = spawn(
+        task_protected,
+        protected_stack,
+        TaskConfig::default().with_stack_canary(true),
+    )
+    .unwrap();
+    PROTECTED_ID.store(protected.id(), Ordering::SeqCst);
+
+    unsafe {
+        if unprotected_bottom.read() != 0 {
+            println!("Canary written to the stack of a task without it");
+            return ExitCode::FAILURE;
+        }
+        if protected_bottom.read() != SchedulerConfig::default().stack_canary_pattern {
+            println!("Canary not written to the stack of a task with it");
+            return ExitCode::FAILURE;
+        }
+
+        // Simulates an overflow of both tasks
+        unprotected_bottom.write(0xFFFF_FFFF);
+        protected_bottom.write(0xFFFF_FFFF);
+    }
+
+    scheduler.start();
+}
+
+fn task_unprotected() {
+    for _ in 0..100 {
+        yield_now();
+
+        if OVERFLOWED.load(Ordering::SeqCst) == PROTECTED_ID.load(Ordering::SeqCst) {
+            // Still running, so the overwritten bottom of this stack was not checked
+            std::process::exit(0);
+        }
+    }
+
+    println!("Overflow of the task with the canary not detected");
+    std::process::exit(1);
+}
+
+fn task_protected() {
+    loop {
+        yield_now();
+    }
+}
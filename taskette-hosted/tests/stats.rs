@@ -0,0 +1,72 @@
+//! Test of the kernel statistics counters
+
+use std::process::ExitCode;
+
+use taskette::{
+    futex::Futex,
+    portable_atomic::Ordering,
+    scheduler::{SchedulerConfig, reset_stats, spawn, stats},
+    sync::interrupt_free,
+    task::TaskConfig,
+    timer::{current_time, wait_until},
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+static FUTEX: Futex = Futex::new(0);
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default().with_tick_freq(100)).unwrap();
+
+    spawn(
+        || {
+            reset_stats();
+
+            // Keeps running until woken up by the checker, which preempts this task every time it wakes up
+            spawn(
+                || {
+                    // A hosted task is only preempted on kernel calls (such as leaving a critical section)
+                    while FUTEX.as_ref().load(Ordering::SeqCst) == 0 {
+                        interrupt_free(|_| ());
+                    }
+                },
+                Box::leak(Box::new(Stack::<8192>::new())),
+                TaskConfig::default().with_priority(1),
+            )
+            .unwrap();
+
+            for _ in 0..5 {
+                wait_until(current_time().unwrap() + 2).unwrap();
+            }
+
+            // Blocks until the waker below runs
+            spawn(
+                || {
+                    FUTEX.as_ref().store(1, Ordering::SeqCst);
+                    FUTEX.wake_all().unwrap();
+                },
+                Box::leak(Box::new(Stack::<8192>::new())),
+                TaskConfig::default().with_priority(1),
+            )
+            .unwrap();
+            FUTEX.wait(0).unwrap();
+
+            let stats = stats();
+            if stats.context_switches >= 10
+                && stats.preemptions >= 5
+                && stats.ticks >= 10
+                && stats.timer_wakeups >= 5
+                && stats.futex_waits >= 1
+            {
+                std::process::exit(0);
+            } else {
+                println!("{:?}", stats);
+                std::process::exit(1);
+            }
+        },
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(2),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
@@ -0,0 +1,55 @@
+//! Test of measuring elapsed time with a stopwatch
+
+use std::{process::ExitCode, time::Duration};
+
+use taskette::{
+    scheduler::{SchedulerConfig, spawn},
+    task::TaskConfig,
+    timer::{current_time, wait_until},
+};
+use taskette_hosted::{Stack, init_scheduler};
+use taskette_utils::time::Stopwatch;
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default().with_tick_freq(100)).unwrap();
+
+    spawn(
+        task_main,
+        Box::leak(Box::new(Stack::<65536>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+fn assert_near(measured: Duration, expected: Duration) {
+    // Within a tick (10 ms) plus some slack for the host
+    let tolerance = Duration::from_millis(15);
+    assert!(
+        measured + tolerance >= expected && measured <= expected + tolerance,
+        "measured {:?}, expected {:?}",
+        measured,
+        expected
+    );
+}
+
+fn task_main() {
+    let mut stopwatch = Stopwatch::start().unwrap();
+
+    // Blocked for 5 ticks
+    wait_until(current_time().unwrap() + 5).unwrap();
+    assert_near(stopwatch.lap(), Duration::from_millis(50));
+
+    // Busy for less than a tick, which is still measured thanks to the sub-tick time
+    std::thread::sleep(Duration::from_millis(4));
+    let lap = stopwatch.lap();
+    assert!(lap >= Duration::from_millis(3), "{:?}", lap);
+    assert_near(lap, Duration::from_millis(4));
+
+    assert_near(stopwatch.elapsed(), Duration::from_millis(54));
+    assert_near(stopwatch.restart(), Duration::from_millis(54));
+    assert!(stopwatch.elapsed() < Duration::from_millis(10));
+
+    std::process::exit(0);
+}
@@ -0,0 +1,46 @@
+//! Test of the scheduler storage supplied by the application
+
+use std::process::ExitCode;
+
+use taskette::{
+    Error,
+    scheduler::{SchedulerStorage, spawn},
+    task::TaskConfig,
+    timer,
+};
+use taskette_hosted::{Stack, init_scheduler_with_storage};
+
+fn main() -> ExitCode {
+    // Room for the idle task and 2 other tasks
+    let storage = Box::leak(Box::new(SchedulerStorage::<3, 2>::new()));
+    let scheduler = init_scheduler_with_storage(Default::default(), storage).unwrap();
+
+    for _ in 0..2 {
+        spawn(
+            task_sleeper,
+            Box::leak(Box::new(Stack::<8192>::new())),
+            TaskConfig::default(),
+        )
+        .unwrap();
+    }
+
+    // The task table is full
+    let result = spawn(
+        task_sleeper,
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default(),
+    );
+    if !matches!(result, Err(Error::TaskFull)) {
+        println!("Spawning beyond the storage size did not fail");
+        return ExitCode::FAILURE;
+    }
+
+    scheduler.start();
+}
+
+fn task_sleeper() {
+    let time = timer::current_time().unwrap();
+    timer::wait_until(time + 10).unwrap();
+
+    std::process::exit(0);
+}
@@ -0,0 +1,74 @@
+//! Test of restarting supervised tasks
+
+use std::{
+    process::ExitCode,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use taskette::{
+    scheduler::{SchedulerConfig, spawn},
+    supervisor::{RestartPolicy, spawn_supervised},
+    task::TaskConfig,
+    timer::{current_time, wait_until},
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+const BACKOFF: u64 = 5;
+
+static ALWAYS_RUNS: AtomicU32 = AtomicU32::new(0);
+static ALWAYS_LAST_START: AtomicU32 = AtomicU32::new(0);
+static ON_PANIC_RUNS: AtomicU32 = AtomicU32::new(0);
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default().with_tick_freq(100)).unwrap();
+
+    spawn_supervised(
+        always_entry,
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default(),
+        RestartPolicy::Always,
+        BACKOFF,
+    )
+    .unwrap();
+    spawn_supervised(
+        || {
+            ON_PANIC_RUNS.fetch_add(1, Ordering::SeqCst);
+        },
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default(),
+        RestartPolicy::OnPanic,
+        BACKOFF,
+    )
+    .unwrap();
+
+    spawn(
+        || {
+            wait_until(4 * BACKOFF + 2).unwrap();
+
+            // Runs at 0, 5, 10, 15, and 20 (with the backoff in between)
+            let always_runs = ALWAYS_RUNS.load(Ordering::SeqCst);
+            let on_panic_runs = ON_PANIC_RUNS.load(Ordering::SeqCst);
+            if always_runs == 5 && on_panic_runs == 1 {
+                std::process::exit(0);
+            } else {
+                println!(
+                    "Always: {} runs (last at {}), OnPanic: {} runs",
+                    always_runs,
+                    ALWAYS_LAST_START.load(Ordering::SeqCst),
+                    on_panic_runs
+                );
+                std::process::exit(1);
+            }
+        },
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(2),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+fn always_entry() {
+    ALWAYS_RUNS.fetch_add(1, Ordering::SeqCst);
+    ALWAYS_LAST_START.store(current_time().unwrap() as u32, Ordering::SeqCst);
+}
@@ -0,0 +1,102 @@
+//! Test of the allocator wrapper serializing heap use of tasks
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    process::ExitCode,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use taskette::{
+    heap::TaskAlloc,
+    scheduler::{SchedulerConfig, reset_stats, spawn, stats},
+    task::TaskConfig,
+    timer::current_time,
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+const ITERATIONS: usize = 200;
+
+/// Allocator detecting calls overlapping with each other, which are slow enough to be preempted
+struct CheckingAlloc {
+    in_use: AtomicBool,
+    overlaps: AtomicUsize,
+}
+
+unsafe impl GlobalAlloc for CheckingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.enter();
+        let ptr = unsafe { System.alloc(layout) };
+        self.exit();
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.enter();
+        unsafe { System.dealloc(ptr, layout) };
+        self.exit();
+    }
+}
+
+impl CheckingAlloc {
+    fn enter(&self) {
+        if self.in_use.swap(true, Ordering::SeqCst) {
+            self.overlaps.fetch_add(1, Ordering::SeqCst);
+        }
+        // Long enough for ticks to arrive in the middle of calls, which the kernel calls let preempt it
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_micros(200) {
+            current_time().unwrap();
+        }
+    }
+
+    fn exit(&self) {
+        self.in_use.store(false, Ordering::SeqCst);
+    }
+}
+
+static HEAP: TaskAlloc<CheckingAlloc> = TaskAlloc::new(CheckingAlloc {
+    in_use: AtomicBool::new(false),
+    overlaps: AtomicUsize::new(0),
+});
+static FINISHED: AtomicUsize = AtomicUsize::new(0);
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default().with_tick_freq(1000)).unwrap();
+
+    reset_stats();
+    // Same-priority tasks switched round-robin in the middle of their allocations
+    for _ in 0..2 {
+        spawn(
+            task_allocate,
+            Box::leak(Box::new(Stack::<65536>::new())),
+            TaskConfig::default().with_priority(1),
+        )
+        .unwrap();
+    }
+
+    scheduler.start();
+}
+
+fn task_allocate() {
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    for _ in 0..ITERATIONS {
+        unsafe {
+            let ptr = HEAP.alloc(layout);
+            assert!(!ptr.is_null());
+            ptr.write_bytes(0xAA, 64);
+            HEAP.dealloc(ptr, layout);
+        }
+    }
+
+    if FINISHED.fetch_add(1, Ordering::SeqCst) == 1 {
+        let overlaps = HEAP.inner().overlaps.load(Ordering::SeqCst);
+        let alloc = stats().alloc;
+        if overlaps == 0 && alloc.samples as usize == ITERATIONS * 4 && alloc.min <= alloc.max {
+            std::process::exit(0);
+        } else {
+            println!("{} overlaps, {:?}", overlaps, alloc);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,52 @@
+//! Test of spawning tasks through `task::Builder`
+
+use std::{
+    process::ExitCode,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use taskette::{
+    scheduler::{SchedulerConfig, for_each_task},
+    task::{self, Builder},
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+static FINISHED: AtomicUsize = AtomicUsize::new(0);
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default()).unwrap();
+
+    Builder::new()
+        .name("static")
+        .priority(3)
+        .stack(Box::leak(Box::new(Stack::<65536>::new())))
+        .spawn(|| check_self("static", 3))
+        .unwrap();
+    Builder::new()
+        .priority(2)
+        .name("heap")
+        .spawn_heap(65536, || check_self("heap", 2))
+        .unwrap();
+
+    scheduler.start();
+}
+
+/// Checks the options of the current task, and exits after both tasks
+fn check_self(name: &'static str, priority: usize) {
+    let id = task::current().unwrap().id();
+    let mut found = false;
+    for_each_task(|task| {
+        if task.id == id {
+            found = task.name == Some(name) && task.priority == priority;
+        }
+    });
+
+    if !found {
+        println!("Task {} has wrong options", name);
+        std::process::exit(1);
+    }
+
+    if FINISHED.fetch_add(1, Ordering::SeqCst) == 1 {
+        std::process::exit(0);
+    }
+}
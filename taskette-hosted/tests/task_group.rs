@@ -0,0 +1,82 @@
+//! Test of joining and cancelling groups of tasks
+
+use std::{
+    process::ExitCode,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use taskette::{
+    Error,
+    scheduler::{SchedulerConfig, spawn},
+    task::{self, TaskConfig, TaskGroup},
+    timer::{current_time, wait_until},
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+static FINISHED: AtomicUsize = AtomicUsize::new(0);
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default().with_tick_freq(1000)).unwrap();
+
+    spawn(
+        task_coordinator,
+        new_stack(),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+fn new_stack() -> &'static mut Stack<65536> {
+    Box::leak(Box::new(Stack::<65536>::new()))
+}
+
+/// Sleeps for `ticks` and counts the finish
+fn sleeper(ticks: u64) -> impl FnOnce() + Send + 'static {
+    move || {
+        wait_until(current_time().unwrap() + ticks).unwrap();
+        FINISHED.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+fn task_coordinator() {
+    let config = TaskConfig::default().with_priority(2);
+
+    // Joining waits for the slowest task
+    let mut group = TaskGroup::<2>::new();
+    group
+        .spawn(sleeper(10), new_stack(), config.clone())
+        .unwrap();
+    group
+        .spawn(sleeper(30), new_stack(), config.clone())
+        .unwrap();
+    assert!(matches!(
+        group.spawn(sleeper(1), new_stack(), config.clone()),
+        Err(Error::TaskFull)
+    ));
+    // Times out before the slow one finishes
+    assert!(!group.join_all_until(current_time().unwrap() + 15).unwrap());
+    assert_eq!(group.running(), 1);
+    group.join_all().unwrap();
+    assert_eq!(FINISHED.load(Ordering::SeqCst), 2);
+    assert!(group.tasks().is_empty());
+
+    // Cancelled tasks never finish
+    let mut group = TaskGroup::<3>::new();
+    for _ in 0..3 {
+        group
+            .spawn(sleeper(50), new_stack(), config.clone())
+            .unwrap();
+    }
+    let handles = group.tasks().to_vec();
+    group.cancel_all().unwrap();
+    wait_until(current_time().unwrap() + 100).unwrap();
+
+    if FINISHED.load(Ordering::SeqCst) == 2 && handles.iter().all(task::TaskHandle::is_finished) {
+        std::process::exit(0);
+    } else {
+        println!("Finished: {}", FINISHED.load(Ordering::SeqCst));
+        std::process::exit(1);
+    }
+}
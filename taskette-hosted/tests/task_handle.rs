@@ -0,0 +1,61 @@
+//! Test of comparing, hashing, and displaying task handles
+
+use std::{
+    collections::HashSet,
+    process::ExitCode,
+    sync::{Mutex, OnceLock},
+};
+
+use taskette::{
+    Error,
+    scheduler::{SchedulerConfig, spawn},
+    task::{self, TaskConfig, TaskHandle, TaskState},
+    timer::wait_until,
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+static SPAWNED: OnceLock<TaskHandle> = OnceLock::new();
+static SEEN: Mutex<Option<HashSet<TaskHandle>>> = Mutex::new(None);
+
+fn main() -> ExitCode {
+    // There is no current task before the initialization
+    if !matches!(task::current(), Err(Error::NotInitialized))
+        || !matches!(wait_until(0), Err(Error::NotInitialized))
+    {
+        println!("Current task is available before the initialization");
+        return ExitCode::FAILURE;
+    }
+
+    let scheduler = init_scheduler(SchedulerConfig::default()).unwrap();
+
+    // Code before the start is counted as the idle task
+    if task::current().ok() != Some(TaskHandle::idle()) {
+        println!("Code before the start is not the idle task");
+        return ExitCode::FAILURE;
+    }
+
+    let handle = spawn(
+        task_check,
+        Box::leak(Box::new(Stack::<65536>::new())),
+        TaskConfig::default(),
+    )
+    .unwrap();
+    SPAWNED.set(handle.clone()).unwrap();
+    *SEEN.lock().unwrap() = Some(HashSet::from([handle, TaskHandle::idle()]));
+
+    scheduler.start();
+}
+
+fn task_check() {
+    let current = task::current().unwrap();
+    let idle = TaskHandle::idle();
+
+    let ok = Some(&current) == SPAWNED.get()
+        && current != idle
+        && idle.state() == TaskState::Ready
+        && SEEN.lock().unwrap().as_ref().unwrap().contains(&current)
+        && idle.to_string() == "Task #0"
+        && current.to_string() == format!("Task #{}", current.id());
+
+    std::process::exit(if ok { 0 } else { 1 });
+}
@@ -0,0 +1,97 @@
+//! Test of log messages prefixed with the running task
+
+use std::{process::ExitCode, sync::Mutex};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use taskette::{
+    scheduler::{SchedulerConfig, spawn},
+    task::{self, LogContext, TaskConfig},
+    task_log,
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+static MESSAGES: Mutex<Vec<(Level, String)>> = Mutex::new(Vec::new());
+
+struct Recorder;
+
+impl Log for Recorder {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        MESSAGES
+            .lock()
+            .unwrap()
+            .push((record.level(), record.args().to_string()));
+    }
+
+    fn flush(&self) {}
+}
+
+static RECORDER: Recorder = Recorder;
+
+fn main() -> ExitCode {
+    log::set_logger(&RECORDER).unwrap();
+    log::set_max_level(LevelFilter::Trace);
+
+    // No task before the scheduler starts
+    task_log!(info, "starting");
+
+    let scheduler = init_scheduler(SchedulerConfig::default()).unwrap();
+
+    spawn(
+        task_worker,
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_name("worker"),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+fn task_worker() {
+    let id = task::current().unwrap().id();
+    let context = LogContext::current();
+    assert_eq!(context.id, Some(id));
+    assert_eq!(context.name, Some("worker"));
+
+    task_log!(warn, "value is {}", 42);
+
+    // Unnamed task
+    spawn(
+        move || {
+            task_log!(info, "from the child");
+            check(id);
+        },
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(2),
+    )
+    .unwrap();
+}
+
+fn check(worker_id: usize) {
+    let child_id = task::current().unwrap().id();
+    let messages = MESSAGES.lock().unwrap();
+    let contains = |level, message: String| messages.contains(&(level, message));
+
+    assert!(contains(Level::Info, "[-] starting".to_string()));
+    assert!(contains(
+        Level::Warn,
+        format!("[#{} worker] value is 42", worker_id)
+    ));
+    assert!(contains(
+        Level::Info,
+        format!("[#{}] from the child", child_id)
+    ));
+    // Kernel messages are prefixed too
+    assert!(contains(
+        Level::Info,
+        format!(
+            "[#{} worker] Task #{} created (priority 2)",
+            worker_id, child_id
+        )
+    ));
+
+    std::process::exit(0);
+}
@@ -0,0 +1,51 @@
+//! Test of tasks defined with the `#[taskette::task]` attribute
+
+use std::{
+    process::ExitCode,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use taskette::{
+    Error,
+    scheduler::{SchedulerConfig, for_each_task},
+    task,
+};
+use taskette_hosted::init_scheduler;
+
+static SUM: AtomicUsize = AtomicUsize::new(0);
+
+#[taskette::task(stack_size = 65536, priority = 2)]
+fn adder(a: usize, mut b: usize) {
+    b += a;
+    SUM.fetch_add(b, Ordering::SeqCst);
+}
+
+#[taskette::task(stack_size = 65536, name = "checker")]
+fn check() -> ! {
+    let id = task::current().unwrap().id();
+    let mut found = false;
+    for_each_task(|task| {
+        if task.id == id {
+            found = task.name == Some("checker") && task.priority == 1;
+        }
+    });
+
+    // The higher-priority adder has finished
+    if found && SUM.load(Ordering::SeqCst) == 3 {
+        std::process::exit(0);
+    } else {
+        println!("Found: {}, sum: {}", found, SUM.load(Ordering::SeqCst));
+        std::process::exit(1);
+    }
+}
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default()).unwrap();
+
+    adder(1, 2).unwrap();
+    // The stack is already in use
+    assert!(matches!(adder(1, 2), Err(Error::NotPermitted)));
+    check().unwrap();
+
+    scheduler.start();
+}
@@ -0,0 +1,69 @@
+//! Test of the state queries of task handles
+
+use std::process::ExitCode;
+
+use taskette::{
+    scheduler::{SchedulerConfig, spawn},
+    task::{self, TaskConfig, TaskState},
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default()).unwrap();
+
+    spawn(
+        task_check,
+        Box::leak(Box::new(Stack::<65536>::new())),
+        TaskConfig::default().with_priority(2),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+fn new_stack() -> &'static mut Stack<65536> {
+    Box::leak(Box::new(Stack::<65536>::new()))
+}
+
+fn task_check() {
+    let own = task::current().unwrap();
+    let parked = spawn(
+        || loop {
+            task::park().unwrap();
+        },
+        new_stack(),
+        TaskConfig::default().with_priority(3),
+    )
+    .unwrap();
+    let ready = spawn(
+        || {
+            loop {
+                std::hint::spin_loop();
+            }
+        },
+        new_stack(),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+    let finished = spawn(|| {}, new_stack(), TaskConfig::default().with_priority(3)).unwrap();
+
+    let states = [own.state(), parked.state(), ready.state(), finished.state()];
+    let expected = [
+        TaskState::Running,
+        TaskState::Blocked,
+        TaskState::Ready,
+        TaskState::Finished,
+    ];
+    if states != expected {
+        println!("{:?}", states);
+        std::process::exit(1);
+    }
+
+    task::kill(parked.id()).unwrap();
+    if parked.is_finished() && !ready.is_finished() {
+        std::process::exit(0);
+    } else {
+        println!("After kill: {:?}", parked.state());
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,44 @@
+//! Test of stopping and restarting the tick source of the port
+
+use std::{process::ExitCode, time::Duration};
+
+use taskette::{
+    arch::tick_source,
+    scheduler::{SchedulerConfig, spawn},
+    task::TaskConfig,
+    timer::{current_time, wait_until},
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default().with_tick_freq(1000)).unwrap();
+
+    spawn(
+        || {
+            wait_until(10).unwrap();
+
+            // Time does not advance while the tick source is stopped
+            tick_source().stop();
+            // A tick may be in progress
+            std::thread::sleep(Duration::from_millis(10));
+            let stopped_at = current_time().unwrap();
+            std::thread::sleep(Duration::from_millis(50));
+            if current_time().unwrap() != stopped_at {
+                std::process::exit(1);
+            }
+
+            tick_source().start();
+            wait_until(stopped_at + 10).unwrap();
+            if current_time().unwrap() >= stopped_at + 10 {
+                std::process::exit(0);
+            } else {
+                std::process::exit(1);
+            }
+        },
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default(),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
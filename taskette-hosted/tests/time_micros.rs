@@ -0,0 +1,51 @@
+//! Test of the time in microseconds interpolated between ticks
+
+use std::{process::ExitCode, time::Duration};
+
+use taskette::{
+    scheduler::{SchedulerConfig, spawn, test_advance_ticks},
+    task::TaskConfig,
+    timer::current_time_micros,
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(
+        SchedulerConfig::default()
+            .with_tick_freq(100)
+            .with_manual_tick(true),
+    )
+    .unwrap();
+
+    spawn(
+        || {
+            test_advance_ticks(3).unwrap();
+            let first = current_time_micros().unwrap();
+            std::thread::sleep(Duration::from_millis(2));
+            let second = current_time_micros().unwrap();
+            // Advances between ticks
+            if !(30_000..40_000).contains(&first) || second < first + 2_000 {
+                std::process::exit(1);
+            }
+
+            // Never reaches the next tick without it
+            std::thread::sleep(Duration::from_millis(20));
+            if current_time_micros().unwrap() != 40_000 {
+                std::process::exit(1);
+            }
+
+            test_advance_ticks(1).unwrap();
+            let after_tick = current_time_micros().unwrap();
+            if (40_000..41_000).contains(&after_tick) {
+                std::process::exit(0);
+            } else {
+                std::process::exit(1);
+            }
+        },
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
@@ -0,0 +1,51 @@
+//! Test of waking up tasks by the timer
+
+use std::{process::ExitCode, sync::Mutex};
+
+use taskette::{
+    scheduler::{SchedulerConfig, spawn},
+    task::TaskConfig,
+    timer::{current_time, wait_until},
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+static WAKEUPS: Mutex<Vec<(usize, u64)>> = Mutex::new(Vec::new());
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default().with_tick_freq(100)).unwrap();
+
+    for (i, wait) in [30, 10, 20].into_iter().enumerate() {
+        spawn(
+            move || {
+                wait_until(wait).unwrap();
+                WAKEUPS.lock().unwrap().push((i, current_time().unwrap()));
+            },
+            Box::leak(Box::new(Stack::<8192>::new())),
+            TaskConfig::default(),
+        )
+        .unwrap();
+    }
+
+    spawn(
+        || {
+            wait_until(40).unwrap();
+
+            let wakeups = WAKEUPS.lock().unwrap();
+            let order = wakeups.iter().map(|(i, _)| *i).collect::<Vec<_>>();
+            let late = wakeups
+                .iter()
+                .any(|(i, time)| *time != [30, 10, 20][*i]);
+            if order == [1, 2, 0] && !late {
+                std::process::exit(0);
+            } else {
+                println!("{:?}", wakeups);
+                std::process::exit(1);
+            }
+        },
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default(),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
@@ -0,0 +1,92 @@
+//! Test of the timer queue capacity with tasks woken up before their timeouts
+
+use std::process::ExitCode;
+
+use taskette::{
+    futex::Futex,
+    portable_atomic::Ordering,
+    scheduler::{SchedulerStorage, spawn},
+    task::TaskConfig,
+    timer,
+};
+use taskette_hosted::{Stack, init_scheduler_with_storage};
+
+const ROUNDS: usize = 50;
+
+static FUTEX: Futex = Futex::new(0);
+static SLEEPER_FUTEX: Futex = Futex::new(0);
+
+fn main() -> ExitCode {
+    // The idle task and 3 other tasks, with as many timer registrations as tasks
+    let storage = Box::leak(Box::new(SchedulerStorage::<4, 4>::new()));
+    let scheduler = init_scheduler_with_storage(Default::default(), storage).unwrap();
+
+    spawn(
+        task_sleeper,
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(3),
+    )
+    .unwrap();
+    spawn(
+        task_waiter,
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(2),
+    )
+    .unwrap();
+    spawn(
+        task_waker,
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+/// Registers timeouts while the waiter has outdated registrations in the queue
+fn task_sleeper() {
+    for round in 0..ROUNDS {
+        let time = timer::current_time().unwrap() + 1;
+        if let Err(error) = timer::wait_until(time) {
+            println!("Sleep failed in round {}: {:?}", round, error);
+            std::process::exit(1);
+        }
+
+        // Waits without a timeout until the waiter has been woken up a few times
+        let counter = SLEEPER_FUTEX.as_ref().load(Ordering::SeqCst);
+        SLEEPER_FUTEX.wait(counter).unwrap();
+    }
+
+    std::process::exit(0);
+}
+
+/// Waits until the same deadline again and again, woken up before it each time
+fn task_waiter() {
+    let deadline = timer::current_time().unwrap() + 1_000_000;
+
+    loop {
+        let counter = FUTEX.as_ref().load(Ordering::SeqCst);
+        match FUTEX.wait_until(counter, deadline) {
+            Ok(true) => {}
+            Ok(false) => {
+                println!("Waiter timed out");
+                std::process::exit(1);
+            }
+            Err(error) => {
+                println!("Wait failed: {:?}", error);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+fn task_waker() {
+    for round in 0.. {
+        for _ in 0..round % 5 + 1 {
+            FUTEX.as_ref().fetch_add(1, Ordering::SeqCst);
+            FUTEX.wake_all().unwrap();
+        }
+        SLEEPER_FUTEX.as_ref().fetch_add(1, Ordering::SeqCst);
+        SLEEPER_FUTEX.wake_all().unwrap();
+    }
+}
@@ -0,0 +1,58 @@
+//! Test of the timer waking every expired registration on a tick, and catching up with lost ticks
+
+use std::{process::ExitCode, sync::Mutex, time::Duration};
+
+use taskette::{
+    scheduler::{SchedulerConfig, spawn},
+    sync::interrupt_free,
+    task::TaskConfig,
+    timer::{current_time, wait_until},
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+const DEADLINE: u64 = 10;
+
+/// Times at which the sleepers woke up
+static WAKEUPS: Mutex<Vec<u64>> = Mutex::new(Vec::new());
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default().with_tick_freq(100)).unwrap();
+
+    // Both sleepers are woken up by the same tick
+    for _ in 0..2 {
+        spawn(
+            || {
+                wait_until(DEADLINE).unwrap();
+                WAKEUPS.lock().unwrap().push(current_time().unwrap());
+            },
+            Box::leak(Box::new(Stack::<8192>::new())),
+            TaskConfig::default().with_priority(2),
+        )
+        .unwrap();
+    }
+
+    spawn(
+        || {
+            wait_until(DEADLINE + 2).unwrap();
+            let wakeups = WAKEUPS.lock().unwrap().clone();
+
+            // The tick interrupt is held off for more than 5 periods, and then counts all of them
+            let start = current_time().unwrap();
+            interrupt_free(|_| std::thread::sleep(Duration::from_millis(55)));
+            std::thread::sleep(Duration::from_millis(5));
+            let elapsed = current_time().unwrap() - start;
+
+            if wakeups.len() == 2 && wakeups[0] == wakeups[1] && elapsed >= 5 {
+                std::process::exit(0);
+            } else {
+                println!("wakeups = {:?}, elapsed = {}", wakeups, elapsed);
+                std::process::exit(1);
+            }
+        },
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
@@ -0,0 +1,78 @@
+//! Test of the trace hooks
+
+use std::{
+    process::ExitCode,
+    sync::atomic::{AtomicU32, AtomicUsize, Ordering},
+};
+
+use taskette::{
+    scheduler::{SchedulerConfig, spawn},
+    task::{TaskConfig, current},
+    timer::{current_time, wait_until},
+    trace::{self, TraceHooks},
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+static SWITCHES_TO_SLEEPER: AtomicU32 = AtomicU32::new(0);
+static READY: AtomicU32 = AtomicU32::new(0);
+static BLOCKED: AtomicU32 = AtomicU32::new(0);
+static TICKS: AtomicU32 = AtomicU32::new(0);
+static SLEEPER: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default().with_tick_freq(100)).unwrap();
+
+    trace::set_hooks(
+        TraceHooks::new()
+            .with_on_switch(|from, to| {
+                let sleeper = SLEEPER.load(Ordering::SeqCst);
+                if to == sleeper && from != sleeper {
+                    SWITCHES_TO_SLEEPER.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+            .with_on_ready(|task_id| {
+                if task_id == SLEEPER.load(Ordering::SeqCst) {
+                    READY.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+            .with_on_block(|task_id| {
+                if task_id == SLEEPER.load(Ordering::SeqCst) {
+                    BLOCKED.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+            .with_on_tick(|| {
+                TICKS.fetch_add(1, Ordering::SeqCst);
+            }),
+    );
+
+    spawn(
+        || {
+            SLEEPER.store(current().unwrap().id(), Ordering::SeqCst);
+            for _ in 0..5 {
+                wait_until(current_time().unwrap() + 2).unwrap();
+            }
+
+            let (switches, ready, blocked, ticks) = (
+                SWITCHES_TO_SLEEPER.load(Ordering::SeqCst),
+                READY.load(Ordering::SeqCst),
+                BLOCKED.load(Ordering::SeqCst),
+                TICKS.load(Ordering::SeqCst),
+            );
+            // Each sleep blocks the task once, and the task is switched in after waking up
+            if blocked == 5 && ready == 5 && switches >= 5 && ticks >= 10 {
+                std::process::exit(0);
+            } else {
+                println!(
+                    "switches = {}, ready = {}, blocked = {}, ticks = {}",
+                    switches, ready, blocked, ticks
+                );
+                std::process::exit(1);
+            }
+        },
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default(),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
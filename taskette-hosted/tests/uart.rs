@@ -0,0 +1,111 @@
+//! Test of the interrupt-driven UART adapter with blocking reads
+
+use std::{collections::VecDeque, convert::Infallible, process::ExitCode, sync::Mutex};
+
+use embedded_hal_nb::{nb, serial};
+use embedded_io::{Read, ReadReady, Write};
+use taskette::{
+    scheduler::{SchedulerConfig, spawn},
+    sync::StreamBuffer,
+    task::TaskConfig,
+    timer::{current_time, wait_until},
+};
+use taskette_hosted::{Stack, init_scheduler};
+use taskette_utils::uart::{IrqUart, on_rx_interrupt};
+
+static RX_BUFFER: StreamBuffer<16> = StreamBuffer::new();
+/// Bytes sent on the line by the fake transmitter
+static SENT: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+
+/// Receiver whose FIFO holds the given results (`None` is a framing error)
+struct FakeRx(VecDeque<Option<u8>>);
+
+impl serial::ErrorType for FakeRx {
+    type Error = serial::ErrorKind;
+}
+
+impl serial::Read<u8> for FakeRx {
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        match self.0.pop_front() {
+            Some(Some(byte)) => Ok(byte),
+            Some(None) => Err(nb::Error::Other(serial::ErrorKind::FrameFormat)),
+            None => Err(nb::Error::WouldBlock),
+        }
+    }
+}
+
+/// Transmitter with a 4-byte FIFO, which is sent on the line whenever it is found full
+struct FakeTx(Vec<u8>);
+
+impl serial::ErrorType for FakeTx {
+    type Error = Infallible;
+}
+
+impl serial::Write<u8> for FakeTx {
+    fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+        if self.0.len() == 4 {
+            SENT.lock().unwrap().append(&mut self.0);
+            return Err(nb::Error::WouldBlock);
+        }
+        self.0.push(byte);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        SENT.lock().unwrap().append(&mut self.0);
+        Ok(())
+    }
+}
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default().with_tick_freq(100)).unwrap();
+
+    spawn(
+        task_reader,
+        Box::leak(Box::new(Stack::<65536>::new())),
+        TaskConfig::default().with_priority(2),
+    )
+    .unwrap();
+    spawn(
+        task_interrupt,
+        Box::leak(Box::new(Stack::<65536>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+/// Plays the RX interrupt handler, receiving a line some time after the reader started waiting
+fn task_interrupt() {
+    wait_until(current_time().unwrap() + 5).unwrap();
+    let mut rx = FakeRx(b"hello world\n".iter().map(|&byte| Some(byte)).collect());
+    assert_eq!(on_rx_interrupt(&mut rx, &RX_BUFFER), 0);
+}
+
+fn task_reader() {
+    let mut uart = IrqUart::new(FakeTx(Vec::new()), &RX_BUFFER);
+
+    // Blocks until the interrupt handler delivers the line
+    let start = current_time().unwrap();
+    let mut buf = [0u8; 12];
+    uart.read_exact(&mut buf).unwrap();
+    assert!(current_time().unwrap() >= start + 5);
+    assert_eq!(&buf, b"hello world\n");
+    assert!(!uart.read_ready().unwrap());
+
+    // Echoes it through the 4-byte FIFO
+    uart.write_all(&buf).unwrap();
+    uart.flush().unwrap();
+    assert_eq!(SENT.lock().unwrap().as_slice(), b"hello world\n");
+
+    // Bytes beyond the buffer size and erroneous ones are lost
+    let mut rx = FakeRx((0..20).map(Some).chain([None]).collect());
+    assert_eq!(on_rx_interrupt(&mut rx, &RX_BUFFER), 5);
+    assert!(uart.read_ready().unwrap());
+    let mut buf = [0u8; 32];
+    assert_eq!(uart.read(&mut buf).unwrap(), 16);
+    assert_eq!(&buf[..16], &(0..16).collect::<Vec<u8>>()[..]);
+
+    std::process::exit(0);
+}
@@ -0,0 +1,134 @@
+//! Test of the USB device task blocking on the USB interrupt between polls
+
+use std::{
+    process::ExitCode,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use taskette::{
+    scheduler::{SchedulerConfig, spawn},
+    sync::IrqEvent,
+    task::TaskConfig,
+    timer::{current_time, wait_until},
+};
+use taskette_hosted::{Stack, init_scheduler};
+use taskette_utils::usb::serve;
+use usb_device::{
+    Result, UsbDirection,
+    bus::{PollResult, UsbBus, UsbBusAllocator},
+    class::UsbClass,
+    device::{UsbDeviceBuilder, UsbVidPid},
+    endpoint::{EndpointAddress, EndpointType},
+};
+
+static USB_EVENT: IrqEvent = IrqEvent::new();
+static POLLS: AtomicUsize = AtomicUsize::new(0);
+static UNMASKS: AtomicUsize = AtomicUsize::new(0);
+static EVENTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Bus without any host, counting polls
+struct IdleBus;
+
+impl UsbBus for IdleBus {
+    fn alloc_ep(
+        &mut self,
+        ep_dir: UsbDirection,
+        ep_addr: Option<EndpointAddress>,
+        _ep_type: EndpointType,
+        _max_packet_size: u16,
+        _interval: u8,
+    ) -> Result<EndpointAddress> {
+        Ok(ep_addr.unwrap_or(EndpointAddress::from_parts(0, ep_dir)))
+    }
+
+    fn enable(&mut self) {}
+
+    fn reset(&self) {}
+
+    fn set_device_address(&self, _addr: u8) {}
+
+    fn write(&self, _ep_addr: EndpointAddress, buf: &[u8]) -> Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn read(&self, _ep_addr: EndpointAddress, _buf: &mut [u8]) -> Result<usize> {
+        Ok(0)
+    }
+
+    fn set_stalled(&self, _ep_addr: EndpointAddress, _stalled: bool) {}
+
+    fn is_stalled(&self, _ep_addr: EndpointAddress) -> bool {
+        false
+    }
+
+    fn suspend(&self) {}
+
+    fn resume(&self) {}
+
+    fn poll(&self) -> PollResult {
+        POLLS.fetch_add(1, Ordering::SeqCst);
+        PollResult::None
+    }
+}
+
+struct NullClass;
+
+impl UsbClass<IdleBus> for NullClass {}
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default().with_tick_freq(100)).unwrap();
+
+    spawn(
+        task_usb,
+        Box::leak(Box::new(Stack::<65536>::new())),
+        TaskConfig::default().with_priority(2),
+    )
+    .unwrap();
+    spawn(
+        task_interrupt,
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+fn task_usb() {
+    let usb_bus: &'static UsbBusAllocator<IdleBus> =
+        Box::leak(Box::new(UsbBusAllocator::new(IdleBus)));
+    let mut usb_dev = UsbDeviceBuilder::new(usb_bus, UsbVidPid(0x16C0, 0x27DD)).build();
+
+    serve(
+        &mut usb_dev,
+        &mut (NullClass,),
+        &USB_EVENT,
+        || {
+            UNMASKS.fetch_add(1, Ordering::SeqCst);
+        },
+        |_, _| {
+            EVENTS.fetch_add(1, Ordering::SeqCst);
+        },
+    )
+}
+
+/// Plays the role of the USB interrupt handler
+fn task_interrupt() {
+    // Polled once at the start, then blocked while no interrupt is raised
+    let start = current_time().unwrap();
+    wait_until(start + 5).unwrap();
+    assert_eq!(POLLS.load(Ordering::SeqCst), 1);
+    assert_eq!(UNMASKS.load(Ordering::SeqCst), 1);
+
+    // Polled once per interrupt
+    for i in 2..=4 {
+        USB_EVENT.signal();
+        assert_eq!(POLLS.load(Ordering::SeqCst), i);
+        assert_eq!(UNMASKS.load(Ordering::SeqCst), i);
+    }
+
+    // No class activity without a host
+    assert_eq!(EVENTS.load(Ordering::SeqCst), 0);
+
+    std::process::exit(0);
+}
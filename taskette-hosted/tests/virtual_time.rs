@@ -0,0 +1,56 @@
+//! Test of the virtual time
+
+use std::{
+    process::ExitCode,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use taskette::{
+    scheduler::{SchedulerConfig, spawn},
+    task::TaskConfig,
+    timer::{current_time, wait_until},
+};
+use taskette_hosted::{Stack, init_scheduler, set_virtual_time};
+
+/// Wake-up times in the order of waking up
+static WAKEUPS: Mutex<Vec<u64>> = Mutex::new(Vec::new());
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default().with_tick_freq(1000)).unwrap();
+    set_virtual_time(true);
+
+    let start = Instant::now();
+
+    // 20 seconds in total, in real time
+    for timeout in [20_000, 5_000, 10_000] {
+        spawn(
+            move || {
+                wait_until(timeout).unwrap();
+                WAKEUPS.lock().unwrap().push(current_time().unwrap());
+            },
+            Box::leak(Box::new(Stack::<8192>::new())),
+            TaskConfig::default().with_priority(1),
+        )
+        .unwrap();
+    }
+
+    spawn(
+        move || {
+            wait_until(20_001).unwrap();
+
+            let wakeups = WAKEUPS.lock().unwrap();
+            if *wakeups == [5_000, 10_000, 20_000] && start.elapsed() < Duration::from_secs(10) {
+                std::process::exit(0);
+            } else {
+                println!("{:?} in {:?}", wakeups, start.elapsed());
+                std::process::exit(1);
+            }
+        },
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
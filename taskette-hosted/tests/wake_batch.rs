@@ -0,0 +1,77 @@
+//! Test of the batched rescheduling after waking several tasks
+
+use std::{
+    process::ExitCode,
+    sync::atomic::{AtomicU32, AtomicUsize, Ordering},
+};
+
+use taskette::{
+    futex::Futex,
+    scheduler::{SchedulerConfig, spawn},
+    task::{TaskConfig, current},
+    timer::{current_time, wait_until},
+    trace::{self, TraceHooks},
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+const NUM_WAITERS: u32 = 4;
+
+static FUTEX: Futex = Futex::new(0);
+static WOKEN: AtomicU32 = AtomicU32::new(0);
+static SWITCHES_FROM_WAKER: AtomicU32 = AtomicU32::new(0);
+static WAKER: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default().with_tick_freq(100)).unwrap();
+
+    // Counts every context switch from the waker, including the ones which switch back to it
+    trace::set_hooks(TraceHooks::new().with_on_switch(|from, _to| {
+        if from == WAKER.load(Ordering::SeqCst) {
+            SWITCHES_FROM_WAKER.fetch_add(1, Ordering::SeqCst);
+        }
+    }));
+
+    for _ in 0..NUM_WAITERS {
+        spawn(
+            || {
+                FUTEX.wait(0).unwrap();
+                WOKEN.fetch_add(1, Ordering::SeqCst);
+            },
+            Box::leak(Box::new(Stack::<8192>::new())),
+            TaskConfig::default().with_priority(1),
+        )
+        .unwrap();
+    }
+
+    spawn(
+        || {
+            // Lets the waiters block
+            wait_until(current_time().unwrap() + 2).unwrap();
+
+            WAKER.store(current().unwrap().id(), Ordering::SeqCst);
+            FUTEX.as_ref().store(1, Ordering::SeqCst);
+            FUTEX.wake_all().unwrap();
+            // None of the woken tasks preempts this one
+            let switches = SWITCHES_FROM_WAKER.load(Ordering::SeqCst);
+            let woken_early = WOKEN.load(Ordering::SeqCst);
+
+            wait_until(current_time().unwrap() + 2).unwrap();
+
+            let woken = WOKEN.load(Ordering::SeqCst);
+            if switches == 0 && woken_early == 0 && woken == NUM_WAITERS {
+                std::process::exit(0);
+            } else {
+                println!(
+                    "switches = {}, woken_early = {}, woken = {}",
+                    switches, woken_early, woken
+                );
+                std::process::exit(1);
+            }
+        },
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default().with_priority(2),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
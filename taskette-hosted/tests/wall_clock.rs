@@ -0,0 +1,100 @@
+//! Test of the wall clock synchronized from an RTC
+
+use std::{process::ExitCode, time::Duration};
+
+use taskette::{
+    scheduler::{SchedulerConfig, spawn},
+    task::TaskConfig,
+    timer::{
+        current_time, wait_until,
+        wall_clock::{self, DateTime, WallClock},
+    },
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+/// 2024-02-29T12:34:56Z
+const LEAP_DAY: u64 = 1_709_210_096;
+
+/// RTC counting whole seconds, which are not advanced in this test
+struct FakeRtc {
+    seconds: u64,
+}
+
+impl WallClock for FakeRtc {
+    type Error = ();
+
+    fn read_utc(&mut self) -> Result<Duration, Self::Error> {
+        Ok(Duration::from_secs(self.seconds))
+    }
+
+    fn write_utc(&mut self, time: Duration) -> Result<(), Self::Error> {
+        self.seconds = time.as_secs();
+        Ok(())
+    }
+}
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default().with_tick_freq(100)).unwrap();
+
+    spawn(
+        task_main,
+        Box::leak(Box::new(Stack::<65536>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+fn task_main() {
+    // Calendar conversions
+    let date = DateTime::from_unix(Duration::from_secs(LEAP_DAY) + Duration::from_micros(789_012));
+    assert_eq!(
+        date,
+        DateTime {
+            year: 2024,
+            month: 2,
+            day: 29,
+            hour: 12,
+            minute: 34,
+            second: 56,
+            micros: 789_012,
+        }
+    );
+    assert_eq!(date.to_string(), "2024-02-29T12:34:56.789012Z");
+    assert_eq!(date.weekday(), 4);
+    assert_eq!(
+        DateTime::from_unix(Duration::from_secs(4_107_542_400)).to_string(),
+        "2100-03-01T00:00:00.000000Z"
+    );
+    for days in (0..100_000).step_by(7) {
+        let time = Duration::from_secs(days * 86400 + 3661);
+        assert_eq!(DateTime::from_unix(time).to_unix(), time);
+    }
+
+    // Not set until synchronized
+    assert!(wall_clock::now_utc().is_none());
+    assert!(DateTime::now().is_none());
+    let mut rtc = FakeRtc { seconds: LEAP_DAY };
+    wall_clock::sync_from(&mut rtc).unwrap();
+    assert!(wall_clock::is_set());
+
+    // Advances with the ticks
+    let before = wall_clock::now_utc().unwrap();
+    assert!(before >= Duration::from_secs(LEAP_DAY));
+    wait_until(current_time().unwrap() + 50).unwrap();
+    let elapsed = wall_clock::now_utc().unwrap() - before;
+    assert!(
+        elapsed >= Duration::from_millis(490) && elapsed < Duration::from_millis(520),
+        "{:?}",
+        elapsed
+    );
+
+    // Corrected by a later synchronization (e.g. NTP), which is written back to the RTC
+    wall_clock::set_utc(Duration::from_secs(LEAP_DAY + 3600)).unwrap();
+    assert_eq!(DateTime::now().unwrap().hour, 13);
+    wall_clock::sync_to(&mut rtc).unwrap();
+    assert_eq!(rtc.seconds, LEAP_DAY + 3600);
+
+    std::process::exit(0);
+}
@@ -0,0 +1,87 @@
+//! Test of the task watchdog
+
+use std::{
+    process::ExitCode,
+    sync::atomic::{AtomicU32, AtomicUsize, Ordering},
+};
+
+use taskette::{
+    scheduler::{SchedulerConfig, spawn},
+    task::TaskConfig,
+    timer::{current_time, wait_until},
+    watchdog,
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+const TIMEOUT: u64 = 5;
+/// The stuck task stops checking in at this time
+const STUCK_AT: u64 = 20;
+
+static FEEDS: AtomicU32 = AtomicU32::new(0);
+static STUCK_TASK: AtomicUsize = AtomicUsize::new(0);
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default().with_tick_freq(100)).unwrap();
+
+    watchdog::set_feed_hook(|| {
+        FEEDS.fetch_add(1, Ordering::SeqCst);
+    });
+    watchdog::set_starvation_hook(|task_id| {
+        let now = current_time().unwrap();
+        if task_id == STUCK_TASK.load(Ordering::SeqCst)
+            && (STUCK_AT..=STUCK_AT + TIMEOUT + 2).contains(&now)
+            && FEEDS.load(Ordering::SeqCst) >= STUCK_AT as u32 - 2
+        {
+            std::process::exit(0);
+        } else {
+            println!(
+                "Task #{} starved at {} after {} feeds",
+                task_id,
+                now,
+                FEEDS.load(Ordering::SeqCst)
+            );
+            std::process::exit(1);
+        }
+    });
+
+    // Checks in regularly
+    let healthy = spawn(
+        || {
+            loop {
+                watchdog::checkin().unwrap();
+                wait_until(current_time().unwrap() + 2).unwrap();
+            }
+        },
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default(),
+    )
+    .unwrap();
+    watchdog::register(&healthy, TIMEOUT).unwrap();
+
+    // Finishes without checking in (unregistered automatically)
+    let finished = spawn(
+        || {},
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default(),
+    )
+    .unwrap();
+    watchdog::register(&finished, TIMEOUT).unwrap();
+
+    // Stops checking in at `STUCK_AT`
+    let stuck = spawn(
+        || {
+            while current_time().unwrap() < STUCK_AT {
+                watchdog::checkin().unwrap();
+                wait_until(current_time().unwrap() + 1).unwrap();
+            }
+            wait_until(u64::MAX).unwrap();
+        },
+        Box::leak(Box::new(Stack::<8192>::new())),
+        TaskConfig::default(),
+    )
+    .unwrap();
+    STUCK_TASK.store(stuck.id(), Ordering::SeqCst);
+    watchdog::register(&stuck, TIMEOUT).unwrap();
+
+    scheduler.start();
+}
@@ -0,0 +1,87 @@
+//! Test of the deferred work queue
+
+use std::{
+    process::ExitCode,
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use taskette::{
+    Error,
+    scheduler::{SchedulerConfig, spawn},
+    task::TaskConfig,
+    work_queue::{WORK_QUEUE_CAPACITY, queue_closure_from_isr, queue_work_from_isr, spawn_worker},
+};
+use taskette_hosted::{Stack, init_scheduler};
+
+/// Values recorded by the work items in the order they ran
+static LOG: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+/// Captured by a closure, counting how many times it is dropped
+struct DropCounter;
+
+impl Drop for DropCounter {
+    fn drop(&mut self) {
+        DROPPED.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+fn record(context: *mut ()) {
+    LOG.lock().unwrap().push(context as usize);
+}
+
+fn main() -> ExitCode {
+    let scheduler = init_scheduler(SchedulerConfig::default().with_tick_freq(100)).unwrap();
+
+    spawn(
+        task_main,
+        Box::leak(Box::new(Stack::<65536>::new())),
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+fn task_main() {
+    // Queued before any worker exists, until the queue is full
+    for i in 0..WORK_QUEUE_CAPACITY {
+        if i % 2 == 0 {
+            queue_work_from_isr(record, i as *mut ()).unwrap();
+        } else {
+            queue_closure_from_isr(move || LOG.lock().unwrap().push(i)).unwrap();
+        }
+    }
+    let counter = DropCounter;
+    assert!(matches!(
+        queue_closure_from_isr(move || drop(counter)),
+        Err(Error::QueueFull)
+    ));
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 1);
+
+    // The higher-priority worker drains the queue at once
+    spawn_worker(
+        Box::leak(Box::new(Stack::<65536>::new())),
+        TaskConfig::default().with_priority(2),
+    )
+    .unwrap();
+    assert_eq!(
+        *LOG.lock().unwrap(),
+        (0..WORK_QUEUE_CAPACITY).collect::<Vec<_>>()
+    );
+
+    // Items queued later run immediately, and a captured value is dropped after running
+    let counter = DropCounter;
+    queue_closure_from_isr(move || {
+        let _counter = counter;
+        LOG.lock().unwrap().push(100);
+    })
+    .unwrap();
+    assert_eq!(LOG.lock().unwrap().last(), Some(&100));
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 2);
+
+    std::process::exit(0);
+}
@@ -0,0 +1,144 @@
+//! Procedural macros of Taskette. Used through the `macros` feature flag of `taskette`.
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::{Expr, FnArg, ItemFn, LitStr, Pat, parse_macro_input, spanned::Spanned};
+
+/// Turns a function into a helper which spawns it as a task with a static stack.
+///
+/// ```ignore
+/// #[taskette::task(stack_size = 4096, priority = 2)]
+/// fn blink(led: Led) {
+///     loop { /* ... */ }
+/// }
+///
+/// // In `main`, after initializing the scheduler
+/// blink(led)?;
+/// ```
+///
+/// The helper takes the arguments of the function (moved into the task),
+/// and returns `Result<TaskHandle, Error>` of `spawn`.
+/// As the stack is static, each function can be spawned only once (a second call returns `Error::NotPermitted`).
+///
+/// Options:
+/// - `stack_size` (required): size of the stack in bytes
+/// - `priority`: priority of the task (default 1)
+/// - `name`: name shown by debugging tools (default the name of the function)
+/// - `affinity`: core the task is pinned to
+#[proc_macro_attribute]
+pub fn task(args: TokenStream, item: TokenStream) -> TokenStream {
+    let func = parse_macro_input!(item as ItemFn);
+
+    let mut options = TaskOptions::default();
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("stack_size") {
+            options.stack_size = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("priority") {
+            options.priority = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("name") {
+            options.name = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("affinity") {
+            options.affinity = Some(meta.value()?.parse()?);
+        } else {
+            return Err(meta.error("unknown option of `task`"));
+        }
+        Ok(())
+    });
+    parse_macro_input!(args with parser);
+
+    match expand(func, options) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+#[derive(Default)]
+struct TaskOptions {
+    stack_size: Option<Expr>,
+    priority: Option<Expr>,
+    name: Option<LitStr>,
+    affinity: Option<Expr>,
+}
+
+fn expand(func: ItemFn, options: TaskOptions) -> syn::Result<proc_macro2::TokenStream> {
+    let sig = &func.sig;
+    if sig.asyncness.is_some() {
+        return Err(syn::Error::new(
+            sig.span(),
+            "task functions cannot be `async`",
+        ));
+    }
+    if !sig.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            sig.generics.span(),
+            "task functions cannot be generic",
+        ));
+    }
+
+    let Some(stack_size) = options.stack_size else {
+        return Err(syn::Error::new(
+            Span::call_site(),
+            "`stack_size` is required",
+        ));
+    };
+
+    // Arguments of the helper are passed to the task function as is
+    let mut arg_names = Vec::new();
+    let mut helper_args = Vec::new();
+    for input in &sig.inputs {
+        let FnArg::Typed(arg) = input else {
+            return Err(syn::Error::new(
+                input.span(),
+                "task functions cannot take `self`",
+            ));
+        };
+        let Pat::Ident(pat) = &*arg.pat else {
+            return Err(syn::Error::new(
+                arg.pat.span(),
+                "arguments of task functions must be identifiers",
+            ));
+        };
+        let name = &pat.ident;
+        let ty = &arg.ty;
+        arg_names.push(name.clone());
+        helper_args.push(quote! { #name: #ty });
+    }
+
+    let ident = &sig.ident;
+    let task_name = options
+        .name
+        .unwrap_or_else(|| LitStr::new(&ident.to_string(), ident.span()));
+    let priority = options
+        .priority
+        .map(|priority| quote! { .priority(#priority) });
+    let affinity = options
+        .affinity
+        .map(|affinity| quote! { .affinity(#affinity) });
+
+    let attrs = &func.attrs;
+    let vis = &func.vis;
+    let inner = format_ident!("__taskette_task_{}", ident);
+    let inputs = &sig.inputs;
+    let output = &sig.output;
+    let block = &func.block;
+
+    Ok(quote! {
+        #(#attrs)*
+        #vis fn #ident(#(#helper_args),*) -> ::core::result::Result<::taskette::task::TaskHandle, ::taskette::Error> {
+            fn #inner(#inputs) #output #block
+
+            static STACK: ::taskette::task::StaticStack<{ #stack_size }> = ::taskette::task::StaticStack::new();
+            let stack = STACK.take().ok_or(::taskette::Error::NotPermitted)?;
+
+            ::taskette::task::Builder::new()
+                .name(#task_name)
+                #priority
+                #affinity
+                .stack(stack)
+                .spawn(move || {
+                    #inner(#(#arg_names),*);
+                })
+        }
+    })
+}
@@ -0,0 +1,85 @@
+/* Minimal POSIX threads of taskette (see the `taskette-posix` crate) */
+#ifndef TASKETTE_PTHREAD_H
+#define TASKETTE_PTHREAD_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef size_t pthread_t;  /* Never 0 */
+
+typedef struct {
+    void *stack_addr;
+    size_t stack_size;
+    int priority;  /* Higher value means higher priority */
+    int detach_state;
+} pthread_attr_t;
+
+struct sched_param {
+    int sched_priority;
+};
+
+typedef struct {
+    size_t state;
+} pthread_mutex_t;
+
+typedef struct {
+    int reserved;
+} pthread_mutexattr_t;
+
+typedef struct {
+    size_t sequence;
+} pthread_cond_t;
+
+typedef struct {
+    int reserved;
+} pthread_condattr_t;
+
+#define PTHREAD_MUTEX_INITIALIZER {0}
+#define PTHREAD_COND_INITIALIZER {0}
+
+#define PTHREAD_CREATE_JOINABLE 0
+#define PTHREAD_CREATE_DETACHED 1
+
+/* Threads */
+int pthread_create(pthread_t *thread, const pthread_attr_t *attr, void *(*start)(void *arg), void *arg);
+int pthread_join(pthread_t thread, void **retval);
+int pthread_detach(pthread_t thread);
+pthread_t pthread_self(void);
+int pthread_equal(pthread_t thread1, pthread_t thread2);
+int sched_yield(void);
+
+int pthread_attr_init(pthread_attr_t *attr);
+int pthread_attr_destroy(pthread_attr_t *attr);
+/* The stack has to live until the thread finishes */
+int pthread_attr_setstack(pthread_attr_t *attr, void *stack_addr, size_t stack_size);
+/* Size of the stack allocated from the heap (`alloc` feature) */
+int pthread_attr_setstacksize(pthread_attr_t *attr, size_t stack_size);
+int pthread_attr_setdetachstate(pthread_attr_t *attr, int detach_state);
+int pthread_attr_setschedparam(pthread_attr_t *attr, const struct sched_param *param);
+
+/* Mutexes (non-recursive) */
+int pthread_mutex_init(pthread_mutex_t *mutex, const pthread_mutexattr_t *attr);
+int pthread_mutex_destroy(pthread_mutex_t *mutex);
+int pthread_mutex_lock(pthread_mutex_t *mutex);
+int pthread_mutex_trylock(pthread_mutex_t *mutex);
+int pthread_mutex_unlock(pthread_mutex_t *mutex);
+int pthread_mutexattr_init(pthread_mutexattr_t *attr);
+int pthread_mutexattr_destroy(pthread_mutexattr_t *attr);
+
+/* Condition variables */
+int pthread_cond_init(pthread_cond_t *cond, const pthread_condattr_t *attr);
+int pthread_cond_destroy(pthread_cond_t *cond);
+int pthread_cond_wait(pthread_cond_t *cond, pthread_mutex_t *mutex);
+int pthread_cond_signal(pthread_cond_t *cond);
+int pthread_cond_broadcast(pthread_cond_t *cond);
+int pthread_condattr_init(pthread_condattr_t *attr);
+int pthread_condattr_destroy(pthread_condattr_t *attr);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* TASKETTE_PTHREAD_H */
@@ -0,0 +1,27 @@
+/* Unnamed semaphores of taskette (see the `taskette-posix` crate) */
+#ifndef TASKETTE_SEMAPHORE_H
+#define TASKETTE_SEMAPHORE_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct {
+    size_t value;
+} sem_t;
+
+/* Return -1 on failure, setting errno only with the `newlib` feature */
+int sem_init(sem_t *sem, int pshared, unsigned int value);
+int sem_destroy(sem_t *sem);
+int sem_wait(sem_t *sem);
+int sem_trywait(sem_t *sem);
+int sem_post(sem_t *sem);
+int sem_getvalue(sem_t *sem, int *value);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* TASKETTE_SEMAPHORE_H */
@@ -0,0 +1,106 @@
+//! Condition variables (`pthread_cond_*`).
+
+use core::{ffi::c_int, sync::atomic::Ordering};
+
+use taskette::portable_atomic::AtomicUsize;
+
+use crate::{EINVAL, mutex::PthreadMutex, wait};
+
+/// Condition variable (`pthread_cond_t`). All zero is a valid one (`PTHREAD_COND_INITIALIZER`).
+#[repr(C)]
+pub struct PthreadCond {
+    /// Incremented on every signal
+    sequence: AtomicUsize,
+}
+
+/// Attributes of a condition variable (`pthread_condattr_t`), which are all ignored
+#[repr(C)]
+pub struct PthreadCondAttr {
+    _reserved: c_int,
+}
+
+/// # Safety
+/// `cond` must be valid or null.
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub unsafe extern "C" fn pthread_cond_init(
+    cond: *mut PthreadCond,
+    _attr: *const PthreadCondAttr,
+) -> c_int {
+    let Some(cond) = (unsafe { cond.as_ref() }) else {
+        return EINVAL;
+    };
+    cond.sequence.store(0, Ordering::SeqCst);
+    0
+}
+
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub extern "C" fn pthread_cond_destroy(_cond: *mut PthreadCond) -> c_int {
+    0
+}
+
+/// Unlocks `mutex`, blocks the calling thread until the condition variable is signaled, and locks `mutex` again.
+///
+/// Like in POSIX, it may also return spuriously.
+///
+/// # Safety
+/// `cond` and `mutex` must be valid or null.
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub unsafe extern "C" fn pthread_cond_wait(
+    cond: *mut PthreadCond,
+    mutex: *mut PthreadMutex,
+) -> c_int {
+    let (Some(cond), Some(mutex)) = (unsafe { cond.as_ref() }, unsafe { mutex.as_ref() }) else {
+        return EINVAL;
+    };
+
+    // Read before unlocking, so that a signal after unlocking makes `wait` return immediately
+    let sequence = cond.sequence.load(Ordering::SeqCst);
+    match mutex.unlock() {
+        0 => (),
+        code => return code,
+    }
+    let _ = wait::wait(&cond.sequence, sequence);
+
+    mutex.lock()
+}
+
+/// Wakes up the threads waiting on the condition variable (every one of them, which POSIX permits).
+///
+/// # Safety
+/// `cond` must be valid or null.
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub unsafe extern "C" fn pthread_cond_signal(cond: *mut PthreadCond) -> c_int {
+    unsafe { pthread_cond_broadcast(cond) }
+}
+
+/// Wakes up all threads waiting on the condition variable.
+///
+/// # Safety
+/// `cond` must be valid or null.
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub unsafe extern "C" fn pthread_cond_broadcast(cond: *mut PthreadCond) -> c_int {
+    let Some(cond) = (unsafe { cond.as_ref() }) else {
+        return EINVAL;
+    };
+    cond.sequence.fetch_add(1, Ordering::SeqCst);
+    wait::wake(&cond.sequence);
+    0
+}
+
+/// # Safety
+/// `attr` must be valid or null.
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub unsafe extern "C" fn pthread_condattr_init(attr: *mut PthreadCondAttr) -> c_int {
+    match unsafe { attr.as_mut() } {
+        Some(attr) => {
+            attr._reserved = 0;
+            0
+        }
+        None => EINVAL,
+    }
+}
+
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub extern "C" fn pthread_condattr_destroy(_attr: *mut PthreadCondAttr) -> c_int {
+    0
+}
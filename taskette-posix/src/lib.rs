@@ -0,0 +1,380 @@
+//! Minimal POSIX threads of Taskette.
+//!
+//! A subset of `pthread_*` (threads, mutexes, and condition variables) and `sem_*` functions is implemented
+//! on top of tasks and futexes, so that portable C libraries needing basic threading can be linked into the firmware.
+//! The declarations are in `include/pthread.h` and `include/semaphore.h`.
+//!
+//! Each thread is a task. Its stack is given by `pthread_attr_setstack`, or allocated from the heap with the `alloc` feature.
+//! Thread-specific data, cancellation, and timed waits are not supported.
+//! Error numbers are those of newlib.
+//!
+//! The functions are exported with their C names only on bare-metal targets (`target_os = "none"`),
+//! so that they never replace those of the C library of an OS (e.g. with `taskette-hosted`, whose cores are threads).
+
+#![no_std]
+
+pub mod cond;
+pub mod mutex;
+pub mod semaphore;
+mod wait;
+
+use core::{
+    ffi::{c_int, c_void},
+    sync::atomic::Ordering,
+};
+
+use taskette::{
+    arch::RawStack,
+    portable_atomic::{AtomicBool, AtomicUsize},
+    scheduler::spawn,
+    task::{self, TaskConfig},
+};
+
+pub const EPERM: c_int = 1;
+pub const ESRCH: c_int = 3;
+pub const EAGAIN: c_int = 11;
+pub const EBUSY: c_int = 16;
+pub const EINVAL: c_int = 22;
+pub const EDEADLK: c_int = 45;
+
+pub const PTHREAD_CREATE_JOINABLE: c_int = 0;
+pub const PTHREAD_CREATE_DETACHED: c_int = 1;
+
+/// Maximum number of threads existing (or finished but not joined) at the same time
+pub const MAX_THREADS: usize = 16;
+/// Size of a stack allocated from the heap when the attributes specify none
+#[cfg(feature = "alloc")]
+pub const DEFAULT_STACK_SIZE: usize = 8192;
+
+/// ID of a thread (`pthread_t`), which is never 0
+pub type PthreadT = usize;
+
+/// Entry function of a thread
+pub type StartRoutine = extern "C" fn(arg: *mut c_void) -> *mut c_void;
+
+/// Attributes of a thread (`pthread_attr_t`)
+#[repr(C)]
+#[derive(Clone, Debug)]
+pub struct PthreadAttr {
+    stack_addr: *mut c_void,
+    stack_size: usize,
+    /// Priority of the task (higher value means higher priority)
+    priority: c_int,
+    detach_state: c_int,
+}
+
+impl Default for PthreadAttr {
+    fn default() -> Self {
+        Self {
+            stack_addr: core::ptr::null_mut(),
+            #[cfg(feature = "alloc")]
+            stack_size: DEFAULT_STACK_SIZE,
+            #[cfg(not(feature = "alloc"))]
+            stack_size: 0,
+            priority: 1,
+            detach_state: PTHREAD_CREATE_JOINABLE,
+        }
+    }
+}
+
+/// Scheduling parameters (`struct sched_param`), whose priority is that of the task
+#[repr(C)]
+#[derive(Clone, Debug)]
+pub struct SchedParam {
+    pub sched_priority: c_int,
+}
+
+/// Slot of a thread in `THREADS`
+struct Thread {
+    used: AtomicBool,
+    task_id: AtomicUsize,
+    state: AtomicUsize,
+    /// Return value of the entry function
+    retval: AtomicUsize,
+}
+
+// States of a thread
+const RUNNING: usize = 0;
+const FINISHED: usize = 1;
+const DETACHED: usize = 2;
+
+static THREADS: [Thread; MAX_THREADS] = [const {
+    Thread {
+        used: AtomicBool::new(false),
+        task_id: AtomicUsize::new(0),
+        state: AtomicUsize::new(RUNNING),
+        retval: AtomicUsize::new(0),
+    }
+}; MAX_THREADS];
+
+fn thread_slot(thread: PthreadT) -> Option<&'static Thread> {
+    let slot = THREADS.get(thread.checked_sub(1)?)?;
+    slot.used.load(Ordering::SeqCst).then_some(slot)
+}
+
+/// Argument of an entry function, which is up to the C code to share safely
+struct ThreadArg(*mut c_void);
+
+unsafe impl Send for ThreadArg {}
+
+/// Body of the task of a thread
+fn run_thread(slot: &'static Thread, start: StartRoutine, arg: ThreadArg) {
+    if let Ok(handle) = task::current() {
+        slot.task_id.store(handle.id(), Ordering::SeqCst);
+    }
+
+    let retval = start(arg.0);
+
+    slot.retval.store(retval as usize, Ordering::SeqCst);
+    if slot
+        .state
+        .compare_exchange(RUNNING, FINISHED, Ordering::SeqCst, Ordering::SeqCst)
+        .is_ok()
+    {
+        wait::wake(&slot.state);
+    } else {
+        // Detached, so nobody joins
+        slot.used.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Creates a thread running `start(arg)` and stores its ID in `thread`.
+///
+/// # Safety
+/// `thread` and `attr` must be valid or null, and the stack in `attr` must not be used by anything else.
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub unsafe extern "C" fn pthread_create(
+    thread: *mut PthreadT,
+    attr: *const PthreadAttr,
+    start: Option<StartRoutine>,
+    arg: *mut c_void,
+) -> c_int {
+    let (Some(thread), Some(start)) = (unsafe { thread.as_mut() }, start) else {
+        return EINVAL;
+    };
+    let attr = unsafe { attr.as_ref() }.cloned().unwrap_or_default();
+    let Ok(priority) = usize::try_from(attr.priority) else {
+        return EINVAL;
+    };
+    #[cfg(not(feature = "alloc"))]
+    if attr.stack_addr.is_null() {
+        return EINVAL;
+    }
+
+    let Some(index) = THREADS.iter().position(|slot| {
+        slot.used
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    }) else {
+        return EAGAIN;
+    };
+    let slot = &THREADS[index];
+    slot.task_id.store(0, Ordering::SeqCst);
+    slot.state.store(
+        if attr.detach_state == PTHREAD_CREATE_DETACHED {
+            DETACHED
+        } else {
+            RUNNING
+        },
+        Ordering::SeqCst,
+    );
+
+    let arg = ThreadArg(arg);
+    let entry = move || run_thread(slot, start, arg);
+    let config = TaskConfig::default().with_priority(priority);
+    let result = if attr.stack_addr.is_null() {
+        #[cfg(feature = "alloc")]
+        {
+            taskette::scheduler::spawn_heap(entry, attr.stack_size, config)
+        }
+        #[cfg(not(feature = "alloc"))]
+        unreachable!()
+    } else {
+        let start = attr.stack_addr as usize;
+        // Unused by anything else (see Safety)
+        let stack = unsafe { RawStack::new(start..start + attr.stack_size) };
+        spawn(entry, stack, config)
+    };
+
+    match result {
+        Ok(_) => {
+            *thread = index + 1;
+            0
+        }
+        Err(_) => {
+            slot.used.store(false, Ordering::SeqCst);
+            EAGAIN
+        }
+    }
+}
+
+/// Blocks the calling thread until `thread` finishes, and stores its return value in `retval` unless it is null.
+///
+/// # Safety
+/// `retval` must be valid or null.
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub unsafe extern "C" fn pthread_join(thread: PthreadT, retval: *mut *mut c_void) -> c_int {
+    let Some(slot) = thread_slot(thread) else {
+        return ESRCH;
+    };
+
+    loop {
+        match slot.state.load(Ordering::SeqCst) {
+            FINISHED => break,
+            DETACHED => return EINVAL,
+            _ => {
+                let task_id = slot.task_id.load(Ordering::SeqCst);
+                if task::current().is_ok_and(|handle| handle.id() == task_id) {
+                    return EDEADLK;
+                }
+                let _ = wait::wait(&slot.state, RUNNING);
+            }
+        }
+    }
+
+    if let Some(retval) = unsafe { retval.as_mut() } {
+        *retval = slot.retval.load(Ordering::SeqCst) as *mut c_void;
+    }
+    slot.used.store(false, Ordering::SeqCst);
+    0
+}
+
+/// Lets `thread` release its slot by itself when it finishes, instead of being joined.
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub extern "C" fn pthread_detach(thread: PthreadT) -> c_int {
+    let Some(slot) = thread_slot(thread) else {
+        return ESRCH;
+    };
+
+    match slot
+        .state
+        .compare_exchange(RUNNING, DETACHED, Ordering::SeqCst, Ordering::SeqCst)
+    {
+        Ok(_) => 0,
+        Err(FINISHED) => {
+            // Already finished, so nobody else releases it
+            slot.used.store(false, Ordering::SeqCst);
+            0
+        }
+        Err(_) => EINVAL,
+    }
+}
+
+/// Returns the ID of the calling thread, or 0 if the calling task was not created by `pthread_create`.
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub extern "C" fn pthread_self() -> PthreadT {
+    let Ok(handle) = task::current() else {
+        return 0;
+    };
+    THREADS
+        .iter()
+        .position(|slot| {
+            slot.used.load(Ordering::SeqCst) && slot.task_id.load(Ordering::SeqCst) == handle.id()
+        })
+        .map_or(0, |index| index + 1)
+}
+
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub extern "C" fn pthread_equal(thread1: PthreadT, thread2: PthreadT) -> c_int {
+    (thread1 == thread2) as c_int
+}
+
+/// Yields the CPU to another thread of the same priority.
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub extern "C" fn sched_yield() -> c_int {
+    taskette::arch::yield_now();
+    0
+}
+
+/// # Safety
+/// `attr` must be valid or null.
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub unsafe extern "C" fn pthread_attr_init(attr: *mut PthreadAttr) -> c_int {
+    match unsafe { attr.as_mut() } {
+        Some(attr) => {
+            *attr = PthreadAttr::default();
+            0
+        }
+        None => EINVAL,
+    }
+}
+
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub extern "C" fn pthread_attr_destroy(_attr: *mut PthreadAttr) -> c_int {
+    0
+}
+
+/// Sets the stack of the thread, which has to live until the thread finishes.
+///
+/// # Safety
+/// `attr` must be valid or null.
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub unsafe extern "C" fn pthread_attr_setstack(
+    attr: *mut PthreadAttr,
+    stack_addr: *mut c_void,
+    stack_size: usize,
+) -> c_int {
+    match unsafe { attr.as_mut() } {
+        Some(attr) if !stack_addr.is_null() && stack_size > 0 => {
+            attr.stack_addr = stack_addr;
+            attr.stack_size = stack_size;
+            0
+        }
+        _ => EINVAL,
+    }
+}
+
+/// Sets the size of the stack allocated from the heap (`alloc` feature).
+///
+/// # Safety
+/// `attr` must be valid or null.
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub unsafe extern "C" fn pthread_attr_setstacksize(
+    attr: *mut PthreadAttr,
+    stack_size: usize,
+) -> c_int {
+    match unsafe { attr.as_mut() } {
+        Some(attr) if stack_size > 0 => {
+            attr.stack_size = stack_size;
+            0
+        }
+        _ => EINVAL,
+    }
+}
+
+/// # Safety
+/// `attr` must be valid or null.
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub unsafe extern "C" fn pthread_attr_setdetachstate(
+    attr: *mut PthreadAttr,
+    detach_state: c_int,
+) -> c_int {
+    match unsafe { attr.as_mut() } {
+        Some(attr)
+            if detach_state == PTHREAD_CREATE_JOINABLE
+                || detach_state == PTHREAD_CREATE_DETACHED =>
+        {
+            attr.detach_state = detach_state;
+            0
+        }
+        _ => EINVAL,
+    }
+}
+
+/// Sets the priority of the task of the thread from `param.sched_priority`.
+///
+/// # Safety
+/// `attr` and `param` must be valid or null.
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub unsafe extern "C" fn pthread_attr_setschedparam(
+    attr: *mut PthreadAttr,
+    param: *const SchedParam,
+) -> c_int {
+    match (unsafe { attr.as_mut() }, unsafe { param.as_ref() }) {
+        (Some(attr), Some(param)) if param.sched_priority >= 0 => {
+            attr.priority = param.sched_priority;
+            0
+        }
+        _ => EINVAL,
+    }
+}
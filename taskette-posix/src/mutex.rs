@@ -0,0 +1,155 @@
+//! Mutexes (`pthread_mutex_*`).
+
+use core::{ffi::c_int, sync::atomic::Ordering};
+
+use taskette::{portable_atomic::AtomicUsize, task};
+
+use crate::{EAGAIN, EBUSY, EDEADLK, EINVAL, EPERM, wait};
+
+/// Non-recursive mutex owned by a thread (`pthread_mutex_t`). All zero is unlocked (`PTHREAD_MUTEX_INITIALIZER`).
+#[repr(C)]
+pub struct PthreadMutex {
+    /// 0 while unlocked, otherwise the ID of the owner task plus one
+    state: AtomicUsize,
+}
+
+/// Attributes of a mutex (`pthread_mutexattr_t`), which are all ignored
+#[repr(C)]
+pub struct PthreadMutexAttr {
+    _reserved: c_int,
+}
+
+/// State value of the calling task holding a mutex
+fn owner_state() -> Result<usize, c_int> {
+    task::current()
+        .map(|handle| handle.id() + 1)
+        .map_err(|_| EPERM)
+}
+
+impl PthreadMutex {
+    pub(crate) fn lock(&self) -> c_int {
+        let me = match owner_state() {
+            Ok(me) => me,
+            Err(code) => return code,
+        };
+
+        loop {
+            match self
+                .state
+                .compare_exchange(0, me, Ordering::SeqCst, Ordering::SeqCst)
+            {
+                Ok(_) => return 0,
+                Err(owner) if owner == me => return EDEADLK,
+                Err(owner) => {
+                    if wait::wait(&self.state, owner).is_err() {
+                        return EAGAIN;
+                    }
+                }
+            }
+        }
+    }
+
+    pub(crate) fn unlock(&self) -> c_int {
+        let me = match owner_state() {
+            Ok(me) => me,
+            Err(code) => return code,
+        };
+
+        if self
+            .state
+            .compare_exchange(me, 0, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            return EPERM;
+        }
+        wait::wake(&self.state);
+        0
+    }
+}
+
+/// # Safety
+/// `mutex` must be valid or null.
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub unsafe extern "C" fn pthread_mutex_init(
+    mutex: *mut PthreadMutex,
+    _attr: *const PthreadMutexAttr,
+) -> c_int {
+    let Some(mutex) = (unsafe { mutex.as_ref() }) else {
+        return EINVAL;
+    };
+    mutex.state.store(0, Ordering::SeqCst);
+    0
+}
+
+/// # Safety
+/// `mutex` must be valid or null.
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub unsafe extern "C" fn pthread_mutex_destroy(mutex: *mut PthreadMutex) -> c_int {
+    match unsafe { mutex.as_ref() } {
+        Some(mutex) if mutex.state.load(Ordering::SeqCst) != 0 => EBUSY,
+        Some(_) => 0,
+        None => EINVAL,
+    }
+}
+
+/// Locks the mutex, blocking the calling thread while another thread holds it.
+///
+/// # Safety
+/// `mutex` must be valid or null.
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub unsafe extern "C" fn pthread_mutex_lock(mutex: *mut PthreadMutex) -> c_int {
+    match unsafe { mutex.as_ref() } {
+        Some(mutex) => mutex.lock(),
+        None => EINVAL,
+    }
+}
+
+/// # Safety
+/// `mutex` must be valid or null.
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub unsafe extern "C" fn pthread_mutex_trylock(mutex: *mut PthreadMutex) -> c_int {
+    let Some(mutex) = (unsafe { mutex.as_ref() }) else {
+        return EINVAL;
+    };
+    let me = match owner_state() {
+        Ok(me) => me,
+        Err(code) => return code,
+    };
+
+    match mutex
+        .state
+        .compare_exchange(0, me, Ordering::SeqCst, Ordering::SeqCst)
+    {
+        Ok(_) => 0,
+        // Also when the calling thread holds it, like in POSIX
+        Err(_) => EBUSY,
+    }
+}
+
+/// # Safety
+/// `mutex` must be valid or null.
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub unsafe extern "C" fn pthread_mutex_unlock(mutex: *mut PthreadMutex) -> c_int {
+    match unsafe { mutex.as_ref() } {
+        Some(mutex) => mutex.unlock(),
+        None => EINVAL,
+    }
+}
+
+/// # Safety
+/// `attr` must be valid or null.
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub unsafe extern "C" fn pthread_mutexattr_init(attr: *mut PthreadMutexAttr) -> c_int {
+    match unsafe { attr.as_mut() } {
+        Some(attr) => {
+            attr._reserved = 0;
+            0
+        }
+        None => EINVAL,
+    }
+}
+
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub extern "C" fn pthread_mutexattr_destroy(_attr: *mut PthreadMutexAttr) -> c_int {
+    0
+}
@@ -0,0 +1,121 @@
+//! Unnamed semaphores (`sem_*`).
+//!
+//! Like in POSIX, they return -1 on failure and the error is stored in `errno`,
+//! which is only possible with the `newlib` feature (otherwise the error is lost).
+
+use core::{
+    ffi::{c_int, c_uint},
+    sync::atomic::Ordering,
+};
+
+use taskette::portable_atomic::AtomicUsize;
+
+use crate::{EAGAIN, EINVAL, wait};
+
+/// Counting semaphore (`sem_t`).
+#[repr(C)]
+pub struct Sem {
+    value: AtomicUsize,
+}
+
+#[cfg(feature = "newlib")]
+unsafe extern "C" {
+    /// Location of `errno` of the calling thread
+    fn __errno() -> *mut c_int;
+}
+
+/// Stores `code` in `errno` and returns -1.
+fn fail(code: c_int) -> c_int {
+    #[cfg(feature = "newlib")]
+    unsafe {
+        *__errno() = code;
+    }
+    #[cfg(not(feature = "newlib"))]
+    let _ = code;
+
+    -1
+}
+
+/// Initializes the semaphore with `value`. `pshared` is ignored.
+///
+/// # Safety
+/// `sem` must be valid or null.
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub unsafe extern "C" fn sem_init(sem: *mut Sem, _pshared: c_int, value: c_uint) -> c_int {
+    let Some(sem) = (unsafe { sem.as_ref() }) else {
+        return fail(EINVAL);
+    };
+    sem.value.store(value as usize, Ordering::SeqCst);
+    0
+}
+
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub extern "C" fn sem_destroy(_sem: *mut Sem) -> c_int {
+    0
+}
+
+/// Decrements the semaphore, blocking the calling thread while it is zero.
+///
+/// # Safety
+/// `sem` must be valid or null.
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub unsafe extern "C" fn sem_wait(sem: *mut Sem) -> c_int {
+    let Some(sem) = (unsafe { sem.as_ref() }) else {
+        return fail(EINVAL);
+    };
+
+    loop {
+        if try_decrement(sem) {
+            return 0;
+        }
+        if wait::wait(&sem.value, 0).is_err() {
+            return fail(EAGAIN);
+        }
+    }
+}
+
+/// Decrements the semaphore if it is not zero. Can also be used in interrupt handlers.
+///
+/// # Safety
+/// `sem` must be valid or null.
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub unsafe extern "C" fn sem_trywait(sem: *mut Sem) -> c_int {
+    match unsafe { sem.as_ref() } {
+        Some(sem) if try_decrement(sem) => 0,
+        Some(_) => fail(EAGAIN),
+        None => fail(EINVAL),
+    }
+}
+
+/// Increments the semaphore and wakes up waiting threads. Can also be used in interrupt handlers.
+///
+/// # Safety
+/// `sem` must be valid or null.
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub unsafe extern "C" fn sem_post(sem: *mut Sem) -> c_int {
+    let Some(sem) = (unsafe { sem.as_ref() }) else {
+        return fail(EINVAL);
+    };
+    sem.value.fetch_add(1, Ordering::SeqCst);
+    wait::wake(&sem.value);
+    0
+}
+
+/// # Safety
+/// `sem` and `value` must be valid or null.
+#[cfg_attr(target_os = "none", unsafe(no_mangle))]
+pub unsafe extern "C" fn sem_getvalue(sem: *mut Sem, value: *mut c_int) -> c_int {
+    let (Some(sem), Some(value)) = (unsafe { sem.as_ref() }, unsafe { value.as_mut() }) else {
+        return fail(EINVAL);
+    };
+    *value = sem.value.load(Ordering::SeqCst) as c_int;
+    0
+}
+
+fn try_decrement(sem: &Sem) -> bool {
+    sem.value
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |value| {
+            value.checked_sub(1)
+        })
+        .is_ok()
+}
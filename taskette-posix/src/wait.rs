@@ -0,0 +1,39 @@
+//! Blocking on a word of a C object, through futexes shared by hashing the address.
+//!
+//! C objects such as `pthread_mutex_t` are plain words (so that they can be initialized statically),
+//! which are too small to hold a `Futex`. A waiter blocks on the futex of the bucket its word belongs to,
+//! and a waker wakes up the whole bucket. Waiters of other words in the same bucket just wake up spuriously.
+
+use core::sync::atomic::Ordering;
+
+use taskette::{Error, futex::Futex, portable_atomic::AtomicUsize};
+
+const NUM_BUCKETS: usize = 8;
+
+/// Values are incremented on every wake
+static BUCKETS: [Futex; NUM_BUCKETS] = [const { Futex::new(0) }; NUM_BUCKETS];
+
+fn bucket(word: &AtomicUsize) -> &'static Futex {
+    let address = word as *const AtomicUsize as usize;
+    &BUCKETS[(address / size_of::<usize>()) % NUM_BUCKETS]
+}
+
+/// Blocks the current task while `word` equals `value`. It may also return spuriously.
+pub(crate) fn wait(word: &AtomicUsize, value: usize) -> Result<(), Error> {
+    let bucket = bucket(word);
+    // Read before the check, so that a wake in between makes `wait` return immediately
+    let sequence = bucket.as_ref().load(Ordering::SeqCst);
+    if word.load(Ordering::SeqCst) != value {
+        return Ok(());
+    }
+
+    bucket.wait(sequence)
+}
+
+/// Wakes up the tasks waiting for `word` to change.
+pub(crate) fn wake(word: &AtomicUsize) {
+    let bucket = bucket(word);
+    bucket.as_ref().fetch_add(1, Ordering::SeqCst);
+    // Waking up cannot fail after the scheduler is initialized
+    let _ = bucket.wake_all();
+}
@@ -0,0 +1,230 @@
+//! SEGGER SystemView integration of Taskette.
+//!
+//! [`init`] installs trace hooks of `taskette` which record the following events:
+//!
+//! | Taskette event      | SystemView event                                    |
+//! |---------------------|-----------------------------------------------------|
+//! | Task creation       | Task create (with the task info)                    |
+//! | Task removal        | Task terminate                                      |
+//! | Context switch      | Task start execution, or idle for idle tasks        |
+//! | Task blocked        | Task stop ready                                     |
+//! | Task woken up       | Task start ready                                    |
+//! | Tick                | Timer enter and exit (timer ID [`TICK_TIMER_ID`])   |
+//!
+//! Interrupt handlers of the application can be recorded by calling [`isr_enter`] and [`isr_exit`].
+//!
+//! The SystemView target sources have to be linked by the application,
+//! and `init` has to be called after the scheduler is initialized but before spawning tasks.
+
+#![no_std]
+
+use core::{cell::RefCell, ffi::c_char};
+
+use critical_section::Mutex;
+use taskette::{
+    scheduler::{NUM_CORES, get_config},
+    sync::interrupt_free,
+    timer::current_time,
+    trace::{self, TraceHooks},
+};
+
+/// Timer ID used for ticks of the scheduler
+pub const TICK_TIMER_ID: u32 = 0;
+
+/// Maximum number of tasks listed to SystemView when it connects
+const MAX_TASKS: usize = 32;
+
+/// Task ID and priority
+type TaskEntry = (usize, usize);
+
+/// Tasks which exist now, sent to SystemView when it connects
+static TASKS: Mutex<RefCell<[Option<TaskEntry>; MAX_TASKS]>> =
+    Mutex::new(RefCell::new([None; MAX_TASKS]));
+
+static OS_API: OsApi = OsApi {
+    get_time: Some(get_time),
+    send_task_list: Some(send_task_list),
+};
+
+/// `SEGGER_SYSVIEW_OS_API`
+#[repr(C)]
+struct OsApi {
+    get_time: Option<extern "C" fn() -> u64>,
+    send_task_list: Option<extern "C" fn()>,
+}
+
+/// `SEGGER_SYSVIEW_TASKINFO`
+#[repr(C)]
+struct TaskInfo {
+    task_id: u32,
+    name: *const c_char,
+    priority: u32,
+    stack_base: u32,
+    stack_size: u32,
+}
+
+unsafe extern "C" {
+    fn SEGGER_SYSVIEW_Init(
+        sys_freq: u32,
+        cpu_freq: u32,
+        os_api: *const OsApi,
+        send_sys_desc: Option<extern "C" fn()>,
+    );
+    fn SEGGER_SYSVIEW_SendSysDesc(desc: *const c_char);
+    fn SEGGER_SYSVIEW_SendTaskInfo(info: *const TaskInfo);
+    fn SEGGER_SYSVIEW_OnTaskCreate(task_id: u32);
+    fn SEGGER_SYSVIEW_OnTaskTerminate(task_id: u32);
+    fn SEGGER_SYSVIEW_OnTaskStartExec(task_id: u32);
+    fn SEGGER_SYSVIEW_OnTaskStartReady(task_id: u32);
+    fn SEGGER_SYSVIEW_OnTaskStopReady(task_id: u32, cause: u32);
+    fn SEGGER_SYSVIEW_OnIdle();
+    fn SEGGER_SYSVIEW_RecordEnterISR();
+    fn SEGGER_SYSVIEW_RecordExitISR();
+    fn SEGGER_SYSVIEW_RecordEnterTimer(timer_id: u32);
+    fn SEGGER_SYSVIEW_RecordExitTimer();
+}
+
+/// Initializes SystemView and starts recording scheduler events.
+///
+/// `timestamp_freq` is the frequency of the timestamp source configured in the SystemView target sources,
+/// and `cpu_freq` is the CPU clock frequency.
+pub fn init(timestamp_freq: u32, cpu_freq: u32) {
+    unsafe {
+        SEGGER_SYSVIEW_Init(
+            timestamp_freq,
+            cpu_freq,
+            &OS_API,
+            Some(send_system_description),
+        );
+    }
+
+    trace::set_hooks(
+        TraceHooks::new()
+            .with_on_create(on_create)
+            .with_on_remove(on_remove)
+            .with_on_switch(on_switch)
+            .with_on_block(on_block)
+            .with_on_ready(on_ready)
+            .with_on_tick(on_tick),
+    );
+}
+
+/// Records the start of an interrupt handler.
+pub fn isr_enter() {
+    unsafe { SEGGER_SYSVIEW_RecordEnterISR() };
+}
+
+/// Records the end of an interrupt handler.
+pub fn isr_exit() {
+    unsafe { SEGGER_SYSVIEW_RecordExitISR() };
+}
+
+fn on_create(task_id: usize, priority: usize) {
+    interrupt_free(|cs| {
+        let mut tasks = TASKS.borrow_ref_mut(cs);
+        if let Some(slot) = tasks.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some((task_id, priority));
+        }
+    });
+
+    unsafe { SEGGER_SYSVIEW_OnTaskCreate(task_id as u32) };
+    send_task_info(task_id, priority);
+}
+
+fn on_remove(task_id: usize) {
+    interrupt_free(|cs| {
+        let mut tasks = TASKS.borrow_ref_mut(cs);
+        if let Some(slot) = tasks
+            .iter_mut()
+            .find(|slot| slot.is_some_and(|(id, _)| id == task_id))
+        {
+            *slot = None;
+        }
+    });
+
+    unsafe { SEGGER_SYSVIEW_OnTaskTerminate(task_id as u32) };
+}
+
+fn on_switch(from: usize, to: usize) {
+    if from == to {
+        return;
+    }
+
+    // Idle task of core N has ID N
+    if to < NUM_CORES {
+        unsafe { SEGGER_SYSVIEW_OnIdle() };
+    } else {
+        unsafe { SEGGER_SYSVIEW_OnTaskStartExec(to as u32) };
+    }
+}
+
+fn on_block(task_id: usize) {
+    unsafe { SEGGER_SYSVIEW_OnTaskStopReady(task_id as u32, 0) };
+}
+
+fn on_ready(task_id: usize) {
+    unsafe { SEGGER_SYSVIEW_OnTaskStartReady(task_id as u32) };
+}
+
+fn on_tick() {
+    unsafe {
+        SEGGER_SYSVIEW_RecordEnterTimer(TICK_TIMER_ID);
+        SEGGER_SYSVIEW_RecordExitTimer();
+    }
+}
+
+extern "C" fn send_system_description() {
+    unsafe { SEGGER_SYSVIEW_SendSysDesc(c"N=Taskette,O=Taskette".as_ptr()) };
+}
+
+extern "C" fn send_task_list() {
+    let tasks = interrupt_free(|cs| *TASKS.borrow_ref(cs));
+    for (task_id, priority) in tasks.into_iter().flatten() {
+        send_task_info(task_id, priority);
+    }
+}
+
+/// System time in microseconds
+extern "C" fn get_time() -> u64 {
+    let (Ok(time), Ok(config)) = (current_time(), get_config()) else {
+        return 0;
+    };
+    time * 1_000_000 / config.tick_freq as u64
+}
+
+fn send_task_info(task_id: usize, priority: usize) {
+    // "Task #" followed by the ID and a NUL terminator
+    let mut name = [0u8; 32];
+    let prefix = b"Task #";
+    name[..prefix.len()].copy_from_slice(prefix);
+    write_decimal(&mut name[prefix.len()..], task_id);
+
+    let info = TaskInfo {
+        task_id: task_id as u32,
+        name: name.as_ptr() as *const c_char,
+        priority: priority as u32,
+        stack_base: 0,
+        stack_size: 0,
+    };
+    // The name is copied into the packet
+    unsafe { SEGGER_SYSVIEW_SendTaskInfo(&info) };
+}
+
+/// Writes `value` in decimal into `buf`, which is large enough for any `usize`.
+fn write_decimal(buf: &mut [u8], value: usize) {
+    let mut digits = [0u8; 20];
+    let mut len = 0;
+    let mut value = value;
+    loop {
+        digits[len] = b'0' + (value % 10) as u8;
+        len += 1;
+        value /= 10;
+        if value == 0 {
+            break;
+        }
+    }
+
+    for (dst, src) in buf.iter_mut().zip(digits[..len].iter().rev()) {
+        *dst = *src;
+    }
+}
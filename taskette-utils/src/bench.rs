@@ -0,0 +1,110 @@
+//! Benchmarks of kernel operations, for comparing configurations on actual hardware.
+//!
+//! Durations are measured with the high-resolution counter of the port ([`taskette::arch::cycle_count`]),
+//! so they are in counts of that counter (CPU cycles on Cortex-M) and include the overhead of reading it.
+
+use taskette::{
+    Error,
+    arch::{StackAllocation, cycle_count, yield_now},
+    futex::Futex,
+    portable_atomic::{AtomicBool, Ordering},
+    scheduler::spawn,
+    sync::SpinLock,
+    task::TaskConfig,
+};
+
+/// Set while the partner task of `measure_context_switch` has to keep yielding
+static PARTNER_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Statistics of repeated measurements (in counts of the counter of the port).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Measurement {
+    pub min: u32,
+    pub max: u32,
+    pub average: u32,
+    pub iterations: u32,
+}
+
+impl Measurement {
+    fn add(&mut self, sum: &mut u64, duration: u32) {
+        if self.iterations == 0 {
+            self.min = duration;
+            self.max = duration;
+        } else {
+            self.min = self.min.min(duration);
+            self.max = self.max.max(duration);
+        }
+
+        self.iterations += 1;
+        *sum += duration as u64;
+        self.average = (*sum / self.iterations as u64) as u32;
+    }
+}
+
+/// Measures each of `iterations` calls of `f`.
+pub fn measure(iterations: u32, mut f: impl FnMut()) -> Measurement {
+    let mut measurement = Measurement::default();
+    let mut sum = 0;
+    for _ in 0..iterations {
+        let start = cycle_count();
+        f();
+        measurement.add(&mut sum, cycle_count().wrapping_sub(start));
+    }
+
+    measurement
+}
+
+/// Measures a context switch between two tasks of the same priority.
+///
+/// Has to be called from a task with `priority`, and no other task of that priority should be ready.
+/// A partner task is spawned on `stack` with the same priority, and finishes before this returns.
+/// Each measurement is half of a round trip (switching to the partner and back).
+pub fn measure_context_switch<S: StackAllocation>(
+    iterations: u32,
+    priority: usize,
+    stack: S,
+) -> Result<Measurement, Error> {
+    PARTNER_ACTIVE.store(true, Ordering::SeqCst);
+    spawn(
+        || {
+            while PARTNER_ACTIVE.load(Ordering::SeqCst) {
+                yield_now();
+            }
+        },
+        stack,
+        TaskConfig::default().with_priority(priority),
+    )?;
+    // Let the partner start
+    yield_now();
+
+    let mut round_trip = measure(iterations, yield_now);
+    round_trip.min /= 2;
+    round_trip.max /= 2;
+    round_trip.average /= 2;
+
+    // Let the partner finish
+    PARTNER_ACTIVE.store(false, Ordering::SeqCst);
+    yield_now();
+
+    Ok(round_trip)
+}
+
+/// Measures acquiring and releasing a [`SpinLock`] which nobody else holds.
+pub fn measure_lock_uncontended(iterations: u32) -> Measurement {
+    let lock = SpinLock::new(0u32);
+    measure(iterations, || *lock.lock() += 1)
+}
+
+/// Measures acquiring and releasing a [`SpinLock`] with interrupts masked ([`SpinLock::lock_irq`]).
+pub fn measure_lock_irq_uncontended(iterations: u32) -> Measurement {
+    let lock = SpinLock::new(0u32);
+    measure(iterations, || *lock.lock_irq() += 1)
+}
+
+/// Measures waking up a [`Futex`] on which no task is waiting (the cost of a kernel call).
+pub fn measure_futex_wake_uncontended(iterations: u32) -> Measurement {
+    let futex = Futex::new(0);
+    measure(iterations, || {
+        let _ = futex.wake_one();
+    })
+}
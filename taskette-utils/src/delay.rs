@@ -1,35 +1,67 @@
 //! `embedded-hal`-compatible delay that yields CPU to other tasks instead of busy looping.
-//! The precision is limited by the tick frequency setting of the scheduler (usually order of a millisecond or more).
-use taskette::{Error, scheduler::get_config, timer::{current_time, wait_until}};
+//! The precision is limited by the tick frequency setting of the scheduler (usually order of a millisecond or more),
+//! unless the hybrid mode is enabled with [`Delay::with_hybrid`].
+use taskette::{Error, scheduler::get_config, timer::{current_time, current_time_micros, wait_until}};
 
 #[derive(Clone)]
 pub struct Delay {
     tick_freq: u32,
+    hybrid: bool,
 }
 
 impl Delay {
     pub fn new() -> Result<Self, Error> {
         let tick_freq = get_config()?.tick_freq;
 
-        Ok(Self { tick_freq })
+        Ok(Self { tick_freq, hybrid: false })
+    }
+
+    /// Enables busy-waiting for the part of a delay shorter than a tick, timed with `current_time_micros`.
+    ///
+    /// A delay shorter than a tick then keeps the CPU busy instead of blocking for a whole tick,
+    /// and a longer one blocks for whole ticks before busy-waiting the rest (less than two ticks).
+    /// The resolution is finer than a tick only on ports with a cycle counter (see `current_time_micros`).
+    pub fn with_hybrid(self, hybrid: bool) -> Self {
+        Self { hybrid, ..self }
     }
 
     pub fn delay_ticks(&mut self, ticks: u64) {
         let now = current_time().expect("Failed to acquire current time");
         wait_until(now + ticks).expect("Failed to register timeout");
     }
+
+    fn delay_nanos(&mut self, ns: u64) {
+        let tick_freq = self.tick_freq as u64;
+        if !self.hybrid {
+            self.delay_ticks((ns * tick_freq).div_ceil(1_000_000_000));
+            return;
+        }
+
+        let us = ns.div_ceil(1_000);
+        let end = current_time_micros().expect("Failed to acquire current time") + us;
+
+        // The current tick started before now, so the wakeup comes no later than `end`
+        let ticks = us * tick_freq / 1_000_000;
+        if ticks > 0 {
+            self.delay_ticks(ticks);
+        }
+
+        while current_time_micros().expect("Failed to acquire current time") < end {
+            core::hint::spin_loop();
+        }
+    }
 }
 
 impl embedded_hal::delay::DelayNs for Delay {
     fn delay_ns(&mut self, ns: u32) {
-        self.delay_ticks(((ns * self.tick_freq) as u64).div_ceil(1_000_000_000));
+        self.delay_nanos(ns as u64);
     }
 
     fn delay_us(&mut self, us: u32) {
-        self.delay_ticks(((us * self.tick_freq) as u64).div_ceil(1_000_000));
+        self.delay_nanos(us as u64 * 1_000);
     }
 
     fn delay_ms(&mut self, ms: u32) {
-        self.delay_ticks(((ms * self.tick_freq) as u64).div_ceil(1_000));
+        self.delay_nanos(ms as u64 * 1_000_000);
     }
 }
@@ -0,0 +1,114 @@
+//! Single task running several interrupt-driven drivers as `async` functions.
+//!
+//! Simple peripherals (a UART, an SPI DMA transfer, a GPIO line) mostly wait for their interrupts,
+//! so giving each of them a task with its own stack wastes memory.
+//! With [`run_drivers`], each driver is an `async` function awaiting its `IrqEvent` with [`wait_event`],
+//! and one host task polls all of them, parking while none can make progress:
+//!
+//! ```ignore
+//! static SPI_DMA_DONE: IrqEvent = IrqEvent::new();
+//! static BUTTON_EVENT: IrqEvent = IrqEvent::new();
+//!
+//! async fn display_driver(spi: Spi) {
+//!     loop {
+//!         start_dma(&spi, &frame);
+//!         wait_event(&SPI_DMA_DONE).await.unwrap();
+//!     }
+//! }
+//!
+//! spawn(
+//!     move || run_drivers([pin!(display_driver(spi)), pin!(button_driver(button))]),
+//!     DRIVER_HOST_STACK.take(),
+//!     TaskConfig::default().with_priority(3),
+//! )?;
+//! ```
+//!
+//! The drivers run concurrently but in the host task only, so one blocking call (e.g. `Delay`) stalls all of them.
+//! Any `Future` can be awaited in a driver, as the waker given to them unparks the host task.
+
+use core::{
+    pin::Pin,
+    task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+};
+
+use taskette::{Error, sync::IrqEvent, task};
+
+const RAW_WAKER_VTABLE: RawWakerVTable = RawWakerVTable::new(
+    raw_waker_clone,
+    raw_waker_wake,
+    raw_waker_wake,
+    raw_waker_drop,
+);
+
+/// Polls `drivers` in the current task forever, parking the task while all of them are pending.
+///
+/// Drivers which complete are not polled again. Every driver is polled on each wakeup,
+/// so `drivers` should be a handful of futures which check their conditions cheaply.
+pub fn run_drivers<const N: usize>(drivers: [Pin<&mut dyn Future<Output = ()>>; N]) -> ! {
+    let task_id = task::current().expect("Scheduler not running").id();
+
+    // SAFETY: the waker only carries the task ID
+    let waker = unsafe { Waker::from_raw(RawWaker::new(task_id as *const (), &RAW_WAKER_VTABLE)) };
+    let mut context = Context::from_waker(&waker);
+
+    let mut drivers = drivers.map(Some);
+    loop {
+        for slot in drivers.iter_mut() {
+            if let Some(driver) = slot
+                && driver.as_mut().poll(&mut context).is_ready()
+            {
+                *slot = None;
+            }
+        }
+
+        // A wakeup during polling leaves the token, so that this returns immediately
+        task::park().expect("Failed to park the driver host");
+    }
+}
+
+/// Waits for `event` to be signaled, and clears it. Awaited by a driver of [`run_drivers`].
+///
+/// Like [`IrqEvent::wait`], only one driver (or task) may wait on an event at a time.
+pub fn wait_event(event: &IrqEvent) -> WaitEvent<'_> {
+    WaitEvent { event }
+}
+
+/// Future returned by [`wait_event`]
+pub struct WaitEvent<'a> {
+    event: &'a IrqEvent,
+}
+
+impl Future for WaitEvent<'_> {
+    type Output = Result<(), Error>;
+
+    fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.event.take() {
+            return Poll::Ready(Ok(()));
+        }
+
+        // The event unparks the host task directly instead of going through the waker
+        if let Err(error) = self.event.listen() {
+            return Poll::Ready(Err(error));
+        }
+
+        // Checks again in case it was signaled before `listen`
+        if self.event.take() {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+unsafe fn raw_waker_clone(data: *const ()) -> RawWaker {
+    RawWaker::new(data, &RAW_WAKER_VTABLE)
+}
+
+unsafe fn raw_waker_wake(data: *const ()) {
+    // The host task never finishes, so this fails only before the scheduler starts
+    let _ = task::unpark(data as usize);
+}
+
+unsafe fn raw_waker_drop(_data: *const ()) {
+    // Do nothing
+}
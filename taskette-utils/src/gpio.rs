@@ -0,0 +1,110 @@
+//! Blocking wait for edges of a GPIO pin (e.g. a button or the IRQ line of a peripheral chip).
+//!
+//! [`wait_for_edge`] enables the edge interrupt of an [`IrqPin`] and blocks the task on an `IrqEvent`
+//! until the interrupt handler signals it. The handler only has to acknowledge the interrupt and signal the event:
+//!
+//! ```ignore
+//! static BUTTON_EVENT: IrqEvent = IrqEvent::new();
+//!
+//! #[interrupt]
+//! fn IO_IRQ_BANK0() {
+//!     // Clear the edge status of the pin here (HAL-specific)
+//!     BUTTON_EVENT.signal();
+//! }
+//!
+//! // In the button task
+//! let mut button = IrqPin::new(pin, &BUTTON_EVENT, |pin, edge| {
+//!     pin.set_interrupt_enabled(Interrupt::EdgeLow, edge == Some(Edge::Falling));
+//! });
+//! loop {
+//!     wait_for_edge(&mut button, Edge::Falling)?;
+//!     info!("Pressed");
+//! }
+//! ```
+
+use taskette::{Error, sync::IrqEvent};
+
+/// Edge of a pin signal
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Edge {
+    /// Low to high
+    Rising,
+    /// High to low
+    Falling,
+    /// Either direction
+    Both,
+}
+
+/// GPIO pin whose edge interrupt signals an `IrqEvent`.
+pub struct IrqPin<'a, P> {
+    pin: P,
+    event: &'a IrqEvent,
+    set_interrupt: fn(&mut P, Option<Edge>),
+}
+
+impl<'a, P> IrqPin<'a, P> {
+    /// Wraps a pin of the HAL, whose interrupt handler signals `event`.
+    ///
+    /// `set_interrupt` enables the interrupt of the pin on the given edge, or disables it on `None`.
+    pub fn new(pin: P, event: &'a IrqEvent, set_interrupt: fn(&mut P, Option<Edge>)) -> Self {
+        Self {
+            pin,
+            event,
+            set_interrupt,
+        }
+    }
+
+    pub fn pin(&mut self) -> &mut P {
+        &mut self.pin
+    }
+
+    /// Gives the pin back.
+    pub fn release(self) -> P {
+        self.pin
+    }
+}
+
+/// Blocks the current task until `edge` occurs on `pin`.
+///
+/// The interrupt is enabled only during the wait, so edges before the call are not seen.
+pub fn wait_for_edge<P>(pin: &mut IrqPin<'_, P>, edge: Edge) -> Result<(), Error> {
+    wait_inner(pin, edge, None).map(|_| ())
+}
+
+/// Same as [`wait_for_edge`], but gives up when the time reaches `time` (in ticks).
+/// Returns whether the edge occurred.
+pub fn wait_for_edge_until<P>(
+    pin: &mut IrqPin<'_, P>,
+    edge: Edge,
+    time: u64,
+) -> Result<bool, Error> {
+    wait_inner(pin, edge, Some(time))
+}
+
+fn wait_inner<P>(pin: &mut IrqPin<'_, P>, edge: Edge, time: Option<u64>) -> Result<bool, Error> {
+    // Drops signals of earlier waits (e.g. a bounce after the interrupt was last handled)
+    pin.event.take();
+    (pin.set_interrupt)(&mut pin.pin, Some(edge));
+
+    let result = match time {
+        Some(time) => pin.event.wait_until(time),
+        None => pin.event.wait().map(|()| true),
+    };
+
+    (pin.set_interrupt)(&mut pin.pin, None);
+    result
+}
+
+impl<P: embedded_hal::digital::ErrorType> embedded_hal::digital::ErrorType for IrqPin<'_, P> {
+    type Error = P::Error;
+}
+
+impl<P: embedded_hal::digital::InputPin> embedded_hal::digital::InputPin for IrqPin<'_, P> {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        self.pin.is_high()
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        self.pin.is_low()
+    }
+}
@@ -1,3 +1,16 @@
 #![no_std]
+pub mod bench;
 pub mod delay;
+pub mod driver_host;
 pub mod futures;
+pub mod gpio;
+pub mod loader;
+#[cfg(feature = "monitor")]
+pub mod monitor;
+#[cfg(feature = "smoltcp")]
+pub mod net;
+pub mod time;
+#[cfg(feature = "uart")]
+pub mod uart;
+#[cfg(feature = "usb")]
+pub mod usb;
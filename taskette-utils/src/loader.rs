@@ -0,0 +1,173 @@
+//! Loader of position-independent tasks ("applets") shipped separately from the firmware.
+//!
+//! An applet is a blob with the following layout (all header fields are little-endian `u32`):
+//!
+//! | Field        | Description                                                             |
+//! |--------------|-------------------------------------------------------------------------|
+//! | magic        | `b"TKAP"`                                                               |
+//! | text_size    | Size of the code and read-only data (padded to 4 bytes in the blob)     |
+//! | data_size    | Size of the initialized data including the GOT (a multiple of 4)        |
+//! | bss_size     | Size of the zero-initialized data                                       |
+//! | entry        | Offset of the entry function in the text                                |
+//! | got          | Offset of the GOT in the data                                           |
+//! | num_relocs   | Number of relocations                                                   |
+//! | text         | `text_size` bytes                                                       |
+//! | data         | `data_size` bytes                                                       |
+//! | relocs       | `num_relocs` little-endian `u32`s                                       |
+//!
+//! The text is executed in place (e.g. from memory-mapped external flash), and the data is copied to RAM.
+//! Each relocation is the offset of a pointer-sized word in the data, to which the address of the text is added
+//! (or the address of the data if bit 31 is set).
+//!
+//! The entry is called as `extern "C" fn(got: usize)`.
+//! On Arm, the GOT address is also placed in R9, so applets can be built with a separate data base
+//! (`-frwpi` or `-msingle-pic-base -mpic-register=r9`).
+
+use taskette::{
+    Error,
+    arch::StackAllocation,
+    scheduler,
+    task::{TaskConfig, TaskHandle},
+};
+
+const MAGIC: u32 = u32::from_le_bytes(*b"TKAP");
+const HEADER_SIZE: usize = 7 * 4;
+/// Bit of a relocation selecting the data (instead of the text) as the base
+const RELOC_DATA: u32 = 1 << 31;
+
+/// Error while loading an applet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadError {
+    /// The blob does not start with the magic number
+    BadMagic,
+    /// The blob is shorter than its header says
+    Truncated,
+    /// The blob or the RAM is not aligned at 4 bytes
+    Misaligned,
+    /// The RAM is smaller than the data and BSS
+    RamTooSmall,
+    /// The sizes in the header overflow when added up
+    Overflow,
+    /// A relocation or the entry points outside of the applet
+    BadRelocation,
+}
+
+/// Addresses of a loaded applet, passed to [`spawn_loaded`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LoadedApplet {
+    pub entry: usize,
+    pub got: usize,
+}
+
+/// Loads the applet in `blob`, using `ram` for its data and BSS.
+///
+/// `blob` has to stay at the same address while the applet runs, because its code is executed in place.
+pub fn load(blob: &'static [u8], ram: &'static mut [u8]) -> Result<LoadedApplet, LoadError> {
+    if !blob.as_ptr().cast::<u32>().is_aligned() || !ram.as_ptr().cast::<u32>().is_aligned() {
+        return Err(LoadError::Misaligned);
+    }
+
+    let header = |index: usize| read_u32(blob, index * 4);
+    if header(0)? != MAGIC {
+        return Err(LoadError::BadMagic);
+    }
+    let (text_size, data_size, bss_size) = (header(1)?, header(2)?, header(3)?);
+    let (entry, got, num_relocs) = (header(4)?, header(5)?, header(6)?);
+
+    // Computed in `u32` like the header fields, so that a broken header is rejected the same way on any target
+    let layout = (|| {
+        let data_start =
+            (HEADER_SIZE as u32).checked_add(text_size.checked_next_multiple_of(4)?)?;
+        let relocs_start = data_start.checked_add(data_size)?;
+        let blob_size = relocs_start.checked_add(num_relocs.checked_mul(4)?)?;
+        let ram_size = data_size.checked_add(bss_size)?;
+        Some([data_start, relocs_start, blob_size, ram_size].map(|value| value as usize))
+    })();
+    let [data_start, relocs_start, blob_size, ram_size] = layout.ok_or(LoadError::Overflow)?;
+    let (text_size, data_size) = (text_size as usize, data_size as usize);
+    let (entry, got, num_relocs) = (entry as usize, got as usize, num_relocs as usize);
+
+    let text_start = HEADER_SIZE;
+    if blob.len() < blob_size {
+        return Err(LoadError::Truncated);
+    }
+    if ram.len() < ram_size {
+        return Err(LoadError::RamTooSmall);
+    }
+    if entry >= text_size || got > data_size {
+        return Err(LoadError::BadRelocation);
+    }
+
+    ram[..data_size].copy_from_slice(&blob[data_start..relocs_start]);
+    ram[data_size..ram_size].fill(0);
+
+    let text_base = blob[text_start..].as_ptr() as usize;
+    let data_base = ram.as_ptr() as usize;
+    for i in 0..num_relocs {
+        let reloc = read_u32(blob, relocs_start + i * 4)?;
+        let offset = (reloc & !RELOC_DATA) as usize;
+        let base = if reloc & RELOC_DATA != 0 {
+            data_base
+        } else {
+            text_base
+        };
+
+        // Only words in the data are relocated
+        let end = offset
+            .checked_add(size_of::<usize>())
+            .filter(|&end| end <= data_size)
+            .ok_or(LoadError::BadRelocation)?;
+        let word = &mut ram[offset..end];
+        let value = usize::from_ne_bytes(word.try_into().unwrap_or_else(|_| unreachable!()));
+        word.copy_from_slice(&value.wrapping_add(base).to_ne_bytes());
+    }
+
+    Ok(LoadedApplet {
+        entry: text_base + entry,
+        got: data_base + got,
+    })
+}
+
+/// Creates a new task running the entry function of a loaded applet.
+///
+/// `entry` and `got` are usually the fields of [`LoadedApplet`] returned by [`load`].
+/// The task finishes when the entry function returns.
+///
+/// # Safety
+/// `entry` has to be the address of a function with the ABI described in the [module documentation](self).
+pub unsafe fn spawn_loaded<S: StackAllocation>(
+    entry: usize,
+    got: usize,
+    stack: S,
+    config: TaskConfig,
+) -> Result<TaskHandle, Error> {
+    scheduler::spawn(move || unsafe { call_entry(entry, got) }, stack, config)
+}
+
+#[cfg(target_arch = "arm")]
+unsafe fn call_entry(entry: usize, got: usize) {
+    unsafe {
+        core::arch::asm!(
+            "push {{r9, r10}}", // R10 keeps the stack aligned at 8 bytes
+            "mov r9, r0",
+            "blx r1",
+            "pop {{r9, r10}}",
+            in("r0") got,
+            in("r1") entry,
+            clobber_abi("C"),
+        );
+    }
+}
+
+#[cfg(not(target_arch = "arm"))]
+unsafe fn call_entry(entry: usize, got: usize) {
+    let entry: extern "C" fn(usize) = unsafe { core::mem::transmute(entry) };
+    entry(got);
+}
+
+fn read_u32(blob: &[u8], offset: usize) -> Result<u32, LoadError> {
+    let bytes = blob.get(offset..offset + 4).ok_or(LoadError::Truncated)?;
+    Ok(u32::from_le_bytes(
+        bytes.try_into().unwrap_or_else(|_| unreachable!()),
+    ))
+}
@@ -0,0 +1,253 @@
+//! Interactive monitor shell over a byte stream (`monitor` feature).
+//!
+//! [`Monitor`] reads command lines from any `embedded-io` stream (e.g. a UART or USB-CDC serial port)
+//! and answers them using the introspection APIs of `taskette`. It is usually run in a dedicated low-priority task:
+//!
+//! ```ignore
+//! spawn(move || { let _ = Monitor::new(uart).run(); }, stack, TaskConfig::default())?;
+//! ```
+//!
+//! Supported commands:
+//!
+//! | Command     | Output                                                    |
+//! |-------------|-----------------------------------------------------------|
+//! | `help`      | List of commands                                          |
+//! | `ps`        | Table of tasks (same as `taskette::scheduler::dump_tasks`) |
+//! | `stacks`    | Stack usage of each task                                  |
+//! | `kill <id>` | Removes the task (see `taskette::task::kill`)             |
+//! | `stats`     | Kernel statistics counters                                |
+//!
+//! Output lines end with CRLF for serial terminals.
+
+use core::fmt::{self, Write as _};
+
+use embedded_io::{Read, Write};
+use taskette::{
+    scheduler::{dump_tasks, for_each_task, stats},
+    task::kill,
+};
+
+/// Maximum length of a command line (longer lines are rejected)
+pub const MAX_LINE_LEN: usize = 64;
+
+const PROMPT: &str = "> ";
+
+/// Monitor shell reading commands from and writing responses to `io`.
+pub struct Monitor<T> {
+    io: T,
+    echo: bool,
+    line: [u8; MAX_LINE_LEN],
+    len: usize,
+    /// Set when the current line exceeded `MAX_LINE_LEN`
+    overflowed: bool,
+    /// Set when the last byte was CR (the following LF is ignored)
+    after_cr: bool,
+}
+
+impl<T: Read + Write> Monitor<T> {
+    pub fn new(io: T) -> Self {
+        Self {
+            io,
+            echo: true,
+            line: [0; MAX_LINE_LEN],
+            len: 0,
+            overflowed: false,
+            after_cr: false,
+        }
+    }
+
+    /// Sets whether received characters are echoed back. Default is `true` (for terminals without local echo).
+    pub fn with_echo(self, echo: bool) -> Self {
+        Self { echo, ..self }
+    }
+
+    /// Processes commands until the stream reaches its end (a read returns 0 bytes) or fails.
+    pub fn run(&mut self) -> Result<(), T::Error> {
+        self.write_str(PROMPT)?;
+
+        let mut buf = [0u8; 16];
+        loop {
+            let len = self.io.read(&mut buf)?;
+            if len == 0 {
+                return Ok(());
+            }
+            for &byte in &buf[..len] {
+                self.receive(byte)?;
+            }
+            self.io.flush()?;
+        }
+    }
+
+    /// Releases the stream.
+    pub fn into_inner(self) -> T {
+        self.io
+    }
+
+    fn receive(&mut self, byte: u8) -> Result<(), T::Error> {
+        let after_cr = core::mem::replace(&mut self.after_cr, byte == b'\r');
+        match byte {
+            b'\n' if after_cr => Ok(()),
+            b'\r' | b'\n' => {
+                if self.echo {
+                    self.write_str("\n")?;
+                }
+                // Copied so that the command can borrow `self` mutably
+                let line = self.line;
+                let len = self.len;
+                self.len = 0;
+                if self.overflowed {
+                    self.write_str("Line too long\n")?;
+                } else if let Ok(line) = core::str::from_utf8(&line[..len]) {
+                    if !line.trim().is_empty() {
+                        self.execute(line.trim())?;
+                    }
+                } else {
+                    self.write_str("Invalid characters\n")?;
+                }
+                self.overflowed = false;
+                self.write_str(PROMPT)
+            }
+            // Backspace and DEL
+            0x08 | 0x7F => {
+                if self.len > 0 {
+                    self.len -= 1;
+                    if self.echo {
+                        self.io.write_all(b"\x08 \x08")?;
+                    }
+                }
+                Ok(())
+            }
+            _ => {
+                if self.len < MAX_LINE_LEN {
+                    self.line[self.len] = byte;
+                    self.len += 1;
+                } else {
+                    self.overflowed = true;
+                }
+                if self.echo {
+                    self.io.write_all(&[byte])?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn execute(&mut self, line: &str) -> Result<(), T::Error> {
+        let mut words = line.split_ascii_whitespace();
+        let command = words.next().unwrap_or_default();
+        let args = (words.next(), words.next());
+
+        let mut out = Output {
+            io: &mut self.io,
+            error: None,
+        };
+        let result = match (command, args) {
+            ("help", (None, _)) => out.write_str(
+                "help       list commands\n\
+                 ps         list tasks\n\
+                 stacks     show stack usage\n\
+                 kill <id>  remove a task\n\
+                 stats      show kernel statistics\n",
+            ),
+            ("ps", (None, _)) => dump_tasks(&mut out),
+            ("stacks", (None, _)) => write_stacks(&mut out),
+            ("kill", (Some(id), None)) => match id.parse() {
+                Ok(id) => match kill(id) {
+                    Ok(()) => writeln!(out, "Task #{} killed", id),
+                    Err(err) => writeln!(out, "Cannot kill Task #{}: {:?}", id, err),
+                },
+                Err(_) => writeln!(out, "Invalid task ID: {}", id),
+            },
+            ("stats", (None, _)) => write_stats(&mut out),
+            ("help" | "ps" | "stacks" | "kill" | "stats", _) => {
+                writeln!(out, "Invalid arguments (type `help`)")
+            }
+            _ => writeln!(out, "Unknown command: {} (type `help`)", command),
+        };
+
+        // Formatting itself never fails, so an error is always an I/O error
+        let _ = result;
+        out.error.map_or(Ok(()), Err)
+    }
+
+    fn write_str(&mut self, s: &str) -> Result<(), T::Error> {
+        let mut out = Output {
+            io: &mut self.io,
+            error: None,
+        };
+        let _ = out.write_str(s);
+        out.error.map_or(Ok(()), Err)
+    }
+}
+
+fn write_stacks<W: fmt::Write>(out: &mut W) -> fmt::Result {
+    writeln!(
+        out,
+        "{:>4} {:<16} {:>6} {:>6} {:>4}",
+        "ID", "NAME", "USED", "SIZE", "USE"
+    )?;
+
+    let mut result = Ok(());
+    for_each_task(|task| {
+        if result.is_err() {
+            return;
+        }
+        result = match task.stack_used {
+            Some(used) => writeln!(
+                out,
+                "{:>4} {:<16} {:>6} {:>6} {:>3}%",
+                task.id,
+                task.name.unwrap_or("-"),
+                used,
+                task.stack_size,
+                (used * 100).checked_div(task.stack_size).unwrap_or(0)
+            ),
+            None => writeln!(
+                out,
+                "{:>4} {:<16} {:>6} {:>6} {:>4}",
+                task.id,
+                task.name.unwrap_or("-"),
+                "-",
+                task.stack_size,
+                "-"
+            ),
+        };
+    });
+
+    result
+}
+
+fn write_stats<W: fmt::Write>(out: &mut W) -> fmt::Result {
+    let stats = stats();
+    writeln!(out, "context switches  {}", stats.context_switches)?;
+    writeln!(out, "preemptions       {}", stats.preemptions)?;
+    writeln!(out, "ticks             {}", stats.ticks)?;
+    writeln!(out, "timer wakeups     {}", stats.timer_wakeups)?;
+    writeln!(out, "futex waits       {}", stats.futex_waits)
+}
+
+/// `fmt::Write` adapter of the stream, converting LF into CRLF and keeping the I/O error
+struct Output<'a, T: Write> {
+    io: &'a mut T,
+    error: Option<T::Error>,
+}
+
+impl<T: Write> fmt::Write for Output<'_, T> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for (i, part) in s.split('\n').enumerate() {
+            let result = if i > 0 {
+                self.io.write_all(b"\r\n")
+            } else {
+                Ok(())
+            }
+            .and_then(|()| self.io.write_all(part.as_bytes()));
+
+            if let Err(err) = result {
+                self.error = Some(err);
+                return Err(fmt::Error);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,329 @@
+//! `smoltcp` network stack running in its own task, with blocking sockets for other tasks (`smoltcp` feature).
+//!
+//! [`NetStack::run`] is the body of the network task. It polls the `Interface` and sleeps until either
+//! the `IrqEvent` signaled by the interrupt handler of the MAC driver (e.g. on frame reception)
+//! or the next poll deadline requested by `smoltcp` (e.g. a TCP retransmission).
+//! Other tasks use the sockets through [`TcpSocket`] and [`UdpSocket`], which block until the operation can proceed.
+//!
+//! ```ignore
+//! static RX_EVENT: IrqEvent = IrqEvent::new();
+//! static STACK: StaticCell<NetStack> = StaticCell::new();
+//!
+//! let stack = &*STACK.init(NetStack::new(iface, SocketSet::new(sockets), &RX_EVENT));
+//! spawn(move || stack.run(&mut device), NET_STACK.take(), TaskConfig::default().with_priority(3))?;
+//!
+//! let socket = stack.tcp(stack.add_socket(tcp::Socket::new(rx_buffer, tx_buffer)));
+//! socket.connect((Ipv4Address::new(192, 168, 1, 2), 8080), 49152)?;
+//! socket.write(b"hello")?;
+//! ```
+//!
+//! Mediums and IP versions are selected by the features of the `smoltcp` dependency of the application.
+//! The time of `smoltcp` comes from `taskette::timer::current_time_micros`.
+
+use core::{cell::UnsafeCell, sync::atomic::Ordering};
+
+use smoltcp::{
+    iface::{Context, Interface, SocketHandle, SocketSet},
+    phy::Device,
+    socket::{AnySocket, tcp, udp},
+    time::Instant,
+    wire::IpEndpoint,
+};
+use taskette::{
+    futex::Futex,
+    scheduler::get_config,
+    sync::IrqEvent,
+    timer::{current_time, current_time_micros},
+};
+
+/// Errors of socket operations
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NetError {
+    /// The socket is not in a state allowing the operation (e.g. connecting an open socket).
+    InvalidState,
+    /// The address or port is invalid (e.g. zero).
+    Unaddressable,
+    /// The connection is closed or reset, so no more data can be sent.
+    Closed,
+    /// A received datagram did not fit in the buffer.
+    Truncated,
+}
+
+/// Interface and sockets, only touched with `lock` held
+struct Inner<'a> {
+    iface: Interface,
+    sockets: SocketSet<'a>,
+}
+
+/// Network stack shared between the network task and the tasks using sockets.
+pub struct NetStack<'a> {
+    inner: UnsafeCell<Inner<'a>>,
+    /// 0 while unlocked, 1 while locked (tasks wait on this)
+    lock: Futex,
+    /// Wakes the network task (signaled by the MAC driver, and after socket operations)
+    event: &'a IrqEvent,
+    /// Incremented after every poll (tasks blocked on sockets wait on this)
+    polled: Futex,
+}
+
+unsafe impl Sync for NetStack<'_> {}
+unsafe impl Send for NetStack<'_> {}
+
+impl<'a> NetStack<'a> {
+    /// Creates a network stack whose task is woken by `event`, which the MAC driver signals on frame reception.
+    pub fn new(iface: Interface, sockets: SocketSet<'a>, event: &'a IrqEvent) -> Self {
+        Self {
+            inner: UnsafeCell::new(Inner { iface, sockets }),
+            lock: Futex::new(0),
+            event,
+            polled: Futex::new(0),
+        }
+    }
+
+    /// Polls the interface forever. Called as the body of the network task, which is the only user of `device`.
+    pub fn run<D: Device + ?Sized>(&self, device: &mut D) -> ! {
+        let tick_freq = get_config().expect("Scheduler not initialized").tick_freq as u64;
+
+        loop {
+            let delay = self.with_inner(|inner| {
+                let now = now();
+                inner.iface.poll(now, device, &mut inner.sockets);
+                inner.iface.poll_delay(now, &inner.sockets)
+            });
+
+            // Tasks blocked on sockets check them again
+            self.polled.as_ref().fetch_add(1, Ordering::SeqCst);
+            let _ = self.polled.wake_all();
+
+            match delay {
+                Some(delay) => {
+                    let ticks = (delay.total_micros() * tick_freq).div_ceil(1_000_000);
+                    let now = current_time().expect("Scheduler not initialized");
+                    let _ = self.event.wait_until(now + ticks);
+                }
+                None => {
+                    let _ = self.event.wait();
+                }
+            }
+        }
+    }
+
+    /// Adds a socket, returning its handle.
+    pub fn add_socket<T: AnySocket<'a>>(&self, socket: T) -> SocketHandle {
+        self.with_inner(|inner| inner.sockets.add(socket))
+    }
+
+    /// Runs `f` on the socket of `handle` and wakes the network task, so that queued data is sent immediately.
+    ///
+    /// Panics if the socket is not of type `T`.
+    pub fn with_socket<T: AnySocket<'a>, R>(
+        &self,
+        handle: SocketHandle,
+        f: impl FnOnce(&mut T, &mut Context) -> R,
+    ) -> R {
+        let result = self.with_inner(|inner| {
+            let socket = inner.sockets.get_mut::<T>(handle);
+            f(socket, inner.iface.context())
+        });
+        self.event.signal();
+        result
+    }
+
+    /// Blocking wrapper of the TCP socket of `handle`.
+    pub fn tcp(&self, handle: SocketHandle) -> TcpSocket<'_, 'a> {
+        TcpSocket {
+            stack: self,
+            handle,
+        }
+    }
+
+    /// Blocking wrapper of the UDP socket of `handle`.
+    pub fn udp(&self, handle: SocketHandle) -> UdpSocket<'_, 'a> {
+        UdpSocket {
+            stack: self,
+            handle,
+        }
+    }
+
+    /// Calls `f` on the socket after every poll until it returns `Some`.
+    ///
+    /// The network task is woken only once `f` succeeds, as a failed check changes nothing to poll.
+    fn wait_socket<T: AnySocket<'a>, R>(
+        &self,
+        handle: SocketHandle,
+        mut f: impl FnMut(&mut T) -> Option<R>,
+    ) -> R {
+        loop {
+            // Read before checking, so that a poll in between makes `wait` return immediately
+            let polled = self.polled.as_ref().load(Ordering::SeqCst);
+            let result = self.with_inner(|inner| f(inner.sockets.get_mut::<T>(handle)));
+            if let Some(result) = result {
+                self.event.signal();
+                return result;
+            }
+
+            let _ = self.polled.wait(polled);
+        }
+    }
+
+    fn with_inner<R>(&self, f: impl FnOnce(&mut Inner<'a>) -> R) -> R {
+        while self
+            .lock
+            .as_ref()
+            .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            let _ = self.lock.wait(1);
+        }
+
+        // SAFETY: the lock is held
+        let result = f(unsafe { &mut *self.inner.get() });
+
+        self.lock.as_ref().store(0, Ordering::Release);
+        let _ = self.lock.wake_one();
+        result
+    }
+}
+
+fn now() -> Instant {
+    Instant::from_micros(current_time_micros().expect("Scheduler not initialized") as i64)
+}
+
+/// TCP socket blocking the calling task (see [`NetStack::tcp`]).
+///
+/// Must not be used by the network task itself.
+pub struct TcpSocket<'s, 'a> {
+    stack: &'s NetStack<'a>,
+    handle: SocketHandle,
+}
+
+impl TcpSocket<'_, '_> {
+    pub fn handle(&self) -> SocketHandle {
+        self.handle
+    }
+
+    /// Connects to `remote` from `local_port`, blocking until the connection is established.
+    pub fn connect(&self, remote: impl Into<IpEndpoint>, local_port: u16) -> Result<(), NetError> {
+        let remote = remote.into();
+        self.stack
+            .with_socket(self.handle, |socket: &mut tcp::Socket, cx| {
+                socket.connect(cx, remote, local_port)
+            })
+            .map_err(|error| match error {
+                tcp::ConnectError::InvalidState => NetError::InvalidState,
+                tcp::ConnectError::Unaddressable => NetError::Unaddressable,
+            })?;
+
+        self.wait_established()
+    }
+
+    /// Listens on `local_port`, blocking until a connection is established.
+    pub fn accept(&self, local_port: u16) -> Result<(), NetError> {
+        self.stack
+            .with_socket(self.handle, |socket: &mut tcp::Socket, _| {
+                socket.listen(local_port)
+            })
+            .map_err(|error| match error {
+                tcp::ListenError::InvalidState => NetError::InvalidState,
+                tcp::ListenError::Unaddressable => NetError::Unaddressable,
+            })?;
+
+        self.wait_established()
+    }
+
+    /// Receives data into `buf`, blocking until some arrives. Returns 0 once the remote has closed the connection.
+    pub fn read(&self, buf: &mut [u8]) -> Result<usize, NetError> {
+        self.stack
+            .wait_socket(self.handle, |socket: &mut tcp::Socket| {
+                if socket.can_recv() {
+                    Some(socket.recv_slice(buf).or(Err(NetError::InvalidState)))
+                } else if !socket.may_recv() {
+                    Some(Ok(0))
+                } else {
+                    None
+                }
+            })
+    }
+
+    /// Queues data from `buf`, blocking until there is space in the transmit buffer. Returns the length queued.
+    pub fn write(&self, buf: &[u8]) -> Result<usize, NetError> {
+        self.stack
+            .wait_socket(self.handle, |socket: &mut tcp::Socket| {
+                if socket.can_send() {
+                    Some(socket.send_slice(buf).or(Err(NetError::InvalidState)))
+                } else if !socket.may_send() {
+                    Some(Err(NetError::Closed))
+                } else {
+                    None
+                }
+            })
+    }
+
+    /// Closes the transmit half of the connection. Data already queued is still sent.
+    pub fn close(&self) {
+        self.stack
+            .with_socket(self.handle, |socket: &mut tcp::Socket, _| socket.close());
+    }
+
+    fn wait_established(&self) -> Result<(), NetError> {
+        self.stack
+            .wait_socket(self.handle, |socket: &mut tcp::Socket| {
+                match socket.state() {
+                    tcp::State::Established | tcp::State::CloseWait => Some(Ok(())),
+                    tcp::State::Closed | tcp::State::TimeWait => Some(Err(NetError::Closed)),
+                    _ => None,
+                }
+            })
+    }
+}
+
+/// UDP socket blocking the calling task (see [`NetStack::udp`]).
+///
+/// Must not be used by the network task itself.
+pub struct UdpSocket<'s, 'a> {
+    stack: &'s NetStack<'a>,
+    handle: SocketHandle,
+}
+
+impl UdpSocket<'_, '_> {
+    pub fn handle(&self) -> SocketHandle {
+        self.handle
+    }
+
+    /// Binds the socket to `local_port`.
+    pub fn bind(&self, local_port: u16) -> Result<(), NetError> {
+        self.stack
+            .with_socket(self.handle, |socket: &mut udp::Socket, _| {
+                socket.bind(local_port)
+            })
+            .map_err(|error| match error {
+                udp::BindError::InvalidState => NetError::InvalidState,
+                udp::BindError::Unaddressable => NetError::Unaddressable,
+            })
+    }
+
+    /// Sends a datagram to `remote`, blocking until there is space in the transmit buffer.
+    pub fn send_to(&self, data: &[u8], remote: impl Into<IpEndpoint>) -> Result<(), NetError> {
+        let remote = remote.into();
+        self.stack
+            .wait_socket(self.handle, |socket: &mut udp::Socket| {
+                match socket.send_slice(data, remote) {
+                    Ok(()) => Some(Ok(())),
+                    Err(udp::SendError::BufferFull) => None,
+                    Err(udp::SendError::Unaddressable) => Some(Err(NetError::Unaddressable)),
+                }
+            })
+    }
+
+    /// Receives a datagram into `buf`, blocking until one arrives. Returns its length and sender.
+    pub fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, IpEndpoint), NetError> {
+        self.stack
+            .wait_socket(self.handle, |socket: &mut udp::Socket| {
+                match socket.recv_slice(buf) {
+                    Ok((len, meta)) => Some(Ok((len, meta.endpoint))),
+                    Err(udp::RecvError::Exhausted) => None,
+                    Err(udp::RecvError::Truncated) => Some(Err(NetError::Truncated)),
+                }
+            })
+    }
+}
@@ -0,0 +1,54 @@
+//! Measurement of elapsed time with the microsecond time of the scheduler.
+//!
+//! The resolution is finer than a tick only on ports with a cycle counter (see `taskette::timer::current_time_micros`).
+
+use core::time::Duration;
+
+use taskette::{Error, timer::current_time_micros};
+
+/// Measures time elapsed since it was started, optionally split into laps.
+#[derive(Clone, Copy, Debug)]
+pub struct Stopwatch {
+    /// Start time (in microseconds)
+    start: u64,
+    /// Start time of the current lap (in microseconds)
+    lap_start: u64,
+}
+
+impl Stopwatch {
+    /// Starts measuring from now.
+    pub fn start() -> Result<Self, Error> {
+        let now = current_time_micros()?;
+
+        Ok(Self {
+            start: now,
+            lap_start: now,
+        })
+    }
+
+    /// Time elapsed since the start.
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_micros(now() - self.start)
+    }
+
+    /// Time elapsed since the previous lap (or the start), starting a new lap.
+    pub fn lap(&mut self) -> Duration {
+        let now = now();
+        let lap = now - self.lap_start;
+        self.lap_start = now;
+        Duration::from_micros(lap)
+    }
+
+    /// Time elapsed since the start, starting again from now.
+    pub fn restart(&mut self) -> Duration {
+        let now = now();
+        let elapsed = now - self.start;
+        self.start = now;
+        self.lap_start = now;
+        Duration::from_micros(elapsed)
+    }
+}
+
+fn now() -> u64 {
+    current_time_micros().expect("Failed to acquire current time")
+}
@@ -0,0 +1,149 @@
+//! Interrupt-driven UART with blocking `embedded-io` reads (`uart` feature).
+//!
+//! The RX interrupt handler moves received bytes from the HAL into a `StreamBuffer` with [`on_rx_interrupt`],
+//! and [`IrqUart`] reads them from there, blocking the task until data arrives instead of polling the UART.
+//! Writes go directly to the transmitter of the HAL.
+//!
+//! ```ignore
+//! static RX_BUFFER: StreamBuffer<256> = StreamBuffer::new();
+//! static UART_RX: Mutex<RefCell<Option<Reader<UART0, Pins>>>> = Mutex::new(RefCell::new(None));
+//!
+//! #[interrupt]
+//! fn UART0_IRQ() {
+//!     critical_section::with(|cs| {
+//!         if let Some(rx) = UART_RX.borrow_ref_mut(cs).as_mut() {
+//!             on_rx_interrupt(rx, &RX_BUFFER);
+//!         }
+//!     });
+//! }
+//!
+//! // In the UART task
+//! let mut uart = IrqUart::new(tx, &RX_BUFFER);
+//! let mut buf = [0u8; 64];
+//! loop {
+//!     let count = uart.read(&mut buf)?;
+//!     uart.write_all(&buf[..count])?;
+//! }
+//! ```
+
+use embedded_hal_nb::{nb, serial};
+use taskette::sync::StreamBuffer;
+
+/// Errors of [`IrqUart`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UartError {
+    /// The transmitter of the HAL reported an error.
+    Tx(serial::ErrorKind),
+    /// The task could not block because the scheduler is not running.
+    Scheduler,
+}
+
+impl core::fmt::Display for UartError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Tx(kind) => write!(f, "UART transmit error: {}", kind),
+            Self::Scheduler => write!(f, "scheduler not running"),
+        }
+    }
+}
+
+impl core::error::Error for UartError {}
+
+impl embedded_io::Error for UartError {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        embedded_io::ErrorKind::Other
+    }
+}
+
+/// Moves bytes from the receiver of the HAL into `buffer` until its RX FIFO is empty.
+/// Called in the RX interrupt handler, which also clears the interrupt this way on most UARTs.
+///
+/// Returns the number of bytes lost, either dropped because `buffer` is full or reported as errors by the HAL
+/// (e.g. framing errors).
+pub fn on_rx_interrupt<R: serial::Read<u8>, const N: usize>(
+    rx: &mut R,
+    buffer: &StreamBuffer<N>,
+) -> usize {
+    let mut lost = 0;
+    loop {
+        match rx.read() {
+            Ok(byte) => {
+                if buffer.try_write(&[byte]) == 0 {
+                    lost += 1;
+                }
+            }
+            Err(nb::Error::WouldBlock) => return lost,
+            Err(nb::Error::Other(_)) => lost += 1,
+        }
+    }
+}
+
+/// UART whose reads block the calling task on a `StreamBuffer` filled by [`on_rx_interrupt`].
+///
+/// Writes busy-wait while the TX FIFO is full, so a long transfer from a high-priority task delays lower ones.
+pub struct IrqUart<'a, Tx, const N: usize> {
+    tx: Tx,
+    rx: &'a StreamBuffer<N>,
+}
+
+impl<'a, Tx: serial::Write<u8>, const N: usize> IrqUart<'a, Tx, N> {
+    /// Creates a UART from the transmitter of the HAL and the buffer filled by the RX interrupt handler.
+    pub fn new(tx: Tx, rx: &'a StreamBuffer<N>) -> Self {
+        Self { tx, rx }
+    }
+
+    /// Buffer of received bytes, e.g. for discarding stale input with `clear`.
+    pub fn rx_buffer(&self) -> &'a StreamBuffer<N> {
+        self.rx
+    }
+
+    /// Gives the transmitter back.
+    pub fn release(self) -> Tx {
+        self.tx
+    }
+}
+
+impl<Tx: serial::Write<u8>, const N: usize> embedded_io::ErrorType for IrqUart<'_, Tx, N> {
+    type Error = UartError;
+}
+
+impl<Tx: serial::Write<u8>, const N: usize> embedded_io::Read for IrqUart<'_, Tx, N> {
+    /// Reads received bytes, blocking the task until at least one is available.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.rx.read(buf).or(Err(UartError::Scheduler))
+    }
+}
+
+impl<Tx: serial::Write<u8>, const N: usize> embedded_io::ReadReady for IrqUart<'_, Tx, N> {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.rx.is_empty())
+    }
+}
+
+impl<Tx: serial::Write<u8>, const N: usize> embedded_io::Write for IrqUart<'_, Tx, N> {
+    /// Writes bytes until the TX FIFO is full, waiting only for the first one.
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        for (count, &byte) in buf.iter().enumerate() {
+            let result = if count == 0 {
+                nb::block!(self.tx.write(byte)).map_err(nb::Error::Other)
+            } else {
+                self.tx.write(byte)
+            };
+
+            match result {
+                Ok(()) => (),
+                Err(nb::Error::WouldBlock) => return Ok(count),
+                Err(nb::Error::Other(error)) => {
+                    return Err(UartError::Tx(serial::Error::kind(&error)));
+                }
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    /// Blocks until all bytes are sent.
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        nb::block!(self.tx.flush()).map_err(|error| UartError::Tx(serial::Error::kind(&error)))
+    }
+}
@@ -0,0 +1,83 @@
+//! USB device task driven by the USB interrupt (`usb` feature).
+//!
+//! Instead of calling `UsbDevice::poll` in a busy loop, [`serve`] blocks on an `IrqEvent` between polls,
+//! so the USB task uses no CPU time while the bus is idle.
+//! The USB interrupt is usually level-triggered and stays pending until `poll` handles it,
+//! so the interrupt handler masks it before signaling the event, and `serve` unmasks it after polling:
+//!
+//! ```ignore
+//! static USB_EVENT: IrqEvent = IrqEvent::new();
+//!
+//! #[interrupt]
+//! fn USBCTRL_IRQ() {
+//!     NVIC::mask(Interrupt::USBCTRL_IRQ);
+//!     USB_EVENT.signal();
+//! }
+//!
+//! // In the USB task
+//! serve(
+//!     &mut usb_dev,
+//!     &mut (serial_port,),
+//!     &USB_EVENT,
+//!     || unsafe { NVIC::unmask(Interrupt::USBCTRL_IRQ) },
+//!     |_usb_dev, (serial_port,)| {
+//!         let mut buf = [0u8; 64];
+//!         if let Ok(count) = serial_port.read(&mut buf) {
+//!             let _ = serial_port.write(&buf[..count]);
+//!         }
+//!     },
+//! )
+//! ```
+
+use taskette::sync::IrqEvent;
+use usb_device::{bus::UsbBus, class::UsbClass, device::UsbDevice};
+
+/// Set of USB classes polled together, implemented for tuples of up to 4 classes.
+pub trait UsbClasses<B: UsbBus> {
+    /// Calls `UsbDevice::poll` with all the classes.
+    fn poll(&mut self, usb_dev: &mut UsbDevice<'_, B>) -> bool;
+}
+
+macro_rules! impl_usb_classes {
+    ($($class:ident),+) => {
+        impl<B: UsbBus, $($class: UsbClass<B>),+> UsbClasses<B> for ($($class,)+) {
+            fn poll(&mut self, usb_dev: &mut UsbDevice<'_, B>) -> bool {
+                #[allow(non_snake_case)]
+                let ($($class,)+) = self;
+                usb_dev.poll(&mut [$($class as &mut dyn UsbClass<B>),+])
+            }
+        }
+    };
+}
+
+impl_usb_classes!(C0);
+impl_usb_classes!(C0, C1);
+impl_usb_classes!(C0, C1, C2);
+impl_usb_classes!(C0, C1, C2, C3);
+
+/// Polls `usb_dev` with `classes` forever, each time the USB interrupt signals `event`.
+///
+/// `unmask` is called after every poll to enable the USB interrupt masked by the handler again,
+/// and `on_event` is called when the poll reports activity of a class (e.g. received data),
+/// with the same arguments as they would be used after `UsbDevice::poll`.
+/// `on_event` must not block waiting for the host (e.g. until a write completes),
+/// because the device is not polled meanwhile.
+pub fn serve<B: UsbBus, C: UsbClasses<B>>(
+    usb_dev: &mut UsbDevice<'_, B>,
+    classes: &mut C,
+    event: &IrqEvent,
+    mut unmask: impl FnMut(),
+    mut on_event: impl FnMut(&mut UsbDevice<'_, B>, &mut C),
+) -> ! {
+    loop {
+        // Polls before the first wait too, as interrupts raised before it only leave the event signaled
+        let active = classes.poll(usb_dev);
+        unmask();
+        if active {
+            on_event(usb_dev, classes);
+        }
+
+        // Fails only if the scheduler is not running, in which case polling continues without blocking
+        let _ = event.wait();
+    }
+}
@@ -1,10 +1,31 @@
 //! Interface for architecture-dependent functions implemented in separate crates.
+//!
+//! # Multi-core ports
+//! With `smp` feature, a port for a multi-core chip (such as RP2040) has to:
+//! - return the index of the executing core from `_taskette_core_id`, and an idle task stack for every core from `_taskette_get_idle_task_stack`,
+//! - start the other cores in `_taskette_setup` and call `scheduler::start_secondary_core` on each of them,
+//! - request a reschedule on the specified core from `_taskette_ipi` (e.g. with a cross-core software interrupt),
+//! - link a `critical-section` implementation which excludes the other cores too (e.g. with a hardware spinlock),
+//!   because the scheduler state is shared by all cores and only guarded by critical sections.
+//!
+//! The core index and the target of `_taskette_ipi` are checked against [`NUM_CORES`] in debug builds,
+//! and single-core ports refuse to build when `NUM_CORES` is not 1 (i.e. with `smp` feature).
+//!
+//! ESP32-S3 dual-core SMP is blocked on an Xtensa port, which does not exist yet.
+//! It would need the above, using the cross-core software interrupts (`FROM_CPU_INTR`) for `_taskette_ipi`
+//! and a tick on each core.
+
+use core::ops::Range;
+
+use portable_atomic::AtomicBool;
+
+use crate::scheduler::NUM_CORES;
 
 unsafe extern "Rust" {
     /// INTERNAL USE ONLY
-    pub unsafe fn _taskette_setup(clock_freq: u32, tick_freq: u32);
+    pub unsafe fn _taskette_setup();
     /// INTERNAL USE ONLY
-    pub unsafe fn _taskette_start_timer();
+    pub unsafe fn _taskette_tick_source() -> &'static dyn TickSource;
     /// INTERNAL USE ONLY
     pub unsafe fn _taskette_yield_now();
     /// INTERNAL USE ONLY
@@ -17,9 +38,24 @@ unsafe extern "Rust" {
     /// INTERNAL USE ONLY
     pub unsafe fn _taskette_run_with_stack(pc: usize, sp: *mut u8, stack_limit: *mut u8) -> !;
     /// INTERNAL USE ONLY
-    pub unsafe fn _taskette_get_idle_task_stack() -> Option<&'static mut [u8]>;
+    pub unsafe fn _taskette_get_idle_task_stack(core_id: usize) -> Option<&'static mut [u8]>;
     /// INTERNAL USE ONLY
     pub unsafe fn _taskette_wait_for_interrupt();
+    /// INTERNAL USE ONLY
+    pub unsafe fn _taskette_core_id() -> usize;
+    /// INTERNAL USE ONLY
+    pub unsafe fn _taskette_ipi(core_id: usize);
+    /// INTERNAL USE ONLY
+    pub unsafe fn _taskette_try_lock(lock: &AtomicBool) -> bool;
+    /// INTERNAL USE ONLY
+    ///
+    /// Masks the interrupts which may call the kernel (including the tick and the context switch) and returns the previous state.
+    /// Used for every kernel critical section of single-core builds, so it has to nest and should be as short as possible.
+    pub unsafe fn _taskette_mask_interrupts() -> usize;
+    /// INTERNAL USE ONLY
+    pub unsafe fn _taskette_restore_interrupts(state: usize);
+    /// INTERNAL USE ONLY
+    pub unsafe fn _taskette_cycle_count() -> u32;
 }
 
 /// Incurs a context switch and yields the CPU to another task.
@@ -29,8 +65,96 @@ pub fn yield_now() {
     }
 }
 
+/// Returns the index of the core executing this code (always 0 on single-core systems).
+pub fn core_id() -> usize {
+    let core_id = unsafe { _taskette_core_id() };
+    debug_assert!(core_id < NUM_CORES, "Core #{} is beyond NUM_CORES", core_id);
+    core_id
+}
+
+/// Requests a reschedule on another core.
+pub(crate) fn ipi(core_id: usize) {
+    debug_assert!(
+        core_id < NUM_CORES && core_id != self::core_id(),
+        "IPI to an invalid core #{}",
+        core_id
+    );
+    unsafe { _taskette_ipi(core_id) }
+}
+
+/// Returns the free-running high-resolution counter of the port, which wraps around.
+///
+/// It counts CPU cycles on Cortex-M (Armv7-M or later), the generic timer on Cortex-A,
+/// and microseconds on ESP and the hosted port.
+pub fn cycle_count() -> u32 {
+    unsafe { _taskette_cycle_count() }
+}
+
+/// Timer generating the tick, provided by the port (or by the application through the port).
+///
+/// Each tick has to call [`crate::scheduler::handle_tick`] from an interrupt handler.
+pub trait TickSource: Sync {
+    /// Configures the tick frequency (in Hz) before the scheduler starts.
+    /// `clock_freq` is the clock frequency passed to the scheduler.
+    fn set_frequency(&self, clock_freq: u32, tick_freq: u32);
+
+    /// Starts generating ticks on the current core. Called on the stack of the idle task of each core.
+    fn start(&self);
+
+    /// Stops generating ticks on the current core.
+    fn stop(&self);
+
+    /// Makes the next tick occur `ticks` ticks from now instead of after one period (for tickless idle).
+    ///
+    /// The ticks skipped in between have to be accounted for with [`crate::timer::skip_ticks`].
+    /// Returns `false` if not supported, which is the default.
+    fn set_next_deadline(&self, ticks: u64) -> bool {
+        let _ = ticks;
+        false
+    }
+
+    /// Returns the frequency (in Hz) of [`cycle_count`] if it can time the instants between ticks,
+    /// which makes [`crate::timer::current_time_micros`] finer than a tick. `None` by default.
+    ///
+    /// It also lets the timer count the ticks lost while the tick interrupt was masked for longer than a period,
+    /// as long as the counter does not wrap around in between (`u32::MAX / cycle_freq` seconds).
+    /// Longer gaps have to be reported with [`crate::timer::skip_ticks`].
+    fn cycle_freq(&self) -> Option<u32> {
+        None
+    }
+}
+
+/// Returns the tick source of the port.
+pub fn tick_source() -> &'static dyn TickSource {
+    unsafe { _taskette_tick_source() }
+}
+
 /// Trait for a stack allocation that meets architecture-specific requirements such as alignment.
 /// Modeled after `rp2040_hal`. https://docs.rs/rp2040-hal/0.11.0/rp2040_hal/multicore/struct.StackAllocation.html
 pub trait StackAllocation {
     fn as_mut_slice(&mut self) -> &mut [u8];
 }
+
+/// Stack alignment meeting the requirements of all ports (16 bytes on RISC-V and x86-64, 8 bytes on Arm).
+pub const STACK_ALIGN: usize = 16;
+
+/// Stack memory given as a raw address range (e.g. from C code or by an unprivileged task).
+///
+/// Both ends are trimmed to [`STACK_ALIGN`].
+pub struct RawStack(Range<usize>);
+
+impl RawStack {
+    /// # Safety
+    /// `range` has to be writable memory which is used for nothing else while the task exists.
+    pub unsafe fn new(range: Range<usize>) -> Self {
+        Self(range)
+    }
+}
+
+impl StackAllocation for RawStack {
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        let start = self.0.start.next_multiple_of(STACK_ALIGN);
+        let end = self.0.end & !(STACK_ALIGN - 1);
+        unsafe { core::slice::from_raw_parts_mut(start as *mut u8, end.saturating_sub(start)) }
+    }
+}
@@ -0,0 +1,132 @@
+//! CPU load measurement (`cpu-load` feature).
+//!
+//! Run time of the idle task of each core is measured at every context switch with a free-running counter
+//! provided by the port (such as a cycle counter), and the load is aggregated in windows of a fixed number of ticks
+//! (see [`crate::scheduler::SchedulerConfig::with_cpu_load_window`]).
+
+use core::cell::RefCell;
+
+use critical_section::{CriticalSection, Mutex};
+
+use crate::{arch, scheduler::NUM_CORES};
+
+static LOADS: Mutex<RefCell<[CoreLoad; NUM_CORES]>> =
+    Mutex::new(RefCell::new([const { CoreLoad::new() }; NUM_CORES]));
+
+/// Statistics of the CPU load of a core (in percent).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CpuLoadStats {
+    /// Load in the last completed window
+    pub last: u8,
+    /// Lowest load among the completed windows
+    pub min: u8,
+    /// Highest load among the completed windows
+    pub max: u8,
+    /// Average load of the completed windows
+    pub average: u8,
+    /// Number of completed windows
+    pub windows: u32,
+}
+
+struct CoreLoad {
+    /// Counter value at the last measurement
+    last_count: u32,
+    /// Counts spent in the idle task in the current window
+    idle: u64,
+    /// Counts elapsed in the current window
+    total: u64,
+    /// Ticks elapsed in the current window
+    ticks: u32,
+    /// Sum of the loads of the completed windows
+    sum: u64,
+    stats: CpuLoadStats,
+}
+
+impl CoreLoad {
+    const fn new() -> Self {
+        Self {
+            last_count: 0,
+            idle: 0,
+            total: 0,
+            ticks: 0,
+            sum: 0,
+            stats: CpuLoadStats {
+                last: 0,
+                min: 0,
+                max: 0,
+                average: 0,
+                windows: 0,
+            },
+        }
+    }
+
+    /// Accounts the time since the last measurement to the running task, and returns it.
+    fn measure(&mut self, idle: bool) -> u64 {
+        let count = unsafe { arch::_taskette_cycle_count() };
+        let elapsed = count.wrapping_sub(self.last_count) as u64;
+        self.last_count = count;
+
+        self.total += elapsed;
+        if idle {
+            self.idle += elapsed;
+        }
+
+        elapsed
+    }
+
+    fn close_window(&mut self) {
+        // Regarded as idle if no time is measured
+        let idle_percent = (self.idle * 100).checked_div(self.total).unwrap_or(100);
+        let load = (100 - idle_percent) as u8;
+
+        let stats = &mut self.stats;
+        stats.min = if stats.windows == 0 {
+            load
+        } else {
+            stats.min.min(load)
+        };
+        stats.max = stats.max.max(load);
+        stats.last = load;
+        stats.windows = stats.windows.saturating_add(1);
+        self.sum += load as u64;
+        stats.average = (self.sum / stats.windows as u64) as u8;
+
+        self.idle = 0;
+        self.total = 0;
+        self.ticks = 0;
+    }
+}
+
+/// Called on every context switch. `from_idle` tells whether the task being switched out is the idle task.
+///
+/// Returns the run time of the task being switched out since it was switched in (or since the last tick).
+pub(crate) fn switch(cs: CriticalSection, core: usize, from_idle: bool) -> u64 {
+    LOADS.borrow_ref_mut(cs)[core].measure(from_idle)
+}
+
+/// Called on every tick of `core`. Closes the window after `window` ticks.
+///
+/// Returns the run time of the running task since it was switched in (or since the last tick).
+pub(crate) fn tick(cs: CriticalSection, core: usize, idle: bool, window: u32) -> u64 {
+    let mut loads = LOADS.borrow_ref_mut(cs);
+    let load = &mut loads[core];
+    let elapsed = load.measure(idle);
+
+    load.ticks += 1;
+    if load.ticks >= window {
+        load.close_window();
+    }
+
+    elapsed
+}
+
+pub(crate) fn stats(cs: CriticalSection, core: usize) -> CpuLoadStats {
+    LOADS.borrow_ref(cs)[core].stats
+}
+
+pub(crate) fn reset(cs: CriticalSection) {
+    for load in LOADS.borrow_ref_mut(cs).iter_mut() {
+        load.sum = 0;
+        load.stats = CpuLoadStats::default();
+    }
+}
@@ -0,0 +1,42 @@
+//! Structured scheduler events for host tools (`defmt-events` feature).
+//!
+//! Unlike the log messages, the format string of the events is stable, so that tools on the host can parse
+//! scheduler activity from the defmt stream. Every event is emitted at the info level in the form of
+//! `taskette:task id={=usize} prio={=usize} state={=TaskState} tick={=u64}`.
+
+use core::cell::Cell;
+
+use critical_section::{CriticalSection, Mutex};
+
+/// Time of the latest tick, readable while the timer is being updated
+static TIME: Mutex<Cell<u64>> = Mutex::new(Cell::new(0));
+
+/// New state of a task reported by an event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum TaskState {
+    /// The task is spawned.
+    Created,
+    /// The task is woken up.
+    Ready,
+    /// The task is switched in.
+    Running,
+    /// The task becomes blocked.
+    Blocked,
+    /// The task finishes or is removed.
+    Removed,
+}
+
+pub(crate) fn task(cs: CriticalSection, task_id: usize, priority: usize, state: TaskState) {
+    let tick = TIME.borrow(cs).get();
+    defmt::info!(
+        "taskette:task id={=usize} prio={=usize} state={} tick={=u64}",
+        task_id,
+        priority,
+        state,
+        tick
+    );
+}
+
+pub(crate) fn set_time(cs: CriticalSection, time: u64) {
+    TIME.borrow(cs).set(time);
+}
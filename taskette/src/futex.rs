@@ -8,7 +8,9 @@ use portable_atomic::AtomicUsize;
 
 use crate::{
     Error,
-    scheduler::{MAX_NUM_TASKS, block_task, current_task_id, unblock_task},
+    scheduler::{MAX_NUM_TASKS, Wakeups, block_task, current_task_id, kernel_section},
+    sync::{CancellationToken, cancel},
+    timer::wait_task_until,
 };
 
 /// Low-level synchronization primitive.
@@ -35,7 +37,7 @@ impl Futex {
     pub fn wait(&self, compare_val: usize) -> Result<(), Error> {
         // Fast path: do nothing if the value is different
         if self.value.load(Ordering::SeqCst) == compare_val {
-            critical_section::with(|cs| {
+            kernel_section(|cs| {
                 // Slow path: eliminates the edge case of value being changed after the fast path check
                 if self.value.load(Ordering::SeqCst) == compare_val {
                     // Add the current task to the wait queue
@@ -46,6 +48,8 @@ impl Futex {
                         .unwrap_or_else(|_| unreachable!());
 
                     block_task(task_id)?;
+                    #[cfg(feature = "stats")]
+                    crate::stats::count(&crate::stats::FUTEX_WAITS);
                 }
 
                 Ok(())
@@ -55,16 +59,108 @@ impl Futex {
         Ok(())
     }
 
+    /// Same as [`Futex::wait`], but gives up when the time reaches `time` (in ticks).
+    ///
+    /// Returns `false` if it timed out instead of being woken up.
+    pub fn wait_until(&self, compare_val: usize, time: u64) -> Result<bool, Error> {
+        self.wait_inner(compare_val, Some(time), None)
+    }
+
+    /// Same as [`Futex::wait`], but returns `Error::Cancelled` when `token` is cancelled (before or during the wait).
+    pub fn wait_cancellable(
+        &self,
+        compare_val: usize,
+        token: &CancellationToken,
+    ) -> Result<(), Error> {
+        self.wait_inner(compare_val, None, Some(token)).map(|_| ())
+    }
+
+    /// Waits with an optional deadline and an optional cancellation token. Returns whether it was woken up.
+    fn wait_inner(
+        &self,
+        compare_val: usize,
+        time: Option<u64>,
+        token: Option<&CancellationToken>,
+    ) -> Result<bool, Error> {
+        if self.value.load(Ordering::SeqCst) != compare_val {
+            return Ok(true);
+        }
+
+        let task_id = kernel_section(|cs| {
+            let task_id = current_task_id()?;
+            if let Some(token) = token {
+                token.check()?;
+            }
+
+            if self.value.load(Ordering::SeqCst) == compare_val {
+                if let Some(token) = token {
+                    cancel::register(cs, token, task_id)?;
+                }
+                match time {
+                    // Does not block if the time has already passed
+                    Some(time) => wait_task_until(time, task_id)?,
+                    None => block_task(task_id)?,
+                }
+                self.waiting_tasks
+                    .borrow_ref_mut(cs)
+                    .push_back(task_id)
+                    .unwrap_or_else(|_| unreachable!());
+                #[cfg(feature = "stats")]
+                crate::stats::count(&crate::stats::FUTEX_WAITS);
+            }
+
+            Ok(task_id)
+        })?;
+
+        // Still in the wait queue only if nobody woke it up
+        let woken = kernel_section(|cs| {
+            let mut waiting_tasks = self.waiting_tasks.borrow_ref_mut(cs);
+            let mut timed_out = false;
+            for _ in 0..waiting_tasks.len() {
+                let waiting = waiting_tasks.pop_front().unwrap_or_else(|| unreachable!());
+                if waiting == task_id {
+                    timed_out = true;
+                } else {
+                    waiting_tasks
+                        .push_back(waiting)
+                        .unwrap_or_else(|_| unreachable!());
+                }
+            }
+
+            !timed_out
+        });
+
+        if let Some(token) = token {
+            cancel::unregister(token, task_id);
+            if token.is_cancelled() {
+                // A wakeup meant for another waiter is passed on
+                if woken {
+                    self.wake_one()?;
+                }
+                return Err(Error::Cancelled);
+            }
+        }
+
+        Ok(woken)
+    }
+
     /// Unblocks at most `num` tasks blocked on this futex.
     pub fn wake(&self, num: usize) -> Result<(), Error> {
-        critical_section::with(|cs| {
-            for _ in 0..num {
+        kernel_section(|cs| {
+            // The woken tasks are switched to at once after all of them are unblocked
+            let mut wakeups = Wakeups::new(cs);
+            let mut woken = 0;
+            while woken < num {
                 let mut waiting_tasks = self.waiting_tasks.borrow_ref_mut(cs);
 
-                if let Some(task_id) = waiting_tasks.pop_front() {
-                    unblock_task(task_id)?;
-                } else {
+                let Some(task_id) = waiting_tasks.pop_front() else {
                     break;
+                };
+                match wakeups.unblock(task_id) {
+                    Ok(()) => woken += 1,
+                    // The task was killed while waiting
+                    Err(Error::NotFound) => (),
+                    Err(err) => return Err(err),
                 }
             }
 
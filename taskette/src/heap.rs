@@ -0,0 +1,146 @@
+//! Global allocator wrapper for heap use from multiple tasks (`alloc` feature).
+//!
+//! [`TaskAlloc`] serializes the calls to an inner allocator with the preemption lock of the calling core
+//! and a spinlock between cores, instead of masking interrupts for the whole call:
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static HEAP: TaskAlloc<LockedHeap> = TaskAlloc::new(LockedHeap::empty());
+//!
+//! unsafe { HEAP.inner().lock().init(HEAP_START, HEAP_SIZE) };
+//! ```
+//!
+//! Interrupt handlers must not allocate, because they may interrupt the owner of the lock.
+//! With the `latency` feature, the duration of each call is recorded in `SchedulerStats::alloc`.
+//!
+//! The wrapper also keeps [`HeapStats`] (the bytes in use, their high-water mark, and failed allocations),
+//! so that devices which must never exhaust the heap can monitor the headroom in the field.
+//! With the `heap-task-stats` feature, the bytes allocated and freed by each task are shown in `TaskSummary`.
+
+use core::alloc::{GlobalAlloc, Layout};
+
+use portable_atomic::{AtomicU32, AtomicUsize, Ordering};
+
+#[cfg(feature = "heap-task-stats")]
+use crate::scheduler::account_heap;
+#[cfg(feature = "latency")]
+use crate::stats::{self, Latency};
+#[cfg(feature = "latency")]
+use crate::sync::interrupt_free;
+use crate::{scheduler::lock_preemption, sync::SpinLock};
+
+static USED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+static FAILED_ALLOCATIONS: AtomicU32 = AtomicU32::new(0);
+
+/// Usage of the heap through [`TaskAlloc`] (also included in `SchedulerStats` with the `stats` feature).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct HeapStats {
+    /// Bytes currently allocated (as requested, excluding the overhead of the inner allocator)
+    pub used: usize,
+    /// Maximum of `used` since the start or the last [`reset_peak`]
+    pub peak: usize,
+    /// Number of allocations (and reallocations) which returned null
+    pub failed_allocations: u32,
+}
+
+/// Returns the current heap statistics.
+pub fn stats() -> HeapStats {
+    HeapStats {
+        used: USED.load(Ordering::Relaxed),
+        peak: PEAK.load(Ordering::Relaxed),
+        failed_allocations: FAILED_ALLOCATIONS.load(Ordering::Relaxed),
+    }
+}
+
+/// Sets the high-water mark to the current usage, and clears the count of failed allocations.
+pub fn reset_peak() {
+    PEAK.store(USED.load(Ordering::Relaxed), Ordering::Relaxed);
+    FAILED_ALLOCATIONS.store(0, Ordering::Relaxed);
+}
+
+/// Accounts an allocation of `size` bytes, which failed if `ptr` is null.
+fn record_alloc(ptr: *mut u8, size: usize) {
+    if ptr.is_null() {
+        FAILED_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+
+    let used = USED.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK.fetch_max(used, Ordering::Relaxed);
+    #[cfg(feature = "heap-task-stats")]
+    account_heap(size as isize);
+}
+
+fn record_dealloc(size: usize) {
+    USED.fetch_sub(size, Ordering::Relaxed);
+    #[cfg(feature = "heap-task-stats")]
+    account_heap(-(size as isize));
+}
+
+/// Allocator calling `A` from one task at a time.
+pub struct TaskAlloc<A> {
+    inner: A,
+    lock: SpinLock<()>,
+}
+
+impl<A> TaskAlloc<A> {
+    pub const fn new(inner: A) -> Self {
+        Self {
+            inner,
+            lock: SpinLock::new(()),
+        }
+    }
+
+    /// Inner allocator, e.g. for initializing its memory region.
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Runs `f` while no other task can use the inner allocator.
+    fn serialize<R>(&self, f: impl FnOnce(&A) -> R) -> R {
+        // The lock owner is never switched out, so other cores spin only for the duration of one call
+        let _preemption = lock_preemption();
+        let _guard = self.lock.lock();
+
+        #[cfg(feature = "latency")]
+        let start = stats::timestamp();
+
+        let result = f(&self.inner);
+
+        #[cfg(feature = "latency")]
+        interrupt_free(|cs| stats::record(cs, Latency::Alloc, start));
+
+        result
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for TaskAlloc<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.serialize(|inner| unsafe { inner.alloc(layout) });
+        record_alloc(ptr, layout.size());
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.serialize(|inner| unsafe { inner.dealloc(ptr, layout) });
+        record_dealloc(layout.size());
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.serialize(|inner| unsafe { inner.alloc_zeroed(layout) });
+        record_alloc(ptr, layout.size());
+        ptr
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = self.serialize(|inner| unsafe { inner.realloc(ptr, layout, new_size) });
+        // The old block stays allocated on failure
+        if !new_ptr.is_null() {
+            record_dealloc(layout.size());
+        }
+        record_alloc(new_ptr, new_size);
+        new_ptr
+    }
+}
@@ -1,17 +1,46 @@
 #![doc = include_str!("../README.md")]
 #![no_std]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
 pub mod arch;
+#[cfg(feature = "cpu-load")]
+pub mod cpu_load;
+#[cfg(feature = "defmt-events")]
+pub mod events;
 pub mod futex;
+#[cfg(feature = "alloc")]
+pub mod heap;
+#[cfg(feature = "lock-watchdog")]
+pub mod lock_watchdog;
+#[cfg(feature = "rtos-awareness")]
+pub mod rtos_awareness;
 pub mod scheduler;
+#[cfg(feature = "stats")]
+pub mod stats;
+pub mod supervisor;
+pub mod sync;
 pub mod task;
 pub mod timer;
+#[cfg(feature = "trace-hooks")]
+pub mod trace;
+pub mod watchdog;
+pub mod work_queue;
 
-mod log_wrapper;
+// Used by exported macros
+#[doc(hidden)]
+pub mod log_wrapper;
+#[cfg(feature = "preemption-critical-section")]
+mod preemption_cs;
+mod profile;
 
 pub use portable_atomic;
+#[cfg(feature = "macros")]
+pub use taskette_macros::task;
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Error {
     /// Cannot create a new task because already maximum number of tasks exist.
     TaskFull,
@@ -23,4 +52,42 @@ pub enum Error {
     NotInitialized,
     /// Already maximum number of timer registrations exist.
     TimerFull,
+    /// The specified core does not exist.
+    InvalidAffinity,
+    /// Memory allocation failed.
+    OutOfMemory,
+    /// The operation is not allowed on the specified task (e.g. killing an idle task).
+    NotPermitted,
+    /// The queue has no space for another item.
+    QueueFull,
+    /// The stack is too small for the initial context of the task.
+    StackTooSmall,
+    /// The deadline passed before the operation completed.
+    TimedOut,
+    /// A non-blocking operation would have to block.
+    WouldBlock,
+    /// The wait was interrupted by a `CancellationToken`.
+    Cancelled,
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Self::TaskFull => "maximum number of tasks reached",
+            Self::InvalidPriority => "invalid priority",
+            Self::NotFound => "task not found",
+            Self::NotInitialized => "scheduler not initialized",
+            Self::TimerFull => "maximum number of timers reached",
+            Self::InvalidAffinity => "invalid core",
+            Self::OutOfMemory => "out of memory",
+            Self::NotPermitted => "operation not permitted",
+            Self::QueueFull => "queue full",
+            Self::StackTooSmall => "stack too small",
+            Self::TimedOut => "timed out",
+            Self::WouldBlock => "operation would block",
+            Self::Cancelled => "cancelled",
+        })
+    }
 }
+
+impl core::error::Error for Error {}
@@ -0,0 +1,112 @@
+//! Hold-time checking of kernel locks (`lock-watchdog` feature).
+//!
+//! The time a [`crate::sync::SpinLock`] or the scheduler lock (the critical section guarding the task table,
+//! ready queues, and timers) is held is measured with the free-running counter of the port (the cycle counter on Cortex-M).
+//! When it exceeds the threshold set by [`set_threshold`], the hook registered by [`set_hook`] is called
+//! with the task which held the lock. Without a hook, a warning is logged instead.
+
+use core::cell::Cell;
+
+use critical_section::{CriticalSection, Mutex};
+use portable_atomic::{AtomicU32, Ordering};
+
+use crate::{arch, scheduler, sync::interrupt_free};
+
+/// Function called when a lock is held longer than the threshold
+pub type HoldTimeHook = fn(HoldTimeViolation);
+
+static THRESHOLD: AtomicU32 = AtomicU32::new(u32::MAX);
+static HOOK: Mutex<Cell<Option<HoldTimeHook>>> = Mutex::new(Cell::new(None));
+
+/// Kind of a lock checked by the watchdog
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LockKind {
+    /// `SpinLock` taken by `lock` or `try_lock`
+    SpinLock,
+    /// `SpinLock` taken by `lock_irq` (interrupts are masked while it is held)
+    SpinLockIrq,
+    /// Critical section of the scheduler (preemption and interrupts are disabled while it is held)
+    Scheduler,
+}
+
+/// Report of a lock held longer than the threshold
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HoldTimeViolation {
+    pub lock: LockKind,
+    /// ID of the task running when the lock was taken (`None` before the scheduler is initialized)
+    pub task_id: Option<usize>,
+    /// Hold time (in counts of the counter of the port)
+    pub duration: u32,
+}
+
+/// Start of a hold, taken when a lock is acquired
+#[derive(Clone, Copy)]
+pub(crate) struct Hold {
+    start: u32,
+    task_id: Option<usize>,
+}
+
+/// Sets the maximum hold time (in counts of the counter of the port). Nothing is reported until this is called.
+pub fn set_threshold(threshold: u32) {
+    THRESHOLD.store(threshold, Ordering::Relaxed);
+}
+
+/// Registers a function called when a lock is held longer than the threshold.
+///
+/// Called right after the lock is released, which may be in an interrupt handler or inside another critical section,
+/// so the hook has to be short and must not block.
+pub fn set_hook(hook: HoldTimeHook) {
+    interrupt_free(|cs| HOOK.borrow(cs).set(Some(hook)));
+}
+
+pub(crate) fn acquire(cs: CriticalSection) -> Hold {
+    Hold {
+        start: unsafe { arch::_taskette_cycle_count() },
+        task_id: scheduler::running_task_id(cs),
+    }
+}
+
+pub(crate) fn release(hold: Hold, lock: LockKind) {
+    let duration = unsafe { arch::_taskette_cycle_count() }.wrapping_sub(hold.start);
+    if duration <= THRESHOLD.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let violation = HoldTimeViolation {
+        lock,
+        task_id: hold.task_id,
+        duration,
+    };
+    match interrupt_free(|cs| HOOK.borrow(cs).get()) {
+        Some(hook) => hook(violation),
+        #[cfg(any(feature = "log", feature = "defmt"))]
+        None => log_violation(violation),
+        #[cfg(not(any(feature = "log", feature = "defmt")))]
+        None => (),
+    }
+}
+
+/// Warns of a violation when no hook is registered.
+#[cfg(any(feature = "log", feature = "defmt"))]
+fn log_violation(violation: HoldTimeViolation) {
+    let lock = match violation.lock {
+        LockKind::SpinLock => "spinlock",
+        LockKind::SpinLockIrq => "spinlock (interrupts masked)",
+        LockKind::Scheduler => "scheduler lock",
+    };
+    match violation.task_id {
+        Some(task_id) => crate::dispatch_log!(
+            warn,
+            "Task #{} held the {} for {} counts",
+            task_id,
+            lock,
+            violation.duration
+        ),
+        None => crate::dispatch_log!(
+            warn,
+            "The {} was held for {} counts",
+            lock,
+            violation.duration
+        ),
+    }
+}
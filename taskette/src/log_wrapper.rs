@@ -1,11 +1,119 @@
+//! Logging through `log` and/or `defmt`, optionally prefixed with the running task (`task-log-context` feature).
+//!
+//! The items of this module are used by exported macros and are not part of the API.
+
+#[cfg(feature = "task-log-context")]
+use core::cell::Cell;
+
+#[cfg(feature = "task-log-context")]
+use critical_section::{CriticalSection, Mutex};
+
+#[cfg(feature = "defmt")]
+pub use defmt;
+#[cfg(feature = "log")]
+pub use log;
+
+#[cfg(feature = "task-log-context")]
+use crate::{
+    arch,
+    scheduler::NUM_CORES,
+    sync::{PerCore, interrupt_free},
+};
+
+/// ID and name of a task
+#[cfg(feature = "task-log-context")]
+type TaskIdName = (usize, Option<&'static str>);
+
+/// Running task of each core, readable while the scheduler state is borrowed
+#[cfg(feature = "task-log-context")]
+static CURRENT_TASKS: PerCore<Mutex<Cell<Option<TaskIdName>>>> =
+    PerCore::from_array([const { Mutex::new(Cell::new(None)) }; NUM_CORES]);
+
+/// Records the task switched in on `core`. Called on every context switch.
+#[cfg(feature = "task-log-context")]
+pub(crate) fn set_current_task(
+    cs: CriticalSection,
+    core: usize,
+    id: usize,
+    name: Option<&'static str>,
+) {
+    CURRENT_TASKS[core].borrow(cs).set(Some((id, name)));
+}
+
+/// Task a log message comes from, shown as `#<id> <name>` (`-` before the scheduler starts).
+#[cfg(feature = "task-log-context")]
+#[derive(Clone, Copy, Debug)]
+pub struct LogContext {
+    pub id: Option<usize>,
+    pub name: Option<&'static str>,
+}
+
+#[cfg(feature = "task-log-context")]
+impl LogContext {
+    /// Returns the context of the running task. Also usable in kernel sections and interrupt handlers
+    /// (which are attributed to the interrupted task).
+    pub fn current() -> Self {
+        let task = interrupt_free(|cs| CURRENT_TASKS[arch::core_id()].borrow(cs).get());
+        Self {
+            id: task.map(|(id, _)| id),
+            name: task.and_then(|(_, name)| name),
+        }
+    }
+}
+
+#[cfg(feature = "task-log-context")]
+impl core::fmt::Display for LogContext {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match (self.id, self.name) {
+            (Some(id), Some(name)) => write!(f, "#{} {}", id, name),
+            (Some(id), None) => write!(f, "#{}", id),
+            (None, _) => write!(f, "-"),
+        }
+    }
+}
+
+#[cfg(all(feature = "task-log-context", feature = "defmt"))]
+impl defmt::Format for LogContext {
+    fn format(&self, f: defmt::Formatter) {
+        match (self.id, self.name) {
+            (Some(id), Some(name)) => defmt::write!(f, "#{=usize} {=str}", id, name),
+            (Some(id), None) => defmt::write!(f, "#{=usize}", id),
+            (None, _) => defmt::write!(f, "-"),
+        }
+    }
+}
+
+/// Formats a message with `defmt` inside another message.
+#[cfg(all(feature = "task-log-context", feature = "defmt"))]
+pub struct FormatWith<F: Fn(defmt::Formatter)>(pub F);
+
+#[cfg(all(feature = "task-log-context", feature = "defmt"))]
+impl<F: Fn(defmt::Formatter)> defmt::Format for FormatWith<F> {
+    fn format(&self, f: defmt::Formatter) {
+        (self.0)(f)
+    }
+}
+
 #[macro_export]
 macro_rules! dispatch_log {
     ( $level:ident, $( $arg:expr ),+ ) => {
         {
-            #[cfg(feature = "log")]
+            #[cfg(all(feature = "log", not(feature = "task-log-context")))]
             log::$level!( $( $arg ),+ );
-            #[cfg(feature = "defmt")]
+            #[cfg(all(feature = "log", feature = "task-log-context"))]
+            log::$level!(
+                "[{}] {}",
+                $crate::log_wrapper::LogContext::current(),
+                format_args!( $( $arg ),+ )
+            );
+            #[cfg(all(feature = "defmt", not(feature = "task-log-context")))]
             defmt::$level!( $( $arg ),+ );
+            #[cfg(all(feature = "defmt", feature = "task-log-context"))]
+            defmt::$level!(
+                "[{}] {}",
+                $crate::log_wrapper::LogContext::current(),
+                $crate::log_wrapper::FormatWith(|f| defmt::write!(f, $( $arg ),+ ))
+            );
         }
     };
 }
@@ -24,3 +132,60 @@ macro_rules! debug {
 macro_rules! trace {
     ( $( $arg:expr ),+ ) => { crate::dispatch_log!(trace, $( $arg ),+ ) };
 }
+
+/// Logs a message prefixed with the ID and name of the running task (`task-log-context` feature),
+/// e.g. `task_log!(info, "received {}", len)` logs `[#3 net] received 42`.
+///
+/// The level is one of `error`, `warn`, `info`, `debug`, and `trace`. The message goes to `log` and/or `defmt`,
+/// whichever features of taskette are enabled. With `defmt`, the format string follows `defmt` syntax,
+/// and the calling crate has to depend on `defmt` too.
+#[cfg(feature = "task-log-context")]
+#[macro_export]
+macro_rules! task_log {
+    ( $level:ident, $( $arg:tt )+ ) => {
+        {
+            $crate::__task_log_log!($level, $( $arg )+);
+            $crate::__task_log_defmt!($level, $( $arg )+);
+        }
+    };
+}
+
+#[cfg(all(feature = "task-log-context", feature = "log"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __task_log_log {
+    ( $level:ident, $( $arg:tt )+ ) => {
+        $crate::log_wrapper::log::$level!(
+            "[{}] {}",
+            $crate::log_wrapper::LogContext::current(),
+            format_args!($( $arg )+)
+        )
+    };
+}
+
+#[cfg(all(feature = "task-log-context", not(feature = "log")))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __task_log_log {
+    ( $( $arg:tt )+ ) => {};
+}
+
+#[cfg(all(feature = "task-log-context", feature = "defmt"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __task_log_defmt {
+    ( $level:ident, $( $arg:tt )+ ) => {
+        $crate::log_wrapper::defmt::$level!(
+            "[{}] {}",
+            $crate::log_wrapper::LogContext::current(),
+            $crate::log_wrapper::FormatWith(|f| $crate::log_wrapper::defmt::write!(f, $( $arg )+))
+        )
+    };
+}
+
+#[cfg(all(feature = "task-log-context", not(feature = "defmt")))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __task_log_defmt {
+    ( $( $arg:tt )+ ) => {};
+}
@@ -0,0 +1,29 @@
+//! `critical-section` implementation locking preemption instead of masking interrupts (`preemption-critical-section` feature).
+//!
+//! `critical_section::with` only keeps other tasks from being switched in (see [`crate::scheduler::lock_preemption`]),
+//! so data shared only between tasks (e.g. `Mutex<RefCell<T>>`) is protected without delaying interrupts.
+//! Interrupt handlers are not excluded at all: data also accessed by them has to be accessed through
+//! [`crate::sync::interrupt_free`], which the kernel itself and the ports use for their state.
+//!
+//! Only for single-core builds, and no other `critical-section` implementation can be linked
+//! (e.g. `critical-section-single-core` of `cortex-m`, the `basepri` feature of `taskette-cortex-m`, or `taskette-hosted`).
+//! Interrupt handlers must not rely on `critical_section::with`, including through other crates.
+
+#[cfg(feature = "smp")]
+compile_error!("`preemption-critical-section` cannot be used with `smp`");
+
+use crate::scheduler::{acquire_preemption_lock, release_preemption_lock};
+
+struct PreemptionCriticalSection;
+
+critical_section::set_impl!(PreemptionCriticalSection);
+
+unsafe impl critical_section::Impl for PreemptionCriticalSection {
+    unsafe fn acquire() -> critical_section::RawRestoreState {
+        acquire_preemption_lock();
+    }
+
+    unsafe fn release(_: critical_section::RawRestoreState) {
+        release_preemption_lock();
+    }
+}
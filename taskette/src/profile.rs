@@ -0,0 +1,46 @@
+//! Capacity constants selected by the size-profile features.
+//!
+//! - Default: 16 tasks, priorities 0 to 10, 32 timer registrations
+//! - `profile-tiny`: 8 tasks, priorities 0 to 3, 8 timer registrations, and no task names in the scheduler,
+//!   for parts with 16 KiB of RAM or less (e.g. Cortex-M0+)
+//! - `profile-large`: 64 tasks, priorities 0 to 63, 128 timer registrations, for parts with plenty of RAM (e.g. ESP32-S3)
+//!
+//! The counts of tasks include the idle tasks.
+
+#[cfg(all(feature = "profile-tiny", feature = "profile-large"))]
+compile_error!("Features `profile-tiny` and `profile-large` cannot be enabled at the same time");
+
+struct Profile {
+    max_num_tasks: usize,
+    max_priority: usize,
+    max_timer_regs: usize,
+    work_queue_capacity: usize,
+}
+
+const PROFILE: Profile = if cfg!(feature = "profile-tiny") {
+    Profile {
+        max_num_tasks: 8,
+        max_priority: 3,
+        max_timer_regs: 8,
+        work_queue_capacity: 8,
+    }
+} else if cfg!(feature = "profile-large") {
+    Profile {
+        max_num_tasks: 64,
+        max_priority: 63,
+        max_timer_regs: 128,
+        work_queue_capacity: 64,
+    }
+} else {
+    Profile {
+        max_num_tasks: 16,
+        max_priority: 10,
+        max_timer_regs: 32,
+        work_queue_capacity: 32,
+    }
+};
+
+pub(crate) const MAX_NUM_TASKS: usize = PROFILE.max_num_tasks;
+pub(crate) const MAX_PRIORITY: usize = PROFILE.max_priority;
+pub(crate) const MAX_TIMER_REGS: usize = PROFILE.max_timer_regs;
+pub(crate) const WORK_QUEUE_CAPACITY: usize = PROFILE.work_queue_capacity;
@@ -0,0 +1,221 @@
+//! Task table for debuggers (`rtos-awareness` feature).
+//!
+//! The scheduler mirrors its tasks into a fixed C-compatible table exported as the `taskette_debug_info` symbol,
+//! so that an OpenOCD `rtos` profile or a GDB script can enumerate tasks of a halted target
+//! and unwind each of them from its saved stack pointer.
+//!
+//! The table is a [`DebugInfo`]. Its layout is identified by `version` ([`LAYOUT_VERSION`]),
+//! and slots are `slot_size` bytes apart so that fields can be appended without breaking readers.
+//! A slot whose `state` is [`DebugTaskState::Unused`] is empty.
+//! The context saved at the stack pointer is specific to each port, which describes it with a [`ContextLayout`]
+//! exported as the `taskette_context_layout` symbol (when the feature of the port is enabled).
+//!
+//! The table is only modified inside critical sections, so it is consistent whenever the target is halted
+//! outside the scheduler.
+
+use core::{cell::UnsafeCell, ops::Range};
+
+use critical_section::CriticalSection;
+
+use crate::scheduler::{MAX_NUM_TASKS, NUM_CORES};
+
+/// Version of the layout of [`DebugInfo`], incremented on incompatible changes
+pub const LAYOUT_VERSION: u32 = 2;
+/// Number of slots in the table (tasks beyond this number are not listed)
+pub const NUM_SLOTS: usize = MAX_NUM_TASKS;
+/// Number of registers described by [`ContextLayout`]
+pub const NUM_CONTEXT_REGISTERS: usize = 32;
+
+#[unsafe(export_name = "taskette_debug_info")]
+static DEBUG_INFO: DebugInfoCell = DebugInfoCell(UnsafeCell::new(DebugInfo {
+    version: LAYOUT_VERSION,
+    num_cores: NUM_CORES as u32,
+    num_slots: NUM_SLOTS as u32,
+    slot_size: size_of::<DebugTask>() as u32,
+    current_task: [0; NUM_CORES],
+    tasks: [DebugTask::UNUSED; NUM_SLOTS],
+}));
+
+/// Root of the table.
+#[repr(C)]
+#[derive(Debug)]
+pub struct DebugInfo {
+    pub version: u32,
+    pub num_cores: u32,
+    pub num_slots: u32,
+    /// Size of [`DebugTask`] (in bytes)
+    pub slot_size: u32,
+    /// ID of the running task of each core
+    pub current_task: [usize; NUM_CORES],
+    pub tasks: [DebugTask; NUM_SLOTS],
+}
+
+/// Slot of a task.
+#[repr(C)]
+#[derive(Debug)]
+pub struct DebugTask {
+    /// [`DebugTaskState`] as an integer
+    pub state: u32,
+    pub priority: u32,
+    pub id: usize,
+    /// UTF-8 name (null if the task has no name)
+    pub name: *const u8,
+    pub name_len: usize,
+    /// Stack pointer saved at the last context switch (not valid while running)
+    pub stack_pointer: usize,
+    /// Bottom of the stack
+    pub stack_limit: usize,
+    /// Core on which the task is running or last ran
+    pub core: usize,
+    /// Top of the stack (initial stack pointer)
+    pub stack_end: usize,
+}
+
+/// Location of registers in the context saved on the stack of a switched-out task.
+///
+/// Offsets are in bytes from the saved stack pointer, so that a debugger can unwind a task which is not running.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct ContextLayout {
+    /// Size of the context. The stack pointer of the task before the switch is the saved one plus this.
+    pub size: u32,
+    /// Offset of the program counter
+    pub pc: i32,
+    /// Offsets of the registers in the DWARF numbering of the architecture (-1 if not saved)
+    pub registers: [i32; NUM_CONTEXT_REGISTERS],
+}
+
+impl ContextLayout {
+    pub const fn new(size: usize, pc: usize) -> Self {
+        Self {
+            size: size as u32,
+            pc: pc as i32,
+            registers: [-1; NUM_CONTEXT_REGISTERS],
+        }
+    }
+
+    /// Sets the offset of the register with DWARF register number `register`.
+    pub const fn with_register(self, register: usize, offset: usize) -> Self {
+        let mut registers = self.registers;
+        registers[register] = offset as i32;
+        Self { registers, ..self }
+    }
+}
+
+/// State of a task in [`DebugTask`].
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugTaskState {
+    Unused = 0,
+    Ready = 1,
+    Running = 2,
+    Blocked = 3,
+}
+
+struct DebugInfoCell(UnsafeCell<DebugInfo>);
+
+// Only accessed inside critical sections
+unsafe impl Sync for DebugInfoCell {}
+
+impl DebugTask {
+    const UNUSED: Self = Self {
+        state: DebugTaskState::Unused as u32,
+        priority: 0,
+        id: 0,
+        name: core::ptr::null(),
+        name_len: 0,
+        stack_pointer: 0,
+        stack_limit: 0,
+        core: 0,
+        stack_end: 0,
+    };
+}
+
+fn with_info<R>(_cs: CriticalSection, f: impl FnOnce(&mut DebugInfo) -> R) -> R {
+    // The critical section guarantees exclusive access (on every core)
+    f(unsafe { &mut *DEBUG_INFO.0.get() })
+}
+
+fn find_slot(info: &mut DebugInfo, task_id: usize) -> Option<&mut DebugTask> {
+    info.tasks
+        .iter_mut()
+        .find(|slot| slot.state != DebugTaskState::Unused as u32 && slot.id == task_id)
+}
+
+/// Adds a new task to the table. `stack` is the address range of its stack.
+pub(crate) fn add(
+    cs: CriticalSection,
+    task_id: usize,
+    priority: usize,
+    name: Option<&'static str>,
+    stack_pointer: usize,
+    stack: Range<usize>,
+    core: usize,
+) {
+    with_info(cs, |info| {
+        let Some(slot) = info
+            .tasks
+            .iter_mut()
+            .find(|slot| slot.state == DebugTaskState::Unused as u32)
+        else {
+            return;
+        };
+
+        *slot = DebugTask {
+            state: DebugTaskState::Ready as u32,
+            priority: priority as u32,
+            id: task_id,
+            name: name.map_or(core::ptr::null(), str::as_ptr),
+            name_len: name.map_or(0, str::len),
+            stack_pointer,
+            stack_limit: stack.start,
+            core,
+            stack_end: stack.end,
+        };
+    });
+}
+
+/// Records the context saved by a task being switched out.
+pub(crate) fn switch_out(cs: CriticalSection, task_id: usize, stack_pointer: usize, blocked: bool) {
+    with_info(cs, |info| {
+        if let Some(slot) = find_slot(info, task_id) {
+            slot.stack_pointer = stack_pointer;
+            slot.state = if blocked {
+                DebugTaskState::Blocked
+            } else {
+                DebugTaskState::Ready
+            } as u32;
+        }
+    });
+}
+
+/// Marks a task as running on `core`.
+pub(crate) fn switch_in(cs: CriticalSection, core: usize, task_id: usize) {
+    with_info(cs, |info| {
+        info.current_task[core] = task_id;
+        if let Some(slot) = find_slot(info, task_id) {
+            slot.state = DebugTaskState::Running as u32;
+            slot.core = core;
+        }
+    });
+}
+
+/// Changes the state of a task which is not running.
+pub(crate) fn set_state(cs: CriticalSection, task_id: usize, state: DebugTaskState) {
+    with_info(cs, |info| {
+        if let Some(slot) = find_slot(info, task_id)
+            && slot.state != DebugTaskState::Running as u32
+        {
+            slot.state = state as u32;
+        }
+    });
+}
+
+/// Removes a task from the table.
+pub(crate) fn remove(cs: CriticalSection, task_id: usize) {
+    with_info(cs, |info| {
+        if let Some(slot) = find_slot(info, task_id) {
+            *slot = DebugTask::UNUSED;
+        }
+    });
+}
@@ -1,30 +1,157 @@
 //! Task scheduler implementation and related functions.
 //!
 //! It uses fixed priority scheduling with round-robin execution for tasks of the same priority.
+//!
+//! With the `smp` feature, each core has its own ready queue and idle task.
+//! A task not pinned by affinity stays in the queue of the core it last ran on,
+//! and migrates to another core only when that core has nothing of the same or higher priority to run.
+//! When tasks are left waiting in the queue of a core, idle cores are notified so that they can steal one of them.
+
+use core::{
+    cell::{Cell, RefCell},
+    fmt,
+    marker::PhantomData,
+    mem::{ManuallyDrop, MaybeUninit, transmute},
+    panic::PanicInfo,
+    ptr::addr_of_mut,
+    sync::atomic::Ordering,
+};
 
-use core::{cell::RefCell, mem::ManuallyDrop};
-
-use critical_section::Mutex;
-use heapless::{Deque, index_map::FnvIndexMap};
+use critical_section::{CriticalSection, Mutex};
+use heapless::{
+    Deque, LinearMap, Vec,
+    binary_heap::{BinaryHeap, Min},
+    deque::DequeView,
+    linear_map::LinearMapView,
+};
+use portable_atomic::{AtomicBool, AtomicUsize};
+
+#[cfg(feature = "cpu-load")]
+use crate::cpu_load::{self, CpuLoadStats};
+#[cfg(feature = "defmt-events")]
+use crate::events::{self, TaskState};
+#[cfg(feature = "lock-watchdog")]
+use crate::lock_watchdog::{self, LockKind};
+#[cfg(feature = "rtos-awareness")]
+use crate::rtos_awareness::{self, DebugTaskState};
+#[cfg(feature = "latency")]
+use crate::stats::Latency;
+#[cfg(feature = "stats")]
+use crate::stats::{self, SchedulerStats};
 
 use crate::{
-    Error, arch::{self, StackAllocation, yield_now}, debug, info, task::{TaskConfig, TaskHandle}, timer, trace
+    Error,
+    arch::{self, StackAllocation, yield_now},
+    debug,
+    futex::Futex,
+    info, profile,
+    supervisor::{self, RestartPolicy, Supervision},
+    sync::{CancellationToken, PerCore, interrupt_free},
+    task::{PanicHook, TaskConfig, TaskHandle},
+    timer, trace, watchdog,
 };
 
-pub(crate) const MAX_NUM_TASKS: usize = 16;
-pub(crate) const MAX_PRIORITY: usize = 10;
+/// Maximum number of tasks (including idle tasks) with the default storage (see `profile`)
+pub(crate) const MAX_NUM_TASKS: usize = profile::MAX_NUM_TASKS;
+/// Highest task priority (see `profile`)
+pub const MAX_PRIORITY: usize = profile::MAX_PRIORITY;
+/// Idle task of core N has ID N
 pub(crate) const IDLE_TASK_ID: usize = 0;
 pub(crate) const IDLE_PRIORITY: usize = 0;
 
-const QUEUE_LEN: usize = MAX_NUM_TASKS + 1;
+/// Number of cores managed by the scheduler
+#[cfg(not(feature = "smp"))]
+pub const NUM_CORES: usize = 1;
+/// Number of cores managed by the scheduler
+#[cfg(feature = "smp")]
+pub const NUM_CORES: usize = 2;
+
+/// Space (in bytes) required in a stack besides the task closure, for the initial context saved by the port
+pub const MIN_STACK_SIZE: usize = 256;
+
+/// Function called with the task ID on stack overflow
+pub type StackOverflowHook = fn(usize);
+/// Function called with the task ID when a task is terminated by a CPU fault
+pub type TaskFaultHook = fn(usize);
+/// Function called at the end of [`request_shutdown`], resetting or powering off the system
+pub type ShutdownHook = fn() -> !;
+/// Function called with the description of a violated scheduler invariant
+#[cfg(feature = "paranoid-checks")]
+pub type AssertHook = fn(&'static str);
+
+/// Storage used by `Scheduler::init`
+static mut DEFAULT_STORAGE: SchedulerStorage<MAX_NUM_TASKS, { timer::MAX_TIMER_REGS }> =
+    SchedulerStorage::new();
+static DEFAULT_STORAGE_TAKEN: AtomicBool = AtomicBool::new(false);
+
+static SCHEDULER_STATE: Mutex<RefCell<SchedulerState>> = {
+    /// Placeholders of the task table and ready queues before `Scheduler::init`, which never hold any task
+    static mut EMPTY_TASKS: LinearMap<usize, TaskInfo, 0> = LinearMap::new();
+    static mut EMPTY_RUN_QUEUES: [[Deque<usize, 1>; MAX_PRIORITY + 1]; NUM_CORES] =
+        [const { [const { Deque::new() }; MAX_PRIORITY + 1] }; NUM_CORES];
+
+    // SAFETY: the placeholders are private to this initializer, which is evaluated only once
+    let (tasks, run_queues) = unsafe {
+        (
+            &mut *addr_of_mut!(EMPTY_TASKS),
+            &mut *addr_of_mut!(EMPTY_RUN_QUEUES),
+        )
+    };
+    Mutex::new(RefCell::new(SchedulerState::new(tasks, run_queues)))
+};
+static SCHEDULER_CONFIG: Mutex<RefCell<Option<SchedulerConfig>>> = Mutex::new(RefCell::new(None));
+static STACK_OVERFLOW_HOOK: Mutex<Cell<Option<StackOverflowHook>>> = Mutex::new(Cell::new(None));
+static TASK_FAULT_HOOK: Mutex<Cell<Option<TaskFaultHook>>> = Mutex::new(Cell::new(None));
+static TASK_PANIC_HOOK: Mutex<Cell<Option<PanicHook>>> = Mutex::new(Cell::new(None));
+static SHUTDOWN_HOOK: Mutex<Cell<Option<ShutdownHook>>> = Mutex::new(Cell::new(None));
+/// Cancelled by `request_shutdown`
+static SHUTDOWN: CancellationToken<'static> = CancellationToken::new();
+/// Incremented whenever a task is removed (tasks joining others wait on this)
+pub(crate) static TASK_EXITS: Futex = Futex::new(0);
+#[cfg(feature = "paranoid-checks")]
+static ASSERT_HOOK: Mutex<Cell<Option<AssertHook>>> = Mutex::new(Cell::new(None));
+/// Stack limit of the running task of each core (readable without a critical section during context switch)
+static CURRENT_STACK_LIMIT: PerCore<AtomicUsize> =
+    PerCore::from_array([const { AtomicUsize::new(0) }; NUM_CORES]);
+/// Nesting depth of the preemption lock of each core (see `lock_preemption`)
+static PREEMPTION_LOCKS: PerCore<AtomicUsize> =
+    PerCore::from_array([const { AtomicUsize::new(0) }; NUM_CORES]);
+/// Whether a context switch of each core was skipped because preemption was locked
+static SWITCH_DEFERRED: PerCore<AtomicBool> =
+    PerCore::from_array([const { AtomicBool::new(false) }; NUM_CORES]);
+/// Idle task stacks (start and end) of secondary cores
+#[cfg(feature = "smp")]
+static SECONDARY_IDLE_STACKS: Mutex<RefCell<PerCore<(usize, usize)>>> =
+    Mutex::new(RefCell::new(PerCore::new((0, 0))));
+
+/// Memory for the task table, ready queues, and timer queue, supplied to [`Scheduler::init_with_storage`].
+///
+/// `TASKS` is the maximum number of tasks including the idle task of each core,
+/// and `TIMERS` is the capacity of the timer queue holding the timeouts of sleeping and waiting tasks.
+/// With `TIMERS >= TASKS`, registering a timeout never fails with `Error::TimerFull`,
+/// and a larger queue only makes the removal of outdated registrations less frequent.
+/// Intended to be placed in a static (e.g. using `ConstStaticCell`, as `new` is `const`), so that RAM usage is determined by the application.
+pub struct SchedulerStorage<const TASKS: usize, const TIMERS: usize> {
+    tasks: LinearMap<usize, TaskInfo, TASKS>,
+    run_queues: [[Deque<usize, TASKS>; MAX_PRIORITY + 1]; NUM_CORES],
+    timers: BinaryHeap<timer::TimerRegistry, Min, TIMERS>,
+}
 
-#[cfg(feature = "stack-canary")]
-const STACK_CANARY: u32 = 0xABCD1234;
-#[cfg(feature = "stack-canary")]
-const STACK_CANARY_LEN: usize = 4;
+impl<const TASKS: usize, const TIMERS: usize> SchedulerStorage<TASKS, TIMERS> {
+    pub const fn new() -> Self {
+        Self {
+            tasks: LinearMap::new(),
+            run_queues: [const { [const { Deque::new() }; MAX_PRIORITY + 1] }; NUM_CORES],
+            timers: BinaryHeap::new(),
+        }
+    }
+}
 
-static SCHEDULER_STATE: Mutex<RefCell<Option<SchedulerState>>> = Mutex::new(RefCell::new(None));
-static SCHEDULER_CONFIG: Mutex<RefCell<Option<SchedulerConfig>>> = Mutex::new(RefCell::new(None));
+impl<const TASKS: usize, const TIMERS: usize> Default for SchedulerStorage<TASKS, TIMERS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 /// Task Control Block (TCB)
 #[derive(Clone, Debug)]
@@ -32,38 +159,210 @@ struct TaskInfo {
     stack_pointer: usize,
     priority: usize,
     blocked: bool,
-    #[cfg(feature = "stack-canary")]
+    /// Core on which the task is allowed to run (`None` means any core)
+    affinity: Option<usize>,
+    /// Core whose ready queue holds the task (the core it last ran on)
+    core: usize,
     stack_limit: usize, // Bottom of the stack (including canary space)
+    /// Whether the bottom of the stack holds the canary, which is checked at context switches
+    #[cfg(feature = "stack-canary")]
+    stack_canary: bool,
+    /// Top of the stack (initial stack pointer before the initial context is pushed)
+    stack_end: usize,
+    /// Name set by `TaskConfig::with_name` (dropped by `profile-tiny` to save RAM)
+    #[cfg(not(feature = "profile-tiny"))]
+    name: Option<&'static str>,
+    /// Total run time measured with the cycle counter of the port
+    #[cfg(feature = "cpu-load")]
+    run_time: u64,
+    /// Hook set by `TaskConfig::with_panic_hook`
+    panic_hook: Option<PanicHook>,
+    /// Restart settings of a task created by `spawn_supervised`
+    supervision: Option<Supervision>,
+    /// Restart after a panic requested by `handle_panic` (the initial context is rebuilt at the next context switch)
+    restart: bool,
+    /// Blocked by `task::park` (only such a block is ended by `task::unpark`)
+    parked: bool,
+    /// Set by `task::unpark` while the task is not parked, and consumed by the next `task::park`
+    unpark_token: bool,
+    /// Time of the timer registration which wakes the task (other registrations of the task are stale)
+    timeout: Option<u64>,
+    /// Stack allocated by `spawn_heap` (freed after the task is removed)
+    #[cfg(feature = "alloc")]
+    heap_stack: Option<HeapStack>,
+    /// Bytes allocated minus bytes freed by the task through `heap::TaskAlloc`
+    #[cfg(feature = "heap-task-stats")]
+    heap_balance: isize,
 }
 
-#[derive(Clone, Debug)]
+impl TaskInfo {
+    fn name(&self) -> Option<&'static str> {
+        #[cfg(not(feature = "profile-tiny"))]
+        let name = self.name;
+        #[cfg(feature = "profile-tiny")]
+        let name = None;
+        name
+    }
+}
+
+#[derive(Debug)]
 struct SchedulerState {
-    tasks: FnvIndexMap<usize, TaskInfo, MAX_NUM_TASKS>,
+    tasks: &'static mut LinearMapView<usize, TaskInfo>,
     last_task_id: usize,
+    /// Ready queue of each core
+    run_queues: PerCore<RunQueue>,
+    /// Running task of each core
+    current_task: PerCore<usize>,
+    /// Set by `Scheduler::init` when the storage is supplied
+    initialized: bool,
+    started: bool,
+    /// Heap-allocated stacks of removed tasks, waiting to be freed (with the task IDs)
+    #[cfg(feature = "alloc")]
+    released_stacks: heapless::Vec<(usize, HeapStack), MAX_NUM_TASKS>,
+}
+
+impl SchedulerState {
+    /// State before `Scheduler::init`, which has no tasks and refers to the placeholder storage
+    const fn new(
+        tasks: &'static mut LinearMap<usize, TaskInfo, 0>,
+        run_queues: &'static mut [[Deque<usize, 1>; MAX_PRIORITY + 1]; NUM_CORES],
+    ) -> Self {
+        #[cfg(not(feature = "smp"))]
+        let [queues0] = run_queues;
+        #[cfg(not(feature = "smp"))]
+        let run_queues = [RunQueue::empty(queues0)];
+        #[cfg(feature = "smp")]
+        let [queues0, queues1] = run_queues;
+        #[cfg(feature = "smp")]
+        let run_queues = [RunQueue::empty(queues0), RunQueue::empty(queues1)];
+
+        let mut current_task = [0; NUM_CORES];
+        let mut core = 0;
+        while core < NUM_CORES {
+            current_task[core] = IDLE_TASK_ID + core;
+            core += 1;
+        }
+
+        Self {
+            tasks,
+            last_task_id: IDLE_TASK_ID + NUM_CORES - 1,
+            run_queues: PerCore::from_array(run_queues),
+            current_task: PerCore::from_array(current_task),
+            initialized: false,
+            started: false,
+            #[cfg(feature = "alloc")]
+            released_stacks: heapless::Vec::new(),
+        }
+    }
+}
+
+/// Ready tasks assigned to a core.
+#[derive(Debug)]
+struct RunQueue {
     /// Task queues for each priority
-    queues: [Deque<usize, QUEUE_LEN>; MAX_PRIORITY + 1],
+    queues: [&'static mut DequeView<usize>; MAX_PRIORITY + 1],
     /// Bit map for finding highest priority of runnable tasks
-    /// `(priority_map & (1 << n)) != 0` when a task with priority n is present
-    priority_map: u32,
-    current_task: usize,
-    started: bool,
+    /// `priority_map.contains(n)` when a task with priority n is present
+    priority_map: PriorityMap,
 }
 
+/// Number of words of [`PriorityMap`]
+const PRIORITY_MAP_WORDS: usize = (MAX_PRIORITY + 1).div_ceil(32);
+
+/// Set of priorities, one bit per priority in as many words as `MAX_PRIORITY` requires.
+#[derive(Clone, Copy, Debug)]
+struct PriorityMap([u32; PRIORITY_MAP_WORDS]);
+
 #[derive(Clone, Debug)]
 #[non_exhaustive]
 pub struct SchedulerConfig {
     pub tick_freq: u32,
+    /// Number of words filled with the canary pattern at the bottom of each stack
+    pub stack_canary_len: usize,
+    pub stack_canary_pattern: u32,
+    /// Whether the stack canary of the running task is also checked on every tick
+    pub check_stack_on_tick: bool,
+    /// Whether tasks have the stack canary unless set otherwise by `TaskConfig::with_stack_canary`
+    pub stack_canary_by_default: bool,
+    /// Length of a window of the CPU load statistics (in ticks, 0 means one second)
+    pub cpu_load_window: u32,
+    /// Whether ticks are injected by `test_advance_ticks` instead of the timer of the port
+    pub manual_tick: bool,
 }
 
 impl SchedulerConfig {
     pub fn with_tick_freq(self, tick_freq: u32) -> Self {
         Self { tick_freq, ..self }
     }
+
+    /// Sets the size (in 32-bit words) of the stack canary. Only meaningful with the `stack-canary` feature.
+    pub fn with_stack_canary_len(self, stack_canary_len: usize) -> Self {
+        Self {
+            stack_canary_len,
+            ..self
+        }
+    }
+
+    /// Sets the pattern written into the stack canary. Only meaningful with the `stack-canary` feature.
+    pub fn with_stack_canary_pattern(self, stack_canary_pattern: u32) -> Self {
+        Self {
+            stack_canary_pattern,
+            ..self
+        }
+    }
+
+    /// Enables checking the stack canary of the running task on every tick, in addition to every context switch.
+    ///
+    /// This catches an overflow in a task which is never switched out (e.g. the highest-priority task that never blocks).
+    /// Only meaningful with the `stack-canary` feature.
+    pub fn with_check_stack_on_tick(self, check_stack_on_tick: bool) -> Self {
+        Self {
+            check_stack_on_tick,
+            ..self
+        }
+    }
+
+    /// Sets whether tasks have the stack canary unless set otherwise by `TaskConfig::with_stack_canary`.
+    /// Default is `true`, and idle tasks always have it. Only meaningful with the `stack-canary` feature.
+    ///
+    /// Disabling it protects only the tasks which opt in, e.g. when most tasks are latency-critical.
+    pub fn with_stack_canary_by_default(self, stack_canary_by_default: bool) -> Self {
+        Self {
+            stack_canary_by_default,
+            ..self
+        }
+    }
+
+    /// Sets the length (in ticks) of a window over which the CPU load is measured. Default is one second.
+    /// Only meaningful with the `cpu-load` feature.
+    pub fn with_cpu_load_window(self, cpu_load_window: u32) -> Self {
+        Self {
+            cpu_load_window,
+            ..self
+        }
+    }
+
+    /// Disables the timer of the port, so that time advances only with [`test_advance_ticks`].
+    /// Only meaningful with the `test-mode` feature.
+    pub fn with_manual_tick(self, manual_tick: bool) -> Self {
+        Self {
+            manual_tick,
+            ..self
+        }
+    }
 }
 
 impl Default for SchedulerConfig {
     fn default() -> Self {
-        Self { tick_freq: 1000 }
+        Self {
+            tick_freq: 1000,
+            stack_canary_len: 4,
+            stack_canary_pattern: 0xABCD1234,
+            check_stack_on_tick: false,
+            stack_canary_by_default: true,
+            cpu_load_window: 0,
+            manual_tick: false,
+        }
     }
 }
 
@@ -82,55 +381,131 @@ impl Scheduler {
     /// Marked unsafe because it uses MCU core peripherals (such as an interrupt controller) without HAL peripheral objects,
     /// so architecture-specific wrappers (such as `taskette_cortex_m::init_scheduler`) should be used instead.
     pub unsafe fn init(clock_freq: u32, config: SchedulerConfig) -> Option<Self> {
-        critical_section::with(|cs| SCHEDULER_CONFIG.replace(cs, Some(config)));
+        if DEFAULT_STORAGE_TAKEN.swap(true, Ordering::SeqCst) {
+            return None;
+        }
+        // Only one reference is created thanks to the flag above
+        let storage = unsafe { &mut *core::ptr::addr_of_mut!(DEFAULT_STORAGE) };
+
+        let scheduler = unsafe { Self::init_with_storage(clock_freq, config, storage) };
+        if scheduler.is_none() {
+            // The storage is not kept on failure, so a later call may take it again
+            DEFAULT_STORAGE_TAKEN.store(false, Ordering::SeqCst);
+        }
+
+        scheduler
+    }
 
-        let Some(idle_task_stack) = (unsafe { arch::_taskette_get_idle_task_stack() }) else {
+    /// Initializes the scheduler using the memory supplied by the application for the task table and queues.
+    ///
+    /// See [`SchedulerStorage`] for the meaning of `TASKS` and `TIMERS`.
+    ///
+    /// # Safety
+    /// Same as [`Scheduler::init`]: architecture-specific wrappers should be used instead.
+    pub unsafe fn init_with_storage<const TASKS: usize, const TIMERS: usize>(
+        clock_freq: u32,
+        config: SchedulerConfig,
+        storage: &'static mut SchedulerStorage<TASKS, TIMERS>,
+    ) -> Option<Self> {
+        if TASKS < NUM_CORES {
+            // No room for idle tasks
             return None;
-        };
-        let idle_task_stack_start = idle_task_stack.as_mut_ptr_range().start;
-        let idle_task_stack_end = idle_task_stack.as_mut_ptr_range().end;
+        }
 
-        #[cfg(feature = "stack-canary")]
-        unsafe {
-            fill_stack_canary(idle_task_stack_start as *mut u32);
+        interrupt_free(|cs| SCHEDULER_CONFIG.replace(cs, Some(config)));
+
+        let mut idle_task_stacks = [(core::ptr::null_mut(), core::ptr::null_mut()); NUM_CORES];
+        for (core, range) in idle_task_stacks.iter_mut().enumerate() {
+            let idle_task_stack = unsafe { arch::_taskette_get_idle_task_stack(core) }?;
+            *range = (
+                idle_task_stack.as_mut_ptr_range().start,
+                idle_task_stack.as_mut_ptr_range().end,
+            );
+
+            #[cfg(feature = "stack-canary")]
+            unsafe {
+                fill_stack_canary(range.0 as *mut u32, stack_canary().ok()?);
+            }
         }
+        let (idle_task_stack_start, idle_task_stack_end) = idle_task_stacks[0];
+
+        #[cfg(feature = "smp")]
+        interrupt_free(|cs| {
+            SECONDARY_IDLE_STACKS.replace(
+                cs,
+                PerCore::from_array(
+                    idle_task_stacks.map(|(start, end)| (start as usize, end as usize)),
+                ),
+            )
+        });
 
-        if !critical_section::with(|cs| {
+        if !interrupt_free(|cs| {
             let mut scheduler_state = SCHEDULER_STATE.borrow_ref_mut(cs);
-            if scheduler_state.is_some() {
+            if scheduler_state.initialized {
                 // Scheduler is already initialized
                 false
             } else {
-                let mut tasks = FnvIndexMap::new();
-                // Reserve Task #0 for idle task
-                tasks
-                    .insert(
-                        IDLE_TASK_ID,
-                        TaskInfo {
-                            stack_pointer: 0,
-                            priority: IDLE_PRIORITY,
-                            blocked: false,
-                            #[cfg(feature = "stack-canary")]
-                            stack_limit: idle_task_stack_start as usize,
-                        },
-                    )
-                    .unwrap_or_else(|_| unreachable!());
-                // Idle task has priority 0
-                let mut queues = [const { Deque::new() }; MAX_PRIORITY + 1];
-                queues[IDLE_PRIORITY]
-                    .push_back(IDLE_TASK_ID)
-                    .unwrap_or_else(|_| unreachable!());
-
-                *scheduler_state = Some(SchedulerState {
+                let SchedulerStorage {
                     tasks,
-                    last_task_id: IDLE_TASK_ID,
-                    queues,
-                    priority_map: 0b1, // Indicates the idle task (priority 0) is present
-                    current_task: IDLE_TASK_ID,
-                    started: false,
-                });
+                    run_queues,
+                    timers,
+                } = storage;
+                let tasks = tasks.as_mut_view();
+                let run_queues = PerCore::from_array(run_queues.each_mut().map(RunQueue::new));
+                // Reserve Task #0 (and following IDs on SMP) for idle tasks
+                for (core, stack) in idle_task_stacks.iter().enumerate() {
+                    tasks
+                        .insert(
+                            IDLE_TASK_ID + core,
+                            TaskInfo {
+                                stack_pointer: 0,
+                                priority: IDLE_PRIORITY,
+                                blocked: false,
+                                affinity: Some(core),
+                                core,
+                                stack_limit: stack.0 as usize,
+                                stack_end: stack.1 as usize,
+                                #[cfg(feature = "stack-canary")]
+                                stack_canary: true,
+                                #[cfg(not(feature = "profile-tiny"))]
+                                name: Some("idle"),
+                                #[cfg(feature = "cpu-load")]
+                                run_time: 0,
+                                panic_hook: None,
+                                supervision: None,
+                                restart: false,
+                                parked: false,
+                                unpark_token: false,
+                                timeout: None,
+                                #[cfg(feature = "alloc")]
+                                heap_stack: None,
+                                #[cfg(feature = "heap-task-stats")]
+                                heap_balance: 0,
+                            },
+                        )
+                        .unwrap_or_else(|_| unreachable!());
+                    // Idle task is not enqueued, as it is running until the first context switch
+
+                    #[cfg(feature = "rtos-awareness")]
+                    {
+                        rtos_awareness::add(
+                            cs,
+                            IDLE_TASK_ID + core,
+                            IDLE_PRIORITY,
+                            Some("idle"),
+                            0,
+                            stack.0 as usize..stack.1 as usize,
+                            core,
+                        );
+                        rtos_awareness::switch_in(cs, core, IDLE_TASK_ID + core);
+                    }
+                }
 
-                timer::init();
+                scheduler_state.tasks = tasks;
+                scheduler_state.run_queues = run_queues;
+                scheduler_state.initialized = true;
+
+                timer::init(timers.as_mut_view());
 
                 true
             }
@@ -148,38 +523,26 @@ impl Scheduler {
 
     /// Starts the scheduler and tasks.
     pub fn start(&self) -> ! {
-        let tick_freq = critical_section::with(|cs| {
-            SCHEDULER_CONFIG.borrow_ref(cs).as_ref().unwrap().tick_freq
-        });
+        let tick_freq =
+            interrupt_free(|cs| SCHEDULER_CONFIG.borrow_ref(cs).as_ref().unwrap().tick_freq);
 
+        // Before the setup, which may start the other cores
+        arch::tick_source().set_frequency(self.clock_freq, tick_freq);
         unsafe {
-            arch::_taskette_setup(self.clock_freq, tick_freq);
+            arch::_taskette_setup();
         }
 
-        critical_section::with(|cs| {
-            let mut state = SCHEDULER_STATE.borrow_ref_mut(cs);
-            if let Some(state) = state.as_mut() {
-                state.started = true;
-            }
+        interrupt_free(|cs| {
+            SCHEDULER_STATE.borrow_ref_mut(cs).started = true;
         });
 
-        let idle_task_fp: fn() -> ! = || {
-            unsafe {
-                arch::_taskette_start_timer();
-            }
+        CURRENT_STACK_LIMIT
+            .get()
+            .store(self.idle_task_stack_start as usize, Ordering::Relaxed);
 
-            info!("Kernel started");
-
-            loop {
-                trace!("Idle");
-                unsafe {
-                    arch::_taskette_wait_for_interrupt();
-                }
-            }
-        };
         unsafe {
             arch::_taskette_run_with_stack(
-                idle_task_fp as usize,
+                idle_task as fn() -> ! as usize,
                 self.idle_task_stack_end,
                 self.idle_task_stack_start,
             );
@@ -187,10 +550,53 @@ impl Scheduler {
     }
 }
 
+/// INTERNAL USE ONLY
+///
+/// Starts the idle task of a secondary core. Called by the architecture-specific crate on that core.
+#[cfg(feature = "smp")]
+pub fn start_secondary_core() -> ! {
+    let (start, end) = interrupt_free(|cs| *SECONDARY_IDLE_STACKS.borrow_ref(cs).get());
+    CURRENT_STACK_LIMIT.get().store(start, Ordering::Relaxed);
+
+    unsafe {
+        arch::_taskette_run_with_stack(
+            idle_task as fn() -> ! as usize,
+            end as *mut u8,
+            start as *mut u8,
+        );
+    }
+}
+
+fn idle_task() -> ! {
+    #[cfg(feature = "test-mode")]
+    let manual_tick = get_config().is_ok_and(|config| config.manual_tick);
+    #[cfg(not(feature = "test-mode"))]
+    let manual_tick = false;
+
+    if manual_tick {
+        // No tick triggers the first context switch, so tasks spawned before start are dispatched here
+        yield_now();
+    } else {
+        arch::tick_source().start();
+    }
+
+    info!("Kernel started on core {}", arch::core_id());
+
+    loop {
+        trace!("Idle");
+
+        #[cfg(feature = "alloc")]
+        free_released_stacks();
+
+        unsafe {
+            arch::_taskette_wait_for_interrupt();
+        }
+    }
+}
+
 /// Retrieves configuration of the scheduler.
 pub fn get_config() -> Result<SchedulerConfig, Error> {
-    critical_section::with(|cs| SCHEDULER_CONFIG.borrow_ref(cs).clone())
-        .ok_or(Error::NotInitialized)
+    interrupt_free(|cs| SCHEDULER_CONFIG.borrow_ref(cs).clone()).ok_or(Error::NotInitialized)
 }
 
 /// Creates a new task and starts it.
@@ -198,217 +604,1080 @@ pub fn spawn<F: FnOnce() + Send + 'static, S: StackAllocation>(
     func: F,
     stack: S,
     config: TaskConfig,
+) -> Result<TaskHandle, Error> {
+    spawn_inner(
+        func,
+        stack,
+        config,
+        None,
+        None,
+        #[cfg(feature = "alloc")]
+        None,
+    )
+}
+
+/// Creates a task with the ID of a finished task (see `TaskHandle::respawn`).
+pub(crate) fn respawn_inner<S: StackAllocation>(
+    entry: fn(),
+    stack: S,
+    config: TaskConfig,
+    id: usize,
+) -> Result<TaskHandle, Error> {
+    spawn_inner(
+        entry,
+        stack,
+        config,
+        Some(id),
+        None,
+        #[cfg(feature = "alloc")]
+        None,
+    )
+}
+
+pub(crate) fn spawn_supervised_inner<S: StackAllocation>(
+    entry: fn(),
+    stack: S,
+    config: TaskConfig,
+    policy: RestartPolicy,
+    backoff: u64,
+) -> Result<TaskHandle, Error> {
+    let mut stack = stack;
+    let supervision = Supervision {
+        entry,
+        policy,
+        backoff,
+        stack_end: stack.as_mut_slice().as_mut_ptr_range().end as usize,
+    };
+
+    spawn_inner(
+        move || supervisor::run(supervision, false),
+        stack,
+        config,
+        None,
+        Some(supervision),
+        #[cfg(feature = "alloc")]
+        None,
+    )
+}
+
+/// Creates a new task with a stack of `stack_size` bytes allocated from the global allocator, and starts it.
+///
+/// The stack is freed after the task finishes.
+#[cfg(feature = "alloc")]
+pub fn spawn_heap<F: FnOnce() + Send + 'static>(
+    func: F,
+    stack_size: usize,
+    config: TaskConfig,
+) -> Result<TaskHandle, Error> {
+    // Reuse memory of finished tasks if possible
+    free_released_stacks();
+
+    let heap_stack = HeapStack::alloc(stack_size)?;
+    let result = spawn_inner(func, heap_stack, config, None, None, Some(heap_stack));
+    if result.is_err() {
+        unsafe {
+            heap_stack.free();
+        }
+    }
+
+    result
+}
+
+/// Creates a task. `id` is given only when respawning a finished task with the same ID.
+fn spawn_inner<F: FnOnce() + Send + 'static, S: StackAllocation>(
+    func: F,
+    mut stack: S,
+    config: TaskConfig,
+    id: Option<usize>,
+    supervision: Option<Supervision>,
+    #[cfg(feature = "alloc")] heap_stack: Option<HeapStack>,
 ) -> Result<TaskHandle, Error> {
     if config.priority > MAX_PRIORITY {
         return Err(Error::InvalidPriority);
     }
+    if config.affinity.is_some_and(|core| core >= NUM_CORES) {
+        return Err(Error::InvalidAffinity);
+    }
+
+    // The closure and the initial context are written at the end of the stack, and the canary at the bottom
+    #[cfg(feature = "stack-canary")]
+    let canary = if config
+        .stack_canary
+        .unwrap_or(get_config()?.stack_canary_by_default)
+    {
+        Some(stack_canary()?)
+    } else {
+        None
+    };
+    #[cfg(feature = "stack-canary")]
+    let canary_size = canary.map_or(0, |(len, _)| len * core::mem::size_of::<u32>());
+    #[cfg(not(feature = "stack-canary"))]
+    let canary_size = 0;
+    let required = core::mem::size_of::<Option<F>>() + MIN_STACK_SIZE + canary_size;
+    if stack.as_mut_slice().len() < required {
+        return Err(Error::StackTooSmall);
+    }
 
+    // Wrapped only after the checks above so that the stack is dropped on those errors
     // TODO: drop when task finished
     let mut stack = ManuallyDrop::new(stack);
 
     // Fill the bottom of the stack with the canary pattern
     #[cfg(feature = "stack-canary")]
-    unsafe {
-        fill_stack_canary(stack.as_mut_slice().as_mut_ptr_range().start as *mut u32);
+    if let Some(canary) = canary {
+        unsafe {
+            fill_stack_canary(
+                stack.as_mut_slice().as_mut_ptr_range().start as *mut u32,
+                canary,
+            );
+        }
     }
 
     // Prepare initial stack of the task
-    let initial_sp = unsafe {
-        let arg1 = Some(func);
-        let sp = arch::_taskette_init_stack(
-            stack.as_mut_slice().as_mut_ptr_range().end,
-            (call_closure as extern "C" fn(&mut Option<F>) -> !) as usize,
-            &arg1 as *const _ as *const u8,
-            core::mem::size_of_val(&arg1),
-        );
-
-        sp
-    };
+    let initial_sp = unsafe { init_task_stack(stack.as_mut_slice().as_mut_ptr_range().end, func) };
+    #[cfg(feature = "rtos-awareness")]
+    let stack_range = stack.as_mut_slice().as_ptr_range();
 
-    let task_id = critical_section::with(|cs| {
+    let task_id = kernel_section(|cs| {
         let mut state = SCHEDULER_STATE.borrow_ref_mut(cs);
-        let Some(state) = state.as_mut() else {
+        if !state.initialized {
             return Err(Error::NotInitialized);
-        };
+        }
 
+        // A task without affinity starts on the spawning core
+        let core = config.affinity.unwrap_or_else(arch::core_id);
         let task = TaskInfo {
-            stack_pointer: initial_sp as usize,
+            stack_pointer: initial_sp,
             priority: config.priority,
             blocked: false,
-            #[cfg(feature = "stack-canary")]
+            affinity: config.affinity,
+            core,
             stack_limit: stack.as_mut_slice().as_ptr() as usize,
+            stack_end: stack.as_mut_slice().as_ptr_range().end as usize,
+            #[cfg(feature = "stack-canary")]
+            stack_canary: canary.is_some(),
+            #[cfg(not(feature = "profile-tiny"))]
+            name: config.name,
+            #[cfg(feature = "cpu-load")]
+            run_time: 0,
+            panic_hook: config.panic_hook,
+            supervision,
+            restart: false,
+            parked: false,
+            unpark_token: false,
+            timeout: None,
+            #[cfg(feature = "alloc")]
+            heap_stack,
+            #[cfg(feature = "heap-task-stats")]
+            heap_balance: 0,
         };
 
-        let task_id = state.last_task_id.wrapping_add(1);
-        let task_id = if task_id == IDLE_TASK_ID {
-            task_id.wrapping_add(1)
+        let task_id = if let Some(id) = id {
+            if state.tasks.contains_key(&id) {
+                return Err(Error::NotPermitted);
+            }
+            id
         } else {
+            let task_id = state.last_task_id.wrapping_add(1);
+            // Skip IDs of idle tasks
+            let task_id = if task_id < IDLE_TASK_ID + NUM_CORES {
+                IDLE_TASK_ID + NUM_CORES
+            } else {
+                task_id
+            };
+            state.last_task_id = task_id;
             task_id
         };
-        state.last_task_id = task_id;
 
         state.tasks.insert(task_id, task).or(Err(Error::TaskFull))?;
 
-        enqueue_task(
-            &mut state.queues,
-            &mut state.priority_map,
+        state.run_queues[core].push(task_id, config.priority)?;
+
+        #[cfg(feature = "trace-hooks")]
+        crate::trace::create(cs, task_id, config.priority);
+        #[cfg(feature = "defmt-events")]
+        events::task(cs, task_id, config.priority, TaskState::Created);
+        #[cfg(feature = "rtos-awareness")]
+        rtos_awareness::add(
+            cs,
             task_id,
             config.priority,
-        )?;
+            config.name,
+            initial_sp,
+            stack_range.start as usize..stack_range.end as usize,
+            core,
+        );
 
         Ok(task_id)
     })?;
 
     info!("Task #{} created (priority {})", task_id, config.priority);
+    #[cfg(any(feature = "log", feature = "defmt"))]
+    let stack_range = stack.as_mut_slice().as_ptr_range();
     debug!(
         "Stack from={:08X} to={:08X}",
-        stack.as_mut_slice().as_ptr_range().start as usize,
-        stack.as_mut_slice().as_ptr_range().end as usize
+        stack_range.start as usize, stack_range.end as usize
     );
 
-    let scheduler_started = critical_section::with(|cs| {
-        if let Some(state) = SCHEDULER_STATE.borrow_ref(cs).as_ref() {
-            state.started
-        } else {
-            false
-        }
-    });
+    let scheduler_started = interrupt_free(|cs| SCHEDULER_STATE.borrow_ref(cs).started);
 
     if scheduler_started {
-        yield_now(); // Preempt if the new task has higher priority
+        request_reschedule(config.affinity); // Preempt if the new task has higher priority
     }
 
     Ok(TaskHandle { id: task_id })
 }
 
-/// INTERNAL USE ONLY
-pub fn handle_tick() {
-    trace!("tick handler");
-
-    timer::tick();
+/// Builds the initial context of a task running `func` at the end of a stack. Returns the initial stack pointer.
+unsafe fn init_task_stack<F: FnOnce() + Send + 'static>(stack_end: *mut u8, func: F) -> usize {
+    let arg1 = Some(func);
+    let sp = unsafe {
+        arch::_taskette_init_stack(
+            stack_end,
+            (call_closure as extern "C" fn(&mut Option<F>) -> !) as usize,
+            &arg1 as *const _ as *const u8,
+            core::mem::size_of_val(&arg1),
+        )
+    };
 
-    #[cfg(feature = "round-robin")]
-    yield_now();
+    sp as usize
 }
 
-/// INTERNAL USE ONLY
-pub unsafe extern "C" fn select_task(orig_sp: usize) -> usize {
-    // Check stack overflow
-    let next_sp = critical_section::with(|cs| {
-        let mut state = SCHEDULER_STATE.borrow_ref_mut(cs);
-        let Some(state) = state.as_mut() else {
-            panic!("Scheduler not initialized")
-        };
-
-        let orig_task_id = state.current_task;
-        // Original task may be removed from the task list, so this is conditional
-        if let Some(orig_task) = state.tasks.get_mut(&orig_task_id) {
-            if !orig_task.blocked {
-                #[cfg(feature = "stack-canary")]
-                unsafe {
-                    check_stack_canary(orig_task.stack_limit as *const u32, orig_task_id);
-                }
-
-                // Enqueue the original task into the queue of the original priority
-                // (Placed afte the dequeue in order to avoid overflow)
-                enqueue_task(
-                    &mut state.queues,
-                    &mut state.priority_map,
-                    orig_task_id,
-                    orig_task.priority,
-                )
-                .unwrap_or_else(|_| unreachable!());
-            }
+/// Returns the CPU load in the last completed window (in percent, averaged over the cores).
+#[cfg(feature = "cpu-load")]
+pub fn cpu_load_percent() -> Result<u8, Error> {
+    get_config()?;
 
-            // Update stack pointer
-            orig_task.stack_pointer = orig_sp;
-        }
+    interrupt_free(|cs| {
+        let sum: u32 = (0..NUM_CORES)
+            .map(|core| cpu_load::stats(cs, core).last as u32)
+            .sum();
+        Ok((sum / NUM_CORES as u32) as u8)
+    })
+}
 
-        // Determine the highest priority of runnable tasks
-        const { assert!(MAX_PRIORITY <= 31) }
-        let highest_priority = (31 - state.priority_map.leading_zeros()) as usize;
+/// Returns the statistics of the CPU load of `core` over the completed windows.
+#[cfg(feature = "cpu-load")]
+pub fn cpu_load_stats(core: usize) -> Result<CpuLoadStats, Error> {
+    get_config()?;
+    if core >= NUM_CORES {
+        return Err(Error::InvalidAffinity);
+    }
 
-        // Dequeue the new task ID from the queue of the highest priority
-        let Some(next_task_id) =
-            dequeue_task(&mut state.queues, &mut state.priority_map, highest_priority)
-        else {
-            unreachable!()
-        };
-        state.current_task = next_task_id;
+    Ok(interrupt_free(|cs| cpu_load::stats(cs, core)))
+}
 
-        let Some(next_task) = state.tasks.get(&next_task_id) else {
-            unreachable!()
-        };
-        next_task.stack_pointer
-    });
-    trace!(
-        "Context switch: orig_sp = {:08X}, next_sp = {:08X}",
-        orig_sp, next_sp
-    );
-    next_sp
+/// Clears the statistics of the CPU load of all cores.
+#[cfg(feature = "cpu-load")]
+pub fn reset_cpu_load_stats() {
+    interrupt_free(cpu_load::reset);
 }
 
-pub(crate) fn block_task(id: usize) -> Result<(), Error> {
-    critical_section::with(|cs| {
-        let mut state = SCHEDULER_STATE.borrow_ref_mut(cs);
-        let Some(state) = state.as_mut() else {
-            return Err(Error::NotInitialized);
-        };
+/// Returns a snapshot of the kernel statistics counters (and the latency measurements with the `latency` feature).
+#[cfg(feature = "stats")]
+pub fn stats() -> SchedulerStats {
+    stats::snapshot()
+}
 
-        let Some(task) = state.tasks.get_mut(&id) else {
-            return Err(Error::NotFound);
-        };
+/// Clears the kernel statistics counters.
+#[cfg(feature = "stats")]
+pub fn reset_stats() {
+    stats::reset();
+}
 
-        if task.blocked {
-            debug!("Task #{} is already blocked", id);
-            return Ok(());
+/// Writes a table of the tasks (ID, name, state, priority, stack usage, and share of CPU time) into `writer`.
+///
+/// Stack usage is measured at the last context switch of each task (shown as `-` if unknown),
+/// and the share of CPU time is only available with the `cpu-load` feature.
+pub fn dump_tasks<W: fmt::Write>(writer: &mut W) -> fmt::Result {
+    writeln!(
+        writer,
+        "{:>4} {:<16} {:<7} {:>4} {:>13} {:>4}",
+        "ID", "NAME", "STATE", "PRIO", "STACK", "CPU"
+    )?;
+
+    for task in task_summaries() {
+        write!(
+            writer,
+            "{:>4} {:<16} {:<7} {:>4} ",
+            task.id,
+            task.name.unwrap_or("-"),
+            task.state,
+            task.priority
+        )?;
+        match task.stack_used {
+            Some(used) => write!(writer, "{:>6}/{:<6}", used, task.stack_size)?,
+            None => write!(writer, "{:>6}/{:<6}", "-", task.stack_size)?,
         }
+        match task.cpu_percent {
+            Some(percent) => writeln!(writer, " {:>3}%", percent)?,
+            None => writeln!(writer, " {:>4}", "-")?,
+        }
+    }
 
-        task.blocked = true;
-        // Remove the task from the task queue
-        remove_task_from_queue(
-            &mut state.queues,
-            &mut state.priority_map,
-            id,
+    Ok(())
+}
+
+/// Logs the same information as [`dump_tasks`] over defmt, one message per task.
+#[cfg(feature = "defmt")]
+pub fn dump_tasks_defmt() {
+    for task in task_summaries() {
+        defmt::info!(
+            "task id={=usize} name={=str} state={=str} prio={=usize} stack={}/{=usize} cpu={}",
+            task.id,
+            task.name.unwrap_or("-"),
+            task.state,
             task.priority,
+            task.stack_used,
+            task.stack_size,
+            task.cpu_percent
         );
+    }
+}
 
-        trace!("Task #{} became blocked", id);
-
-        yield_now();
+/// Snapshot of a task, as listed by [`dump_tasks`] and [`for_each_task`].
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct TaskSummary {
+    pub id: usize,
+    pub name: Option<&'static str>,
+    /// `"Running"`, `"Ready"`, or `"Blocked"`
+    pub state: &'static str,
+    pub priority: usize,
+    /// Stack usage at the last context switch (in bytes)
+    pub stack_used: Option<usize>,
+    /// Size of the stack (in bytes)
+    pub stack_size: usize,
+    /// Share of the total run time (in percent, only with the `cpu-load` feature)
+    pub cpu_percent: Option<u8>,
+    /// Bytes allocated minus bytes freed by the task (only with the `heap-task-stats` feature).
+    /// Negative for a task freeing memory allocated by others (e.g. the receiver of messages).
+    pub heap_balance: Option<isize>,
+}
 
-        Ok(())
-    })?;
+/// Calls `f` for each task (including idle tasks).
+///
+/// The tasks are snapshotted at once before calling `f`, so `f` may call other functions of this crate.
+pub fn for_each_task<F: FnMut(&TaskSummary)>(mut f: F) {
+    for task in task_summaries() {
+        f(&task);
+    }
+}
 
-    Ok(())
+/// Whether the task `id` is the running task of a core (which is still the case for a while after it finishes).
+pub(crate) fn is_running_on_any_core(id: usize) -> bool {
+    interrupt_free(|cs| {
+        let state = SCHEDULER_STATE.borrow_ref(cs);
+        state.current_task.iter().any(|current| *current == id)
+    })
 }
 
-pub(crate) fn unblock_task(id: usize) -> Result<(), Error> {
-    critical_section::with(|cs| {
-        let mut state = SCHEDULER_STATE.borrow_ref_mut(cs);
-        let Some(state) = state.as_mut() else {
-            return Err(Error::NotInitialized);
-        };
+/// State of the task with ID `id`. A task which does not exist is regarded as finished.
+pub(crate) fn task_state(id: usize) -> crate::task::TaskState {
+    use crate::task::TaskState;
 
-        let Some(task) = state.tasks.get_mut(&id) else {
-            return Err(Error::NotFound);
+    interrupt_free(|cs| {
+        let state = SCHEDULER_STATE.borrow_ref(cs);
+        let Some(task) = state.tasks.get(&id) else {
+            return TaskState::Finished;
         };
 
-        if !task.blocked {
-            debug!("Task #{} is not blocked", id);
-            return Ok(());
+        if (0..NUM_CORES).any(|core| state.current_task[core] == id) {
+            TaskState::Running
+        } else if task.blocked {
+            TaskState::Blocked
+        } else {
+            TaskState::Ready
         }
+    })
+}
 
-        task.blocked = false;
-        // Add task at the end of the task queue
-        enqueue_task(
-            &mut state.queues,
-            &mut state.priority_map,
-            id,
-            task.priority,
-        )?;
+/// Takes a snapshot of the tasks (up to `MAX_NUM_TASKS`).
+fn task_summaries() -> Vec<TaskSummary, MAX_NUM_TASKS> {
+    interrupt_free(|cs| {
+        let mut summaries = Vec::new();
+        let state = SCHEDULER_STATE.borrow_ref(cs);
 
-        trace!("Task #{} is unblocked", id);
+        #[cfg(feature = "cpu-load")]
+        let total_run_time: u64 = state.tasks.values().map(|task| task.run_time).sum();
+
+        for (&id, task) in state.tasks.iter() {
+            let running = (0..NUM_CORES).any(|core| state.current_task[core] == id);
+            let summary = TaskSummary {
+                id,
+                name: task.name(),
+                state: if running {
+                    "Running"
+                } else if task.blocked {
+                    "Blocked"
+                } else {
+                    "Ready"
+                },
+                priority: task.priority,
+                // The saved stack pointer is not valid before the first switch of an idle task
+                stack_used: (task.stack_limit..=task.stack_end)
+                    .contains(&task.stack_pointer)
+                    .then(|| task.stack_end - task.stack_pointer),
+                stack_size: task.stack_end - task.stack_limit,
+                #[cfg(feature = "cpu-load")]
+                cpu_percent: (task.run_time * 100)
+                    .checked_div(total_run_time)
+                    .map(|percent| percent as u8),
+                #[cfg(not(feature = "cpu-load"))]
+                cpu_percent: None,
+                #[cfg(feature = "heap-task-stats")]
+                heap_balance: Some(task.heap_balance),
+                #[cfg(not(feature = "heap-task-stats"))]
+                heap_balance: None,
+            };
+            if summaries.push(summary).is_err() {
+                break;
+            }
+        }
+
+        summaries
+    })
+}
+
+/// Advances the time by `ticks`, as if the tick interrupt occurred `ticks` times (`test-mode` feature).
+///
+/// Intended for deterministic tests with [`SchedulerConfig::with_manual_tick`], and has to be called on core 0.
+/// The context switch requested by each tick is performed before the next tick (synchronously on the hosted port),
+/// so tasks woken up with a higher priority than the caller run before this returns.
+#[cfg(feature = "test-mode")]
+pub fn test_advance_ticks(ticks: u64) -> Result<(), Error> {
+    get_config()?;
+
+    for _ in 0..ticks {
+        // Like an interrupt, the tick handler runs with interrupts disabled and the switch is taken after it
+        interrupt_free(|_| handle_tick());
+    }
+
+    Ok(())
+}
+
+/// INTERNAL USE ONLY
+pub fn handle_tick() {
+    trace!("tick handler");
+
+    #[cfg(feature = "latency")]
+    let start = stats::timestamp();
+    #[cfg(feature = "latency")]
+    let record_latency = || interrupt_free(|cs| stats::record(cs, Latency::TickHandler, start));
+
+    #[cfg(feature = "stats")]
+    stats::count(&stats::TICKS);
+
+    #[cfg(feature = "trace-hooks")]
+    crate::trace::tick();
+
+    // Time is managed by the first core only
+    if arch::core_id() == 0 {
+        timer::tick();
+        watchdog::tick();
+    }
+
+    #[cfg(feature = "cpu-load")]
+    if let Ok(config) = get_config() {
+        let window = if config.cpu_load_window == 0 {
+            config.tick_freq
+        } else {
+            config.cpu_load_window
+        };
+        interrupt_free(|cs| {
+            let core = arch::core_id();
+            let mut state = SCHEDULER_STATE.borrow_ref_mut(cs);
+            let task_id = state.current_task[core];
+            let run_time = cpu_load::tick(cs, core, task_id == IDLE_TASK_ID + core, window);
+            if let Some(task) = state.tasks.get_mut(&task_id) {
+                task.run_time += run_time;
+            }
+        });
+    }
+
+    #[cfg(feature = "stack-canary")]
+    if get_config().is_ok_and(|config| config.check_stack_on_tick) {
+        let overflowed_task = interrupt_free(|cs| {
+            let state = SCHEDULER_STATE.borrow_ref(cs);
+            let task_id = *state.current_task.get();
+            let task = state.tasks.get(&task_id)?;
+            let intact = !task.stack_canary
+                || unsafe {
+                    check_stack_canary(
+                        task.stack_limit as *const u32,
+                        stack_canary().unwrap_or_else(|_| unreachable!()),
+                    )
+                };
+            (!intact).then_some(task_id)
+        });
+
+        if let Some(task_id) = overflowed_task {
+            handle_stack_overflow(task_id);
+            #[cfg(feature = "latency")]
+            record_latency();
+            // Switch away from the removed task
+            yield_now();
+            return;
+        }
+    }
+
+    #[cfg(feature = "latency")]
+    record_latency();
+
+    #[cfg(feature = "round-robin")]
+    yield_now();
+}
+
+/// INTERNAL USE ONLY
+pub unsafe extern "C" fn select_task(orig_sp: usize) -> usize {
+    #[cfg(feature = "latency")]
+    let start = stats::timestamp();
+
+    let (next_sp, overflowed_task) = kernel_section(|cs| {
+        let mut state = SCHEDULER_STATE.borrow_ref_mut(cs);
+        let state = &mut *state;
+
+        let core = arch::core_id();
+        let orig_task_id = state.current_task[core];
+
+        // The running task keeps the CPU while it holds the preemption lock, unless it has blocked or been removed
+        if PREEMPTION_LOCKS[core].load(Ordering::SeqCst) > 0
+            && state
+                .tasks
+                .get(&orig_task_id)
+                .is_some_and(|task| !task.blocked && !task.restart)
+        {
+            SWITCH_DEFERRED[core].store(true, Ordering::SeqCst);
+            return (orig_sp, None);
+        }
+
+        #[cfg(feature = "cpu-load")]
+        let run_time = cpu_load::switch(cs, core, orig_task_id == IDLE_TASK_ID + core);
+        // Original task may be removed from the task list, so this is conditional
+        let mut overflowed_task = None;
+        if let Some(orig_task) = state.tasks.get_mut(&orig_task_id) {
+            // Check stack overflow (an overflowed task is not enqueued again)
+            #[cfg(feature = "stack-canary")]
+            let overflowed = orig_task.stack_canary
+                && !unsafe {
+                    check_stack_canary(
+                        orig_task.stack_limit as *const u32,
+                        stack_canary().unwrap_or_else(|_| unreachable!()),
+                    )
+                };
+            #[cfg(not(feature = "stack-canary"))]
+            let overflowed = false;
+
+            if overflowed {
+                overflowed_task = Some(orig_task_id);
+            }
+            // An overflowed idle task is still enqueued so that a task is always found (the overflow causes a panic anyway)
+            let is_idle_task = orig_task_id < IDLE_TASK_ID + NUM_CORES;
+            if !orig_task.blocked && (!overflowed || is_idle_task) {
+                // Enqueue the original task into the queue of the original priority
+                state.run_queues[core]
+                    .push(orig_task_id, orig_task.priority)
+                    .unwrap_or_else(|_| unreachable!());
+                orig_task.core = core;
+            }
+
+            #[cfg(feature = "cpu-load")]
+            {
+                orig_task.run_time += run_time;
+            }
+
+            // Update stack pointer (a task restarted after a panic starts over from a new initial context,
+            // which is only written now that the task no longer runs on its stack)
+            orig_task.stack_pointer = match orig_task.supervision {
+                Some(supervision) if core::mem::take(&mut orig_task.restart) => unsafe {
+                    init_task_stack(supervision.stack_end as *mut u8, move || {
+                        supervisor::run(supervision, true)
+                    })
+                },
+                _ => orig_sp,
+            };
+
+            #[cfg(feature = "rtos-awareness")]
+            rtos_awareness::switch_out(
+                cs,
+                orig_task_id,
+                orig_task.stack_pointer,
+                orig_task.blocked,
+            );
+        }
+
+        let next_task_id = dequeue_task(state, core);
+        state.current_task[core] = next_task_id;
+        #[cfg(feature = "task-log-context")]
+        crate::log_wrapper::set_current_task(
+            cs,
+            core,
+            next_task_id,
+            state.tasks.get(&next_task_id).and_then(TaskInfo::name),
+        );
+
+        #[cfg(feature = "stats")]
+        if next_task_id != orig_task_id {
+            stats::count(&stats::CONTEXT_SWITCHES);
+            // Switched away from a task still ready to run (other than the idle task)
+            if orig_task_id != IDLE_TASK_ID + core
+                && state
+                    .tasks
+                    .get(&orig_task_id)
+                    .is_some_and(|task| !task.blocked)
+            {
+                stats::count(&stats::PREEMPTIONS);
+            }
+        }
+
+        #[cfg(feature = "trace-hooks")]
+        crate::trace::switch(cs, orig_task_id, next_task_id);
+
+        #[cfg(feature = "smp")]
+        notify_idle_cores(state, core);
+
+        let Some(next_task) = state.tasks.get_mut(&next_task_id) else {
+            unreachable!()
+        };
+        next_task.core = core;
+        #[cfg(feature = "rtos-awareness")]
+        rtos_awareness::switch_in(cs, core, next_task_id);
+        #[cfg(feature = "defmt-events")]
+        if next_task_id != orig_task_id {
+            events::task(cs, next_task_id, next_task.priority, TaskState::Running);
+        }
+        CURRENT_STACK_LIMIT[core].store(next_task.stack_limit, Ordering::Relaxed);
+        #[cfg(feature = "latency")]
+        stats::record(cs, Latency::ContextSwitch, start);
+        (next_task.stack_pointer, overflowed_task)
+    });
+
+    // Handled after leaving the critical section, so that the hook can log or reset cleanly
+    if let Some(task_id) = overflowed_task {
+        handle_stack_overflow(task_id);
+    }
+
+    trace!(
+        "Context switch: orig_sp = {:08X}, next_sp = {:08X}",
+        orig_sp, next_sp
+    );
+    next_sp
+}
+
+/// INTERNAL USE ONLY
+///
+/// Returns the bottom of the stack of the task running (or selected by `select_task` to run next) on this core.
+/// Used by architectures with a hardware stack limit register.
+pub fn current_stack_limit() -> usize {
+    CURRENT_STACK_LIMIT.get().load(Ordering::Relaxed)
+}
+
+/// Registers a function called when a stack overflow of a task is detected.
+///
+/// The hook receives the ID of the overflowed task, which is already removed from the scheduler.
+/// It may log the event or reset the system. If it returns, the rest of the system keeps running without the task.
+/// Without a hook (or if the overflowed task is an idle task), a stack overflow causes a panic.
+///
+/// The hook is called from the context switch or an interrupt handler, but not inside a critical section.
+pub fn set_stack_overflow_hook(hook: StackOverflowHook) {
+    interrupt_free(|cs| STACK_OVERFLOW_HOOK.borrow(cs).set(Some(hook)));
+}
+
+/// INTERNAL USE ONLY
+///
+/// Removes the task `task_id` and calls the stack overflow hook.
+/// Also used by architectures with a hardware stack guard. The caller has to switch away from the task if it is running.
+pub fn handle_stack_overflow(task_id: usize) {
+    let hook = interrupt_free(|cs| STACK_OVERFLOW_HOOK.borrow(cs).get());
+    let is_idle_task = task_id < IDLE_TASK_ID + NUM_CORES;
+    let (Some(hook), false) = (hook, is_idle_task) else {
+        panic!("Stack overflow detected in Task #{}", task_id);
+    };
+
+    // The task may already be removed if the overflow is detected more than once
+    let _ = remove_task(task_id);
+    hook(task_id);
+}
+
+/// Registers a function called when a task is terminated because of a CPU fault
+/// (e.g. a memory protection violation or an illegal instruction).
+///
+/// Faults are only recovered by architectures which support it (see the documentation of each port).
+/// The hook receives the ID of the faulting task, which is already removed from the scheduler.
+/// Without a hook (or if the fault occurs in an idle task or outside of tasks), the fault handler of the port halts the system.
+pub fn set_task_fault_hook(hook: TaskFaultHook) {
+    interrupt_free(|cs| TASK_FAULT_HOOK.borrow(cs).set(Some(hook)));
+}
+
+/// INTERNAL USE ONLY
+///
+/// Terminates the running task of this core after a CPU fault, and returns whether the system can continue.
+/// Returns `false` without doing anything if no task fault hook is registered or the running task is an idle task.
+/// Otherwise a context switch is requested, which has to happen before the fault handler returns to the task.
+pub fn handle_task_fault() -> bool {
+    let hook = interrupt_free(|cs| TASK_FAULT_HOOK.borrow(cs).get());
+    let (Some(hook), Ok(task_id)) = (hook, current_task_id()) else {
+        return false;
+    };
+    if task_id < IDLE_TASK_ID + NUM_CORES {
+        return false;
+    }
+
+    if exit_current_task().is_err() {
+        return false;
+    }
+    hook(task_id);
+
+    true
+}
+
+/// Registers a function called by [`request_shutdown`] after the tasks have exited (e.g. a system reset).
+pub fn set_shutdown_hook(hook: ShutdownHook) {
+    interrupt_free(|cs| SHUTDOWN_HOOK.borrow(cs).set(Some(hook)));
+}
+
+/// Token cancelled when a shutdown is requested.
+///
+/// Long-running tasks pass it (or a child of it) to cancellable waits, and exit when they return `Error::Cancelled`.
+pub fn shutdown_token() -> &'static CancellationToken<'static> {
+    &SHUTDOWN
+}
+
+/// Whether [`request_shutdown`] has been called.
+pub fn is_shutdown_requested() -> bool {
+    SHUTDOWN.is_cancelled()
+}
+
+/// Winds down the system, e.g. before applying a firmware update.
+///
+/// Cancels [`shutdown_token`], and blocks the calling task until all other tasks (except idle tasks) exit
+/// or the time reaches `deadline` (in ticks). Supervised tasks are no longer restarted.
+/// Then the hook set by [`set_shutdown_hook`] is called, which does not return.
+/// Without a hook, this returns whether all other tasks have exited, leaving the rest to the caller.
+pub fn request_shutdown(deadline: u64) -> Result<bool, Error> {
+    info!("Shutdown requested");
+    SHUTDOWN.cancel();
+
+    let current = current_task_id()?;
+    let exited = loop {
+        // Read before counting the tasks, so that an exit in between makes the wait return immediately
+        let exits = TASK_EXITS.as_ref().load(Ordering::SeqCst);
+        let remaining = interrupt_free(|cs| {
+            let state = SCHEDULER_STATE.borrow_ref(cs);
+            state
+                .tasks
+                .keys()
+                .filter(|&&id| id >= IDLE_TASK_ID + NUM_CORES && id != current)
+                .count()
+        });
+        if remaining == 0 {
+            break true;
+        }
+
+        if !TASK_EXITS.wait_until(exits, deadline)? {
+            debug!(
+                "{} tasks did not exit before the shutdown deadline",
+                remaining
+            );
+            break false;
+        }
+    };
+
+    match interrupt_free(|cs| SHUTDOWN_HOOK.borrow(cs).get()) {
+        Some(hook) => hook(),
+        None => Ok(exited),
+    }
+}
+
+/// Registers a function called when a task without its own hook (see [`TaskConfig::with_panic_hook`]) panics.
+pub fn set_task_panic_hook(hook: PanicHook) {
+    interrupt_free(|cs| TASK_PANIC_HOOK.borrow(cs).set(Some(hook)));
+}
+
+/// Lets the scheduler handle a panic. Meant to be called at the beginning of the `#[panic_handler]` of the application.
+///
+/// If the panic occurred in a task which has a panic hook (its own or the global one),
+/// the task is removed, the hook is called, and this function never returns while the rest of the system keeps running.
+/// Otherwise (no hook, a panic in an idle task or before the scheduler starts), it returns,
+/// and the panic handler should halt or reset the system as usual.
+///
+/// A panic inside a critical section (including the scheduler itself) cannot be recovered,
+/// because the task is not switched out while interrupts are masked.
+pub fn handle_panic(info: &PanicInfo) {
+    let task = interrupt_free(|cs| {
+        // The scheduler state may be borrowed if the panic occurred inside the scheduler
+        let state = SCHEDULER_STATE
+            .borrow(cs)
+            .try_borrow()
+            .ok()
+            .filter(|state| state.started)?;
+        let task_id = *state.current_task.get();
+        let task = state.tasks.get(&task_id)?;
+
+        let hook = task.panic_hook.or(TASK_PANIC_HOOK.borrow(cs).get());
+        let supervision = task
+            .supervision
+            .filter(|supervision| supervision.policy != RestartPolicy::Never);
+        Some((task_id, hook, supervision))
+    });
+    let Some((task_id, hook, supervision)) = task else {
+        return;
+    };
+    if task_id < IDLE_TASK_ID + NUM_CORES {
+        return;
+    }
+
+    match (hook, supervision) {
+        (hook, Some(_)) => {
+            if let Some(hook) = hook {
+                hook(task_id, info);
+            }
+            // The task is still running on its stack, so only the restart is requested here
+            let restarted = interrupt_free(|cs| {
+                let mut state = SCHEDULER_STATE.borrow_ref_mut(cs);
+                let task = state.tasks.get_mut(&task_id)?;
+                task.restart = true;
+                Some(())
+            });
+            if restarted.is_none() {
+                return;
+            }
+            info!("Task #{} restarting after a panic", task_id);
+        }
+        (Some(hook), None) => {
+            if remove_task(task_id).is_err() {
+                return;
+            }
+            hook(task_id, info);
+        }
+        (None, None) => return,
+    }
+
+    yield_now();
+    // The task is switched out soon
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+/// Guard of the preemption lock returned by [`lock_preemption`]. Dropping it releases the lock.
+pub struct PreemptionGuard {
+    // Released on the core which acquired it
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for PreemptionGuard {
+    fn drop(&mut self) {
+        release_preemption_lock();
+    }
+}
+
+/// Keeps the calling task running on this core until the returned guard is dropped.
+///
+/// Unlike a critical section, interrupts are still served, but no other task is switched in (even one of higher priority).
+/// Tasks woken in the meantime run after the lock is released. Locks nest.
+/// The task must not block (e.g. sleep or wait on a futex) while holding the lock.
+pub fn lock_preemption() -> PreemptionGuard {
+    acquire_preemption_lock();
+    PreemptionGuard {
+        _not_send: PhantomData,
+    }
+}
+
+pub(crate) fn acquire_preemption_lock() {
+    PREEMPTION_LOCKS.get().fetch_add(1, Ordering::SeqCst);
+}
+
+pub(crate) fn release_preemption_lock() {
+    if PREEMPTION_LOCKS.get().fetch_sub(1, Ordering::SeqCst) == 1
+        && SWITCH_DEFERRED.get().swap(false, Ordering::SeqCst)
+    {
+        yield_now();
+    }
+}
+
+/// Removes a task. Called by [`crate::task::kill`].
+pub(crate) fn kill_task(id: usize) -> Result<(), Error> {
+    if id < IDLE_TASK_ID + NUM_CORES {
+        return Err(Error::NotPermitted);
+    }
+
+    remove_task(id)?;
+
+    // A removed task is never dispatched again, so the cores running it can be looked up afterwards
+    let running_cores: [bool; NUM_CORES] = interrupt_free(|cs| {
+        let state = SCHEDULER_STATE.borrow_ref(cs);
+        core::array::from_fn(|core| state.current_task[core] == id)
+    });
+    for (core, running) in running_cores.into_iter().enumerate() {
+        if running {
+            request_reschedule(Some(core));
+        }
+    }
+
+    if running_cores[arch::core_id()] {
+        // The task killed itself and is switched out soon
+        loop {
+            core::hint::spin_loop();
+        }
+    }
+
+    Ok(())
+}
+
+/// INTERNAL USE ONLY
+///
+/// Removes the running task of this core and requests a context switch.
+/// Used by architectures where a finishing task cannot enter the scheduler by itself (e.g. unprivileged tasks).
+pub fn exit_current_task() -> Result<(), Error> {
+    remove_task(current_task_id()?)?;
+    yield_now();
+
+    Ok(())
+}
+
+/// Runs `f` in a critical section which modifies the scheduler state.
+///
+/// With the `latency` feature, the length of the section is recorded,
+/// and with the `lock-watchdog` feature, it is checked against the hold-time threshold.
+/// With the `paranoid-checks` feature, the scheduler invariants are validated at the end of the section.
+pub(crate) fn kernel_section<R>(f: impl FnOnce(CriticalSection) -> R) -> R {
+    #[cfg(feature = "lock-watchdog")]
+    let mut hold = None;
+    #[cfg(feature = "paranoid-checks")]
+    let mut invariants = Ok(());
+
+    let result = interrupt_free(|cs| {
+        #[cfg(feature = "lock-watchdog")]
+        {
+            hold = Some(lock_watchdog::acquire(cs));
+        }
+        #[cfg(feature = "latency")]
+        let start = stats::timestamp();
+        let result = f(cs);
+        #[cfg(feature = "latency")]
+        stats::record(cs, Latency::CriticalSection, start);
+        #[cfg(feature = "paranoid-checks")]
+        {
+            invariants = check_invariants(cs);
+        }
+        result
+    });
+
+    #[cfg(feature = "paranoid-checks")]
+    if let Err(message) = invariants {
+        handle_assertion_failure(message);
+    }
+
+    // Checked outside the critical section, so that the hook is not called with the scheduler locked
+    #[cfg(feature = "lock-watchdog")]
+    if let Some(hold) = hold {
+        lock_watchdog::release(hold, LockKind::Scheduler);
+    }
+
+    result
+}
+
+/// Validates the consistency of the task table, the ready queues, and the timer queue.
+///
+/// Skipped while an outer section is modifying them.
+#[cfg(feature = "paranoid-checks")]
+fn check_invariants(cs: CriticalSection) -> Result<(), &'static str> {
+    if let Ok(state) = SCHEDULER_STATE.borrow(cs).try_borrow() {
+        let queued_count = |id: usize| {
+            state
+                .run_queues
+                .iter()
+                .flat_map(|run_queue| run_queue.queues.iter())
+                .map(|queue| queue.iter().filter(|queued| **queued == id).count())
+                .sum::<usize>()
+        };
+
+        for core in 0..NUM_CORES {
+            // A finished task stays the current task until it is switched out, even though it is removed
+            if queued_count(state.current_task[core]) > 0 {
+                return Err("Running task is queued");
+            }
+
+            let run_queue = &state.run_queues[core];
+            for (priority, queue) in run_queue.queues.iter().enumerate() {
+                if queue.is_empty() == run_queue.priority_map.contains(priority) {
+                    return Err("Priority map is inconsistent with the ready queues");
+                }
+
+                for &id in queue.iter() {
+                    let Some(task) = state.tasks.get(&id) else {
+                        return Err("Queued task does not exist");
+                    };
+                    if task.blocked {
+                        return Err("Blocked task is queued");
+                    }
+                    if task.priority != priority || task.core != core {
+                        return Err("Task is queued with a wrong priority or core");
+                    }
+                    if queued_count(id) > 1 {
+                        return Err("Task is queued more than once");
+                    }
+                }
+            }
+        }
+    }
+
+    timer::check_invariants(cs)
+}
+
+/// Registers a function called when a scheduler invariant is violated (`paranoid-checks` feature).
+///
+/// The hook receives the description of the violation, and may log it or reset the system.
+/// Without a hook, a violation causes a panic. The hook is called right after the critical section of the scheduler
+/// where the violation is found, which may be in an interrupt handler or inside another critical section.
+#[cfg(feature = "paranoid-checks")]
+pub fn set_assert_hook(hook: AssertHook) {
+    interrupt_free(|cs| ASSERT_HOOK.borrow(cs).set(Some(hook)));
+}
+
+#[cfg(feature = "paranoid-checks")]
+fn handle_assertion_failure(message: &'static str) {
+    let hook = interrupt_free(|cs| ASSERT_HOOK.borrow(cs).get());
+    match hook {
+        Some(hook) => hook(message),
+        None => panic!("Scheduler invariant violated: {}", message),
+    }
+}
+
+/// Returns the ID of the task running on this core, or `None` if the task table is not available.
+///
+/// Unlike `current_task_id`, this can be called while the task table is borrowed.
+#[cfg(feature = "lock-watchdog")]
+pub(crate) fn running_task_id(cs: CriticalSection) -> Option<usize> {
+    let state = SCHEDULER_STATE.borrow(cs).try_borrow().ok()?;
+    Some(*state.current_task.get())
+}
+
+pub(crate) fn block_task(id: usize) -> Result<(), Error> {
+    kernel_section(|cs| {
+        let mut state = SCHEDULER_STATE.borrow_ref_mut(cs);
+        let state = &mut *state;
+        let Some(task) = state.tasks.get_mut(&id) else {
+            return Err(Error::NotFound);
+        };
+
+        if task.blocked {
+            debug!("Task #{} is already blocked", id);
+            return Ok(());
+        }
+
+        task.blocked = true;
+        // Remove the task from the task queue
+        state.run_queues[task.core].remove(id, task.priority);
+
+        trace!("Task #{} became blocked", id);
+        #[cfg(feature = "trace-hooks")]
+        crate::trace::block(cs, id);
+        #[cfg(feature = "defmt-events")]
+        events::task(cs, id, task.priority, TaskState::Blocked);
+        #[cfg(feature = "rtos-awareness")]
+        rtos_awareness::set_state(cs, id, DebugTaskState::Blocked);
 
         yield_now();
 
@@ -418,99 +1687,548 @@ pub(crate) fn unblock_task(id: usize) -> Result<(), Error> {
     Ok(())
 }
 
+pub(crate) fn unblock_task(id: usize) -> Result<(), Error> {
+    kernel_section(|cs| Wakeups::new(cs).unblock(id))
+}
+
+/// Batch of tasks unblocked in one kernel section (e.g. all waiters of a futex).
+///
+/// The cores are rescheduled once when the batch is dropped, and only those where a woken task may preempt the running one.
+pub(crate) struct Wakeups<'cs> {
+    cs: CriticalSection<'cs>,
+    /// Highest priority of the tasks woken for each core
+    highest: [Option<usize>; NUM_CORES],
+}
+
+impl<'cs> Wakeups<'cs> {
+    pub(crate) fn new(cs: CriticalSection<'cs>) -> Self {
+        Self {
+            cs,
+            highest: [None; NUM_CORES],
+        }
+    }
+
+    /// Unblocks a task, deferring the rescheduling to the end of the batch.
+    pub(crate) fn unblock(&mut self, id: usize) -> Result<(), Error> {
+        let mut state = SCHEDULER_STATE.borrow_ref_mut(self.cs);
+        let state = &mut *state;
+        let Some(task) = state.tasks.get_mut(&id) else {
+            return Err(Error::NotFound);
+        };
+
+        if !task.blocked {
+            debug!("Task #{} is not blocked", id);
+            return Ok(());
+        }
+
+        task.blocked = false;
+        // Woken before its timeout (if any), which is left in the timer queue but ignored
+        task.timeout = None;
+        // Add task at the end of the task queue, unless it has not been switched out yet after blocking
+        // (then it is enqueued by the pending context switch)
+        if !state.current_task.iter().any(|current| *current == id) {
+            state.run_queues[task.core].push(id, task.priority)?;
+        }
+
+        trace!("Task #{} is unblocked", id);
+        #[cfg(feature = "trace-hooks")]
+        crate::trace::ready(self.cs, id);
+        #[cfg(feature = "defmt-events")]
+        events::task(self.cs, id, task.priority, TaskState::Ready);
+        #[cfg(feature = "rtos-awareness")]
+        rtos_awareness::set_state(self.cs, id, DebugTaskState::Ready);
+
+        for (core, highest) in self.highest.iter_mut().enumerate() {
+            if task.affinity.is_none_or(|affinity| affinity == core) {
+                *highest = (*highest).max(Some(task.priority));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Wakeups<'_> {
+    fn drop(&mut self) {
+        let state = SCHEDULER_STATE.borrow_ref(self.cs);
+        let this_core = arch::core_id();
+        for (core, highest) in self.highest.into_iter().enumerate() {
+            let Some(highest) = highest else {
+                continue;
+            };
+            // A task of the same priority takes turns with the running one, as with a yield
+            let preempts = state
+                .tasks
+                .get(&state.current_task[core])
+                .is_none_or(|running| running.blocked || highest >= running.priority);
+            if !preempts {
+                continue;
+            }
+
+            if core == this_core {
+                yield_now();
+            } else {
+                arch::ipi(core);
+            }
+        }
+    }
+}
+
+/// Records the time of the timer registration which wakes the task. Called when it blocks on a timer.
+pub(crate) fn set_timeout(cs: CriticalSection, id: usize, time: u64) -> Result<(), Error> {
+    let mut state = SCHEDULER_STATE.borrow_ref_mut(cs);
+    let Some(task) = state.tasks.get_mut(&id) else {
+        return Err(Error::NotFound);
+    };
+
+    task.timeout = Some(time);
+    Ok(())
+}
+
+/// Returns whether the timer registration of a task for `time` is still waited for (i.e. not stale).
+pub(crate) fn is_timeout_pending(cs: CriticalSection, id: usize, time: u64) -> bool {
+    let state = SCHEDULER_STATE.borrow_ref(cs);
+    state
+        .tasks
+        .get(&id)
+        .is_some_and(|task| task.timeout == Some(time))
+}
+
+/// Unblocks a task whose timer registration for `time` rings, unless the registration is stale.
+pub(crate) fn unblock_timed_task(wakeups: &mut Wakeups, id: usize, time: u64) -> Result<(), Error> {
+    {
+        let mut state = SCHEDULER_STATE.borrow_ref_mut(wakeups.cs);
+        let Some(task) = state.tasks.get_mut(&id) else {
+            return Err(Error::NotFound);
+        };
+
+        if task.timeout != Some(time) {
+            return Ok(());
+        }
+        // A timed-out park ends here, so that `unpark` leaves a token from now on
+        task.parked = false;
+    }
+
+    wakeups.unblock(id)
+}
+
+/// Blocks the running task until `unpark_task` or `time`, unless its token is left. Called by [`crate::task::park`].
+pub(crate) fn park_current_task(time: Option<u64>) -> Result<(), Error> {
+    kernel_section(|cs| {
+        let id = {
+            let mut state = SCHEDULER_STATE.borrow_ref_mut(cs);
+
+            let id = *state.current_task.get();
+            if id < IDLE_TASK_ID + NUM_CORES {
+                return Err(Error::NotPermitted);
+            }
+            let Some(task) = state.tasks.get_mut(&id) else {
+                return Err(Error::NotFound);
+            };
+
+            if core::mem::take(&mut task.unpark_token) {
+                return Ok(());
+            }
+            if time.is_some_and(|time| timer::current_time().is_ok_and(|now| time <= now)) {
+                return Ok(());
+            }
+            task.parked = true;
+            id
+        };
+
+        match time {
+            Some(time) => timer::wait_task_until(time, id),
+            None => block_task(id),
+        }
+    })
+}
+
+/// Unblocks a parked task, or leaves the token for its next park. Called by [`crate::task::unpark`].
+pub(crate) fn unpark_task(id: usize) -> Result<(), Error> {
+    kernel_section(|cs| {
+        let parked = {
+            let mut state = SCHEDULER_STATE.borrow_ref_mut(cs);
+            let Some(task) = state.tasks.get_mut(&id) else {
+                return Err(Error::NotFound);
+            };
+
+            if task.parked {
+                task.parked = false;
+                true
+            } else {
+                task.unpark_token = true;
+                false
+            }
+        };
+
+        if parked { unblock_task(id) } else { Ok(()) }
+    })
+}
+
+/// ID of the running task of this core. Code running before `Scheduler::start` is counted as the idle task.
 pub(crate) fn current_task_id() -> Result<usize, Error> {
-    critical_section::with(|cs| {
+    interrupt_free(|cs| {
         let state = SCHEDULER_STATE.borrow_ref(cs);
-        let Some(state) = state.as_ref() else {
+        if !state.initialized {
             return Err(Error::NotInitialized);
+        }
+        Ok(*state.current_task.get())
+    })
+}
+
+/// Adds `delta` bytes to the heap balance of the running task. Called by `heap::TaskAlloc`.
+///
+/// Allocations before the scheduler starts are counted for the idle task.
+#[cfg(feature = "heap-task-stats")]
+pub(crate) fn account_heap(delta: isize) {
+    interrupt_free(|cs| {
+        // Skipped if the allocator is (wrongly) called while the state is borrowed
+        let Ok(mut state) = SCHEDULER_STATE.borrow(cs).try_borrow_mut() else {
+            return;
         };
 
-        Ok(state.current_task)
+        let id = *state.current_task.get();
+        if let Some(task) = state.tasks.get_mut(&id) {
+            task.heap_balance += delta;
+        }
     })
 }
 
+/// Requests a reschedule on every core which may run a task with `affinity`.
+fn request_reschedule(affinity: Option<usize>) {
+    let this_core = arch::core_id();
+    for core in 0..NUM_CORES {
+        if affinity.is_some_and(|affinity| affinity != core) {
+            continue;
+        }
+
+        if core == this_core {
+            yield_now();
+        } else {
+            arch::ipi(core);
+        }
+    }
+}
+
 fn remove_task(id: usize) -> Result<(), Error> {
-    critical_section::with(|cs| {
+    kernel_section(|cs| {
         let mut state = SCHEDULER_STATE.borrow_ref_mut(cs);
-        let Some(state) = state.as_mut() else {
-            panic!("Scheduler not initialized");
-        };
+        let state = &mut *state;
 
         // Remove from the task list
         let Some(task) = state.tasks.remove(&id) else {
             return Err(Error::NotFound);
         };
-        let priority = task.priority;
 
         // Remove from the task queue
-        remove_task_from_queue(&mut state.queues, &mut state.priority_map, id, priority);
+        state.run_queues[task.core].remove(id, task.priority);
+
+        // The stack may still be in use until the task is switched out, so it is freed later
+        #[cfg(feature = "alloc")]
+        if let Some(heap_stack) = task.heap_stack
+            && state.released_stacks.push((id, heap_stack)).is_err()
+        {
+            debug!("Stack of Task #{} is leaked", id);
+        }
 
         info!("Task #{} removed", id);
+        #[cfg(feature = "trace-hooks")]
+        crate::trace::remove(cs, id);
+        #[cfg(feature = "defmt-events")]
+        events::task(cs, id, task.priority, TaskState::Removed);
+        #[cfg(feature = "rtos-awareness")]
+        rtos_awareness::remove(cs, id);
 
         Ok(())
-    })
+    })?;
+
+    // A finished task is no longer expected to check in
+    let _ = watchdog::forget(id);
+
+    TASK_EXITS.as_ref().fetch_add(1, Ordering::SeqCst);
+    TASK_EXITS.wake_all()
 }
 
-fn enqueue_task(
-    queues: &mut [Deque<usize, QUEUE_LEN>],
-    priority_map: &mut u32,
-    task_id: usize,
-    priority: usize,
-) -> Result<(), Error> {
-    queues[priority]
-        .push_back(task_id)
-        .or(Err(Error::TaskFull))?;
+/// Dequeues the task to run next on `core`.
+///
+/// A task waiting in the queue of another core is migrated if it has higher priority than any task in the queue of `core`.
+fn dequeue_task(state: &mut SchedulerState, core: usize) -> usize {
+    // The idle task of this core is always found in the worst case
+    let Some((local_priority, local_position)) =
+        state.run_queues[core].find(IDLE_PRIORITY, |_| true)
+    else {
+        unreachable!()
+    };
 
-    *priority_map |= 1 << priority;
+    let migratable = |id| is_migratable(state.tasks, id);
+    let mut best = None;
+    for (other, run_queue) in state.run_queues.iter().enumerate() {
+        if other == core {
+            continue;
+        }
+        let min_priority = best.map_or(local_priority, |(priority, _, _)| priority) + 1;
+        if let Some((priority, position)) = run_queue.find(min_priority, migratable) {
+            best = Some((priority, other, position));
+        }
+    }
 
-    Ok(())
+    if let Some((priority, other, position)) = best {
+        let task_id = state.run_queues[other].take(priority, position);
+        debug!(
+            "Task #{} migrated from core {} to core {}",
+            task_id, other, core
+        );
+        task_id
+    } else {
+        state.run_queues[core].take(local_priority, local_position)
+    }
 }
 
-fn dequeue_task(
-    queues: &mut [Deque<usize, QUEUE_LEN>],
-    priority_map: &mut u32,
-    priority: usize,
-) -> Option<usize> {
-    let task_id = queues[priority].pop_front();
+/// Returns true if the task is not pinned to a specific core.
+fn is_migratable(tasks: &LinearMapView<usize, TaskInfo>, id: usize) -> bool {
+    tasks.get(&id).is_some_and(|task| task.affinity.is_none())
+}
 
-    if queues[priority].is_empty() {
-        *priority_map &= !(1 << priority);
+/// Wakes up idle cores if tasks they can steal are waiting in the queue of `core`.
+#[cfg(feature = "smp")]
+fn notify_idle_cores(state: &SchedulerState, core: usize) {
+    let migratable = |id| is_migratable(state.tasks, id);
+    if state.run_queues[core]
+        .find(IDLE_PRIORITY + 1, migratable)
+        .is_none()
+    {
+        return;
     }
 
-    task_id
+    for (other, task_id) in state.current_task.iter().enumerate() {
+        if other != core && *task_id == IDLE_TASK_ID + other {
+            trace!("Core {} notified for stealing", other);
+            arch::ipi(other);
+        }
+    }
 }
 
-fn remove_task_from_queue(
-    queues: &mut [Deque<usize, QUEUE_LEN>],
-    priority_map: &mut u32,
-    task_id: usize,
-    priority: usize,
-) {
-    queues[priority].retain(|elem| *elem != task_id);
+impl RunQueue {
+    fn new<const N: usize>(queues: &'static mut [Deque<usize, N>; MAX_PRIORITY + 1]) -> Self {
+        Self {
+            queues: queues.each_mut().map(Deque::as_mut_view),
+            priority_map: PriorityMap::new(),
+        }
+    }
+
+    /// Run queue of `SchedulerState::new`, whose queues are never pushed to
+    const fn empty(queues: &'static mut [Deque<usize, 1>; MAX_PRIORITY + 1]) -> Self {
+        let mut views: [MaybeUninit<&'static mut DequeView<usize>>; MAX_PRIORITY + 1] =
+            [const { MaybeUninit::uninit() }; MAX_PRIORITY + 1];
+        let mut queues: &'static mut [Deque<usize, 1>] = queues;
+        let mut priority = 0;
+        while let Some((queue, rest)) = queues.split_first_mut() {
+            views[priority] = MaybeUninit::new(queue);
+            queues = rest;
+            priority += 1;
+        }
+
+        Self {
+            // SAFETY: all the elements are initialized above
+            queues: unsafe {
+                transmute::<
+                    [MaybeUninit<&'static mut DequeView<usize>>; MAX_PRIORITY + 1],
+                    [&'static mut DequeView<usize>; MAX_PRIORITY + 1],
+                >(views)
+            },
+            priority_map: PriorityMap::new(),
+        }
+    }
+
+    fn push(&mut self, task_id: usize, priority: usize) -> Result<(), Error> {
+        self.queues[priority]
+            .push_back(task_id)
+            .or(Err(Error::TaskFull))?;
+
+        self.priority_map.insert(priority);
+
+        Ok(())
+    }
+
+    fn remove(&mut self, task_id: usize, priority: usize) {
+        self.queues[priority].retain(|elem| *elem != task_id);
+
+        if self.queues[priority].is_empty() {
+            self.priority_map.remove(priority);
+        }
+    }
+
+    /// Finds the first task satisfying `pred` in the highest priority not lower than `min_priority`.
+    ///
+    /// Returns the priority and the position in the queue of that priority.
+    fn find(&self, min_priority: usize, pred: impl Fn(usize) -> bool) -> Option<(usize, usize)> {
+        let mut below = MAX_PRIORITY + 1;
+        while let Some(priority) = self.priority_map.highest(min_priority, below) {
+            if let Some(position) = self.queues[priority].iter().position(|id| pred(*id)) {
+                return Some((priority, position));
+            }
+            below = priority;
+        }
+
+        None
+    }
+
+    /// Takes out the task found by `find` while keeping the order of the others.
+    fn take(&mut self, priority: usize, position: usize) -> usize {
+        let queue = &mut self.queues[priority];
+        if position > 0 {
+            // Move the task to the front, shifting only the tasks before it
+            queue.make_contiguous()[..=position].rotate_right(1);
+        }
+        let task_id = queue.pop_front().unwrap_or_else(|| unreachable!());
+
+        if queue.is_empty() {
+            self.priority_map.remove(priority);
+        }
+
+        task_id
+    }
+}
+
+impl PriorityMap {
+    const fn new() -> Self {
+        Self([0; PRIORITY_MAP_WORDS])
+    }
+
+    fn insert(&mut self, priority: usize) {
+        self.0[priority / 32] |= 1 << (priority % 32);
+    }
+
+    fn remove(&mut self, priority: usize) {
+        self.0[priority / 32] &= !(1 << (priority % 32));
+    }
 
-    if queues[priority].is_empty() {
-        *priority_map &= !(1 << priority);
+    #[cfg(feature = "paranoid-checks")]
+    fn contains(&self, priority: usize) -> bool {
+        self.0[priority / 32] & (1 << (priority % 32)) != 0
     }
+
+    /// Highest priority in the set which is lower than `below` and not lower than `min`.
+    fn highest(&self, min: usize, below: usize) -> Option<usize> {
+        if below <= min {
+            return None;
+        }
+
+        let mut index = (below - 1) / 32;
+        // Bits at `below` and above are masked out of the top word
+        let mut word = self.0[index] & (u32::MAX >> (32 - (below - index * 32)));
+        loop {
+            if word != 0 {
+                let priority = index * 32 + (31 - word.leading_zeros()) as usize;
+                return (priority >= min).then_some(priority);
+            }
+            // The lower words hold only priorities lower than `min`
+            if index * 32 <= min {
+                return None;
+            }
+
+            index -= 1;
+            word = self.0[index];
+        }
+    }
+}
+
+/// Frees heap-allocated stacks of removed tasks which are no longer running.
+#[cfg(feature = "alloc")]
+fn free_released_stacks() {
+    loop {
+        let heap_stack = interrupt_free(|cs| {
+            let mut state = SCHEDULER_STATE.borrow_ref_mut(cs);
+            let state = &mut *state;
+
+            let position = state
+                .released_stacks
+                .iter()
+                .position(|(id, _)| !state.current_task.iter().any(|current| current == id))?;
+            Some(state.released_stacks.swap_remove(position).1)
+        });
+
+        // Freed outside the critical section because the allocator may be slow
+        match heap_stack {
+            Some(heap_stack) => unsafe { heap_stack.free() },
+            None => break,
+        }
+    }
+}
+
+/// Stack memory allocated from the global allocator.
+#[cfg(feature = "alloc")]
+#[derive(Clone, Copy, Debug)]
+struct HeapStack {
+    ptr: usize,
+    size: usize,
 }
 
+#[cfg(feature = "alloc")]
+impl HeapStack {
+    /// Alignment satisfying the requirements of all supported architectures
+    const ALIGN: usize = 16;
+
+    fn alloc(size: usize) -> Result<Self, Error> {
+        // The end (initial stack pointer) is also aligned
+        let size = size.next_multiple_of(Self::ALIGN);
+        let layout =
+            alloc::alloc::Layout::from_size_align(size, Self::ALIGN).or(Err(Error::OutOfMemory))?;
+        if size == 0 {
+            return Err(Error::OutOfMemory);
+        }
+
+        let ptr = unsafe { alloc::alloc::alloc(layout) };
+        if ptr.is_null() {
+            return Err(Error::OutOfMemory);
+        }
+
+        Ok(Self {
+            ptr: ptr as usize,
+            size,
+        })
+    }
+
+    /// Must be called only once, after the stack is no longer used.
+    unsafe fn free(self) {
+        unsafe {
+            alloc::alloc::dealloc(
+                self.ptr as *mut u8,
+                alloc::alloc::Layout::from_size_align_unchecked(self.size, Self::ALIGN),
+            );
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl StackAllocation for HeapStack {
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.ptr as *mut u8, self.size) }
+    }
+}
+
+/// Length (in words) and pattern of the stack canary
 #[cfg(feature = "stack-canary")]
-unsafe fn check_stack_canary(stack_bottom: *const u32, task_id: usize) {
+fn stack_canary() -> Result<(usize, u32), Error> {
+    let config = get_config()?;
+    Ok((config.stack_canary_len, config.stack_canary_pattern))
+}
+
+/// Returns `false` if the canary is overwritten.
+#[cfg(feature = "stack-canary")]
+unsafe fn check_stack_canary(stack_bottom: *const u32, (len, pattern): (usize, u32)) -> bool {
     unsafe {
-        let stack_bottom = core::slice::from_raw_parts(stack_bottom, STACK_CANARY_LEN);
-        if stack_bottom.iter().any(|elem| *elem != STACK_CANARY) {
-            panic!("Stack overflow detected in Task #{}", task_id);
-        }
+        let stack_bottom = core::slice::from_raw_parts(stack_bottom, len);
+        stack_bottom.iter().all(|elem| *elem == pattern)
     }
 }
 
 // Fill the bottom of the stack with the canary pattern
 #[cfg(feature = "stack-canary")]
-unsafe fn fill_stack_canary(stack_bottom: *mut u32) {
+unsafe fn fill_stack_canary(stack_bottom: *mut u32, (len, pattern): (usize, u32)) {
     unsafe {
-        let stack_bottom = core::slice::from_raw_parts_mut(stack_bottom, STACK_CANARY_LEN);
-        stack_bottom
-            .iter_mut()
-            .for_each(|elem| *elem = STACK_CANARY);
+        let stack_bottom = core::slice::from_raw_parts_mut(stack_bottom, len);
+        stack_bottom.iter_mut().for_each(|elem| *elem = pattern);
     }
 }
 
@@ -521,17 +2239,14 @@ extern "C" fn call_closure<F: FnOnce()>(f: &mut Option<F>) -> ! {
         unreachable!()
     }
 
-    let id = critical_section::with(|cs| {
-        let state = SCHEDULER_STATE.borrow_ref(cs);
-        let Some(state) = state.as_ref() else {
-            unreachable!()
-        };
-        state.current_task
-    });
+    let id = interrupt_free(|cs| *SCHEDULER_STATE.borrow_ref(cs).current_task.get());
 
     info!("Task #{} finished", id);
 
     remove_task(id).expect("Failed to remove the finished task");
 
-    loop {}
+    // The removed task is never selected again
+    loop {
+        yield_now();
+    }
 }
@@ -0,0 +1,185 @@
+//! Kernel statistics counters (`stats` feature).
+//!
+//! Counters are plain atomic integers incremented at each event, and wrap around on overflow.
+//! A snapshot is taken with [`crate::scheduler::stats`].
+//!
+//! With the `latency` feature, durations of the tick handler, context switches, and critical sections of the kernel
+//! are also measured with the free-running counter of the port (the cycle counter on Cortex-M).
+//! They are in counts of that counter, and include the overhead of the measurement itself.
+//! Calls of the allocator wrapped by `heap::TaskAlloc` are measured too, with the `alloc` feature.
+
+#[cfg(feature = "latency")]
+use core::cell::RefCell;
+
+#[cfg(feature = "latency")]
+use critical_section::{CriticalSection, Mutex};
+use portable_atomic::{AtomicU32, Ordering};
+
+#[cfg(feature = "latency")]
+use crate::{arch, sync::interrupt_free};
+
+pub(crate) static CONTEXT_SWITCHES: AtomicU32 = AtomicU32::new(0);
+pub(crate) static PREEMPTIONS: AtomicU32 = AtomicU32::new(0);
+pub(crate) static TICKS: AtomicU32 = AtomicU32::new(0);
+pub(crate) static TIMER_WAKEUPS: AtomicU32 = AtomicU32::new(0);
+pub(crate) static FUTEX_WAITS: AtomicU32 = AtomicU32::new(0);
+
+#[cfg(feature = "latency")]
+static LATENCIES: Mutex<RefCell<[Accumulator; 4]>> =
+    Mutex::new(RefCell::new([const { Accumulator::new() }; 4]));
+
+/// Snapshot of the kernel statistics counters.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SchedulerStats {
+    /// Number of switches to a different task
+    pub context_switches: u32,
+    /// Number of switches away from a task which was still ready to run
+    pub preemptions: u32,
+    /// Number of ticks (counted on every core)
+    pub ticks: u32,
+    /// Number of tasks woken up by timers
+    pub timer_wakeups: u32,
+    /// Number of times a task blocked on a futex
+    pub futex_waits: u32,
+    /// Duration from the entry to the end of the tick handler
+    #[cfg(feature = "latency")]
+    pub tick_handler: LatencyStats,
+    /// Duration of selecting the next task at a context switch (excluding saving and restoring registers)
+    #[cfg(feature = "latency")]
+    pub context_switch: LatencyStats,
+    /// Length of critical sections guarding the task table, ready queues, and timers
+    #[cfg(feature = "latency")]
+    pub critical_section: LatencyStats,
+    /// Duration of a call of the inner allocator of `heap::TaskAlloc` (excluding locking)
+    #[cfg(all(feature = "latency", feature = "alloc"))]
+    pub alloc: LatencyStats,
+    /// Usage of the heap through `heap::TaskAlloc`
+    #[cfg(feature = "alloc")]
+    pub heap: crate::heap::HeapStats,
+}
+
+/// Statistics of a measured duration (in counts of the counter of the port).
+#[cfg(feature = "latency")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LatencyStats {
+    pub min: u32,
+    pub max: u32,
+    pub average: u32,
+    /// Number of measurements
+    pub samples: u32,
+}
+
+/// Kind of a measured duration
+#[cfg(feature = "latency")]
+#[derive(Clone, Copy)]
+pub(crate) enum Latency {
+    TickHandler,
+    ContextSwitch,
+    CriticalSection,
+    #[cfg(feature = "alloc")]
+    Alloc,
+}
+
+#[cfg(feature = "latency")]
+struct Accumulator {
+    sum: u64,
+    stats: LatencyStats,
+}
+
+#[cfg(feature = "latency")]
+impl Accumulator {
+    const fn new() -> Self {
+        Self {
+            sum: 0,
+            stats: LatencyStats {
+                min: 0,
+                max: 0,
+                average: 0,
+                samples: 0,
+            },
+        }
+    }
+
+    fn add(&mut self, duration: u32) {
+        let stats = &mut self.stats;
+        if stats.samples == 0 {
+            stats.min = duration;
+            stats.max = duration;
+        } else {
+            stats.min = stats.min.min(duration);
+            stats.max = stats.max.max(duration);
+        }
+
+        // Stops accumulating instead of overflowing
+        if let Some(samples) = stats.samples.checked_add(1) {
+            stats.samples = samples;
+            self.sum += duration as u64;
+            stats.average = (self.sum / samples as u64) as u32;
+        }
+    }
+}
+
+pub(crate) fn count(counter: &AtomicU32) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn snapshot() -> SchedulerStats {
+    SchedulerStats {
+        context_switches: CONTEXT_SWITCHES.load(Ordering::Relaxed),
+        preemptions: PREEMPTIONS.load(Ordering::Relaxed),
+        ticks: TICKS.load(Ordering::Relaxed),
+        timer_wakeups: TIMER_WAKEUPS.load(Ordering::Relaxed),
+        futex_waits: FUTEX_WAITS.load(Ordering::Relaxed),
+        #[cfg(feature = "latency")]
+        tick_handler: latency(Latency::TickHandler),
+        #[cfg(feature = "latency")]
+        context_switch: latency(Latency::ContextSwitch),
+        #[cfg(feature = "latency")]
+        critical_section: latency(Latency::CriticalSection),
+        #[cfg(all(feature = "latency", feature = "alloc"))]
+        alloc: latency(Latency::Alloc),
+        #[cfg(feature = "alloc")]
+        heap: crate::heap::stats(),
+    }
+}
+
+pub(crate) fn reset() {
+    for counter in [
+        &CONTEXT_SWITCHES,
+        &PREEMPTIONS,
+        &TICKS,
+        &TIMER_WAKEUPS,
+        &FUTEX_WAITS,
+    ] {
+        counter.store(0, Ordering::Relaxed);
+    }
+
+    #[cfg(feature = "alloc")]
+    crate::heap::reset_peak();
+
+    #[cfg(feature = "latency")]
+    interrupt_free(|cs| {
+        for accumulator in LATENCIES.borrow_ref_mut(cs).iter_mut() {
+            *accumulator = Accumulator::new();
+        }
+    });
+}
+
+/// Returns the current value of the counter, passed to [`record`] later.
+#[cfg(feature = "latency")]
+pub(crate) fn timestamp() -> u32 {
+    unsafe { arch::_taskette_cycle_count() }
+}
+
+/// Accounts the time since `start` (taken by [`timestamp`]).
+#[cfg(feature = "latency")]
+pub(crate) fn record(cs: CriticalSection, kind: Latency, start: u32) {
+    let duration = timestamp().wrapping_sub(start);
+    LATENCIES.borrow_ref_mut(cs)[kind as usize].add(duration);
+}
+
+#[cfg(feature = "latency")]
+fn latency(kind: Latency) -> LatencyStats {
+    interrupt_free(|cs| LATENCIES.borrow_ref(cs)[kind as usize].stats)
+}
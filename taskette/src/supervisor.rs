@@ -0,0 +1,80 @@
+//! Supervised tasks which are restarted when they finish or panic.
+//!
+//! A supervised task runs a plain `fn()` entry, so that the kernel can run it again from the beginning.
+//! On restart, the task keeps its ID and reuses the top of its stack, and the entry is called again after the backoff.
+//!
+//! Restarting after a panic requires the `#[panic_handler]` of the application to call [`crate::scheduler::handle_panic`].
+//! Once a shutdown is requested (see [`crate::scheduler::request_shutdown`]), tasks are no longer restarted.
+
+use crate::{
+    Error,
+    arch::StackAllocation,
+    scheduler::{is_shutdown_requested, shutdown_token, spawn_supervised_inner},
+    task::{TaskConfig, TaskHandle},
+    timer,
+};
+
+/// When a supervised task is restarted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RestartPolicy {
+    /// The task is not restarted (same as a normal task).
+    Never,
+    /// The task is restarted when it panics, but finishes when the entry returns.
+    OnPanic,
+    /// The task is restarted both when it panics and when the entry returns.
+    Always,
+}
+
+/// Restart settings kept with a supervised task
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Supervision {
+    pub(crate) entry: fn(),
+    pub(crate) policy: RestartPolicy,
+    /// Delay before restarting (in ticks)
+    pub(crate) backoff: u64,
+    /// End (initial stack pointer) of the stack of the task
+    pub(crate) stack_end: usize,
+}
+
+/// Creates a new task which runs `entry` and is restarted according to `policy`.
+///
+/// Each restart waits for `backoff` ticks before calling `entry` again.
+/// The panic hook of the task (or the global one) is still called before a restart after a panic.
+pub fn spawn_supervised<S: StackAllocation>(
+    entry: fn(),
+    stack: S,
+    config: TaskConfig,
+    policy: RestartPolicy,
+    backoff: u64,
+) -> Result<TaskHandle, Error> {
+    spawn_supervised_inner(entry, stack, config, policy, backoff)
+}
+
+/// Body of a supervised task. `restarted` is true when the task is restarted after a panic.
+pub(crate) fn run(supervision: Supervision, restarted: bool) {
+    if restarted {
+        wait_backoff(supervision.backoff);
+    }
+
+    loop {
+        // Not started again once a shutdown is requested
+        if is_shutdown_requested() {
+            return;
+        }
+        (supervision.entry)();
+
+        if supervision.policy != RestartPolicy::Always {
+            return;
+        }
+        wait_backoff(supervision.backoff);
+    }
+}
+
+/// Waits for the backoff, which is cut short by a shutdown.
+fn wait_backoff(backoff: u64) {
+    if backoff > 0
+        && let Ok(now) = timer::current_time()
+    {
+        let _ = shutdown_token().sleep_until(now + backoff);
+    }
+}
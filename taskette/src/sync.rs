@@ -0,0 +1,656 @@
+//! Synchronization primitives for protecting data shared between tasks, interrupt handlers, and cores.
+
+pub(crate) mod cancel;
+
+use core::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    ops::{Deref, DerefMut, Index, IndexMut},
+    sync::atomic::Ordering,
+};
+
+use critical_section::CriticalSection;
+use heapless::Deque;
+use portable_atomic::{AtomicBool, AtomicUsize};
+
+#[cfg(feature = "lock-watchdog")]
+use crate::lock_watchdog::{self, Hold, LockKind};
+use crate::{Error, arch, futex::Futex, scheduler::NUM_CORES, task, timer};
+pub use cancel::{CancellationToken, Cancelled};
+
+/// Busy-waiting lock which also works between cores.
+///
+/// Intended for tiny structures shared in SMP builds, where blocking on a futex is too heavy.
+/// The lock must not be held for a long time because waiting tasks keep the CPU busy.
+///
+/// If the same lock is also taken by an interrupt handler, tasks must use [`SpinLock::lock_irq`],
+/// otherwise the handler spins forever when it interrupts the lock owner.
+pub struct SpinLock<T> {
+    locked: AtomicBool,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for SpinLock<T> {}
+unsafe impl<T: Send> Send for SpinLock<T> {}
+
+impl<T> SpinLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Acquires the lock, spinning until it becomes available.
+    pub fn lock(&self) -> SpinLockGuard<'_, T> {
+        while !self.acquire() {
+            core::hint::spin_loop();
+        }
+
+        SpinLockGuard {
+            lock: self,
+            irq_state: None,
+            #[cfg(feature = "lock-watchdog")]
+            hold: interrupt_free(lock_watchdog::acquire),
+        }
+    }
+
+    /// Acquires the lock with interrupts of the current core masked until the guard is dropped.
+    pub fn lock_irq(&self) -> SpinLockGuard<'_, T> {
+        loop {
+            let irq_state = unsafe { arch::_taskette_mask_interrupts() };
+            if self.acquire() {
+                return SpinLockGuard {
+                    lock: self,
+                    irq_state: Some(irq_state),
+                    #[cfg(feature = "lock-watchdog")]
+                    hold: interrupt_free(lock_watchdog::acquire),
+                };
+            }
+
+            // Let pending interrupts run while waiting
+            unsafe {
+                arch::_taskette_restore_interrupts(irq_state);
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Tries to acquire the lock once and returns `None` if it is held by someone else.
+    pub fn try_lock(&self) -> Option<SpinLockGuard<'_, T>> {
+        self.acquire().then(|| SpinLockGuard {
+            lock: self,
+            irq_state: None,
+            #[cfg(feature = "lock-watchdog")]
+            hold: interrupt_free(lock_watchdog::acquire),
+        })
+    }
+
+    /// Returns a mutable reference to the underlying data without locking.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
+    fn acquire(&self) -> bool {
+        unsafe { arch::_taskette_try_lock(&self.locked) }
+    }
+}
+
+/// Guard object of [`SpinLock`]. The lock is released when this is dropped.
+pub struct SpinLockGuard<'a, T> {
+    lock: &'a SpinLock<T>,
+    /// Interrupt state before `lock_irq` (`None` if interrupts are not masked)
+    irq_state: Option<usize>,
+    #[cfg(feature = "lock-watchdog")]
+    hold: Hold,
+}
+
+impl<T> Deref for SpinLockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for SpinLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for SpinLockGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.locked.store(false, Ordering::Release);
+
+        if let Some(irq_state) = self.irq_state {
+            unsafe {
+                arch::_taskette_restore_interrupts(irq_state);
+            }
+        }
+
+        #[cfg(feature = "lock-watchdog")]
+        lock_watchdog::release(
+            self.hold,
+            if self.irq_state.is_some() {
+                LockKind::SpinLockIrq
+            } else {
+                LockKind::SpinLock
+            },
+        );
+    }
+}
+
+/// Runs `f` with interrupts masked, for data shared with interrupt handlers.
+///
+/// On single-core builds, the port masks interrupts directly (e.g. PRIMASK or BASEPRI on Cortex-M, `mstatus.MIE` on RISC-V),
+/// which is cheaper than going through the `critical-section` implementation on every kernel call.
+/// It is therefore not excluded from another core running code outside of taskette, which has to use `critical_section::with`.
+/// With the `smp` feature, this is the same as `critical_section::with`.
+///
+/// With the `preemption-critical-section` feature, `critical_section::with` only locks preemption,
+/// so data also accessed by interrupt handlers has to be accessed through this function instead (everywhere, including the tasks).
+#[inline]
+pub fn interrupt_free<R>(f: impl FnOnce(CriticalSection) -> R) -> R {
+    #[cfg(feature = "smp")]
+    {
+        critical_section::with(f)
+    }
+    #[cfg(not(feature = "smp"))]
+    {
+        struct Guard(usize);
+
+        impl Drop for Guard {
+            #[inline]
+            fn drop(&mut self) {
+                unsafe { arch::_taskette_restore_interrupts(self.0) };
+            }
+        }
+
+        let _guard = Guard(unsafe { arch::_taskette_mask_interrupts() });
+        // SAFETY: interrupts are masked on the only core
+        f(unsafe { CriticalSection::new() })
+    }
+}
+
+/// Container holding a separate value for each core.
+///
+/// [`PerCore::get`] returns the value of the executing core, and indexing by a core ID returns the value of that core.
+/// Note that a task may migrate to another core when preempted,
+/// so a reference obtained in a task may no longer belong to the core the task is running on.
+#[derive(Clone, Debug)]
+pub struct PerCore<T> {
+    values: [T; NUM_CORES],
+}
+
+impl<T: Copy> PerCore<T> {
+    /// Creates a container with the same initial value for all cores.
+    pub const fn new(value: T) -> Self {
+        Self {
+            values: [value; NUM_CORES],
+        }
+    }
+}
+
+impl<T> PerCore<T> {
+    /// Creates a container from values ordered by core ID.
+    pub const fn from_array(values: [T; NUM_CORES]) -> Self {
+        Self { values }
+    }
+
+    /// Creates a container by calling `f` with each core ID.
+    pub fn from_fn(f: impl FnMut(usize) -> T) -> Self {
+        Self {
+            values: core::array::from_fn(f),
+        }
+    }
+
+    /// Returns the value of the executing core.
+    pub fn get(&self) -> &T {
+        &self.values[arch::core_id()]
+    }
+
+    /// Returns the value of the executing core.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.values[arch::core_id()]
+    }
+
+    /// Iterates over values of all cores in the order of core ID.
+    pub fn iter(&self) -> core::slice::Iter<'_, T> {
+        self.values.iter()
+    }
+
+    /// Iterates over values of all cores in the order of core ID.
+    pub fn iter_mut(&mut self) -> core::slice::IterMut<'_, T> {
+        self.values.iter_mut()
+    }
+}
+
+impl<T> Index<usize> for PerCore<T> {
+    type Output = T;
+
+    fn index(&self, core_id: usize) -> &T {
+        &self.values[core_id]
+    }
+}
+
+impl<T> IndexMut<usize> for PerCore<T> {
+    fn index_mut(&mut self, core_id: usize) -> &mut T {
+        &mut self.values[core_id]
+    }
+}
+
+/// Bounded multi-producer multi-consumer channel which also works between cores.
+///
+/// A task blocked on an empty (or full) channel is woken up by the scheduler, even if it is pinned to another core.
+/// `try_send` and `try_recv` can also be used in interrupt handlers.
+pub struct Channel<T, const N: usize> {
+    buffer: SpinLock<Deque<T, N>>,
+    /// Incremented on every send (receivers wait on this)
+    sent: Futex,
+    /// Incremented on every receive (senders wait on this)
+    received: Futex,
+}
+
+impl<T, const N: usize> Channel<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            buffer: SpinLock::new(Deque::new()),
+            sent: Futex::new(0),
+            received: Futex::new(0),
+        }
+    }
+
+    /// Sends a value, blocking the current task while the channel is full.
+    pub fn send(&self, value: T) -> Result<(), Error> {
+        let mut value = value;
+        loop {
+            // The counter is read before the attempt, so that a receive in between makes `wait` return immediately
+            let received = self.received.as_ref().load(Ordering::SeqCst);
+            match self.try_send(value) {
+                Ok(()) => return Ok(()),
+                Err(returned) => value = returned,
+            }
+
+            self.received.wait(received)?;
+        }
+    }
+
+    /// Same as [`Channel::send`], but gives up (dropping `value`) with `Error::Cancelled` when `token` is cancelled.
+    pub fn send_cancellable(&self, value: T, token: &CancellationToken) -> Result<(), Error> {
+        let mut value = value;
+        loop {
+            let received = self.received.as_ref().load(Ordering::SeqCst);
+            match self.try_send(value) {
+                Ok(()) => return Ok(()),
+                Err(returned) => value = returned,
+            }
+
+            self.received.wait_cancellable(received, token)?;
+        }
+    }
+
+    /// Sends a value if the channel is not full. Otherwise the value is given back.
+    pub fn try_send(&self, value: T) -> Result<(), T> {
+        self.buffer.lock_irq().push_back(value)?;
+
+        // The value has to be visible to other cores before receivers are woken up
+        self.sent.as_ref().fetch_add(1, Ordering::SeqCst);
+        // Waking up cannot fail after the scheduler is initialized
+        let _ = self.sent.wake_one();
+
+        Ok(())
+    }
+
+    /// Receives a value, blocking the current task while the channel is empty.
+    pub fn recv(&self) -> Result<T, Error> {
+        loop {
+            let sent = self.sent.as_ref().load(Ordering::SeqCst);
+            if let Some(value) = self.try_recv() {
+                return Ok(value);
+            }
+
+            self.sent.wait(sent)?;
+        }
+    }
+
+    /// Same as [`Channel::recv`], but gives up with `Error::Cancelled` when `token` is cancelled.
+    ///
+    /// Values already in the channel are still received after cancellation.
+    pub fn recv_cancellable(&self, token: &CancellationToken) -> Result<T, Error> {
+        loop {
+            let sent = self.sent.as_ref().load(Ordering::SeqCst);
+            if let Some(value) = self.try_recv() {
+                return Ok(value);
+            }
+
+            self.sent.wait_cancellable(sent, token)?;
+        }
+    }
+
+    /// Receives a value if the channel is not empty.
+    pub fn try_recv(&self) -> Option<T> {
+        let value = self.buffer.lock_irq().pop_front()?;
+
+        self.received.as_ref().fetch_add(1, Ordering::SeqCst);
+        let _ = self.received.wake_one();
+
+        Some(value)
+    }
+}
+
+impl<T, const N: usize> Default for Channel<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Bounded byte stream between a writer and a reader, which also works between cores.
+///
+/// Unlike a [`Channel`] of bytes, data is moved in slices, and a read returns whatever is available
+/// instead of a fixed count. Typical use is passing received bytes from the RX interrupt of a UART to a task.
+/// `try_write` and `try_read` can also be used in interrupt handlers.
+pub struct StreamBuffer<const N: usize> {
+    buffer: SpinLock<Deque<u8, N>>,
+    /// Incremented on every write (readers wait on this)
+    written: Futex,
+    /// Incremented on every read (writers wait on this)
+    read: Futex,
+}
+
+impl<const N: usize> StreamBuffer<N> {
+    pub const fn new() -> Self {
+        Self {
+            buffer: SpinLock::new(Deque::new()),
+            written: Futex::new(0),
+            read: Futex::new(0),
+        }
+    }
+
+    /// Writes as many bytes of `data` as fit, blocking the current task while the buffer is full.
+    /// Returns the number of bytes written, which is 0 only if `data` is empty.
+    pub fn write(&self, data: &[u8]) -> Result<usize, Error> {
+        loop {
+            // The counter is read before the attempt, so that a read in between makes `wait` return immediately
+            let read = self.read.as_ref().load(Ordering::SeqCst);
+            let count = self.try_write(data);
+            if count > 0 || data.is_empty() {
+                return Ok(count);
+            }
+
+            self.read.wait(read)?;
+        }
+    }
+
+    /// Writes as many bytes of `data` as fit without blocking. Returns the number of bytes written.
+    pub fn try_write(&self, data: &[u8]) -> usize {
+        let count = {
+            let mut buffer = self.buffer.lock_irq();
+            let count = data.len().min(N - buffer.len());
+            for &byte in &data[..count] {
+                // Cannot fail as the free space is checked above
+                let _ = buffer.push_back(byte);
+            }
+            count
+        };
+
+        if count > 0 {
+            self.written.as_ref().fetch_add(1, Ordering::SeqCst);
+            // Waking up cannot fail after the scheduler is initialized
+            let _ = self.written.wake_one();
+        }
+
+        count
+    }
+
+    /// Reads available bytes into `buf`, blocking the current task while the buffer is empty.
+    /// Returns the number of bytes read, which is 0 only if `buf` is empty.
+    pub fn read(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        loop {
+            let written = self.written.as_ref().load(Ordering::SeqCst);
+            let count = self.try_read(buf);
+            if count > 0 || buf.is_empty() {
+                return Ok(count);
+            }
+
+            self.written.wait(written)?;
+        }
+    }
+
+    /// Reads available bytes into `buf` without blocking. Returns the number of bytes read.
+    pub fn try_read(&self, buf: &mut [u8]) -> usize {
+        let count = {
+            let mut buffer = self.buffer.lock_irq();
+            let count = buf.len().min(buffer.len());
+            for byte in &mut buf[..count] {
+                // Cannot fail as the length is checked above
+                *byte = buffer.pop_front().unwrap_or_default();
+            }
+            count
+        };
+
+        if count > 0 {
+            self.read.as_ref().fetch_add(1, Ordering::SeqCst);
+            let _ = self.read.wake_one();
+        }
+
+        count
+    }
+
+    /// Number of bytes available for reading at the moment.
+    pub fn len(&self) -> usize {
+        self.buffer.lock_irq().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Discards all buffered bytes.
+    pub fn clear(&self) {
+        self.buffer.lock_irq().clear();
+
+        self.read.as_ref().fetch_add(1, Ordering::SeqCst);
+        let _ = self.read.wake_all();
+    }
+}
+
+impl<const N: usize> Default for StreamBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Event set by an interrupt handler and awaited by a single task (e.g. "frame received" of a driver).
+///
+/// Signals are not counted: several signals before the task waits are seen as one.
+/// The waiting task is woken with `task::unpark`, so it must not use parking for anything else while waiting.
+pub struct IrqEvent {
+    signaled: AtomicBool,
+    /// ID of the waiting task plus one, or 0 if no task is waiting
+    waiter: AtomicUsize,
+}
+
+impl IrqEvent {
+    pub const fn new() -> Self {
+        Self {
+            signaled: AtomicBool::new(false),
+            waiter: AtomicUsize::new(0),
+        }
+    }
+
+    /// Sets the event and wakes the waiting task. Can be called from interrupt handlers.
+    pub fn signal(&self) {
+        self.signaled.store(true, Ordering::SeqCst);
+        if let Some(task_id) = self.waiter.load(Ordering::SeqCst).checked_sub(1) {
+            // The task may have finished waiting in the meantime
+            let _ = task::unpark(task_id);
+        }
+    }
+
+    /// Blocks the current task until the event is set, and clears it.
+    pub fn wait(&self) -> Result<(), Error> {
+        self.wait_inner(None).map(|_| ())
+    }
+
+    /// Same as [`IrqEvent::wait`], but gives up when the time reaches `time` (in ticks).
+    /// Returns whether the event was set.
+    pub fn wait_until(&self, time: u64) -> Result<bool, Error> {
+        self.wait_inner(Some(time))
+    }
+
+    /// Clears the event and returns whether it was set, without blocking.
+    pub fn take(&self) -> bool {
+        self.signaled.swap(false, Ordering::SeqCst)
+    }
+
+    /// Makes `signal` unpark the current task from now on, without blocking.
+    ///
+    /// For waiting on several events at once (e.g. in an executor), which is done by parking after checking
+    /// each event with `take`. The registration is cleared when a blocking wait on this event finishes.
+    pub fn listen(&self) -> Result<(), Error> {
+        self.waiter
+            .store(task::current()?.id() + 1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn wait_inner(&self, time: Option<u64>) -> Result<bool, Error> {
+        self.waiter
+            .store(task::current()?.id() + 1, Ordering::SeqCst);
+        let result = self.wait_signaled(time);
+        self.waiter.store(0, Ordering::SeqCst);
+        result
+    }
+
+    fn wait_signaled(&self, time: Option<u64>) -> Result<bool, Error> {
+        while !self.take() {
+            match time {
+                Some(time) => {
+                    if timer::current_time()? >= time {
+                        return Ok(false);
+                    }
+                    task::park_until(time)?;
+                }
+                None => task::park()?,
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+impl Default for IrqEvent {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Fixed-size pool of `N` blocks which can hold a `T` each.
+///
+/// Blocks are handed out as [`PoolBox`], which returns the block to the pool when dropped.
+/// Useful for owning messages passed through a [`Channel`] without a heap.
+/// `try_alloc` can also be used in interrupt handlers.
+pub struct Pool<T, const N: usize> {
+    blocks: [UnsafeCell<MaybeUninit<T>>; N],
+    /// Whether each block is allocated
+    used: [AtomicBool; N],
+    /// Incremented on every release (tasks waiting for a free block wait on this)
+    released: Futex,
+}
+
+unsafe impl<T: Send, const N: usize> Sync for Pool<T, N> {}
+
+impl<T, const N: usize> Pool<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            blocks: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+            used: [const { AtomicBool::new(false) }; N],
+            released: Futex::new(0),
+        }
+    }
+
+    /// Moves a value into a free block, blocking the current task while the pool is exhausted.
+    pub fn alloc(&self, value: T) -> Result<PoolBox<'_, T>, Error> {
+        let mut value = value;
+        loop {
+            // The counter is read before the attempt, so that a release in between makes `wait` return immediately
+            let released = self.released.as_ref().load(Ordering::SeqCst);
+            match self.try_alloc(value) {
+                Ok(pool_box) => return Ok(pool_box),
+                Err(returned) => value = returned,
+            }
+
+            self.released.wait(released)?;
+        }
+    }
+
+    /// Moves a value into a free block if there is one. Otherwise the value is given back.
+    pub fn try_alloc(&self, value: T) -> Result<PoolBox<'_, T>, T> {
+        let Some(index) = self.used.iter().position(|used| {
+            used.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+        }) else {
+            return Err(value);
+        };
+
+        // The block is exclusively owned until `used` is cleared
+        let block = unsafe { &mut *self.blocks[index].get() };
+        Ok(PoolBox {
+            value: block.write(value),
+            used: &self.used[index],
+            released: &self.released,
+        })
+    }
+
+    /// Number of free blocks at the moment.
+    pub fn available(&self) -> usize {
+        self.used
+            .iter()
+            .filter(|used| !used.load(Ordering::Relaxed))
+            .count()
+    }
+}
+
+impl<T, const N: usize> Default for Pool<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Value stored in a block of [`Pool`]. The block is returned to the pool when this is dropped.
+pub struct PoolBox<'a, T> {
+    value: &'a mut T,
+    used: &'a AtomicBool,
+    released: &'a Futex,
+}
+
+impl<T> Deref for PoolBox<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T> DerefMut for PoolBox<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.value
+    }
+}
+
+impl<T> Drop for PoolBox<'_, T> {
+    fn drop(&mut self) {
+        unsafe {
+            core::ptr::drop_in_place(self.value as *mut T);
+        }
+        self.used.store(false, Ordering::Release);
+
+        self.released.as_ref().fetch_add(1, Ordering::SeqCst);
+        // Waking up cannot fail after the scheduler is initialized
+        let _ = self.released.wake_one();
+    }
+}
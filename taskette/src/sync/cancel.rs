@@ -0,0 +1,274 @@
+//! Cooperative cancellation of tasks.
+//!
+//! A [`CancellationToken`] is a flag set once by [`CancellationToken::cancel`].
+//! Tasks check it in their loops, or block in cancellable waits
+//! ([`CancellationToken::wait`], [`CancellationToken::sleep_until`], `Futex::wait_cancellable`,
+//! `Channel::recv_cancellable`, and `Channel::send_cancellable`), which return `Error::Cancelled` when it is set:
+//!
+//! ```ignore
+//! static SHUTDOWN: CancellationToken = CancellationToken::new();
+//!
+//! // In a worker task
+//! let session = SHUTDOWN.child();
+//! loop {
+//!     match REQUESTS.recv_cancellable(&session) {
+//!         Ok(request) => handle(request),
+//!         Err(Error::Cancelled) => break,
+//!         Err(error) => return Err(error),
+//!     }
+//! }
+//! ```
+//!
+//! A child token is cancelled together with its parent, but cancelling a child does not affect the parent.
+
+use core::{
+    cell::RefCell,
+    pin::Pin,
+    sync::atomic::Ordering,
+    task::{Context, Poll, Waker},
+};
+
+use critical_section::{CriticalSection, Mutex};
+use heapless::Vec;
+use portable_atomic::AtomicBool;
+
+use crate::{
+    Error,
+    scheduler::{MAX_NUM_TASKS, Wakeups, block_task, current_task_id, kernel_section},
+    sync::interrupt_free,
+    timer::wait_task_until,
+};
+
+/// Maximum number of tasks and futures waiting on any tokens at the same time
+const MAX_WAITERS: usize = MAX_NUM_TASKS * 2;
+
+/// Task or future blocked in a cancellable wait
+struct Waiter {
+    token: *const CancellationToken<'static>,
+    /// Task ID, or the address of a [`Cancelled`] future
+    key: usize,
+    /// Waker of a future (`None` for a task)
+    waker: Option<Waker>,
+}
+
+// The token is only dereferenced while its waiter is registered, which the waiter ensures by unregistering
+unsafe impl Send for Waiter {}
+
+static WAITERS: Mutex<RefCell<Vec<Waiter, MAX_WAITERS>>> = Mutex::new(RefCell::new(Vec::new()));
+
+/// Flag for asking tasks to stop, optionally linked to a parent token.
+pub struct CancellationToken<'a> {
+    cancelled: AtomicBool,
+    parent: Option<&'a CancellationToken<'a>>,
+}
+
+impl CancellationToken<'_> {
+    /// Creates a token without a parent.
+    pub const fn new() -> Self {
+        Self {
+            cancelled: AtomicBool::new(false),
+            parent: None,
+        }
+    }
+
+    /// Creates a token which is also cancelled when `self` (or one of its ancestors) is cancelled.
+    pub const fn child(&self) -> CancellationToken<'_> {
+        CancellationToken {
+            cancelled: AtomicBool::new(false),
+            parent: Some(self),
+        }
+    }
+
+    /// Cancels the token and its descendants, waking the tasks blocked in cancellable waits on them.
+    ///
+    /// Can be called from interrupt handlers.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+
+        let mut wakers: Vec<Waker, MAX_WAITERS> = Vec::new();
+        kernel_section(|cs| {
+            let mut wakeups = Wakeups::new(cs);
+            let mut waiters = WAITERS.borrow_ref_mut(cs);
+            waiters.retain_mut(|waiter| {
+                // SAFETY: registered tokens are alive (see `Waiter`)
+                if !unsafe { &*waiter.token }.is_cancelled() {
+                    return true;
+                }
+
+                match waiter.waker.take() {
+                    Some(waker) => wakers.push(waker).unwrap_or_else(|_| unreachable!()),
+                    // Fails only if the task was killed while waiting
+                    None => {
+                        let _ = wakeups.unblock(waiter.key);
+                    }
+                }
+                false
+            });
+        });
+
+        // Outside the critical section, as wakers may call back into the scheduler
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    /// Whether the token or one of its ancestors has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+            || self.parent.is_some_and(|parent| parent.is_cancelled())
+    }
+
+    /// Returns `Error::Cancelled` if the token has been cancelled, for use with `?` in loops.
+    pub fn check(&self) -> Result<(), Error> {
+        if self.is_cancelled() {
+            Err(Error::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Blocks the current task until the token is cancelled.
+    pub fn wait(&self) -> Result<(), Error> {
+        while !self.is_cancelled() {
+            let task_id = current_task_id()?;
+            let result = kernel_section(|cs| {
+                if self.is_cancelled() {
+                    return Ok(());
+                }
+                register(cs, self, task_id)?;
+                block_task(task_id)
+            });
+            unregister(self, task_id);
+            result?;
+        }
+
+        Ok(())
+    }
+
+    /// Blocks the current task until the time reaches `time` (in ticks),
+    /// or returns `Error::Cancelled` as soon as the token is cancelled.
+    pub fn sleep_until(&self, time: u64) -> Result<(), Error> {
+        let task_id = current_task_id()?;
+        let result = kernel_section(|cs| {
+            self.check()?;
+            register(cs, self, task_id)?;
+            wait_task_until(time, task_id)
+        });
+        unregister(self, task_id);
+        result?;
+
+        self.check()
+    }
+
+    /// Returns a future which completes when the token is cancelled.
+    pub fn cancelled(&self) -> Cancelled<'_> {
+        Cancelled { token: self }
+    }
+}
+
+impl Default for CancellationToken<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl core::fmt::Debug for CancellationToken<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// Future returned by [`CancellationToken::cancelled`]
+pub struct Cancelled<'a> {
+    token: &'a CancellationToken<'a>,
+}
+
+impl Future for Cancelled<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.token.is_cancelled() {
+            return Poll::Ready(());
+        }
+
+        let key = &*self as *const Self as usize;
+        let registered = kernel_section(|cs| {
+            // Checked again, as `cancel` wakes only the futures registered before it
+            if self.token.is_cancelled() {
+                return false;
+            }
+
+            let mut waiters = WAITERS.borrow_ref_mut(cs);
+            if let Some(waiter) = waiters
+                .iter_mut()
+                .find(|waiter| waiter.key == key && waiter.waker.is_some())
+            {
+                waiter.waker = Some(cx.waker().clone());
+                true
+            } else {
+                waiters
+                    .push(Waiter {
+                        token: erase(self.token),
+                        key,
+                        waker: Some(cx.waker().clone()),
+                    })
+                    .is_ok()
+            }
+        });
+
+        if self.token.is_cancelled() {
+            Poll::Ready(())
+        } else {
+            if !registered {
+                // No room to register, so polled again later instead
+                cx.waker().wake_by_ref();
+            }
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for Cancelled<'_> {
+    fn drop(&mut self) {
+        let key = self as *const Self as usize;
+        interrupt_free(|cs| {
+            WAITERS
+                .borrow_ref_mut(cs)
+                .retain(|waiter| !(waiter.key == key && waiter.waker.is_some()));
+        });
+    }
+}
+
+/// Registers the current task as waiting on `token`, so that `cancel` unblocks it.
+///
+/// The task must call [`unregister`] after the wait, before `token` can be dropped.
+pub(crate) fn register(
+    cs: CriticalSection,
+    token: &CancellationToken,
+    task_id: usize,
+) -> Result<(), Error> {
+    WAITERS
+        .borrow_ref_mut(cs)
+        .push(Waiter {
+            token: erase(token),
+            key: task_id,
+            waker: None,
+        })
+        .or(Err(Error::QueueFull))
+}
+
+/// Removes the registration made by [`register`], if `cancel` has not removed it yet.
+pub(crate) fn unregister(token: &CancellationToken, task_id: usize) {
+    let token = erase(token);
+    interrupt_free(|cs| {
+        WAITERS.borrow_ref_mut(cs).retain(|waiter| {
+            !(waiter.key == task_id && waiter.waker.is_none() && waiter.token == token)
+        });
+    });
+}
+
+fn erase(token: &CancellationToken) -> *const CancellationToken<'static> {
+    (token as *const CancellationToken).cast()
+}
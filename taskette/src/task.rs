@@ -2,27 +2,104 @@
 //!
 //! The API is basically modeled after `std::thread` of the Rust standard library but many functions are changed to return `Result`.
 
-use crate::{Error, scheduler::current_task_id};
+mod group;
+mod respawn;
+
+use core::{cell::UnsafeCell, fmt, panic::PanicInfo};
+
+use portable_atomic::{AtomicBool, Ordering};
+
+#[cfg(feature = "task-log-context")]
+pub use crate::log_wrapper::LogContext;
+#[cfg(feature = "alloc")]
+use crate::scheduler::spawn_heap;
+use crate::{
+    Error,
+    arch::{STACK_ALIGN, StackAllocation, core_id},
+    scheduler::{
+        IDLE_TASK_ID, current_task_id, kill_task, park_current_task, spawn, task_state, unpark_task,
+    },
+};
+pub use group::TaskGroup;
+pub use respawn::{MAX_RESPAWNABLE_TASKS, spawn_respawnable};
+
+/// Function called with the task ID when a task panics (see [`crate::scheduler::handle_panic`])
+pub type PanicHook = fn(usize, &PanicInfo);
 
 /// Handle object for a task.
 ///
 /// This is just a surrogate for a task ID.
 /// Dropping this has no effect on the actual task.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct TaskHandle {
     pub(crate) id: usize,
 }
 
 impl TaskHandle {
+    /// Handle of the idle task of the calling core.
+    pub fn idle() -> Self {
+        Self {
+            id: IDLE_TASK_ID + core_id(),
+        }
+    }
+
     pub fn id(&self) -> usize {
         self.id
     }
+
+    /// Current state of the task.
+    ///
+    /// The state may change right after this returns (except `Finished`), so this is for monitoring rather than synchronization.
+    pub fn state(&self) -> TaskState {
+        task_state(self.id)
+    }
+
+    /// Whether the task has finished (returned, panicked, or been killed).
+    pub fn is_finished(&self) -> bool {
+        self.state() == TaskState::Finished
+    }
 }
 
+/// Shown as `Task #<ID>`, like the log messages of the scheduler.
+impl fmt::Display for TaskHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Task #{}", self.id)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for TaskHandle {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "Task #{=usize}", self.id)
+    }
+}
+
+/// State of a task, returned by [`TaskHandle::state`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TaskState {
+    /// Running on a core
+    Running,
+    /// Waiting for a core
+    Ready,
+    /// Waiting for an event (e.g. a futex, a timer, or `unpark`)
+    Blocked,
+    /// Removed from the scheduler
+    Finished,
+}
+
+/// Options of a new task, passed to `spawn` and its variants. [`Builder`] sets them with the stack in one chain.
 #[derive(Clone, Debug)]
 #[non_exhaustive]
 pub struct TaskConfig {
     pub(crate) priority: usize,
+    pub(crate) affinity: Option<usize>,
+    pub(crate) panic_hook: Option<PanicHook>,
+    // Only passed to debugger awareness with `profile-tiny`, which drops the name from the TCB
+    #[cfg_attr(feature = "profile-tiny", allow(dead_code))]
+    pub(crate) name: Option<&'static str>,
+    #[cfg_attr(not(feature = "stack-canary"), allow(dead_code))]
+    pub(crate) stack_canary: Option<bool>,
 }
 
 impl TaskConfig {
@@ -32,11 +109,214 @@ impl TaskConfig {
     pub fn with_priority(self, priority: usize) -> Self {
         Self { priority, ..self }
     }
+
+    /// Pins the task to the specified core.
+    ///
+    /// By default, a task may run on any core. Only meaningful with the `smp` feature.
+    pub fn with_affinity(self, core_id: usize) -> Self {
+        Self {
+            affinity: Some(core_id),
+            ..self
+        }
+    }
+
+    /// Sets a function called when the task panics, instead of the global one set by [`crate::scheduler::set_task_panic_hook`].
+    pub fn with_panic_hook(self, hook: PanicHook) -> Self {
+        Self {
+            panic_hook: Some(hook),
+            ..self
+        }
+    }
+
+    /// Sets a name of the task, which is shown by debugging tools.
+    ///
+    /// With `profile-tiny`, the name is not kept in the scheduler, so only debugger awareness shows it.
+    pub fn with_name(self, name: &'static str) -> Self {
+        Self {
+            name: Some(name),
+            ..self
+        }
+    }
+
+    /// Enables or disables the stack canary of the task, overriding `SchedulerConfig::with_stack_canary_by_default`.
+    ///
+    /// A task without the canary is not checked at context switches, e.g. to shorten the switch to a latency-critical task,
+    /// and its stack has no room reserved for the pattern. Only meaningful with the `stack-canary` feature.
+    pub fn with_stack_canary(self, enabled: bool) -> Self {
+        Self {
+            stack_canary: Some(enabled),
+            ..self
+        }
+    }
 }
 
 impl Default for TaskConfig {
     fn default() -> Self {
-        Self { priority: 1 }
+        Self {
+            priority: 1,
+            affinity: None,
+            panic_hook: None,
+            name: None,
+            stack_canary: None,
+        }
+    }
+}
+
+/// Factory of a task with its options, like `std::thread::Builder`:
+///
+/// ```ignore
+/// let handle = task::Builder::new()
+///     .name("usb")
+///     .priority(2)
+///     .stack(USB_STACK.take())
+///     .spawn(usb_task)?;
+/// ```
+///
+/// `S` is the type of the stack, which is `()` until [`Builder::stack`] is called.
+/// Options not set keep the defaults of [`TaskConfig`].
+#[derive(Clone, Debug)]
+pub struct Builder<S = ()> {
+    config: TaskConfig,
+    stack: S,
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self::from_config(TaskConfig::default())
+    }
+
+    /// Starts from the options of `config`.
+    pub fn from_config(config: TaskConfig) -> Self {
+        Self { config, stack: () }
+    }
+
+    /// Creates the task with a stack of `stack_size` bytes allocated from the global allocator (see `spawn_heap`).
+    #[cfg(feature = "alloc")]
+    pub fn spawn_heap<F: FnOnce() + Send + 'static>(
+        self,
+        stack_size: usize,
+        func: F,
+    ) -> Result<TaskHandle, Error> {
+        spawn_heap(func, stack_size, self.config)
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Builder<S> {
+    /// Sets the name (see [`TaskConfig::with_name`]).
+    pub fn name(self, name: &'static str) -> Self {
+        Self {
+            config: self.config.with_name(name),
+            ..self
+        }
+    }
+
+    /// Sets the priority (see [`TaskConfig::with_priority`]).
+    pub fn priority(self, priority: usize) -> Self {
+        Self {
+            config: self.config.with_priority(priority),
+            ..self
+        }
+    }
+
+    /// Pins the task to a core (see [`TaskConfig::with_affinity`]).
+    pub fn affinity(self, core_id: usize) -> Self {
+        Self {
+            config: self.config.with_affinity(core_id),
+            ..self
+        }
+    }
+
+    /// Sets the panic hook of the task (see [`TaskConfig::with_panic_hook`]).
+    pub fn panic_hook(self, hook: PanicHook) -> Self {
+        Self {
+            config: self.config.with_panic_hook(hook),
+            ..self
+        }
+    }
+
+    /// Enables or disables the stack canary of the task (see [`TaskConfig::with_stack_canary`]).
+    pub fn stack_canary(self, enabled: bool) -> Self {
+        Self {
+            config: self.config.with_stack_canary(enabled),
+            ..self
+        }
+    }
+
+    /// Sets the stack of the task.
+    pub fn stack<T: StackAllocation>(self, stack: T) -> Builder<T> {
+        Builder {
+            config: self.config,
+            stack,
+        }
+    }
+
+    /// Options set so far
+    pub fn config(&self) -> &TaskConfig {
+        &self.config
+    }
+}
+
+impl<S: StackAllocation> Builder<S> {
+    /// Creates the task running `func` and starts it.
+    pub fn spawn<F: FnOnce() + Send + 'static>(self, func: F) -> Result<TaskHandle, Error> {
+        spawn(func, self.stack, self.config)
+    }
+}
+
+/// Stack in a `static`, which can be taken only once. Used by the `#[taskette::task]` macro.
+///
+/// Unlike the `Stack` of each port, this needs no `StaticCell` to get a `'static` reference,
+/// and is aligned at [`STACK_ALIGN`] to meet the requirements of all ports.
+pub struct StaticStack<const N: usize> {
+    memory: UnsafeCell<StackMemory<N>>,
+    taken: AtomicBool,
+}
+
+#[repr(align(16))]
+struct StackMemory<const N: usize>([u8; N]);
+
+const _: () = assert!(align_of::<StackMemory<0>>() == STACK_ALIGN);
+
+// The memory is only accessed through the reference given once by `take`
+unsafe impl<const N: usize> Sync for StaticStack<N> {}
+
+impl<const N: usize> StaticStack<N> {
+    pub const fn new() -> Self {
+        Self {
+            memory: UnsafeCell::new(StackMemory([0; N])),
+            taken: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the stack, or `None` if it was already taken.
+    pub fn take(&'static self) -> Option<StaticStackRef> {
+        if self.taken.swap(true, Ordering::AcqRel) {
+            return None;
+        }
+
+        // SAFETY: the flag above ensures that this is the only reference
+        Some(StaticStackRef(unsafe { &mut (*self.memory.get()).0 }))
+    }
+}
+
+impl<const N: usize> Default for StaticStack<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Stack taken from a [`StaticStack`]
+pub struct StaticStackRef(&'static mut [u8]);
+
+impl StackAllocation for StaticStackRef {
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        self.0
     }
 }
 
@@ -45,3 +325,35 @@ pub fn current() -> Result<TaskHandle, Error> {
         id: current_task_id()?,
     })
 }
+
+/// Removes the task with ID `task_id`, wherever it is running or waiting.
+///
+/// The task is stopped without unwinding: its closure is not dropped, and locks held by it are never released.
+/// Intended for debugging tools rather than normal shutdown. Idle tasks cannot be killed (`Error::NotPermitted`).
+/// If `task_id` is the calling task, this does not return.
+pub fn kill(task_id: usize) -> Result<(), Error> {
+    kill_task(task_id)
+}
+
+/// Blocks the calling task until [`unpark`] is called for it.
+///
+/// Like `std::thread::park`, each task has a token which `unpark` sets when the task is not parked.
+/// A set token is consumed by the next call, which then returns immediately, so a wakeup is never lost.
+/// Tokens do not accumulate. Idle tasks cannot park (`Error::NotPermitted`).
+pub fn park() -> Result<(), Error> {
+    park_current_task(None)
+}
+
+/// Same as [`park`], but also returns when the time reaches `time` (in ticks).
+///
+/// Which one ended the wait is not reported; the caller checks its own condition and the time.
+pub fn park_until(time: u64) -> Result<(), Error> {
+    park_current_task(Some(time))
+}
+
+/// Wakes the task with ID `task_id` blocked by [`park`], or sets its token if it is not parked.
+///
+/// Tasks blocked by anything else (e.g. a futex or a timer) are not woken. Can be called from interrupt handlers.
+pub fn unpark(task_id: usize) -> Result<(), Error> {
+    unpark_task(task_id)
+}
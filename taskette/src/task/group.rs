@@ -0,0 +1,166 @@
+//! Groups of related tasks, joined or cancelled together.
+//!
+//! For example, a connection handler spawns its helper tasks into a [`TaskGroup`] per session,
+//! and tears all of them down with one call when the session ends:
+//!
+//! ```ignore
+//! let mut session = TaskGroup::<3>::new();
+//! session.spawn(rx_task, RX_STACK.take(), TaskConfig::default())?;
+//! session.spawn(tx_task, TX_STACK.take(), TaskConfig::default())?;
+//! session.spawn(keepalive_task, KEEPALIVE_STACK.take(), TaskConfig::default())?;
+//!
+//! // The session ended
+//! session.cancel_all()?;
+//! ```
+
+use core::sync::atomic::Ordering;
+
+use heapless::Vec;
+
+#[cfg(feature = "alloc")]
+use crate::scheduler::spawn_heap;
+use crate::{
+    Error,
+    arch::StackAllocation,
+    scheduler::{TASK_EXITS, spawn},
+    task::{TaskConfig, TaskHandle, current, kill},
+};
+
+/// Set of up to `N` tasks.
+///
+/// The group only holds handles, so dropping it does not affect the tasks.
+#[derive(Clone, Debug, Default)]
+pub struct TaskGroup<const N: usize> {
+    tasks: Vec<TaskHandle, N>,
+}
+
+impl<const N: usize> TaskGroup<N> {
+    pub const fn new() -> Self {
+        Self { tasks: Vec::new() }
+    }
+
+    /// Creates a new task in the group (see `spawn`).
+    ///
+    /// Returns `Error::TaskFull` without creating the task if the group already has `N` unfinished tasks.
+    pub fn spawn<F: FnOnce() + Send + 'static, S: StackAllocation>(
+        &mut self,
+        func: F,
+        stack: S,
+        config: TaskConfig,
+    ) -> Result<TaskHandle, Error> {
+        self.reserve()?;
+        let handle = spawn(func, stack, config)?;
+        self.tasks
+            .push(handle.clone())
+            .unwrap_or_else(|_| unreachable!());
+        Ok(handle)
+    }
+
+    /// Creates a new task with a stack allocated from the global allocator in the group (see `spawn_heap`).
+    #[cfg(feature = "alloc")]
+    pub fn spawn_heap<F: FnOnce() + Send + 'static>(
+        &mut self,
+        func: F,
+        stack_size: usize,
+        config: TaskConfig,
+    ) -> Result<TaskHandle, Error> {
+        self.reserve()?;
+        let handle = spawn_heap(func, stack_size, config)?;
+        self.tasks
+            .push(handle.clone())
+            .unwrap_or_else(|_| unreachable!());
+        Ok(handle)
+    }
+
+    /// Adds a task created in another way (e.g. by `Builder`) to the group.
+    pub fn add(&mut self, handle: TaskHandle) -> Result<(), Error> {
+        self.reserve()?;
+        self.tasks.push(handle).unwrap_or_else(|_| unreachable!());
+        Ok(())
+    }
+
+    /// Tasks in the group (including finished ones not yet joined)
+    pub fn tasks(&self) -> &[TaskHandle] {
+        &self.tasks
+    }
+
+    /// Number of unfinished tasks in the group
+    pub fn running(&self) -> usize {
+        self.tasks.iter().filter(|task| !task.is_finished()).count()
+    }
+
+    /// Blocks the current task until all tasks in the group finish, and empties the group.
+    ///
+    /// Returns `Error::NotPermitted` if called from a task in the group, which would never return.
+    pub fn join_all(&mut self) -> Result<(), Error> {
+        self.join_inner(None).map(|_| ())
+    }
+
+    /// Same as [`TaskGroup::join_all`], but gives up when the time reaches `time` (in ticks).
+    ///
+    /// Returns whether all tasks finished. On timeout, the unfinished tasks stay in the group.
+    pub fn join_all_until(&mut self, time: u64) -> Result<bool, Error> {
+        self.join_inner(Some(time))
+    }
+
+    /// Kills all unfinished tasks in the group (see `kill`), and empties the group.
+    ///
+    /// If the calling task is in the group, it is killed last and this does not return.
+    pub fn cancel_all(&mut self) -> Result<(), Error> {
+        let current = current()?;
+
+        for task in self.tasks.iter().filter(|task| **task != current) {
+            match kill(task.id()) {
+                Ok(()) | Err(Error::NotFound) => (),
+                Err(error) => return Err(error),
+            }
+        }
+
+        let in_group = self.tasks.contains(&current);
+        self.tasks.clear();
+        if in_group {
+            kill(current.id())?;
+        }
+
+        Ok(())
+    }
+
+    fn join_inner(&mut self, time: Option<u64>) -> Result<bool, Error> {
+        if self.tasks.contains(&current()?) {
+            return Err(Error::NotPermitted);
+        }
+
+        loop {
+            // Read before checking the tasks, so that an exit in between makes the wait return immediately
+            let exits = TASK_EXITS.as_ref().load(Ordering::SeqCst);
+            self.tasks.retain(|task| !task.is_finished());
+            if self.tasks.is_empty() {
+                return Ok(true);
+            }
+
+            match time {
+                Some(time) => {
+                    if !TASK_EXITS.wait_until(exits, time)? {
+                        // Tasks may have finished just at the deadline
+                        self.tasks.retain(|task| !task.is_finished());
+                        return Ok(self.tasks.is_empty());
+                    }
+                }
+                None => TASK_EXITS.wait(exits)?,
+            }
+        }
+    }
+
+    /// Makes room for one more task by dropping finished ones.
+    fn reserve(&mut self) -> Result<(), Error> {
+        if self.tasks.is_full() {
+            self.tasks.retain(|task| !task.is_finished());
+        }
+
+        if self.tasks.is_full() {
+            Err(Error::TaskFull)
+        } else {
+            Ok(())
+        }
+    }
+}
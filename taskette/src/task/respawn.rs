@@ -0,0 +1,131 @@
+//! Tasks which can be run again on their original stack after they finish.
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use heapless::Vec;
+
+use crate::{
+    Error,
+    arch::StackAllocation,
+    scheduler::{MAX_NUM_TASKS, is_running_on_any_core, respawn_inner, spawn},
+    sync::interrupt_free,
+    task::{TaskConfig, TaskHandle, TaskState},
+};
+
+/// Maximum number of tasks created by [`spawn_respawnable`]
+pub const MAX_RESPAWNABLE_TASKS: usize = MAX_NUM_TASKS;
+
+/// What is needed to run a respawnable task again
+struct Respawnable {
+    /// `None` while the first spawn is in progress
+    id: Option<usize>,
+    entry: fn(),
+    /// Start and end of the stack memory
+    stack: (usize, usize),
+    config: TaskConfig,
+    /// Set while the task is being respawned, so that two callers do not initialize the stack at the same time
+    claimed: bool,
+}
+
+static RESPAWNABLE: Mutex<RefCell<Vec<Respawnable, MAX_RESPAWNABLE_TASKS>>> =
+    Mutex::new(RefCell::new(Vec::new()));
+
+/// Stack memory of a finished task
+struct ReusedStack(usize, usize);
+
+impl StackAllocation for ReusedStack {
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        // SAFETY: the memory was given to the task for `'static` by `spawn_respawnable` and is no longer used by it
+        unsafe { core::slice::from_raw_parts_mut(self.0 as *mut u8, self.1 - self.0) }
+    }
+}
+
+/// Creates a new task running `entry`, which can be run again with [`TaskHandle::respawn`] after it finishes.
+///
+/// The stack is kept for the task forever, so that no new stack is needed for each run
+/// (e.g. a worker which runs to completion on demand).
+/// Returns `Error::TaskFull` if [`MAX_RESPAWNABLE_TASKS`] respawnable tasks already exist.
+pub fn spawn_respawnable<S: StackAllocation + 'static>(
+    entry: fn(),
+    stack: S,
+    config: TaskConfig,
+) -> Result<TaskHandle, Error> {
+    let mut stack = stack;
+    let range = stack.as_mut_slice().as_mut_ptr_range();
+
+    // Reserved before spawning, so that the task is never left without a record
+    interrupt_free(|cs| {
+        RESPAWNABLE
+            .borrow_ref_mut(cs)
+            .push(Respawnable {
+                id: None,
+                entry,
+                stack: (range.start as usize, range.end as usize),
+                config: config.clone(),
+                claimed: false,
+            })
+            .or(Err(Error::TaskFull))
+    })?;
+    let stack_start = range.start as usize;
+
+    let result = spawn(entry, stack, config);
+    interrupt_free(|cs| {
+        let mut records = RESPAWNABLE.borrow_ref_mut(cs);
+        let index = records
+            .iter()
+            .position(|record| record.id.is_none() && record.stack.0 == stack_start)
+            .unwrap_or_else(|| unreachable!());
+        match &result {
+            Ok(handle) => records[index].id = Some(handle.id()),
+            Err(_) => {
+                records.swap_remove(index);
+            }
+        }
+    });
+
+    result
+}
+
+impl TaskHandle {
+    /// Runs the entry of a task created by [`spawn_respawnable`] again on the same stack, keeping its ID.
+    ///
+    /// Returns `Error::NotFound` if the task was not created by `spawn_respawnable`,
+    /// `Error::NotPermitted` if it has not finished (or is being respawned by another caller),
+    /// and `Error::WouldBlock` if it has finished but is still being switched out of a core (try again later).
+    pub fn respawn(&self) -> Result<(), Error> {
+        let id = self.id();
+
+        let (entry, stack, config) = interrupt_free(|cs| {
+            let mut records = RESPAWNABLE.borrow_ref_mut(cs);
+            let record = records
+                .iter_mut()
+                .find(|record| record.id == Some(id))
+                .ok_or(Error::NotFound)?;
+            if record.claimed || self.state() != TaskState::Finished {
+                return Err(Error::NotPermitted);
+            }
+            // A removed task never runs again, so its stack is free once it is switched out
+            if is_running_on_any_core(id) {
+                return Err(Error::WouldBlock);
+            }
+
+            record.claimed = true;
+            Ok((record.entry, record.stack, record.config.clone()))
+        })?;
+
+        let result = respawn_inner(entry, ReusedStack(stack.0, stack.1), config, id);
+
+        interrupt_free(|cs| {
+            if let Some(record) = RESPAWNABLE
+                .borrow_ref_mut(cs)
+                .iter_mut()
+                .find(|record| record.id == Some(id))
+            {
+                record.claimed = false;
+            }
+        });
+
+        result.map(|_| ())
+    }
+}
@@ -6,19 +6,37 @@
 
 use core::cell::RefCell;
 
-use critical_section::Mutex;
-use heapless::{BinaryHeap, binary_heap::Min};
+use critical_section::{CriticalSection, Mutex};
+use heapless::binary_heap::{BinaryHeapView, Min};
+use portable_atomic::{AtomicU64, Ordering};
 
 use crate::{
-    Error,
-    scheduler::{block_task, current_task_id, unblock_task},
+    Error, arch,
+    scheduler::{
+        MAX_NUM_TASKS, Wakeups, block_task, current_task_id, get_config, is_timeout_pending,
+        kernel_section, set_timeout, unblock_timed_task,
+    },
+    sync::interrupt_free,
 };
 
-const MAX_TIMER_REGS: usize = 32;
+pub mod wall_clock;
+
+/// Maximum number of timer registrations with the default storage (see `profile`).
+///
+/// Never less than the number of tasks, so that registering a timeout never fails with `Error::TimerFull`.
+pub(crate) const MAX_TIMER_REGS: usize = if crate::profile::MAX_TIMER_REGS > MAX_NUM_TASKS {
+    crate::profile::MAX_TIMER_REGS
+} else {
+    MAX_NUM_TASKS
+};
 
 static TIMER: Mutex<RefCell<Option<Timer>>> = Mutex::new(RefCell::new(None));
+/// Current time (in ticks), read without a critical section. Only written while `TIMER` is borrowed.
+static TIME: AtomicU64 = AtomicU64::new(UNINITIALIZED);
+/// Value of `TIME` before the timer is initialized
+const UNINITIALIZED: u64 = u64::MAX;
 
-struct TimerRegistry {
+pub(crate) struct TimerRegistry {
     time: u64,
     task_id: usize,
 }
@@ -45,77 +63,240 @@ impl PartialEq for TimerRegistry {
 impl Eq for TimerRegistry {}
 
 struct Timer {
-    time: u64,
-    queue: BinaryHeap<TimerRegistry, Min, MAX_TIMER_REGS>,
+    /// Cycle count of the port at the last tick
+    tick_cycles: u32,
+    /// Whether a tick has occurred, after which `tick_cycles` follows the tick period
+    ticking: bool,
+    queue: &'static mut BinaryHeapView<TimerRegistry, Min>,
 }
 
-pub(crate) fn init() {
-    critical_section::with(|cs| {
+pub(crate) fn init(queue: &'static mut BinaryHeapView<TimerRegistry, Min>) {
+    interrupt_free(|cs| {
         TIMER.replace(
             cs,
             Some(Timer {
-                time: 0,
-                queue: BinaryHeap::new(),
+                tick_cycles: arch::cycle_count(),
+                ticking: false,
+                queue,
             }),
-        )
+        );
+        TIME.store(0, Ordering::Release);
     });
 }
 
 pub(crate) fn tick() {
-    critical_section::with(|cs| {
+    // Cycles per tick period, if the cycle counter of the port can tell how many ticks were lost
+    // (manual ticks are injected regardless of the period)
+    let period = match (arch::tick_source().cycle_freq(), get_config()) {
+        (Some(cycle_freq), Ok(config)) if config.tick_freq > 0 && !config.manual_tick => {
+            Some(cycle_freq / config.tick_freq).filter(|&period| period > 0)
+        }
+        _ => None,
+    };
+
+    kernel_section(|cs| {
         let mut timer = TIMER.borrow_ref_mut(cs);
         let Some(timer) = timer.as_mut() else {
             return;
         };
 
-        timer.time += 1;
-
-        if let Some(top) = timer.queue.peek() {
-            if top.time <= timer.time {
-                // Timer ringing
-                let top = unsafe { timer.queue.pop_unchecked() }; // Safe because the heap is obviously not empty.
-                let _ = unblock_task(top.task_id);
+        // A tick interrupt masked for longer than a period stands for all the ticks since the last one.
+        // Only gaps shorter than a wrap-around of the cycle counter can be counted (e.g. about 4.3 s for 1 GHz);
+        // ports which stop the tick for longer (e.g. tickless idle) have to report the gap with `skip_ticks`.
+        let cycles = arch::cycle_count();
+        let ticks = match period {
+            Some(period) if timer.ticking => {
+                let ticks = cycles.wrapping_sub(timer.tick_cycles) / period;
+                if ticks == 0 {
+                    // Earlier than the period (e.g. after `skip_ticks`)
+                    timer.tick_cycles = cycles;
+                    1
+                } else {
+                    // Kept at the nominal time of the tick, so that interrupt latency is not counted as lost ticks
+                    timer.tick_cycles = timer.tick_cycles.wrapping_add(ticks * period);
+                    ticks
+                }
             }
+            _ => {
+                timer.tick_cycles = cycles;
+                1
+            }
+        };
+        timer.ticking = true;
+
+        let now = TIME.load(Ordering::Relaxed) + ticks as u64;
+        TIME.store(now, Ordering::Release);
+        #[cfg(feature = "defmt-events")]
+        crate::events::set_time(cs, now);
+
+        // Every expired registration rings, including ones with the same time and ones missed by lost ticks
+        let mut wakeups = Wakeups::new(cs);
+        while let Some(top) = timer.queue.peek()
+            && top.time <= now
+        {
+            let top = unsafe { timer.queue.pop_unchecked() }; // Safe because the heap is obviously not empty.
+            let _ = unblock_timed_task(&mut wakeups, top.task_id, top.time);
+            #[cfg(feature = "stats")]
+            crate::stats::count(&crate::stats::TIMER_WAKEUPS);
         }
     })
 }
 
+/// Checks the ordering of the timer queue (`paranoid-checks` feature). Skipped while the queue is being modified.
+#[cfg(feature = "paranoid-checks")]
+pub(crate) fn check_invariants(cs: CriticalSection) -> Result<(), &'static str> {
+    let Ok(timer) = TIMER.borrow(cs).try_borrow() else {
+        return Ok(());
+    };
+    let Some(timer) = timer.as_ref() else {
+        return Ok(());
+    };
+
+    // Each registration is not earlier than its parent in the binary heap
+    let heap = timer.queue.iter().as_slice();
+    if (1..heap.len()).any(|i| heap[i] < heap[(i - 1) / 2]) {
+        return Err("Timer queue is out of order");
+    }
+
+    Ok(())
+}
+
 /// Registers a one-shot timeout that wakes the specified task up on `time`.
 pub(crate) fn wait_task_until(time: u64, task_id: usize) -> Result<(), Error> {
     let registry = TimerRegistry { time, task_id };
 
-    critical_section::with(|cs| {
+    kernel_section(|cs| {
         let mut timer = TIMER.borrow_ref_mut(cs);
         let Some(timer) = timer.as_mut() else {
             return Err(Error::NotInitialized);
         };
 
-        if registry.time <= timer.time {
+        if registry.time <= TIME.load(Ordering::Relaxed) {
             // The timer is ringing before queueing
             return Ok(());
         }
 
+        let time = registry.time;
+        if timer.queue.is_full() {
+            remove_stale(cs, timer.queue);
+        }
         timer.queue.push(registry).or(Err(Error::TimerFull))?;
 
+        set_timeout(cs, task_id, time)?;
         block_task(task_id)?;
 
         Ok(())
     })
 }
 
+/// Removes registrations of tasks woken up before their time (by a futex or `unpark`), to make room in a full queue.
+///
+/// Afterwards, each task has at most one registration,
+/// so a queue with room for all the tasks never stays full.
+fn remove_stale(cs: CriticalSection, queue: &mut BinaryHeapView<TimerRegistry, Min>) {
+    // Stale registrations are moved to the top by giving them time 0, which no valid registration has.
+    // A sorted array is a valid heap, so they can then be popped.
+    let registrations = queue.iter_mut().into_slice();
+    registrations.sort_unstable_by_key(|registration| (registration.time, registration.task_id));
+    let mut previous = None;
+    for registration in registrations.iter_mut() {
+        // A task woken up early and waiting again until the same time has duplicate registrations
+        let key = (registration.time, registration.task_id);
+        let duplicate = previous == Some(key);
+        previous = Some(key);
+        if duplicate || !is_timeout_pending(cs, registration.task_id, registration.time) {
+            registration.time = 0;
+        }
+    }
+    registrations.sort_unstable();
+
+    while queue.peek().is_some_and(|top| top.time == 0) {
+        queue.pop();
+    }
+}
+
+/// Returns the earliest time at which a sleeping task wakes up, or `None` if no task is sleeping.
+pub fn next_wakeup() -> Result<Option<u64>, Error> {
+    interrupt_free(|cs| {
+        let timer = TIMER.borrow_ref(cs);
+        let Some(timer) = timer.as_ref() else {
+            return Err(Error::NotInitialized);
+        };
+
+        Ok(timer.queue.peek().map(|registry| registry.time))
+    })
+}
+
+/// INTERNAL USE ONLY
+///
+/// Advances the time by `ticks` at once, for ports which stop the tick interrupt while idle (e.g. during sleep).
+/// The time stops just before the next wakeup, which is left to the next tick, and the skipped ticks are not counted as tick interrupts.
+pub fn skip_ticks(ticks: u64) {
+    kernel_section(|cs| {
+        let mut timer = TIMER.borrow_ref_mut(cs);
+        let Some(timer) = timer.as_mut() else {
+            return;
+        };
+
+        let now = TIME.load(Ordering::Relaxed);
+        let limit = timer.queue.peek().map_or(UNINITIALIZED - 1, |registry| {
+            registry.time.saturating_sub(1)
+        });
+        let now = now.saturating_add(ticks).min(limit.max(now));
+        TIME.store(now, Ordering::Release);
+        timer.tick_cycles = arch::cycle_count();
+        #[cfg(feature = "defmt-events")]
+        crate::events::set_time(cs, now);
+    })
+}
+
 /// Blocks the current task until the specificed time.
 pub fn wait_until(time: u64) -> Result<(), Error> {
     wait_task_until(time, current_task_id()?)
 }
 
 /// Retrieves current time (in ticks).
+///
+/// A single atomic load, so it is cheap enough for polling in a loop (e.g. `Delay`).
+/// On targets without lock-free 64-bit atomics (e.g. Armv7-M without the `unsafe-assume-single-core` feature
+/// of `portable-atomic`), the load takes a short critical section instead.
 pub fn current_time() -> Result<u64, Error> {
-    critical_section::with(|cs| {
+    let time = if AtomicU64::is_always_lock_free() {
+        TIME.load(Ordering::Acquire)
+    } else {
+        // The global lock of `portable-atomic` would deadlock if the tick interrupted its holder
+        interrupt_free(|_| TIME.load(Ordering::Acquire))
+    };
+
+    match time {
+        UNINITIALIZED => Err(Error::NotInitialized),
+        time => Ok(time),
+    }
+}
+
+/// Retrieves current time in microseconds, interpolated between ticks with the cycle counter of the port.
+///
+/// Only ports whose tick source reports the frequency of the cycle counter (see [`crate::arch::TickSource::cycle_freq`])
+/// give a resolution finer than a tick. The cycle counter only has to count one tick period without wrapping around,
+/// since the cycles are counted from the last tick.
+pub fn current_time_micros() -> Result<u64, Error> {
+    let tick_freq = get_config()?.tick_freq as u64;
+    let cycle_freq = arch::tick_source().cycle_freq();
+
+    interrupt_free(|cs| {
         let timer = TIMER.borrow_ref(cs);
         let Some(timer) = timer.as_ref() else {
             return Err(Error::NotInitialized);
         };
 
-        Ok(timer.time)
+        let micros = TIME.load(Ordering::Relaxed) * 1_000_000 / tick_freq;
+        let Some(cycle_freq) = cycle_freq else {
+            return Ok(micros);
+        };
+        let cycles = arch::cycle_count().wrapping_sub(timer.tick_cycles) as u64;
+        // Never beyond the next tick, which may be pending while interrupts are masked
+        let sub_tick = (cycles * 1_000_000 / cycle_freq as u64).min(1_000_000 / tick_freq);
+
+        Ok(micros + sub_tick)
     })
 }
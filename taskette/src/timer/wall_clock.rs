@@ -0,0 +1,264 @@
+//! Wall-clock (UTC) time, kept as an offset from the time of the scheduler.
+//!
+//! The scheduler only counts ticks since its start. Once the UTC time is known from a [`WallClock`] source
+//! (e.g. an RTC chip at boot, or an NTP client later), [`now_utc`] gives real timestamps for logs and stored records.
+//! The wall clock advances with the tick counter (interpolated by `current_time_micros`),
+//! so it drifts with the tick source until it is synchronized again.
+//!
+//! Tasks can sleep until a UTC time with [`wait_until_utc`], or until a time of day with [`DailyAlarm`].
+//! Their deadlines are converted to ticks again whenever the wall clock is set
+//! (e.g. resynchronized from an RTC after a deep sleep), so they follow the corrected time.
+
+use core::{cell::Cell, fmt, sync::atomic::Ordering, time::Duration};
+
+use critical_section::Mutex;
+
+use crate::{
+    Error,
+    futex::Futex,
+    scheduler::get_config,
+    sync::interrupt_free,
+    timer::{current_time, current_time_micros},
+};
+
+/// UTC time (in microseconds since the Unix epoch) at time 0 of the scheduler, or `None` before it is set
+static OFFSET: Mutex<Cell<Option<u64>>> = Mutex::new(Cell::new(None));
+/// Incremented whenever the wall clock is set (tasks waiting for a UTC time wait on this)
+static SETS: Futex = Futex::new(0);
+
+/// Source of UTC time, such as an RTC chip or an NTP client.
+pub trait WallClock {
+    type Error;
+
+    /// Reads the current time as the duration since the Unix epoch (1970-01-01 00:00:00 UTC).
+    fn read_utc(&mut self) -> Result<Duration, Self::Error>;
+
+    /// Sets the time of the source (e.g. an RTC to be corrected after NTP synchronization).
+    ///
+    /// The default does nothing, for sources which cannot be set.
+    fn write_utc(&mut self, _time: Duration) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Errors of synchronization with a [`WallClock`]
+#[derive(Clone, Debug)]
+pub enum SyncError<E> {
+    /// The source failed.
+    Clock(E),
+    /// The time of the scheduler is not available (or the wall clock is not set yet when writing to a source).
+    Kernel(Error),
+}
+
+/// Sets the current UTC time (as the duration since the Unix epoch).
+pub fn set_utc(time: Duration) -> Result<(), Error> {
+    let now = current_time_micros()?;
+    let offset = (time.as_micros() as u64).saturating_sub(now);
+    interrupt_free(|cs| OFFSET.borrow(cs).set(Some(offset)));
+
+    // Tasks waiting for a UTC time recompute their deadlines
+    SETS.as_ref().fetch_add(1, Ordering::SeqCst);
+    SETS.wake_all()
+}
+
+/// Current UTC time as the duration since the Unix epoch.
+/// `None` if the wall clock is not set yet or the scheduler is not initialized.
+pub fn now_utc() -> Option<Duration> {
+    let offset = interrupt_free(|cs| OFFSET.borrow(cs).get())?;
+    let now = current_time_micros().ok()?;
+    Some(Duration::from_micros(offset + now))
+}
+
+/// Whether the wall clock has been set.
+pub fn is_set() -> bool {
+    interrupt_free(|cs| OFFSET.borrow(cs).get().is_some())
+}
+
+/// Sets the wall clock from `clock`.
+pub fn sync_from<C: WallClock + ?Sized>(clock: &mut C) -> Result<(), SyncError<C::Error>> {
+    let time = clock.read_utc().map_err(SyncError::Clock)?;
+    set_utc(time).map_err(SyncError::Kernel)
+}
+
+/// Sets `clock` to the time of the wall clock.
+pub fn sync_to<C: WallClock + ?Sized>(clock: &mut C) -> Result<(), SyncError<C::Error>> {
+    let time = now_utc().ok_or(SyncError::Kernel(Error::NotInitialized))?;
+    clock.write_utc(time).map_err(SyncError::Clock)
+}
+
+/// Blocks the current task until the UTC time (as the duration since the Unix epoch) reaches `time`.
+///
+/// Returns `Error::NotInitialized` if the wall clock is not set.
+/// If the wall clock is set during the wait, the remaining time is computed again from the new time.
+pub fn wait_until_utc(time: Duration) -> Result<(), Error> {
+    let tick_freq = get_config()?.tick_freq as u128;
+
+    loop {
+        // Read before checking the time, so that setting it in between makes `wait_until` return immediately
+        let sets = SETS.as_ref().load(Ordering::SeqCst);
+        let now = now_utc().ok_or(Error::NotInitialized)?;
+        if now >= time {
+            return Ok(());
+        }
+
+        let ticks = ((time - now).as_micros() * tick_freq).div_ceil(1_000_000) as u64;
+        SETS.wait_until(sets, current_time()? + ticks)?;
+    }
+}
+
+/// Alarm going off at the same time of day (in UTC) every day, e.g. for a data logger sampling at noon.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DailyAlarm {
+    /// Seconds from midnight
+    second_of_day: u32,
+}
+
+impl DailyAlarm {
+    /// Creates an alarm at `hour:minute:second` UTC. Panics if a field is out of range.
+    pub const fn new(hour: u8, minute: u8, second: u8) -> Self {
+        assert!(
+            hour < 24 && minute < 60 && second < 60,
+            "Invalid time of day"
+        );
+
+        Self {
+            second_of_day: hour as u32 * 3600 + minute as u32 * 60 + second as u32,
+        }
+    }
+
+    /// Next time (as the duration since the Unix epoch) the alarm goes off, strictly after `time`.
+    pub fn next_after(&self, time: Duration) -> Duration {
+        let midnight = time.as_secs() / 86400 * 86400;
+        let today = Duration::from_secs(midnight + self.second_of_day as u64);
+        if today > time {
+            today
+        } else {
+            today + Duration::from_secs(86400)
+        }
+    }
+
+    /// Blocks the current task until the alarm goes off next, and returns that time (see [`wait_until_utc`]).
+    pub fn wait(&self) -> Result<Duration, Error> {
+        let time = self.next_after(now_utc().ok_or(Error::NotInitialized)?);
+        wait_until_utc(time)?;
+        Ok(time)
+    }
+}
+
+/// UTC time broken down into calendar fields (proleptic Gregorian calendar).
+///
+/// Displayed in the ISO 8601 format, e.g. `2024-02-29T12:34:56.789012Z`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    pub year: u32,
+    /// 1 to 12
+    pub month: u8,
+    /// 1 to 31
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub micros: u32,
+}
+
+impl DateTime {
+    /// Converts a duration since the Unix epoch.
+    pub fn from_unix(time: Duration) -> Self {
+        let secs = time.as_secs();
+        let (year, month, day) = civil_from_days(secs / 86400);
+        let secs_of_day = secs % 86400;
+
+        Self {
+            year,
+            month,
+            day,
+            hour: (secs_of_day / 3600) as u8,
+            minute: (secs_of_day / 60 % 60) as u8,
+            second: (secs_of_day % 60) as u8,
+            micros: time.subsec_micros(),
+        }
+    }
+
+    /// Converts to a duration since the Unix epoch. The fields must be within their ranges and after 1970.
+    pub fn to_unix(&self) -> Duration {
+        let days = days_from_civil(self.year, self.month, self.day);
+        let secs =
+            days * 86400 + self.hour as u64 * 3600 + self.minute as u64 * 60 + self.second as u64;
+        Duration::from_secs(secs) + Duration::from_micros(self.micros as u64)
+    }
+
+    /// Current UTC time, or `None` if the wall clock is not set.
+    pub fn now() -> Option<Self> {
+        now_utc().map(Self::from_unix)
+    }
+
+    /// Day of the week, from 0 (Sunday) to 6 (Saturday).
+    pub fn weekday(&self) -> u8 {
+        // 1970-01-01 was a Thursday
+        ((days_from_civil(self.year, self.month, self.day) + 4) % 7) as u8
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second, self.micros
+        )
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for DateTime {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "{=u32:04}-{=u8:02}-{=u8:02}T{=u8:02}:{=u8:02}:{=u8:02}.{=u32:06}Z",
+            self.year,
+            self.month,
+            self.day,
+            self.hour,
+            self.minute,
+            self.second,
+            self.micros
+        )
+    }
+}
+
+/// Date of a number of days since 1970-01-01.
+///
+/// Algorithm of H. Hinnant, "chrono-Compatible Low-Level Date Algorithms", shifted so that years start in March.
+fn civil_from_days(days: u64) -> (u32, u8, u8) {
+    // Days since 0000-03-01
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days % 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    // From March
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u8;
+    let month = (if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    }) as u8;
+    let year = (year_of_era + era * 400 + if month <= 2 { 1 } else { 0 }) as u32;
+
+    (year, month, day)
+}
+
+/// Number of days since 1970-01-01 of a date (the inverse of `civil_from_days`).
+fn days_from_civil(year: u32, month: u8, day: u8) -> u64 {
+    let year = year as u64 - if month <= 2 { 1 } else { 0 };
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let month = month as u64;
+    let month_index = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month_index + 2) / 5 + day as u64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146097 + day_of_era - 719468
+}
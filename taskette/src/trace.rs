@@ -0,0 +1,126 @@
+//! Hooks notified of scheduler events, for profiling and visualization tools (`trace-hooks` feature).
+//!
+//! Except for `on_tick`, hooks are called inside the critical section of the scheduler,
+//! so they have to be short and must not call functions of this crate.
+
+use core::cell::Cell;
+
+use critical_section::{CriticalSection, Mutex};
+
+use crate::sync::interrupt_free;
+
+static HOOKS: Mutex<Cell<TraceHooks>> = Mutex::new(Cell::new(TraceHooks::new()));
+
+/// Set of functions called on scheduler events. Each hook is optional.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TraceHooks {
+    on_switch: Option<fn(usize, usize)>,
+    on_ready: Option<fn(usize)>,
+    on_block: Option<fn(usize)>,
+    on_tick: Option<fn()>,
+    on_create: Option<fn(usize, usize)>,
+    on_remove: Option<fn(usize)>,
+}
+
+impl TraceHooks {
+    pub const fn new() -> Self {
+        Self {
+            on_switch: None,
+            on_ready: None,
+            on_block: None,
+            on_tick: None,
+            on_create: None,
+            on_remove: None,
+        }
+    }
+
+    /// Called with the IDs of the original and the next task on every context switch (they may be the same).
+    pub fn with_on_switch(self, hook: fn(usize, usize)) -> Self {
+        Self {
+            on_switch: Some(hook),
+            ..self
+        }
+    }
+
+    /// Called with the task ID when a blocked task becomes ready.
+    pub fn with_on_ready(self, hook: fn(usize)) -> Self {
+        Self {
+            on_ready: Some(hook),
+            ..self
+        }
+    }
+
+    /// Called with the task ID when a task becomes blocked.
+    pub fn with_on_block(self, hook: fn(usize)) -> Self {
+        Self {
+            on_block: Some(hook),
+            ..self
+        }
+    }
+
+    /// Called at the beginning of every tick interrupt (on each core).
+    pub fn with_on_tick(self, hook: fn()) -> Self {
+        Self {
+            on_tick: Some(hook),
+            ..self
+        }
+    }
+
+    /// Called with the task ID and the priority when a task is created.
+    pub fn with_on_create(self, hook: fn(usize, usize)) -> Self {
+        Self {
+            on_create: Some(hook),
+            ..self
+        }
+    }
+
+    /// Called with the task ID when a task finishes or is removed.
+    pub fn with_on_remove(self, hook: fn(usize)) -> Self {
+        Self {
+            on_remove: Some(hook),
+            ..self
+        }
+    }
+}
+
+/// Replaces the trace hooks.
+pub fn set_hooks(hooks: TraceHooks) {
+    interrupt_free(|cs| HOOKS.borrow(cs).set(hooks));
+}
+
+pub(crate) fn switch(cs: CriticalSection, from: usize, to: usize) {
+    if let Some(hook) = HOOKS.borrow(cs).get().on_switch {
+        hook(from, to);
+    }
+}
+
+pub(crate) fn ready(cs: CriticalSection, task_id: usize) {
+    if let Some(hook) = HOOKS.borrow(cs).get().on_ready {
+        hook(task_id);
+    }
+}
+
+pub(crate) fn block(cs: CriticalSection, task_id: usize) {
+    if let Some(hook) = HOOKS.borrow(cs).get().on_block {
+        hook(task_id);
+    }
+}
+
+pub(crate) fn create(cs: CriticalSection, task_id: usize, priority: usize) {
+    if let Some(hook) = HOOKS.borrow(cs).get().on_create {
+        hook(task_id, priority);
+    }
+}
+
+pub(crate) fn remove(cs: CriticalSection, task_id: usize) {
+    if let Some(hook) = HOOKS.borrow(cs).get().on_remove {
+        hook(task_id);
+    }
+}
+
+pub(crate) fn tick() {
+    let hooks = interrupt_free(|cs| HOOKS.borrow(cs).get());
+    if let Some(hook) = hooks.on_tick {
+        hook();
+    }
+}
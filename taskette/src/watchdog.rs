@@ -0,0 +1,134 @@
+//! Liveness tracking of critical tasks, gating the hardware watchdog.
+//!
+//! Each registered task has to call [`checkin`] at least once per its timeout.
+//! On every tick, the feed hook (which should feed the hardware watchdog) is called only if all registered tasks
+//! have checked in recently. Otherwise the starvation hook is called with the ID of a late task,
+//! and the hardware watchdog eventually resets the system unless the hook recovers.
+//! Tasks are unregistered automatically when they finish.
+
+use core::cell::{Cell, RefCell};
+
+use critical_section::Mutex;
+use heapless::Vec;
+
+use crate::{
+    Error,
+    scheduler::{MAX_NUM_TASKS, current_task_id},
+    sync::interrupt_free,
+    task::TaskHandle,
+    timer::current_time,
+};
+
+/// Function called on every tick while all registered tasks are alive
+pub type FeedHook = fn();
+/// Function called with the ID of a task which has not checked in within its timeout
+pub type StarvationHook = fn(usize);
+
+static WATCHED: Mutex<RefCell<Vec<Watched, MAX_NUM_TASKS>>> = Mutex::new(RefCell::new(Vec::new()));
+static FEED_HOOK: Mutex<Cell<Option<FeedHook>>> = Mutex::new(Cell::new(None));
+static STARVATION_HOOK: Mutex<Cell<Option<StarvationHook>>> = Mutex::new(Cell::new(None));
+
+struct Watched {
+    task_id: usize,
+    /// Maximum interval of check-ins (in ticks)
+    timeout: u64,
+    last_checkin: u64,
+}
+
+/// Starts tracking `task`, which has to check in at least every `timeout` ticks.
+///
+/// Registering the same task again changes its timeout. The task is regarded as checked in at registration.
+pub fn register(task: &TaskHandle, timeout: u64) -> Result<(), Error> {
+    let now = current_time()?;
+
+    interrupt_free(|cs| {
+        let mut watched = WATCHED.borrow_ref_mut(cs);
+        let entry = Watched {
+            task_id: task.id(),
+            timeout,
+            last_checkin: now,
+        };
+        match watched
+            .iter_mut()
+            .find(|watched| watched.task_id == task.id())
+        {
+            Some(existing) => *existing = entry,
+            None => watched.push(entry).or(Err(Error::TaskFull))?,
+        }
+
+        Ok(())
+    })
+}
+
+/// Stops tracking `task`.
+pub fn unregister(task: &TaskHandle) -> Result<(), Error> {
+    forget(task.id()).ok_or(Error::NotFound)
+}
+
+/// Tells that the current task is alive.
+pub fn checkin() -> Result<(), Error> {
+    let task_id = current_task_id()?;
+    let now = current_time()?;
+
+    interrupt_free(|cs| {
+        let mut watched = WATCHED.borrow_ref_mut(cs);
+        let entry = watched
+            .iter_mut()
+            .find(|watched| watched.task_id == task_id)
+            .ok_or(Error::NotFound)?;
+        entry.last_checkin = now;
+
+        Ok(())
+    })
+}
+
+/// Registers a function which feeds the hardware watchdog.
+pub fn set_feed_hook(hook: FeedHook) {
+    interrupt_free(|cs| FEED_HOOK.borrow(cs).set(Some(hook)));
+}
+
+/// Registers a function called on every tick while a registered task is late.
+///
+/// Called from the tick interrupt, but not inside a critical section.
+pub fn set_starvation_hook(hook: StarvationHook) {
+    interrupt_free(|cs| STARVATION_HOOK.borrow(cs).set(Some(hook)));
+}
+
+/// Removes the entry of a task. Returns `None` if it is not registered.
+pub(crate) fn forget(task_id: usize) -> Option<()> {
+    interrupt_free(|cs| {
+        let mut watched = WATCHED.borrow_ref_mut(cs);
+        let index = watched
+            .iter()
+            .position(|watched| watched.task_id == task_id)?;
+        watched.swap_remove(index);
+
+        Some(())
+    })
+}
+
+/// Checks the registered tasks and calls either of the hooks. Called on every tick.
+pub(crate) fn tick() {
+    let Ok(now) = current_time() else {
+        return;
+    };
+
+    let (late_task, feed_hook, starvation_hook) = interrupt_free(|cs| {
+        let late_task = WATCHED
+            .borrow_ref(cs)
+            .iter()
+            .find(|watched| now.saturating_sub(watched.last_checkin) > watched.timeout)
+            .map(|watched| watched.task_id);
+        (
+            late_task,
+            FEED_HOOK.borrow(cs).get(),
+            STARVATION_HOOK.borrow(cs).get(),
+        )
+    });
+
+    match (late_task, feed_hook, starvation_hook) {
+        (None, Some(feed_hook), _) => feed_hook(),
+        (Some(task_id), _, Some(starvation_hook)) => starvation_hook(task_id),
+        _ => (),
+    }
+}
@@ -0,0 +1,111 @@
+//! Deferred work queue ("bottom halves") drained by worker tasks.
+//!
+//! Interrupt handlers queue small work items with [`queue_work_from_isr`] or [`queue_closure_from_isr`],
+//! and a worker task spawned with [`spawn_worker`] runs them in order at its own priority.
+//! This suits work too long for an interrupt handler but too small for a dedicated task
+//! (e.g. parsing a received packet, or updating a display after a button press).
+//!
+//! Items are run one at a time by a worker, so a long item delays all the later ones.
+//! Several workers can drain the queue in parallel (e.g. one per core).
+
+use core::mem::{MaybeUninit, align_of, size_of};
+
+use crate::{
+    Error,
+    arch::StackAllocation,
+    scheduler::spawn,
+    sync::Channel,
+    task::{TaskConfig, TaskHandle},
+};
+
+/// Maximum number of queued work items (8 with `profile-tiny`, 64 with `profile-large`)
+pub const WORK_QUEUE_CAPACITY: usize = crate::profile::WORK_QUEUE_CAPACITY;
+/// Maximum size (in words) of the captured variables of a closure passed to [`queue_closure_from_isr`]
+pub const MAX_CLOSURE_WORDS: usize = 4;
+
+type Storage = MaybeUninit<[usize; MAX_CLOSURE_WORDS]>;
+
+/// Closure stored inline with its calling function
+struct Work {
+    storage: Storage,
+    /// Moves the closure out of `storage` and calls it
+    call: unsafe fn(*mut Storage),
+}
+
+// Only closures which are `Send` are stored
+unsafe impl Send for Work {}
+
+/// Context pointer of a work function, passed to the worker task as is
+struct Context(*mut ());
+
+unsafe impl Send for Context {}
+
+impl Context {
+    fn get(&self) -> *mut () {
+        self.0
+    }
+}
+
+static QUEUE: Channel<Work, WORK_QUEUE_CAPACITY> = Channel::new();
+
+/// Queues a call of `func` with `context`. Can be called from interrupt handlers (and tasks).
+///
+/// `context` is passed to `func` in the worker task as is, so it must stay valid until then.
+/// Returns `Error::QueueFull` if [`WORK_QUEUE_CAPACITY`] items are already queued.
+pub fn queue_work_from_isr(func: fn(*mut ()), context: *mut ()) -> Result<(), Error> {
+    let context = Context(context);
+    queue_closure_from_isr(move || func(context.get()))
+}
+
+/// Queues a call of `f`. Can be called from interrupt handlers (and tasks).
+///
+/// The closure is stored in the queue without allocation,
+/// so its captured variables must fit in [`MAX_CLOSURE_WORDS`] words (checked at compile time).
+/// Returns `Error::QueueFull` (dropping `f`) if [`WORK_QUEUE_CAPACITY`] items are already queued.
+pub fn queue_closure_from_isr<F: FnOnce() + Send + 'static>(f: F) -> Result<(), Error> {
+    const {
+        assert!(
+            size_of::<F>() <= size_of::<Storage>() && align_of::<F>() <= align_of::<Storage>(),
+            "Closure too large for the work queue"
+        );
+    }
+
+    let mut storage = Storage::uninit();
+    // SAFETY: the size and alignment are checked above
+    unsafe { storage.as_mut_ptr().cast::<F>().write(f) };
+
+    QUEUE
+        .try_send(Work {
+            storage,
+            call: call_closure::<F>,
+        })
+        .map_err(|mut work| {
+            // SAFETY: the closure was written above and is not called
+            drop(unsafe { work.storage.as_mut_ptr().cast::<F>().read() });
+            Error::QueueFull
+        })
+}
+
+/// Creates a worker task running queued work items forever. The priority is set through `config`.
+pub fn spawn_worker<S: StackAllocation>(stack: S, config: TaskConfig) -> Result<TaskHandle, Error> {
+    spawn(run_worker, stack, config)
+}
+
+fn run_worker() {
+    loop {
+        // Fails only if the scheduler is not running, which is not the case in a task
+        if let Ok(mut work) = QUEUE.recv() {
+            // SAFETY: `call` matches the type of the closure in `storage`, which is called only once
+            unsafe { (work.call)(&mut work.storage) };
+        }
+    }
+}
+
+/// Calls the closure of type `F` stored in `storage`.
+///
+/// # Safety
+/// `storage` must hold a closure of type `F`, which is moved out.
+unsafe fn call_closure<F: FnOnce()>(storage: *mut Storage) {
+    let f = unsafe { storage.cast::<F>().read() };
+    f();
+}
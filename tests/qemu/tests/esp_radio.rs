@@ -0,0 +1,146 @@
+//! Test of the semaphores and queues registered for `esp-radio`, used through the driver API
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+mod panic_handler;
+mod utils;
+
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    cell::UnsafeCell,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use esp_radio_rtos_driver::{
+    queue::QueueHandle,
+    semaphore::{SemaphoreHandle, SemaphoreKind},
+};
+use semihosting::{println, process::ExitCode};
+use static_cell::StaticCell;
+use taskette::{
+    scheduler::{Scheduler, spawn},
+    task::TaskConfig,
+};
+
+use crate::utils::{Stack, entry, init_scheduler};
+
+/// Bump allocator, enough for the objects created by this test
+struct BumpAllocator {
+    heap: UnsafeCell<[u8; 16384]>,
+    next: AtomicUsize,
+}
+
+unsafe impl Sync for BumpAllocator {}
+
+unsafe impl GlobalAlloc for BumpAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let base = self.heap.get() as usize;
+        // Offset of the allocation following the previous one
+        let start = |next: usize| (base + next).next_multiple_of(layout.align()) - base;
+        match self
+            .next
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |next| {
+                (start(next) + layout.size() <= 16384).then_some(start(next) + layout.size())
+            }) {
+            Ok(next) => (base + start(next)) as *mut u8,
+            Err(_) => core::ptr::null_mut(),
+        }
+    }
+
+    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {}
+}
+
+#[global_allocator]
+static ALLOCATOR: BumpAllocator = BumpAllocator {
+    heap: UnsafeCell::new([0; 16384]),
+    next: AtomicUsize::new(0),
+};
+
+/// Handles shared with the waiter task, which are only used through the driver API
+struct Shared(&'static SemaphoreHandle, &'static QueueHandle);
+
+unsafe impl Send for Shared {}
+
+impl Shared {
+    /// Moves the whole `Shared` into a closure, unlike destructuring, which captures the fields one by one
+    fn into_inner(self) -> (&'static SemaphoreHandle, &'static QueueHandle) {
+        (self.0, self.1)
+    }
+}
+
+static SCHEDULER: StaticCell<Scheduler> = StaticCell::new();
+static TASK_STACK: StaticCell<Stack<8192>> = StaticCell::new();
+static WAITER_STACK: StaticCell<Stack<8192>> = StaticCell::new();
+
+#[entry]
+fn main() -> ! {
+    let scheduler = SCHEDULER.init(init_scheduler(100).unwrap());
+
+    let task_stack = TASK_STACK.init(Stack::new());
+    let waiter_stack = WAITER_STACK.init(Stack::new());
+
+    spawn(
+        move || task_main(waiter_stack),
+        task_stack,
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+fn task_main(waiter_stack: &'static mut Stack<8192>) {
+    let semaphore: &'static SemaphoreHandle = alloc::boxed::Box::leak(alloc::boxed::Box::new(
+        SemaphoreHandle::new(SemaphoreKind::Counting { max: 1, initial: 0 }),
+    ));
+    let queue: &'static QueueHandle =
+        alloc::boxed::Box::leak(alloc::boxed::Box::new(QueueHandle::new(2, 4)));
+
+    // Nothing is given yet, so both time out (after 50 ms)
+    if semaphore.take(Some(50_000)) {
+        fail("semaphore taken before given");
+    }
+    let mut item = [0u8; 4];
+    if unsafe { queue.receive(item.as_mut_ptr(), Some(50_000)) } {
+        fail("item received before sent");
+    }
+
+    // A higher-priority task blocks on them until this task gives and sends
+    let shared = Shared(semaphore, queue);
+    spawn(
+        move || {
+            let (semaphore, queue) = shared.into_inner();
+            if !semaphore.take(None) {
+                fail("semaphore not taken");
+            }
+            let mut item = [0u8; 4];
+            if !unsafe { queue.receive(item.as_mut_ptr(), None) } || item != [1, 2, 3, 4] {
+                fail("item not received");
+            }
+            ExitCode::SUCCESS.exit_process();
+        },
+        waiter_stack,
+        TaskConfig::default().with_priority(2),
+    )
+    .unwrap();
+
+    if !semaphore.give() {
+        fail("semaphore not given");
+    }
+    let item = [1u8, 2, 3, 4];
+    if !unsafe { queue.send_to_back(item.as_ptr(), Some(50_000)) } {
+        fail("item not sent");
+    }
+
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+fn fail(message: &str) -> ! {
+    println!("{}", message);
+    ExitCode::FAILURE.exit_process();
+}
@@ -0,0 +1,124 @@
+//! Test of the size of the context saved by lazy FPU context switching
+//! (FP registers are only saved for a task which has used the FPU)
+
+#![no_std]
+#![no_main]
+
+mod panic_handler;
+mod utils;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use semihosting::{println, process::ExitCode};
+use static_cell::StaticCell;
+use taskette::{
+    rtos_awareness::{DebugInfo, DebugTaskState},
+    scheduler::{Scheduler, spawn},
+    task::{self, TaskConfig},
+    timer::{current_time, wait_until},
+};
+
+use crate::utils::{Stack, entry, init_scheduler};
+
+/// R4-R11, EXC_RETURN, and CONTROL saved by software
+const SOFTWARE_FRAME_SIZE: usize = 10 * 4;
+/// R0-R3, R12, LR, PC, and xPSR saved by hardware
+const BASIC_FRAME_SIZE: usize = SOFTWARE_FRAME_SIZE + 8 * 4;
+/// S16-S31 saved by software, and S0-S15, FPSCR, and a reserved word saved by hardware
+const FP_REGS_SIZE: usize = 16 * 4 + 18 * 4;
+
+unsafe extern "C" {
+    static taskette_debug_info: DebugInfo;
+}
+
+static SCHEDULER: StaticCell<Scheduler> = StaticCell::new();
+static OBSERVER_STACK: StaticCell<Stack<4096>> = StaticCell::new();
+static INTEGER_STACK: StaticCell<Stack<4096>> = StaticCell::new();
+static FP_STACK: StaticCell<Stack<4096>> = StaticCell::new();
+
+/// Stack pointer of the measured task, written continuously while it runs
+static TASK_SP: AtomicUsize = AtomicUsize::new(0);
+
+#[entry]
+fn main() -> ! {
+    let scheduler = SCHEDULER.init(init_scheduler(1000).unwrap());
+
+    let integer_stack = INTEGER_STACK.init(Stack::new());
+    let fp_stack = FP_STACK.init(Stack::new());
+    spawn(
+        move || {
+            let integer = spawn(integer_task, integer_stack, TaskConfig::default()).unwrap();
+            check_frame(integer.id(), BASIC_FRAME_SIZE, "Integer-only task");
+            task::kill(integer.id()).unwrap();
+
+            let fp = spawn(fp_task, fp_stack, TaskConfig::default()).unwrap();
+            check_frame(fp.id(), BASIC_FRAME_SIZE + FP_REGS_SIZE, "FP task");
+
+            ExitCode::SUCCESS.exit_process();
+        },
+        OBSERVER_STACK.init(Stack::new()),
+        TaskConfig::default().with_priority(2),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+/// Lets the task run for a while, and compares the size of the context saved when it was switched out with `expected`.
+fn check_frame(task_id: usize, expected: usize, name: &str) {
+    wait_until(current_time().unwrap() + 5).unwrap();
+
+    let saved_sp = critical_section::with(|_| {
+        let info = unsafe { &*&raw const taskette_debug_info };
+        info.tasks
+            .iter()
+            .find(|slot| slot.state != DebugTaskState::Unused as u32 && slot.id == task_id)
+            .map(|slot| slot.stack_pointer)
+    })
+    .unwrap();
+
+    // Bit 9 of the stacked xPSR indicates a padding word added by the hardware for alignment
+    let fp_regs_saved = expected > BASIC_FRAME_SIZE;
+    let xpsr = saved_sp + SOFTWARE_FRAME_SIZE + if fp_regs_saved { 16 * 4 } else { 0 } + 7 * 4;
+    let padding = if unsafe { (xpsr as *const u32).read_volatile() } & (1 << 9) != 0 {
+        4
+    } else {
+        0
+    };
+
+    let size = TASK_SP.load(Ordering::SeqCst) - saved_sp - padding;
+    if size != expected {
+        println!("{}: {} bytes saved instead of {}", name, size, expected);
+        ExitCode::FAILURE.exit_process();
+    }
+}
+
+fn integer_task() {
+    // The stack pointer stays the same while the loop runs
+    unsafe {
+        core::arch::asm!(
+            "2:",
+            "mov {tmp}, sp",
+            "str {tmp}, [{task_sp}]",
+            "b 2b",
+            task_sp = in(reg) TASK_SP.as_ptr(),
+            tmp = out(reg) _,
+        );
+    }
+}
+
+fn fp_task() {
+    // Same as `integer_task`, except that an FP instruction gives the task an FP context
+    unsafe {
+        core::arch::asm!(
+            "2:",
+            "vmov.f32 s16, #1.0",
+            "mov {tmp}, sp",
+            "str {tmp}, [{task_sp}]",
+            "b 2b",
+            task_sp = in(reg) TASK_SP.as_ptr(),
+            tmp = out(reg) _,
+            out("s16") _,
+        );
+    }
+}
@@ -0,0 +1,268 @@
+//! Test of FPU register saving in context switch on RISC-V (F extension)
+//! RISC-V counterpart of `fpu.rs`.
+//! Inspired by the RegTests of FreeRTOS:
+//!     https://freertos.org/Documentation/02-Kernel/06-Coding-guidelines/02-FreeRTOS-Coding-Standard-and-Style-Guide#testing
+//!     https://github.com/FreeRTOS/FreeRTOS/blob/5424d9d36a364ba9c73955c500d16773f543bb9c/FreeRTOS/Demo/CORTEX_M4F_M0_LPC43xx_Keil/M4/RegTest.c
+
+#![no_std]
+#![no_main]
+
+mod panic_handler;
+mod utils;
+
+use semihosting::{println, process::ExitCode};
+use static_cell::StaticCell;
+use taskette::{scheduler::spawn, task::TaskConfig};
+
+use crate::utils::{Stack, entry, init_scheduler};
+
+static TASK1_STACK: StaticCell<Stack<8192>> = StaticCell::new();
+static TASK2_STACK: StaticCell<Stack<8192>> = StaticCell::new();
+
+/// Number of iterations of the busy loop which spans several ticks
+const SPIN_COUNT: u32 = 1_000_000;
+
+#[entry]
+fn main() -> ! {
+    let scheduler = init_scheduler(1000).unwrap();
+
+    let task1_stack = TASK1_STACK.init(Stack::new());
+    let _task1 = spawn(move || unsafe {
+        loop {
+            // Continuously overwrite FPU registers
+            core::arch::asm!(
+                "li t0, -1",
+                "fcvt.s.w f0, t0",
+                "fcvt.s.w f1, t0",
+                "fcvt.s.w f2, t0",
+                "fcvt.s.w f3, t0",
+                "fcvt.s.w f4, t0",
+                "fcvt.s.w f5, t0",
+                "fcvt.s.w f6, t0",
+                "fcvt.s.w f7, t0",
+                "fcvt.s.w f8, t0",
+                "fcvt.s.w f9, t0",
+                "fcvt.s.w f10, t0",
+                "fcvt.s.w f11, t0",
+                "fcvt.s.w f12, t0",
+                "fcvt.s.w f13, t0",
+                "fcvt.s.w f14, t0",
+                "fcvt.s.w f15, t0",
+                "fcvt.s.w f16, t0",
+                "fcvt.s.w f17, t0",
+                "fcvt.s.w f18, t0",
+                "fcvt.s.w f19, t0",
+                "fcvt.s.w f20, t0",
+                "fcvt.s.w f21, t0",
+                "fcvt.s.w f22, t0",
+                "fcvt.s.w f23, t0",
+                "fcvt.s.w f24, t0",
+                "fcvt.s.w f25, t0",
+                "fcvt.s.w f26, t0",
+                "fcvt.s.w f27, t0",
+                "fcvt.s.w f28, t0",
+                "fcvt.s.w f29, t0",
+                "fcvt.s.w f30, t0",
+                "fcvt.s.w f31, t0",
+                out("t0") _,
+                out("f0") _,
+                out("f1") _,
+                out("f2") _,
+                out("f3") _,
+                out("f4") _,
+                out("f5") _,
+                out("f6") _,
+                out("f7") _,
+                out("f8") _,
+                out("f9") _,
+                out("f10") _,
+                out("f11") _,
+                out("f12") _,
+                out("f13") _,
+                out("f14") _,
+                out("f15") _,
+                out("f16") _,
+                out("f17") _,
+                out("f18") _,
+                out("f19") _,
+                out("f20") _,
+                out("f21") _,
+                out("f22") _,
+                out("f23") _,
+                out("f24") _,
+                out("f25") _,
+                out("f26") _,
+                out("f27") _,
+                out("f28") _,
+                out("f29") _,
+                out("f30") _,
+                out("f31") _,
+            );
+        }
+    }, task1_stack, TaskConfig::default()).unwrap();
+
+    let task2_stack = TASK2_STACK.init(Stack::new());
+    let _task2 = spawn(move || unsafe {
+        let mut result = true;
+
+        for _ in 0..10 {
+                let mut values = [0.0f32; 32];
+
+                // Set values to registers
+                core::arch::asm!(
+                    "li t0, 0",
+                    "fcvt.s.w f0, t0",
+                    "li t0, 1",
+                    "fcvt.s.w f1, t0",
+                    "li t0, 2",
+                    "fcvt.s.w f2, t0",
+                    "li t0, 3",
+                    "fcvt.s.w f3, t0",
+                    "li t0, 4",
+                    "fcvt.s.w f4, t0",
+                    "li t0, 5",
+                    "fcvt.s.w f5, t0",
+                    "li t0, 6",
+                    "fcvt.s.w f6, t0",
+                    "li t0, 7",
+                    "fcvt.s.w f7, t0",
+                    "li t0, 8",
+                    "fcvt.s.w f8, t0",
+                    "li t0, 9",
+                    "fcvt.s.w f9, t0",
+                    "li t0, 10",
+                    "fcvt.s.w f10, t0",
+                    "li t0, 11",
+                    "fcvt.s.w f11, t0",
+                    "li t0, 12",
+                    "fcvt.s.w f12, t0",
+                    "li t0, 13",
+                    "fcvt.s.w f13, t0",
+                    "li t0, 14",
+                    "fcvt.s.w f14, t0",
+                    "li t0, 15",
+                    "fcvt.s.w f15, t0",
+                    "li t0, 16",
+                    "fcvt.s.w f16, t0",
+                    "li t0, 17",
+                    "fcvt.s.w f17, t0",
+                    "li t0, 18",
+                    "fcvt.s.w f18, t0",
+                    "li t0, 19",
+                    "fcvt.s.w f19, t0",
+                    "li t0, 20",
+                    "fcvt.s.w f20, t0",
+                    "li t0, 21",
+                    "fcvt.s.w f21, t0",
+                    "li t0, 22",
+                    "fcvt.s.w f22, t0",
+                    "li t0, 23",
+                    "fcvt.s.w f23, t0",
+                    "li t0, 24",
+                    "fcvt.s.w f24, t0",
+                    "li t0, 25",
+                    "fcvt.s.w f25, t0",
+                    "li t0, 26",
+                    "fcvt.s.w f26, t0",
+                    "li t0, 27",
+                    "fcvt.s.w f27, t0",
+                    "li t0, 28",
+                    "fcvt.s.w f28, t0",
+                    "li t0, 29",
+                    "fcvt.s.w f29, t0",
+                    "li t0, 30",
+                    "fcvt.s.w f30, t0",
+                    "li t0, 31",
+                    "fcvt.s.w f31, t0",
+                    // Spin for several ticks to be preempted by the tick interrupt
+                    "2:",
+                    "addi {count}, {count}, -1",
+                    "bnez {count}, 2b",
+                    // Load register values
+                    "fsw f0, 4*0({values})",
+                    "fsw f1, 4*1({values})",
+                    "fsw f2, 4*2({values})",
+                    "fsw f3, 4*3({values})",
+                    "fsw f4, 4*4({values})",
+                    "fsw f5, 4*5({values})",
+                    "fsw f6, 4*6({values})",
+                    "fsw f7, 4*7({values})",
+                    "fsw f8, 4*8({values})",
+                    "fsw f9, 4*9({values})",
+                    "fsw f10, 4*10({values})",
+                    "fsw f11, 4*11({values})",
+                    "fsw f12, 4*12({values})",
+                    "fsw f13, 4*13({values})",
+                    "fsw f14, 4*14({values})",
+                    "fsw f15, 4*15({values})",
+                    "fsw f16, 4*16({values})",
+                    "fsw f17, 4*17({values})",
+                    "fsw f18, 4*18({values})",
+                    "fsw f19, 4*19({values})",
+                    "fsw f20, 4*20({values})",
+                    "fsw f21, 4*21({values})",
+                    "fsw f22, 4*22({values})",
+                    "fsw f23, 4*23({values})",
+                    "fsw f24, 4*24({values})",
+                    "fsw f25, 4*25({values})",
+                    "fsw f26, 4*26({values})",
+                    "fsw f27, 4*27({values})",
+                    "fsw f28, 4*28({values})",
+                    "fsw f29, 4*29({values})",
+                    "fsw f30, 4*30({values})",
+                    "fsw f31, 4*31({values})",
+                    out("t0") _,
+                    out("f0") _,
+                    out("f1") _,
+                    out("f2") _,
+                    out("f3") _,
+                    out("f4") _,
+                    out("f5") _,
+                    out("f6") _,
+                    out("f7") _,
+                    out("f8") _,
+                    out("f9") _,
+                    out("f10") _,
+                    out("f11") _,
+                    out("f12") _,
+                    out("f13") _,
+                    out("f14") _,
+                    out("f15") _,
+                    out("f16") _,
+                    out("f17") _,
+                    out("f18") _,
+                    out("f19") _,
+                    out("f20") _,
+                    out("f21") _,
+                    out("f22") _,
+                    out("f23") _,
+                    out("f24") _,
+                    out("f25") _,
+                    out("f26") _,
+                    out("f27") _,
+                    out("f28") _,
+                    out("f29") _,
+                    out("f30") _,
+                    out("f31") _,
+                    count = inout(reg) SPIN_COUNT => _,
+                    values = in(reg) values.as_mut_ptr(),
+                );
+
+                // Verify values
+                for i in 0..=31 {
+                    if (values[i] - i as f32).abs() > core::f32::EPSILON {
+                        result = false;
+                        println!("f{} = {}", i, values[i]);
+                    }
+                }
+        }
+
+        if result {
+            ExitCode::SUCCESS.exit_process();
+        } else {
+            ExitCode::FAILURE.exit_process();
+        }
+    }, task2_stack, TaskConfig::default()).unwrap();
+
+    scheduler.start();
+}
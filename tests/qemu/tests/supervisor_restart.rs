@@ -0,0 +1,73 @@
+//! Test of restarting a supervised task after several panics in a row
+
+#![no_std]
+#![no_main]
+
+mod utils;
+
+use core::{
+    hint::black_box,
+    panic::PanicInfo,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use semihosting::{println, process::ExitCode};
+use static_cell::{ConstStaticCell, StaticCell};
+use taskette::{
+    scheduler::{Scheduler, handle_panic},
+    supervisor::{RestartPolicy, spawn_supervised},
+    task::TaskConfig,
+};
+
+use crate::utils::{Stack, entry, init_scheduler};
+
+const NUM_PANICS: u32 = 5;
+
+static SCHEDULER: StaticCell<Scheduler> = StaticCell::new();
+static TASK1_STACK: ConstStaticCell<Stack<8192>> = ConstStaticCell::new(Stack::new());
+
+static RUNS: AtomicU32 = AtomicU32::new(0);
+
+#[panic_handler]
+fn panic_handler(info: &PanicInfo<'_>) -> ! {
+    // Returns only when the panic cannot be recovered
+    handle_panic(info);
+
+    println!("{:?}", info);
+    ExitCode::FAILURE.exit_process();
+}
+
+#[entry]
+fn main() -> ! {
+    let scheduler = SCHEDULER.init(init_scheduler(100).unwrap());
+
+    spawn_supervised(
+        task1,
+        TASK1_STACK.take(),
+        TaskConfig::default(),
+        RestartPolicy::OnPanic,
+        1,
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+fn task1() {
+    let runs = RUNS.fetch_add(1, Ordering::SeqCst) + 1;
+    if runs > NUM_PANICS {
+        ExitCode::SUCCESS.exit_process();
+    }
+
+    // Panics deep in the stack, so that the frames abandoned by the panic cover the top of the stack
+    nested_panic(8);
+}
+
+fn nested_panic(depth: u32) {
+    let buffer = black_box([depth as u8; 64]);
+    if depth == 0 {
+        panic!("Run #{} panicked", RUNS.load(Ordering::SeqCst));
+    }
+    nested_panic(black_box(depth - 1));
+    black_box(buffer);
+}
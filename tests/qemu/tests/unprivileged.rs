@@ -0,0 +1,102 @@
+//! Test of unprivileged tasks and system calls
+
+#![no_std]
+#![no_main]
+
+mod panic_handler;
+mod utils;
+
+use core::sync::atomic::Ordering;
+
+use cortex_m::register::control::{self, Npriv};
+use semihosting::{println, process::ExitCode};
+use static_cell::StaticCell;
+use taskette::{
+    futex::Futex,
+    scheduler::{Scheduler, spawn},
+    task::TaskConfig,
+};
+use taskette_cortex_m::{spawn_unprivileged, syscall};
+
+use crate::utils::{Stack, entry, init_scheduler};
+
+static SCHEDULER: StaticCell<Scheduler> = StaticCell::new();
+static CHECKER_STACK: StaticCell<Stack<8192>> = StaticCell::new();
+static USER_STACK: StaticCell<Stack<8192>> = StaticCell::new();
+static CHILD_STACK: StaticCell<[u8; 4096]> = StaticCell::new();
+
+/// Set by the child task
+static STARTED: Futex = Futex::new(0);
+/// Set by the unprivileged task when all system calls succeeded
+static DONE: Futex = Futex::new(0);
+
+#[entry]
+fn main() -> ! {
+    let scheduler = SCHEDULER.init(init_scheduler(100).unwrap());
+
+    let checker_stack = CHECKER_STACK.init(Stack::new());
+    let user_stack = USER_STACK.init(Stack::new());
+
+    spawn(
+        task_checker,
+        checker_stack,
+        TaskConfig::default().with_priority(1),
+    )
+    .unwrap();
+    spawn_unprivileged(
+        task_user,
+        user_stack,
+        TaskConfig::default().with_priority(2),
+    )
+    .unwrap();
+
+    scheduler.start();
+}
+
+/// Privileged task
+fn task_checker() {
+    if control::read().npriv() != Npriv::Privileged {
+        println!("Privileged task is running unprivileged");
+        ExitCode::FAILURE.exit_process();
+    }
+
+    while DONE.as_ref().load(Ordering::SeqCst) == 0 {
+        DONE.wait(0).unwrap();
+    }
+
+    ExitCode::SUCCESS.exit_process();
+}
+
+fn task_user() {
+    if control::read().npriv() != Npriv::Unprivileged {
+        println!("Unprivileged task is running privileged");
+        ExitCode::FAILURE.exit_process();
+    }
+
+    let time = syscall::current_time().unwrap();
+    syscall::wait_until(time + 2).unwrap();
+    if syscall::current_time().unwrap() < time + 2 {
+        println!("Woken up too early");
+        ExitCode::FAILURE.exit_process();
+    }
+
+    syscall::spawn(task_child, CHILD_STACK.init([0; 4096]), 1).unwrap();
+
+    // The child has lower priority, so it runs only while this task is blocked
+    while STARTED.as_ref().load(Ordering::SeqCst) == 0 {
+        syscall::futex_wait(&STARTED, 0).unwrap();
+    }
+
+    DONE.as_ref().store(1, Ordering::SeqCst);
+    syscall::futex_wake(&DONE, 1).unwrap();
+}
+
+fn task_child() {
+    if control::read().npriv() != Npriv::Unprivileged {
+        println!("Child of an unprivileged task is running privileged");
+        ExitCode::FAILURE.exit_process();
+    }
+
+    STARTED.as_ref().store(1, Ordering::SeqCst);
+    syscall::futex_wake(&STARTED, 1).unwrap();
+}
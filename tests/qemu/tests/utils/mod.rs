@@ -28,10 +28,10 @@ pub fn init_scheduler(tick_freq: u32) -> Option<Scheduler> {
     {
         let peripherals = esp_hal::init(esp_hal::Config::default());
         let swint = esp_hal::interrupt::software::SoftwareInterruptControl::new(peripherals.SW_INTERRUPT);
+        let systimer = esp_hal::timer::systimer::SystemTimer::new(peripherals.SYSTIMER);
         taskette_esp_riscv::init_scheduler(
-            peripherals.SYSTIMER,
+            systimer.alarm1,
             swint.software_interrupt0,
-            168_000_000,
             SchedulerConfig::default().with_tick_freq(tick_freq),
         )
     }